@@ -0,0 +1,382 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use crate::filesystem::FileSystemError;
+use crate::security::Actor;
+
+/// File name of the audit log (inside the app data directory)
+pub const AUDIT_LOG_FILE: &str = "audit.jsonl";
+
+/// Operation recorded in the audit log
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditOperation {
+    PermissionRequest,
+    PermissionGrant,
+    PermissionDeny,
+    PermissionRevoke,
+    /// An actor's capability ceiling or a project's deny rules were changed
+    PolicyChange,
+    ReadFile,
+    WriteFile,
+    DeleteFile,
+    GetMetadata,
+    ListDirectory,
+    CreateDirectory,
+    DeleteDirectory,
+    Watch,
+    RunCommand,
+    OpenTerminal,
+}
+
+/// Outcome of an audited operation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditResult {
+    Ok,
+    Error,
+}
+
+/// A single audit log record (one JSON line)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    #[serde(with = "chrono::serde::ts_milliseconds")]
+    pub timestamp: DateTime<Utc>,
+    #[serde(default)]
+    pub actor: Actor,
+    pub operation: AuditOperation,
+    pub path: String,
+    #[serde(default = "default_result")]
+    pub result: AuditResult,
+    /// Bytes read or written
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bytes: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+fn default_result() -> AuditResult {
+    AuditResult::Ok
+}
+
+/// Filter for `AuditLog::query` (all criteria optional)
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AuditQuery {
+    /// Only entries at or after this time (ms since epoch)
+    pub since: Option<i64>,
+    /// Only entries at or before this time (ms since epoch)
+    pub until: Option<i64>,
+    /// Only entries for this path or paths beneath it
+    pub path: Option<String>,
+    pub actor: Option<Actor>,
+    pub operation: Option<AuditOperation>,
+    /// Return at most this many of the most recent matches
+    pub limit: Option<usize>,
+}
+
+impl AuditQuery {
+    fn matches(&self, entry: &AuditEntry) -> bool {
+        let millis = entry.timestamp.timestamp_millis();
+        self.since.is_none_or(|since| millis >= since)
+            && self.until.is_none_or(|until| millis <= until)
+            && self
+                .path
+                .as_ref()
+                .is_none_or(|path| Path::new(&entry.path).starts_with(path))
+            && self.actor.is_none_or(|actor| entry.actor == actor)
+            && self.operation.is_none_or(|operation| entry.operation == operation)
+    }
+}
+
+/// Append-only audit log stored as JSON lines
+pub struct AuditLog {
+    path: PathBuf,
+    file: Mutex<Option<File>>,
+}
+
+impl AuditLog {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            file: Mutex::new(None),
+        }
+    }
+
+    /// Record a permission or policy event
+    ///
+    /// # Arguments
+    /// * `actor` - Who asked for or made the change
+    /// * `operation` - The event
+    /// * `path` - The resolved path it concerns (empty for policy changes)
+    /// * `detail` - Free-form description
+    /// 
+    /// # Returns
+    /// The error if the entry could not be written
+    pub fn record(
+        &self,
+        actor: Actor,
+        operation: AuditOperation,
+        path: &str,
+        detail: Option<String>,
+    ) -> Result<(), FileSystemError> {
+        self.append(AuditEntry {
+            timestamp: Utc::now(),
+            actor,
+            operation,
+            path: path.to_string(),
+            result: AuditResult::Ok,
+            bytes: None,
+            detail,
+        })
+    }
+
+    /// Record the outcome of a file operation
+    ///
+    /// # Arguments
+    /// * `actor` - Who performed the operation
+    /// * `operation` - The operation
+    /// * `path` - The resolved path (as requested if it couldn't be resolved)
+    /// * `result` - `Ok` with the bytes read or written (if any), or the error
+    /// 
+    /// # Returns
+    /// The error if the entry could not be written
+    pub fn record_operation(
+        &self,
+        actor: Actor,
+        operation: AuditOperation,
+        path: &str,
+        result: Result<Option<u64>, &FileSystemError>,
+    ) -> Result<(), FileSystemError> {
+        let (result, bytes, detail) = match result {
+            Ok(bytes) => (AuditResult::Ok, bytes, None),
+            Err(error) => (AuditResult::Error, None, Some(error.to_string())),
+        };
+        self.append(AuditEntry {
+            timestamp: Utc::now(),
+            actor,
+            operation,
+            path: path.to_string(),
+            result,
+            bytes,
+            detail,
+        })
+    }
+
+    /// Append an entry to the log
+    fn append(&self, entry: AuditEntry) -> Result<(), FileSystemError> {
+        self.write_entry(&entry).map_err(|e| {
+            FileSystemError::IoError(std::io::Error::new(
+                e.kind(),
+                format!("Failed to write audit log {}: {}", self.path.to_string_lossy(), e),
+            ))
+        })
+    }
+
+    fn write_entry(&self, entry: &AuditEntry) -> std::io::Result<()> {
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+
+        let mut file = self.file.lock().unwrap();
+        if file.is_none() {
+            if let Some(parent) = self.path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            *file = Some(OpenOptions::new().create(true).append(true).open(&self.path)?);
+        }
+
+        let log = file.as_mut().expect("audit log file opened above");
+        log.write_all(line.as_bytes())?;
+        log.flush()
+    }
+
+    /// Read entries matching a filter, oldest first
+    ///
+    /// Lines that fail to parse (e.g. a partial final line) are skipped.
+    pub async fn query(&self, query: &AuditQuery) -> Result<Vec<AuditEntry>, FileSystemError> {
+        let content = match tokio::fs::read_to_string(&self.path).await {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut entries: Vec<AuditEntry> = content
+            .lines()
+            .filter_map(|line| serde_json::from_str::<AuditEntry>(line).ok())
+            .filter(|entry| query.matches(entry))
+            .collect();
+
+        if let Some(limit) = query.limit {
+            let skip = entries.len().saturating_sub(limit);
+            entries.drain(..skip);
+        }
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    /// A log in a fresh directory
+    fn log(name: &str) -> AuditLog {
+        let dir = std::env::temp_dir()
+            .join(format!("nexus-audit-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        AuditLog::new(dir.join(AUDIT_LOG_FILE))
+    }
+    
+    fn entry(millis: i64, actor: Actor, operation: AuditOperation, path: &str) -> AuditEntry {
+        AuditEntry {
+            timestamp: DateTime::from_timestamp_millis(millis).unwrap(),
+            actor,
+            operation,
+            path: path.to_string(),
+            result: AuditResult::Ok,
+            bytes: None,
+            detail: None,
+        }
+    }
+    
+    /// A log holding one entry per second from 1000 ms on
+    fn filled_log(name: &str) -> AuditLog {
+        let log = log(name);
+        for entry in [
+            entry(1000, Actor::User, AuditOperation::ReadFile, "/work/app/src/main.rs"),
+            entry(2000, Actor::ImplementationAi, AuditOperation::WriteFile, "/work/app/lib.rs"),
+            entry(3000, Actor::ImplementationAi, AuditOperation::ReadFile, "/work/app/README"),
+            entry(4000, Actor::OverseerAi, AuditOperation::DeleteFile, "/work/app-old/a.txt"),
+            entry(5000, Actor::User, AuditOperation::PolicyChange, ""),
+        ] {
+            log.append(entry).unwrap();
+        }
+        log
+    }
+    
+    async fn times(log: &AuditLog, query: AuditQuery) -> Vec<i64> {
+        log.query(&query)
+            .await
+            .unwrap()
+            .iter()
+            .map(|entry| entry.timestamp.timestamp_millis())
+            .collect()
+    }
+    
+    #[tokio::test]
+    async fn empty_query_returns_everything_oldest_first() {
+        let log = filled_log("all");
+        assert_eq!(times(&log, AuditQuery::default()).await, [1000, 2000, 3000, 4000, 5000]);
+    }
+    
+    #[tokio::test]
+    async fn missing_log_has_no_entries() {
+        let log = log("missing");
+        assert!(log.query(&AuditQuery::default()).await.unwrap().is_empty());
+    }
+    
+    #[tokio::test]
+    async fn time_range_is_inclusive() {
+        let log = filled_log("time");
+        let since = AuditQuery {
+            since: Some(3000),
+            ..AuditQuery::default()
+        };
+        assert_eq!(times(&log, since).await, [3000, 4000, 5000]);
+        let until = AuditQuery {
+            until: Some(2000),
+            ..AuditQuery::default()
+        };
+        assert_eq!(times(&log, until).await, [1000, 2000]);
+        let between = AuditQuery {
+            since: Some(1500),
+            until: Some(4000),
+            ..AuditQuery::default()
+        };
+        assert_eq!(times(&log, between).await, [2000, 3000, 4000]);
+    }
+    
+    #[tokio::test]
+    async fn path_matches_whole_components() {
+        let log = filled_log("path");
+        let project = AuditQuery {
+            path: Some("/work/app".to_string()),
+            ..AuditQuery::default()
+        };
+        // `/work/app-old` only shares a prefix of the name
+        assert_eq!(times(&log, project).await, [1000, 2000, 3000]);
+        let file = AuditQuery {
+            path: Some("/work/app/lib.rs".to_string()),
+            ..AuditQuery::default()
+        };
+        assert_eq!(times(&log, file).await, [2000]);
+    }
+    
+    #[tokio::test]
+    async fn actor_and_operation_combine() {
+        let log = filled_log("actor");
+        let actor = AuditQuery {
+            actor: Some(Actor::ImplementationAi),
+            ..AuditQuery::default()
+        };
+        assert_eq!(times(&log, actor).await, [2000, 3000]);
+        let operation = AuditQuery {
+            operation: Some(AuditOperation::ReadFile),
+            ..AuditQuery::default()
+        };
+        assert_eq!(times(&log, operation).await, [1000, 3000]);
+        let both = AuditQuery {
+            actor: Some(Actor::ImplementationAi),
+            operation: Some(AuditOperation::ReadFile),
+            ..AuditQuery::default()
+        };
+        assert_eq!(times(&log, both).await, [3000]);
+    }
+    
+    #[tokio::test]
+    async fn limit_keeps_the_most_recent_matches() {
+        let log = filled_log("limit");
+        let limit = AuditQuery {
+            limit: Some(2),
+            ..AuditQuery::default()
+        };
+        assert_eq!(times(&log, limit).await, [4000, 5000]);
+        let filtered = AuditQuery {
+            operation: Some(AuditOperation::ReadFile),
+            limit: Some(1),
+            ..AuditQuery::default()
+        };
+        assert_eq!(times(&log, filtered).await, [3000]);
+    }
+    
+    #[tokio::test]
+    async fn unparsable_lines_are_skipped() {
+        let log = filled_log("partial");
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(&log.path)
+            .unwrap()
+            .write_all(b"{\"timestamp\":")
+            .unwrap();
+        assert_eq!(times(&log, AuditQuery::default()).await.len(), 5);
+    }
+    
+    #[test]
+    fn failed_writes_are_reported() {
+        // A file where the log's directory should be
+        let blocker = std::env::temp_dir()
+            .join(format!("nexus-audit-blocked-{}", std::process::id()));
+        std::fs::write(&blocker, "").unwrap();
+        let log = AuditLog::new(blocker.join(AUDIT_LOG_FILE));
+        
+        let error = log
+            .record(Actor::User, AuditOperation::PolicyChange, "", None)
+            .unwrap_err();
+        assert!(error.to_string().contains("Failed to write audit log"), "{}", error);
+        let failed = FileSystemError::NotFound("gone".to_string());
+        assert!(log
+            .record_operation(Actor::User, AuditOperation::ReadFile, "/a", Err(&failed))
+            .is_err());
+    }
+}
@@ -0,0 +1,177 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+use crate::filesystem::FileSystemError;
+use crate::security::{Actor, GrantScope};
+
+/// Event emitted to trusted windows when a permission request needs a decision
+pub const PERMISSION_REQUESTED: &str = "permission-requested";
+
+/// Event emitted to trusted windows once a request has been decided
+pub const PERMISSION_RESOLVED: &str = "permission-resolved";
+
+/// Windows allowed to answer permission requests
+pub const TRUSTED_CONSENT_WINDOWS: &[&str] = &["main"];
+
+/// How long a request waits for the user before it is treated as denied
+pub const CONSENT_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// Identifier of a pending permission request
+pub type ConsentRequestId = u64;
+
+/// A permission request awaiting the user's decision
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PermissionRequest {
+    pub id: ConsentRequestId,
+    pub path: String,
+    pub scope: GrantScope,
+    pub reason: Option<String>,
+    /// Label of the window that asked for the permission
+    pub requested_by: String,
+    /// Who the permission is for
+    pub actor: Actor,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub requested_at: DateTime<Utc>,
+}
+
+/// The user's answer to a permission request
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "decision", rename_all = "snake_case")]
+pub enum ConsentDecision {
+    /// Approved, possibly with a narrower scope than requested
+    Approved { scope: GrantScope },
+    Denied,
+}
+
+/// Payload of `PERMISSION_RESOLVED`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PermissionResolution {
+    pub id: ConsentRequestId,
+    pub path: String,
+    #[serde(flatten)]
+    pub decision: ConsentDecision,
+}
+
+struct PendingRequest {
+    request: PermissionRequest,
+    /// Everyone waiting on this request (duplicate requests share one prompt)
+    waiters: Vec<oneshot::Sender<ConsentDecision>>,
+}
+
+/// Permission requests waiting for the user (thread-safe)
+pub struct ConsentState {
+    pending: Mutex<HashMap<ConsentRequestId, PendingRequest>>,
+    next_id: AtomicU64,
+}
+
+impl ConsentState {
+    pub fn new() -> Self {
+        Self {
+            pending: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    /// Open a request, or join an identical one that is already pending
+    ///
+    /// # Returns
+    /// * The request, whether it is new (and must be announced), and a receiver for the decision
+    pub fn open(
+        &self,
+        path: PathBuf,
+        scope: GrantScope,
+        reason: Option<String>,
+        requested_by: &str,
+        actor: Actor,
+    ) -> (PermissionRequest, bool, oneshot::Receiver<ConsentDecision>) {
+        let (tx, rx) = oneshot::channel();
+        let path = path.to_string_lossy().to_string();
+        let mut pending = self.pending.lock().unwrap();
+
+        if let Some(existing) = pending
+            .values_mut()
+            .find(|p| {
+                p.request.path == path && p.request.scope == scope && p.request.actor == actor
+            })
+        {
+            existing.waiters.push(tx);
+            return (existing.request.clone(), false, rx);
+        }
+
+        let request = PermissionRequest {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            path,
+            scope,
+            reason,
+            requested_by: requested_by.to_string(),
+            actor,
+            requested_at: Utc::now(),
+        };
+        pending.insert(
+            request.id,
+            PendingRequest {
+                request: request.clone(),
+                waiters: vec![tx],
+            },
+        );
+
+        (request, true, rx)
+    }
+
+    /// Look up a pending request
+    pub fn get(&self, id: ConsentRequestId) -> Result<PermissionRequest, FileSystemError> {
+        self.pending
+            .lock()
+            .unwrap()
+            .get(&id)
+            .map(|p| p.request.clone())
+            .ok_or_else(|| {
+                FileSystemError::NotFound(format!("No pending permission request {}", id))
+            })
+    }
+
+    /// Resolve a pending request, waking everyone waiting on it
+    ///
+    /// # Returns
+    /// * `Some(PermissionRequest)` - The resolved request
+    /// * `None` - The request was already resolved or cancelled
+    pub fn resolve(
+        &self,
+        id: ConsentRequestId,
+        decision: ConsentDecision,
+    ) -> Option<PermissionRequest> {
+        let entry = self.pending.lock().unwrap().remove(&id)?;
+        for waiter in entry.waiters {
+            let _ = waiter.send(decision);
+        }
+        Some(entry.request)
+    }
+
+    /// Drop a request without a decision (e.g. after a timeout)
+    pub fn cancel(&self, id: ConsentRequestId) -> Option<PermissionRequest> {
+        self.pending
+            .lock()
+            .unwrap()
+            .remove(&id)
+            .map(|entry| entry.request)
+    }
+
+    /// All requests still waiting for a decision, oldest first
+    pub fn pending_requests(&self) -> Vec<PermissionRequest> {
+        let mut requests: Vec<PermissionRequest> = self
+            .pending
+            .lock()
+            .unwrap()
+            .values()
+            .map(|p| p.request.clone())
+            .collect();
+        requests.sort_by_key(|r| r.id);
+        requests
+    }
+}
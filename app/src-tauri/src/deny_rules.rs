@@ -0,0 +1,258 @@
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use crate::filesystem::FileSystemError;
+
+/// Sensitive files AI actors may never touch, even inside an allowed root
+pub const DEFAULT_DENY_PATTERNS: &[&str] = &[
+    "**/.env",
+    "**/.env.*",
+    "**/id_rsa*",
+    "**/id_dsa*",
+    "**/id_ecdsa*",
+    "**/id_ed25519*",
+    "**/*.pem",
+    "**/*.key",
+    "**/*.p12",
+    "**/*.pfx",
+    "**/.git/config",
+    "**/.git-credentials",
+    "**/.ssh",
+    "**/.ssh/**",
+    "**/.aws/credentials",
+    "**/.netrc",
+    "**/.npmrc",
+    "**/.pypirc",
+];
+
+/// Exceptions to the default deny patterns
+pub const DEFAULT_ALLOW_PATTERNS: &[&str] = &["**/.env.example", "**/.env.sample"];
+
+/// Per-project additions to the default rules (patterns relative to the project root)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DenyOverrides {
+    /// Extra patterns to deny
+    #[serde(default)]
+    pub deny: Vec<String>,
+    /// Patterns exempt from denial (beats both default and project deny patterns)
+    #[serde(default)]
+    pub allow: Vec<String>,
+}
+
+/// How denied entries appear in directory listings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeniedEntryMode {
+    /// Leave denied entries out
+    Redact,
+    /// Keep denied entries with `denied: true`
+    Mark,
+}
+
+/// Compiled deny and allow globs
+struct Matcher {
+    deny: GlobSet,
+    allow: GlobSet,
+}
+
+impl Matcher {
+    fn new<S: AsRef<str>>(deny: &[S], allow: &[S]) -> Result<Self, FileSystemError> {
+        Ok(Self {
+            deny: build_set(deny)?,
+            allow: build_set(allow)?,
+        })
+    }
+}
+
+fn build_set<S: AsRef<str>>(patterns: &[S]) -> Result<GlobSet, FileSystemError> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = GlobBuilder::new(pattern.as_ref())
+            .literal_separator(true)
+            .case_insensitive(cfg!(windows))
+            .build()
+            .map_err(|e| {
+                FileSystemError::InvalidPath(format!("Invalid pattern {}: {}", pattern.as_ref(), e))
+            })?;
+        builder.add(glob);
+    }
+    builder
+        .build()
+        .map_err(|e| FileSystemError::InvalidPath(format!("Invalid patterns: {}", e)))
+}
+
+/// Deny rules: defaults plus per-project overrides
+pub struct DenyRules {
+    defaults: Matcher,
+    overrides: HashMap<PathBuf, DenyOverrides>,
+    compiled: HashMap<PathBuf, Matcher>,
+}
+
+impl DenyRules {
+    pub fn new() -> Self {
+        Self {
+            defaults: Matcher::new(DEFAULT_DENY_PATTERNS, DEFAULT_ALLOW_PATTERNS)
+                .expect("default deny patterns are valid"),
+            overrides: HashMap::new(),
+            compiled: HashMap::new(),
+        }
+    }
+
+    /// Build rules from persisted project overrides
+    ///
+    /// # Returns
+    /// The rules, or the error naming the first project with an invalid pattern
+    pub fn with_overrides(
+        overrides: HashMap<PathBuf, DenyOverrides>,
+    ) -> Result<Self, FileSystemError> {
+        let mut rules = Self::new();
+        for (project, project_overrides) in overrides {
+            rules
+                .set_overrides(project.clone(), project_overrides)
+                .map_err(|e| {
+                    FileSystemError::InvalidPath(format!(
+                        "Invalid deny rules for {}: {}",
+                        project.to_string_lossy(),
+                        e
+                    ))
+                })?;
+        }
+        Ok(rules)
+    }
+
+    /// Replace the overrides for a project root (empty overrides remove it)
+    pub fn set_overrides(
+        &mut self,
+        project: PathBuf,
+        overrides: DenyOverrides,
+    ) -> Result<(), FileSystemError> {
+        if overrides == DenyOverrides::default() {
+            self.overrides.remove(&project);
+            self.compiled.remove(&project);
+            return Ok(());
+        }
+
+        let matcher = Matcher::new(&overrides.deny, &overrides.allow)?;
+        self.compiled.insert(project.clone(), matcher);
+        self.overrides.insert(project, overrides);
+        Ok(())
+    }
+
+    pub fn overrides(&self) -> &HashMap<PathBuf, DenyOverrides> {
+        &self.overrides
+    }
+
+    /// Whether a resolved path is denied
+    ///
+    /// Overrides of every project root containing the path apply; an allow
+    /// pattern wins over any deny pattern.
+    pub fn is_denied(&self, path: &Path) -> bool {
+        let projects: Vec<(&Matcher, &Path)> = self
+            .compiled
+            .iter()
+            .filter_map(|(project, matcher)| {
+                path.strip_prefix(project).ok().map(|relative| (matcher, relative))
+            })
+            .collect();
+
+        let denied = self.defaults.deny.is_match(path)
+            || projects.iter().any(|(matcher, relative)| matcher.deny.is_match(relative));
+        let allowed = self.defaults.allow.is_match(path)
+            || projects.iter().any(|(matcher, relative)| matcher.allow.is_match(relative));
+        denied && !allowed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn denied(rules: &DenyRules, path: &str) -> bool {
+        rules.is_denied(Path::new(path))
+    }
+
+    #[test]
+    fn default_patterns_deny_secrets() {
+        let rules = DenyRules::new();
+        for path in [
+            "/work/app/.env",
+            "/work/app/.env.local",
+            "/work/app/config/.env.production",
+            "/home/me/.ssh/id_rsa",
+            "/home/me/keys/id_rsa.pub",
+            "/home/me/keys/id_ed25519",
+            "/work/app/certs/server.pem",
+            "/work/app/tls.key",
+            "/work/app/.git/config",
+            "/home/me/.ssh",
+            "/home/me/.ssh/known_hosts",
+            "/home/me/.aws/credentials",
+            "/home/me/.npmrc",
+        ] {
+            assert!(denied(&rules, path), "{} should be denied", path);
+        }
+    }
+
+    #[test]
+    fn ordinary_files_are_not_denied() {
+        let rules = DenyRules::new();
+        for path in [
+            "/work/app/src/main.rs",
+            "/work/app/environment.ts",
+            "/work/app/.envrc.md/readme",
+            "/work/app/.git/HEAD",
+            "/work/app/docs/pem-format.md",
+            "/work/app/keyboard.rs",
+        ] {
+            assert!(!denied(&rules, path), "{} should be allowed", path);
+        }
+    }
+
+    #[test]
+    fn env_examples_are_allowed() {
+        let rules = DenyRules::new();
+        assert!(!denied(&rules, "/work/app/.env.example"));
+        assert!(!denied(&rules, "/work/app/server/.env.sample"));
+        assert!(denied(&rules, "/work/app/.env.example.local"));
+    }
+
+    #[test]
+    fn project_overrides_apply_inside_their_project() {
+        let mut rules = DenyRules::new();
+        let overrides = DenyOverrides {
+            deny: vec!["secrets/**".to_string()],
+            allow: vec!["config/dev.key".to_string(), ".env".to_string()],
+        };
+        rules.set_overrides(PathBuf::from("/work/app"), overrides).unwrap();
+
+        assert!(denied(&rules, "/work/app/secrets/token.txt"));
+        assert!(!denied(&rules, "/work/other/secrets/token.txt"));
+        // An exception beats both default and project patterns, only where it points
+        assert!(!denied(&rules, "/work/app/config/dev.key"));
+        assert!(!denied(&rules, "/work/app/.env"));
+        assert!(denied(&rules, "/work/app/config/prod.key"));
+        assert!(denied(&rules, "/work/app/server/.env"));
+        assert!(denied(&rules, "/work/other/.env"));
+
+        // Empty overrides remove the project's rules
+        rules.set_overrides(PathBuf::from("/work/app"), DenyOverrides::default()).unwrap();
+        assert!(!denied(&rules, "/work/app/secrets/token.txt"));
+        assert!(rules.overrides().is_empty());
+    }
+
+    #[test]
+    fn invalid_override_patterns_are_reported() {
+        let overrides = DenyOverrides {
+            deny: vec!["secrets/[".to_string()],
+            allow: Vec::new(),
+        };
+        let mut rules = DenyRules::new();
+        assert!(rules.set_overrides(PathBuf::from("/work/app"), overrides.clone()).is_err());
+        assert!(rules.overrides().is_empty());
+
+        let persisted = HashMap::from([(PathBuf::from("/work/app"), overrides)]);
+        let error = DenyRules::with_overrides(persisted).err().unwrap();
+        assert!(error.to_string().contains("/work/app"), "{}", error);
+    }
+}
@@ -1,11 +1,20 @@
+use chrono::{DateTime, Utc};
+use notify::event::{ModifyKind, RenameMode};
 use notify::{RecommendedWatcher, RecursiveMode, Watcher};
-use std::collections::HashMap;
-use std::path::PathBuf;
-use std::sync::Mutex;
-use tauri::{AppHandle, Emitter};
-use tokio::sync::mpsc;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, UNIX_EPOCH};
+use tauri::{AppHandle, Emitter, Runtime};
+use tokio::sync::{broadcast, mpsc};
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
 
-use crate::filesystem::FileSystemError;
+use crate::filesystem::{temp_write_path, FileSystemError};
 
 /// Event names for file watch events
 pub const FILE_CREATED: &str = "file-created";
@@ -13,259 +22,932 @@ pub const FILE_MODIFIED: &str = "file-modified";
 pub const FILE_DELETED: &str = "file-deleted";
 pub const FILE_RENAMED: &str = "file-renamed";
 
+/// Identifier of an OS-level watch (shared by every subscriber of a path)
+pub type WatchId = u64;
+
+/// Identifier of a single consumer's subscription to a watch
+pub type SubscriptionId = u64;
+
+/// Files up to this size get a content hash in their watch events (1MB)
+const MAX_HASH_SIZE: u64 = 1024 * 1024;
+
+/// How long after a backend write its watcher echo is attributed to the backend
+const SELF_WRITE_WINDOW: Duration = Duration::from_secs(2);
+
+/// Capacity of the channel between a watcher and its event task
+const EVENT_CHANNEL_CAPACITY: usize = 100;
+
+/// Events buffered for each backend change listener before it lags
+const CHANGE_CHANNEL_CAPACITY: usize = 256;
+
+/// How long shutdown waits for event tasks to finish
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Window over which `events_per_second` is averaged
+const RATE_WINDOW: Duration = Duration::from_secs(10);
+
+/// Advice attached to errors caused by OS watch limits
+#[cfg(target_os = "linux")]
+const WATCH_LIMIT_HINT: &str = "the system inotify limit was reached. Raise it with \
+    `sudo sysctl fs.inotify.max_user_watches=524288` and \
+    `sudo sysctl fs.inotify.max_user_instances=512`, or watch fewer directories recursively";
+#[cfg(not(target_os = "linux"))]
+const WATCH_LIMIT_HINT: &str =
+    "the system limit on watched files was reached. Watch fewer directories recursively";
+
+/// Kind of change reported by a watch event
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileWatchEventKind {
+    Created,
+    Modified,
+    Deleted,
+    Renamed,
+}
+
 /// File watch event payload
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
-pub enum FileWatchEvent {
-    Created(String),
-    Modified(String),
-    Deleted(String),
-    Renamed { old: String, new: String },
+///
+/// Metadata fields describe the path after the change and are `None` when
+/// the path no longer exists (deletions, the old side of a rename).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileWatchEvent {
+    pub kind: FileWatchEventKind,
+    pub path: String,
+    /// Previous path (renames only)
+    pub old_path: Option<String>,
+    pub is_dir: Option<bool>,
+    pub size: Option<u64>,
+    /// Last modification time in milliseconds since the Unix epoch
+    pub modified: Option<i64>,
+    /// SHA-256 of the file content (files up to `MAX_HASH_SIZE` only)
+    pub content_hash: Option<String>,
+    /// Watch that observed the change
+    pub watch_id: WatchId,
+    /// Per-watch sequence number, increasing by one for every emitted event
+    pub sequence: u64,
+    /// True when the change was caused by the backend's own `write_file`
+    pub self_originated: bool,
+}
+
+impl FileWatchEvent {
+    /// Build an event for `path`, reading its current metadata from disk
+    async fn describe(
+        kind: FileWatchEventKind,
+        path: &Path,
+        old_path: Option<&Path>,
+        watch_id: WatchId,
+        sequence: u64,
+    ) -> Self {
+        let mut event = Self {
+            kind,
+            path: path.to_string_lossy().to_string(),
+            old_path: old_path.map(|p| p.to_string_lossy().to_string()),
+            is_dir: None,
+            size: None,
+            modified: None,
+            content_hash: None,
+            watch_id,
+            sequence,
+            self_originated: false,
+        };
+
+        if kind == FileWatchEventKind::Deleted {
+            return event;
+        }
+
+        if let Ok(metadata) = tokio::fs::metadata(path).await {
+            event.is_dir = Some(metadata.is_dir());
+            event.size = Some(metadata.len());
+            event.modified = metadata
+                .modified()
+                .ok()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map(|d| d.as_millis() as i64);
+
+            if metadata.is_file() && metadata.len() <= MAX_HASH_SIZE {
+                if let Ok(content) = tokio::fs::read(path).await {
+                    event.content_hash = Some(format!("{:x}", Sha256::digest(&content)));
+                }
+            }
+        }
+
+        event
+    }
+}
+
+/// A write performed by the backend that the watcher is expected to report
+struct PendingWrite {
+    /// Hash of the written content (`None` for the temp file used by atomic writes)
+    content_hash: Option<String>,
+    expires: Instant,
+}
+
+/// Registry of backend writes, used to recognise watcher events we caused ourselves
+#[derive(Clone, Default)]
+struct SelfWrites {
+    pending: Arc<Mutex<HashMap<PathBuf, PendingWrite>>>,
+}
+
+impl SelfWrites {
+    /// Comparison key for a path (parent canonicalized, so event and request paths agree)
+    fn key(path: &Path) -> PathBuf {
+        match (path.parent(), path.file_name()) {
+            (Some(parent), Some(name)) => parent
+                .canonicalize()
+                .map(|p| p.join(name))
+                .unwrap_or_else(|_| path.to_path_buf()),
+            _ => path.to_path_buf(),
+        }
+    }
+
+    fn record(&self, path: &Path, content: &[u8]) {
+        let expires = Instant::now() + SELF_WRITE_WINDOW;
+        let mut pending = self.pending.lock().unwrap();
+        pending.retain(|_, write| write.expires > Instant::now());
+        pending.insert(
+            Self::key(path),
+            PendingWrite {
+                content_hash: Some(format!("{:x}", Sha256::digest(content))),
+                expires,
+            },
+        );
+        pending.insert(
+            Self::key(&temp_write_path(path)),
+            PendingWrite {
+                content_hash: None,
+                expires,
+            },
+        );
+    }
+
+    /// Whether a change to `path` (with its current content hash) came from a backend write
+    ///
+    /// A content mismatch means the file was changed again by someone else,
+    /// so the event is treated as external.
+    fn is_self_write(&self, path: &Path, content_hash: Option<&str>) -> bool {
+        let pending = self.pending.lock().unwrap();
+        match pending.get(&Self::key(path)) {
+            Some(write) if write.expires > Instant::now() => {
+                match (write.content_hash.as_deref(), content_hash) {
+                    (Some(expected), Some(actual)) => expected == actual,
+                    // No hash to compare (temp file, large file, or path gone)
+                    _ => true,
+                }
+            }
+            _ => false,
+        }
+    }
+}
+
+/// How a path is being watched
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WatchMode {
+    File,
+    Directory,
+    RecursiveDirectory,
+}
+
+/// Subscription handle returned to the frontend
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WatchSubscription {
+    pub id: SubscriptionId,
+    pub watch_id: WatchId,
+    pub path: String,
+}
+
+/// A subscriber of a watch, as reported by `get_watch_status`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WatchSubscriberStatus {
+    pub subscription_id: SubscriptionId,
+    pub window_label: String,
+}
+
+/// Health and statistics of a single watch
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WatchStatus {
+    pub watch_id: WatchId,
+    pub path: String,
+    pub mode: WatchMode,
+    pub subscribers: Vec<WatchSubscriberStatus>,
+    /// Events emitted since the watch started
+    pub total_events: u64,
+    /// Average emitted events per second over the last `RATE_WINDOW`
+    pub events_per_second: f64,
+    /// Raw notify events dropped because the event task fell behind
+    pub dropped_events: u64,
+    pub last_error: Option<String>,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub started_at: DateTime<Utc>,
+}
+
+/// Counters shared between a watcher callback, its event task and status queries
+#[derive(Default)]
+struct WatchStats {
+    total_events: AtomicU64,
+    dropped_events: AtomicU64,
+    recent_events: Mutex<VecDeque<Instant>>,
+    last_error: Mutex<Option<String>>,
+}
+
+impl WatchStats {
+    fn record_event(&self) {
+        self.total_events.fetch_add(1, Ordering::Relaxed);
+        let now = Instant::now();
+        let mut recent = self.recent_events.lock().unwrap();
+        recent.push_back(now);
+        while recent
+            .front()
+            .is_some_and(|t| now.duration_since(*t) > RATE_WINDOW)
+        {
+            recent.pop_front();
+        }
+    }
+
+    fn record_error(&self, error: String) {
+        *self.last_error.lock().unwrap() = Some(error);
+    }
+
+    fn events_per_second(&self) -> f64 {
+        let now = Instant::now();
+        let recent = self.recent_events.lock().unwrap();
+        let count = recent
+            .iter()
+            .filter(|t| now.duration_since(**t) <= RATE_WINDOW)
+            .count();
+        count as f64 / RATE_WINDOW.as_secs_f64()
+    }
+}
+
+/// Subscribers of a watch, keyed by subscription id (value is the window label)
+type Subscribers = Arc<Mutex<HashMap<SubscriptionId, String>>>;
+
+/// A single OS-level watch and the consumers subscribed to it
+///
+/// The event task runs until `cancel` is triggered; `stop` (or dropping the
+/// entry) cancels it, so no event is delivered once the entry is removed.
+struct WatchEntry {
+    id: WatchId,
+    mode: WatchMode,
+    watcher: RecommendedWatcher,
+    subscribers: Subscribers,
+    stats: Arc<WatchStats>,
+    started_at: DateTime<Utc>,
+    cancel: CancellationToken,
+    task: Option<JoinHandle<()>>,
+}
+
+impl WatchEntry {
+    /// Cancel the event task and return its handle so callers can await it
+    fn stop(mut self) -> Option<JoinHandle<()>> {
+        self.cancel.cancel();
+        self.task.take()
+    }
+}
+
+impl Drop for WatchEntry {
+    fn drop(&mut self) {
+        self.cancel.cancel();
+    }
 }
 
 /// File watcher state (thread-safe)
 pub struct FileWatcherState {
-    watchers: Mutex<HashMap<PathBuf, RecommendedWatcher>>,
+    watchers: Mutex<HashMap<PathBuf, WatchEntry>>,
+    next_id: AtomicU64,
+    self_writes: SelfWrites,
+    changes: broadcast::Sender<FileWatchEvent>,
 }
 
 impl FileWatcherState {
     pub fn new() -> Self {
         Self {
             watchers: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(1),
+            self_writes: SelfWrites::default(),
+            changes: broadcast::channel(CHANGE_CHANNEL_CAPACITY).0,
+        }
+    }
+
+    /// Receive every event any watch emits, for backend consumers
+    ///
+    /// A receiver that falls more than `CHANGE_CHANNEL_CAPACITY` events behind
+    /// gets `RecvError::Lagged` and should assume anything may have changed.
+    pub fn changes(&self) -> broadcast::Receiver<FileWatchEvent> {
+        self.changes.subscribe()
+    }
+
+    /// Record that the backend is about to write `content` to `path`
+    ///
+    /// Watch events caused by the write are reported with `self_originated`
+    /// set, so the editor doesn't prompt to reload its own save.
+    pub fn expect_self_write(&self, path: &Path, content: &[u8]) {
+        self.self_writes.record(path, content);
+    }
+
+    fn next_id(&self) -> u64 {
+        self.next_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Add a subscription for `window_label`, creating the underlying watch if needed
+    fn subscribe<R: Runtime>(
+        &self,
+        path_buf: PathBuf,
+        mode: WatchMode,
+        window_label: &str,
+        app: AppHandle<R>,
+    ) -> Result<WatchSubscription, FileSystemError> {
+        let mut watchers = self.watchers.lock().unwrap();
+        let subscription_id = self.next_id();
+
+        // Path already watched: share the existing watch
+        if let Some(entry) = watchers.get_mut(&path_buf) {
+            // Upgrade a non-recursive directory watch if a recursive one is requested
+            if mode == WatchMode::RecursiveDirectory && entry.mode == WatchMode::Directory {
+                let _ = entry.watcher.unwatch(&path_buf);
+                if let Err(e) = entry.watcher.watch(&path_buf, RecursiveMode::Recursive) {
+                    // Keep the existing subscribers' watch alive
+                    let _ = entry.watcher.watch(&path_buf, RecursiveMode::NonRecursive);
+                    return Err(watch_error("Failed to watch directory", e));
+                }
+                entry.mode = WatchMode::RecursiveDirectory;
+            }
+
+            entry
+                .subscribers
+                .lock()
+                .unwrap()
+                .insert(subscription_id, window_label.to_string());
+
+            return Ok(WatchSubscription {
+                id: subscription_id,
+                watch_id: entry.id,
+                path: path_buf.to_string_lossy().to_string(),
+            });
+        }
+
+        // Create channel for file system events
+        let (tx, rx) = mpsc::channel(EVENT_CHANNEL_CAPACITY);
+        let stats = Arc::new(WatchStats::default());
+
+        // Create watcher
+        let callback_stats = stats.clone();
+        let mut watcher = RecommendedWatcher::new(
+            move |result: Result<notify::Event, notify::Error>| match result {
+                Ok(event) => {
+                    if let Err(TrySendError::Full(_)) = tx.try_send(event) {
+                        callback_stats.dropped_events.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+                Err(e) => callback_stats.record_error(watch_error("Watcher failed", e).to_string()),
+            },
+            notify::Config::default(),
+        )
+        .map_err(|e| watch_error("Failed to create watcher", e))?;
+
+        let recursive_mode = match mode {
+            WatchMode::RecursiveDirectory => RecursiveMode::Recursive,
+            WatchMode::File | WatchMode::Directory => RecursiveMode::NonRecursive,
+        };
+
+        watcher
+            .watch(&path_buf, recursive_mode)
+            .map_err(|e| watch_error("Failed to watch path", e))?;
+
+        let subscribers: Subscribers = Arc::new(Mutex::new(HashMap::new()));
+        subscribers
+            .lock()
+            .unwrap()
+            .insert(subscription_id, window_label.to_string());
+
+        // Spawn task to handle events (runs until the watch is cancelled)
+        let watch_id = self.next_id();
+        let cancel = CancellationToken::new();
+        let task = tokio::spawn(run_event_task(
+            EventTask {
+                watch_id,
+                mode,
+                watched_path: path_buf.clone(),
+                app,
+                subscribers: subscribers.clone(),
+                stats: stats.clone(),
+                self_writes: self.self_writes.clone(),
+                changes: self.changes.clone(),
+                cancel: cancel.clone(),
+            },
+            rx,
+        ));
+
+        watchers.insert(
+            path_buf.clone(),
+            WatchEntry {
+                id: watch_id,
+                mode,
+                watcher,
+                subscribers,
+                stats,
+                started_at: Utc::now(),
+                cancel,
+                task: Some(task),
+            },
+        );
+
+        Ok(WatchSubscription {
+            id: subscription_id,
+            watch_id,
+            path: path_buf.to_string_lossy().to_string(),
+        })
+    }
+
+    /// Snapshot the health and statistics of every active watch
+    pub fn status(&self) -> Vec<WatchStatus> {
+        let watchers = self.watchers.lock().unwrap();
+        let mut statuses: Vec<WatchStatus> = watchers
+            .iter()
+            .map(|(path, entry)| {
+                let mut subscribers: Vec<WatchSubscriberStatus> = entry
+                    .subscribers
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .map(|(id, label)| WatchSubscriberStatus {
+                        subscription_id: *id,
+                        window_label: label.clone(),
+                    })
+                    .collect();
+                subscribers.sort_by_key(|s| s.subscription_id);
+
+                WatchStatus {
+                    watch_id: entry.id,
+                    path: path.to_string_lossy().to_string(),
+                    mode: entry.mode,
+                    subscribers,
+                    total_events: entry.stats.total_events.load(Ordering::Relaxed),
+                    events_per_second: entry.stats.events_per_second(),
+                    dropped_events: entry.stats.dropped_events.load(Ordering::Relaxed),
+                    last_error: entry.stats.last_error.lock().unwrap().clone(),
+                    started_at: entry.started_at,
+                }
+            })
+            .collect();
+        statuses.sort_by_key(|s| s.watch_id);
+        statuses
+    }
+
+    /// Remove subscriptions matching `predicate`, dropping watches left without subscribers
+    ///
+    /// Returns the number of subscriptions removed.
+    fn remove_subscriptions<F>(&self, predicate: F) -> usize
+    where
+        F: Fn(SubscriptionId, &str) -> bool,
+    {
+        let mut watchers = self.watchers.lock().unwrap();
+        let mut removed = 0;
+
+        // Entries dropped by `retain` cancel their event tasks
+        watchers.retain(|_, entry| {
+            let mut subscribers = entry.subscribers.lock().unwrap();
+            let before = subscribers.len();
+            subscribers.retain(|id, label| !predicate(*id, label));
+            removed += before - subscribers.len();
+            !subscribers.is_empty()
+        });
+
+        removed
+    }
+
+    /// Stop every watch and wait (bounded) for their event tasks to finish
+    ///
+    /// Called when the application exits.
+    pub async fn shutdown(&self) {
+        let entries: Vec<WatchEntry> = {
+            let mut watchers = self.watchers.lock().unwrap();
+            watchers.drain().map(|(_, entry)| entry).collect()
+        };
+
+        let tasks: Vec<JoinHandle<()>> = entries.into_iter().filter_map(WatchEntry::stop).collect();
+        for task in tasks {
+            let _ = tokio::time::timeout(SHUTDOWN_TIMEOUT, task).await;
+        }
+    }
+}
+
+/// Everything an event task needs to turn notify events into emitted watch events
+struct EventTask<R: Runtime> {
+    watch_id: WatchId,
+    mode: WatchMode,
+    watched_path: PathBuf,
+    app: AppHandle<R>,
+    subscribers: Subscribers,
+    stats: Arc<WatchStats>,
+    self_writes: SelfWrites,
+    changes: broadcast::Sender<FileWatchEvent>,
+    cancel: CancellationToken,
+}
+
+/// Forward notify events for one watch until it is cancelled or its watcher is dropped
+async fn run_event_task<R: Runtime>(task: EventTask<R>, mut rx: mpsc::Receiver<notify::Event>) {
+    let mut sequence = 0;
+
+    loop {
+        let event = tokio::select! {
+            biased;
+            _ = task.cancel.cancelled() => break,
+            event = rx.recv() => match event {
+                Some(event) => event,
+                None => break,
+            },
+        };
+
+        let changes = match task.mode {
+            WatchMode::File => file_changes(&event, &task.watched_path),
+            WatchMode::Directory | WatchMode::RecursiveDirectory => {
+                directory_changes(&event, &task.watched_path)
+            }
+        };
+
+        for (kind, path, old_path) in changes {
+            sequence += 1;
+            let mut payload = FileWatchEvent::describe(
+                kind,
+                &path,
+                old_path.as_deref(),
+                task.watch_id,
+                sequence,
+            )
+            .await;
+            payload.self_originated = task
+                .self_writes
+                .is_self_write(&path, payload.content_hash.as_deref());
+            // No receivers is fine
+            let _ = task.changes.send(payload.clone());
+
+            if !emit_to_subscribers(
+                &task.app,
+                &task.subscribers,
+                &task.cancel,
+                event_name(kind),
+                &payload,
+            ) {
+                return;
+            }
+            task.stats.record_event();
         }
     }
 }
 
 /// Watch a single file for changes
-/// 
+///
 /// # Arguments
 /// * `path` - The file path to watch
+/// * `window_label` - Label of the window that receives events for this subscription
 /// * `app` - Tauri app handle for emitting events
 /// * `state` - File watcher state
-/// 
+///
 /// # Returns
-/// * `Ok(())` - Success
+/// * `Ok(WatchSubscription)` - Subscription handle on success
 /// * `Err(FileSystemError)` - Error on failure
-pub fn watch_file(
+pub fn watch_file<R: Runtime>(
     path: &str,
-    app: AppHandle,
+    window_label: &str,
+    app: AppHandle<R>,
     state: &FileWatcherState,
-) -> Result<(), FileSystemError> {
+) -> Result<WatchSubscription, FileSystemError> {
     let path_buf = PathBuf::from(path);
-    
+
     if !path_buf.exists() {
         return Err(FileSystemError::NotFound(format!("File not found: {}", path)));
     }
-    
-    // Check if already watching
-    {
-        let watchers = state.watchers.lock().unwrap();
-        if watchers.contains_key(&path_buf) {
-            return Ok(()); // Already watching
-        }
-    }
-    
-    // Create channel for file system events
-    let (tx, mut rx) = mpsc::channel(100);
-    
-    // Create watcher
-    let mut watcher = RecommendedWatcher::new(
-        move |result: Result<notify::Event, notify::Error>| {
-            if let Ok(event) = result {
-                let _ = tx.try_send(event);
-            }
-        },
-        notify::Config::default(),
-    )
-    .map_err(|e| FileSystemError::WatchError(format!("Failed to create watcher: {}", e)))?;
-    
-    // Watch the file
-    watcher
-        .watch(&path_buf, RecursiveMode::NonRecursive)
-        .map_err(|e| FileSystemError::WatchError(format!("Failed to watch file: {}", e)))?;
-    
-    // Store watcher
-    {
-        let mut watchers = state.watchers.lock().unwrap();
-        watchers.insert(path_buf.clone(), watcher);
-    }
-    
-    // Spawn task to handle events
-    let watched_path = path_buf.clone();
-    tokio::spawn(async move {
-        while let Some(event) = rx.recv().await {
-            handle_file_event(&event, &watched_path, &app);
-        }
-    });
-    
-    Ok(())
+
+    state.subscribe(path_buf, WatchMode::File, window_label, app)
 }
 
 /// Watch a directory for changes
-/// 
+///
 /// # Arguments
 /// * `path` - The directory path to watch
 /// * `recursive` - Whether to watch recursively
+/// * `window_label` - Label of the window that receives events for this subscription
 /// * `app` - Tauri app handle for emitting events
 /// * `state` - File watcher state
-/// 
+///
 /// # Returns
-/// * `Ok(())` - Success
+/// * `Ok(WatchSubscription)` - Subscription handle on success
 /// * `Err(FileSystemError)` - Error on failure
-pub fn watch_directory(
+pub fn watch_directory<R: Runtime>(
     path: &str,
     recursive: bool,
-    app: AppHandle,
+    window_label: &str,
+    app: AppHandle<R>,
     state: &FileWatcherState,
-) -> Result<(), FileSystemError> {
+) -> Result<WatchSubscription, FileSystemError> {
     let path_buf = PathBuf::from(path);
-    
+
     if !path_buf.exists() {
         return Err(FileSystemError::NotFound(format!("Directory not found: {}", path)));
     }
-    
+
     if !path_buf.is_dir() {
         return Err(FileSystemError::InvalidPath(format!("Path is not a directory: {}", path)));
     }
-    
-    // Check if already watching
-    {
-        let watchers = state.watchers.lock().unwrap();
-        if watchers.contains_key(&path_buf) {
-            return Ok(()); // Already watching
-        }
-    }
-    
-    // Create channel for file system events
-    let (tx, mut rx) = mpsc::channel(100);
-    
-    // Create watcher
-    let mut watcher = RecommendedWatcher::new(
-        move |result: Result<notify::Event, notify::Error>| {
-            if let Ok(event) = result {
-                let _ = tx.try_send(event);
-            }
-        },
-        notify::Config::default(),
-    )
-    .map_err(|e| FileSystemError::WatchError(format!("Failed to create watcher: {}", e)))?;
-    
-    // Watch the directory
+
     let mode = if recursive {
-        RecursiveMode::Recursive
+        WatchMode::RecursiveDirectory
     } else {
-        RecursiveMode::NonRecursive
+        WatchMode::Directory
     };
-    
-    watcher
-        .watch(&path_buf, mode)
-        .map_err(|e| FileSystemError::WatchError(format!("Failed to watch directory: {}", e)))?;
-    
-    // Store watcher
-    {
-        let mut watchers = state.watchers.lock().unwrap();
-        watchers.insert(path_buf.clone(), watcher);
-    }
-    
-    // Spawn task to handle events
-    let watched_path = path_buf.clone();
-    tokio::spawn(async move {
-        while let Some(event) = rx.recv().await {
-            handle_directory_event(&event, &watched_path, &app);
-        }
-    });
-    
-    Ok(())
+
+    state.subscribe(path_buf, mode, window_label, app)
 }
 
-/// Stop watching a path
-/// 
+/// Release a single watch subscription
+///
+/// The underlying watch is stopped once its last subscription is released.
+///
 /// # Arguments
-/// * `path` - The path to stop watching
+/// * `subscription_id` - The subscription to release
+/// * `window_label` - Label of the window that owns the subscription
 /// * `state` - File watcher state
-/// 
+///
 /// # Returns
 /// * `Ok(())` - Success
 /// * `Err(FileSystemError)` - Error on failure
-pub fn unwatch(path: &str, state: &FileWatcherState) -> Result<(), FileSystemError> {
-    let path_buf = PathBuf::from(path);
-    
-    let mut watchers = state.watchers.lock().unwrap();
-    if watchers.remove(&path_buf).is_some() {
+pub fn unwatch(
+    subscription_id: SubscriptionId,
+    window_label: &str,
+    state: &FileWatcherState,
+) -> Result<(), FileSystemError> {
+    let removed =
+        state.remove_subscriptions(|id, label| id == subscription_id && label == window_label);
+
+    if removed > 0 {
         Ok(())
     } else {
-        Err(FileSystemError::NotFound(format!("Path not being watched: {}", path)))
+        Err(FileSystemError::NotFound(format!(
+            "No watch subscription {} for window {}",
+            subscription_id, window_label
+        )))
     }
 }
 
-/// Stop watching all paths
-/// 
+/// Release every subscription owned by a window
+///
+/// Called from `unwatch_all_command` and when a window is destroyed.
+///
 /// # Arguments
+/// * `window_label` - Label of the window whose subscriptions are released
 /// * `state` - File watcher state
-pub fn unwatch_all(state: &FileWatcherState) {
-    let mut watchers = state.watchers.lock().unwrap();
-    watchers.clear();
+pub fn unwatch_window(window_label: &str, state: &FileWatcherState) {
+    state.remove_subscriptions(|_, label| label == window_label);
 }
 
-/// Handle file system event for a single file
-fn handle_file_event(event: &notify::Event, watched_path: &PathBuf, app: &AppHandle) {
-    for path in &event.paths {
-        if path == watched_path {
-            match event.kind {
-                notify::EventKind::Create(_) => {
-                    let payload = FileWatchEvent::Created(path.to_string_lossy().to_string());
-                    let _ = app.emit(FILE_CREATED, &payload);
-                }
-                notify::EventKind::Modify(_) => {
-                    let payload = FileWatchEvent::Modified(path.to_string_lossy().to_string());
-                    let _ = app.emit(FILE_MODIFIED, &payload);
-                }
-                notify::EventKind::Remove(_) => {
-                    let payload = FileWatchEvent::Deleted(path.to_string_lossy().to_string());
-                    let _ = app.emit(FILE_DELETED, &payload);
-                }
-                notify::EventKind::Any => {
-                    // Handle any other event types (including rename in some notify versions)
-                    // For rename events, notify 6.x may emit them differently
-                }
-                _ => {}
-            }
+/// Convert a notify error, turning OS watch-limit exhaustion into an actionable error
+fn watch_error(context: &str, error: notify::Error) -> FileSystemError {
+    // EMFILE: too many inotify instances for this user
+    #[cfg(target_os = "linux")]
+    let instances_exhausted =
+        matches!(&error.kind, notify::ErrorKind::Io(e) if e.raw_os_error() == Some(24));
+    #[cfg(not(target_os = "linux"))]
+    let instances_exhausted = false;
+
+    if matches!(error.kind, notify::ErrorKind::MaxFilesWatch) || instances_exhausted {
+        FileSystemError::WatchLimitReached(format!("{}: {}", context, WATCH_LIMIT_HINT))
+    } else {
+        FileSystemError::WatchError(format!("{}: {}", context, error))
+    }
+}
+
+/// Emit an event to every window subscribed to a watch (once per window)
+///
+/// The subscriber lock is held while emitting and cancellation is checked
+/// under it, so once a subscription is removed it receives no further events.
+/// Returns false if the watch has been cancelled.
+fn emit_to_subscribers<R: Runtime, S: Serialize + Clone>(
+    app: &AppHandle<R>,
+    subscribers: &Subscribers,
+    cancel: &CancellationToken,
+    event: &str,
+    payload: &S,
+) -> bool {
+    let subscribers = subscribers.lock().unwrap();
+    if cancel.is_cancelled() {
+        return false;
+    }
+
+    let labels: HashSet<&String> = subscribers.values().collect();
+    for label in labels {
+        let _ = app.emit_to(label.as_str(), event, payload.clone());
+    }
+    true
+}
+
+/// A change derived from a notify event: kind, path and (for renames) the old path
+type Change = (FileWatchEventKind, PathBuf, Option<PathBuf>);
+
+/// Event name emitted for each kind of change
+fn event_name(kind: FileWatchEventKind) -> &'static str {
+    match kind {
+        FileWatchEventKind::Created => FILE_CREATED,
+        FileWatchEventKind::Modified => FILE_MODIFIED,
+        FileWatchEventKind::Deleted => FILE_DELETED,
+        FileWatchEventKind::Renamed => FILE_RENAMED,
+    }
+}
+
+/// Map a notify event kind to a watch event kind
+fn change_kind(kind: &notify::EventKind) -> Option<FileWatchEventKind> {
+    match kind {
+        notify::EventKind::Create(_) => Some(FileWatchEventKind::Created),
+        notify::EventKind::Modify(_) => Some(FileWatchEventKind::Modified),
+        notify::EventKind::Remove(_) => Some(FileWatchEventKind::Deleted),
+        _ => None,
+    }
+}
+
+/// Detect a rename reported with both paths in one event
+///
+/// notify 6.x reports these as `Modify(Name(Both))` on most platforms and as
+/// `Any` with two paths on some backends.
+fn rename_change(event: &notify::Event) -> Option<Change> {
+    let is_rename = matches!(
+        event.kind,
+        notify::EventKind::Modify(ModifyKind::Name(RenameMode::Both)) | notify::EventKind::Any
+    );
+
+    if is_rename && event.paths.len() == 2 {
+        Some((
+            FileWatchEventKind::Renamed,
+            event.paths[1].clone(),
+            Some(event.paths[0].clone()),
+        ))
+    } else {
+        None
+    }
+}
+
+/// Collect the changes a notify event reports for a single watched file
+fn file_changes(event: &notify::Event, watched_path: &Path) -> Vec<Change> {
+    if let Some(rename) = rename_change(event) {
+        if event.paths.iter().any(|p| p == watched_path) {
+            return vec![rename];
         }
+        return Vec::new();
     }
+
+    let Some(kind) = change_kind(&event.kind) else {
+        return Vec::new();
+    };
+
+    event
+        .paths
+        .iter()
+        .filter(|path| path.as_path() == watched_path)
+        .map(|path| (kind, path.clone(), None))
+        .collect()
 }
 
-/// Handle file system event for a directory
-fn handle_directory_event(event: &notify::Event, watched_dir: &PathBuf, app: &AppHandle) {
-    for path in &event.paths {
-        // Only emit events for files/directories within the watched directory
-        if path.starts_with(watched_dir) {
-            match event.kind {
-                notify::EventKind::Create(_) => {
-                    let payload = FileWatchEvent::Created(path.to_string_lossy().to_string());
-                    let _ = app.emit(FILE_CREATED, &payload);
-                }
-                notify::EventKind::Modify(_) => {
-                    let payload = FileWatchEvent::Modified(path.to_string_lossy().to_string());
-                    let _ = app.emit(FILE_MODIFIED, &payload);
-                }
-                notify::EventKind::Remove(_) => {
-                    let payload = FileWatchEvent::Deleted(path.to_string_lossy().to_string());
-                    let _ = app.emit(FILE_DELETED, &payload);
-                }
-                notify::EventKind::Any => {
-                    // Handle any other event types (including rename in some notify versions)
-                    // For rename events, notify 6.x may emit them differently
-                    // If event has multiple paths, it might be a rename
-                    if event.paths.len() == 2 {
-                        let payload = FileWatchEvent::Renamed {
-                            old: event.paths[0].to_string_lossy().to_string(),
-                            new: event.paths[1].to_string_lossy().to_string(),
-                        };
-                        let _ = app.emit(FILE_RENAMED, &payload);
-                    }
-                }
-                _ => {}
-            }
+/// Collect the changes a notify event reports within a watched directory
+fn directory_changes(event: &notify::Event, watched_dir: &Path) -> Vec<Change> {
+    if let Some(rename) = rename_change(event) {
+        if event.paths.iter().any(|p| p.starts_with(watched_dir)) {
+            return vec![rename];
         }
+        return Vec::new();
     }
+
+    let Some(kind) = change_kind(&event.kind) else {
+        return Vec::new();
+    };
+
+    // Only report files/directories within the watched directory
+    event
+        .paths
+        .iter()
+        .filter(|path| path.starts_with(watched_dir))
+        .map(|path| (kind, path.clone(), None))
+        .collect()
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::event::DataChange;
+    use tauri::test::{mock_app, MockRuntime};
+    use tauri::{App, Listener};
+    use tokio::time::timeout;
+
+    /// How long to wait for an event that should arrive
+    const EVENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// How long to wait before concluding that no event is coming
+    const QUIET_PERIOD: Duration = Duration::from_millis(500);
+
+    /// A fresh directory holding one file, returned with the file's path
+    fn watched_file(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("nexus-watch-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        // Watchers report resolved paths (e.g. /private/var on macOS)
+        let file = dir.canonicalize().unwrap().join("watched.txt");
+        std::fs::write(&file, "initial").unwrap();
+        file
+    }
+
+    /// Forward every `file-modified` event the app emits into a channel
+    fn modified_events(app: &App<MockRuntime>) -> mpsc::UnboundedReceiver<FileWatchEvent> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        app.listen_any(FILE_MODIFIED, move |event| {
+            let _ = tx.send(serde_json::from_str(event.payload()).unwrap());
+        });
+        rx
+    }
+
+    #[tokio::test]
+    async fn subscriber_receives_changes() {
+        let app = mock_app();
+        let mut events = modified_events(&app);
+        let state = FileWatcherState::new();
+        let file = watched_file("deliver");
+
+        let subscription =
+            watch_file(file.to_str().unwrap(), "main", app.handle().clone(), &state).unwrap();
+        std::fs::write(&file, "changed").unwrap();
+
+        let event = timeout(EVENT_TIMEOUT, events.recv())
+            .await
+            .expect("no event before the timeout")
+            .unwrap();
+        assert_eq!(event.path, file.to_string_lossy());
+        assert_eq!(event.watch_id, subscription.watch_id);
+        assert!(!event.self_originated);
+    }
+
+    #[tokio::test]
+    async fn unwatch_stops_delivery() {
+        let app = mock_app();
+        let mut events = modified_events(&app);
+        let state = FileWatcherState::new();
+        let file = watched_file("unwatch");
+
+        let subscription =
+            watch_file(file.to_str().unwrap(), "main", app.handle().clone(), &state).unwrap();
+        std::fs::write(&file, "changed").unwrap();
+        timeout(EVENT_TIMEOUT, events.recv())
+            .await
+            .expect("no event before the timeout");
+
+        unwatch(subscription.id, "main", &state).unwrap();
+        assert!(state.status().is_empty());
+        // Events emitted before `unwatch` returned are already in the channel
+        while events.try_recv().is_ok() {}
+
+        std::fs::write(&file, "changed again").unwrap();
+        assert!(
+            timeout(QUIET_PERIOD, events.recv()).await.is_err(),
+            "event delivered after unwatch"
+        );
+    }
+
+    #[tokio::test]
+    async fn cancel_stops_event_task() {
+        let app = mock_app();
+        let mut events = modified_events(&app);
+        let file = watched_file("cancel");
+        let (tx, rx) = mpsc::channel(EVENT_CHANNEL_CAPACITY);
+        let cancel = CancellationToken::new();
+        let subscribers: Subscribers =
+            Arc::new(Mutex::new(HashMap::from([(1, "main".to_string())])));
+        let task = tokio::spawn(run_event_task(
+            EventTask {
+                watch_id: 1,
+                mode: WatchMode::File,
+                watched_path: file.clone(),
+                app: app.handle().clone(),
+                subscribers,
+                stats: Arc::default(),
+                self_writes: SelfWrites::default(),
+                changes: broadcast::channel(CHANGE_CHANNEL_CAPACITY).0,
+                cancel: cancel.clone(),
+            },
+            rx,
+        ));
+        let modified = notify::Event::new(notify::EventKind::Modify(ModifyKind::Data(
+            DataChange::Any,
+        )))
+        .add_path(file.clone());
+
+        tx.send(modified.clone()).await.unwrap();
+        timeout(EVENT_TIMEOUT, events.recv())
+            .await
+            .expect("no event before the timeout");
+
+        cancel.cancel();
+        timeout(EVENT_TIMEOUT, task)
+            .await
+            .expect("event task still running after cancel")
+            .unwrap();
+        // The task dropped its receiver, so nothing more can be delivered
+        assert!(tx.send(modified).await.is_err());
+        assert!(events.try_recv().is_err());
+    }
+}
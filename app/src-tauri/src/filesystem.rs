@@ -1,8 +1,9 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use std::io;
-use std::path::PathBuf;
-use tokio::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use crate::safe_fs;
 
 /// Maximum file size for reading (10MB)
 const MAX_FILE_SIZE_READ: u64 = 10 * 1024 * 1024;
@@ -11,6 +12,9 @@ const MAX_FILE_SIZE_READ: u64 = 10 * 1024 * 1024;
 const MAX_FILE_SIZE_WRITE: u64 = 50 * 1024 * 1024;
 
 /// Custom error type for file system operations
+/// 
+/// Commands return it to the frontend serialized as
+/// `{ code, message, path, retryable }` (see `code`).
 #[derive(Debug)]
 pub enum FileSystemError {
     NotFound(String),
@@ -20,6 +24,72 @@ pub enum FileSystemError {
     IoError(io::Error),
     EncodingError(String),
     WatchError(String),
+    WatchLimitReached(String),
+    QuotaExceeded(String),
+    /// The target already exists or changed concurrently
+    Conflict(String),
+    /// A command argument other than a path is invalid
+    InvalidInput(String),
+    /// Another error attributed to the path it occurred at
+    AtPath {
+        path: String,
+        error: Box<FileSystemError>,
+    },
+}
+
+impl FileSystemError {
+    /// Attribute the error to a path (an already attributed error keeps its path)
+    pub fn at(self, path: &str) -> Self {
+        match self {
+            FileSystemError::AtPath { .. } => self,
+            error => FileSystemError::AtPath {
+                path: path.to_string(),
+                error: Box::new(error),
+            },
+        }
+    }
+    
+    /// Stable code the frontend branches on
+    pub fn code(&self) -> &'static str {
+        match self {
+            FileSystemError::NotFound(_) => "NotFound",
+            FileSystemError::PermissionDenied(_) => "PermissionDenied",
+            FileSystemError::InvalidPath(_) => "InvalidPath",
+            FileSystemError::FileTooLarge(_) => "TooLarge",
+            FileSystemError::IoError(_) => "Io",
+            FileSystemError::EncodingError(_) => "Encoding",
+            FileSystemError::WatchError(_) => "Watch",
+            FileSystemError::WatchLimitReached(_) => "WatchLimitReached",
+            FileSystemError::QuotaExceeded(_) => "QuotaExceeded",
+            FileSystemError::Conflict(_) => "Conflict",
+            FileSystemError::InvalidInput(_) => "InvalidInput",
+            FileSystemError::AtPath { error, .. } => error.code(),
+        }
+    }
+    
+    /// The path the error is attributed to, if any
+    pub fn path(&self) -> Option<&str> {
+        match self {
+            FileSystemError::AtPath { path, .. } => Some(path),
+            _ => None,
+        }
+    }
+    
+    /// Whether repeating the same operation unchanged may succeed
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            FileSystemError::IoError(e) => matches!(
+                e.kind(),
+                io::ErrorKind::Interrupted
+                    | io::ErrorKind::WouldBlock
+                    | io::ErrorKind::TimedOut
+                    | io::ErrorKind::ResourceBusy
+            ),
+            FileSystemError::Conflict(_) => true,
+            FileSystemError::AtPath { error, .. } => error.is_retryable(),
+            _ => false,
+        }
+    }
 }
 
 impl std::fmt::Display for FileSystemError {
@@ -32,22 +102,54 @@ impl std::fmt::Display for FileSystemError {
             FileSystemError::IoError(e) => write!(f, "IO error: {}", e),
             FileSystemError::EncodingError(msg) => write!(f, "Encoding error: {}", msg),
             FileSystemError::WatchError(msg) => write!(f, "Watch error: {}", msg),
+            FileSystemError::WatchLimitReached(msg) => write!(f, "Watch limit reached: {}", msg),
+            FileSystemError::QuotaExceeded(msg) => write!(f, "Quota exceeded: {}", msg),
+            FileSystemError::Conflict(msg) => write!(f, "Conflict: {}", msg),
+            FileSystemError::InvalidInput(msg) => write!(f, "Invalid input: {}", msg),
+            FileSystemError::AtPath { error, .. } => write!(f, "{}", error),
         }
     }
 }
 
 impl std::error::Error for FileSystemError {}
 
+impl Serialize for FileSystemError {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+        
+        let mut state = serializer.serialize_struct("FileSystemError", 4)?;
+        state.serialize_field("code", self.code())?;
+        state.serialize_field("message", &self.to_string())?;
+        state.serialize_field("path", &self.path())?;
+        state.serialize_field("retryable", &self.is_retryable())?;
+        state.end()
+    }
+}
+
 impl From<io::Error> for FileSystemError {
     fn from(error: io::Error) -> Self {
         match error.kind() {
             io::ErrorKind::NotFound => FileSystemError::NotFound(error.to_string()),
             io::ErrorKind::PermissionDenied => FileSystemError::PermissionDenied(error.to_string()),
+            io::ErrorKind::AlreadyExists | io::ErrorKind::DirectoryNotEmpty => {
+                FileSystemError::Conflict(error.to_string())
+            }
             _ => FileSystemError::IoError(error),
         }
     }
 }
 
+/// Run blocking filesystem work off the async runtime
+async fn blocking<T, F>(work: F) -> Result<T, FileSystemError>
+where
+    F: FnOnce() -> Result<T, FileSystemError> + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(work)
+        .await
+        .map_err(|e| FileSystemError::IoError(io::Error::other(e)))?
+}
+
 /// File permissions information
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FilePermissions {
@@ -101,6 +203,9 @@ pub struct DirectoryEntry {
     pub size: Option<u64>,
     #[serde(with = "chrono::serde::ts_seconds")]
     pub modified: DateTime<Utc>,
+    /// Matches a deny rule (set when listings mark rather than hide such entries)
+    #[serde(default)]
+    pub denied: bool,
 }
 
 /// Read a file from the file system
@@ -119,18 +224,29 @@ pub async fn read_file(path: &str) -> Result<FileReadResult, FileSystemError> {
         return Err(FileSystemError::NotFound(format!("File not found: {}", path)));
     }
     
-    // Check file size before reading
-    let metadata = fs::metadata(&path_buf).await?;
-    if metadata.len() > MAX_FILE_SIZE_READ {
-        return Err(FileSystemError::FileTooLarge(format!(
-            "File size {} exceeds maximum read size {}",
-            metadata.len(),
-            MAX_FILE_SIZE_READ
-        )));
-    }
-    
-    // Read file content
-    let content = fs::read_to_string(&path_buf).await?;
+    // Check size and read through the same handle
+    let (metadata, content) = blocking(move || {
+        let mut file = safe_fs::open(&path_buf)?;
+        let metadata = file.metadata()?;
+        if !metadata.is_file() {
+            return Err(FileSystemError::InvalidPath(format!(
+                "Path is not a file: {}",
+                path_buf.to_string_lossy()
+            )));
+        }
+        if metadata.len() > MAX_FILE_SIZE_READ {
+            return Err(FileSystemError::FileTooLarge(format!(
+                "File size {} exceeds maximum read size {}",
+                metadata.len(),
+                MAX_FILE_SIZE_READ
+            )));
+        }
+        
+        let mut content = String::new();
+        file.read_to_string(&mut content)?;
+        Ok((metadata, content))
+    })
+    .await?;
     let line_count = content.lines().count();
     let size = metadata.len();
     
@@ -163,29 +279,26 @@ pub async fn write_file(request: FileWriteRequest) -> Result<(), FileSystemError
         )));
     }
     
-    // Create backup if requested and file exists
-    if request.backup && path_buf.exists() {
-        let backup_path = format!("{}.backup", request.path);
-        fs::copy(&path_buf, &backup_path).await?;
-    }
-    
-    // Create parent directories if needed
-    if request.create_if_not_exists {
-        if let Some(parent) = path_buf.parent() {
-            fs::create_dir_all(parent).await?;
-        }
-    }
-    
-    // Atomic write: write to temp file, then rename
-    let temp_path = path_buf.with_extension(".tmp");
-    
-    // Write to temp file
-    fs::write(&temp_path, &request.content).await?;
-    
-    // Atomic rename
-    fs::rename(&temp_path, &path_buf).await?;
-    
-    Ok(())
+    // Backup, temp write and rename all happen relative to one directory handle
+    let temp_path = temp_write_path(&path_buf);
+    blocking(move || {
+        let temp_name = temp_path.file_name().unwrap_or_default();
+        let backup_name = format!("{}.backup", path_buf.file_name().unwrap_or_default().to_string_lossy());
+        safe_fs::write_atomic(
+            &path_buf,
+            temp_name,
+            request.content.as_bytes(),
+            request.create_if_not_exists,
+            request.backup.then_some(backup_name.as_ref()),
+        )?;
+        Ok(())
+    })
+    .await
+}
+
+/// Temporary path used by `write_file` for atomic writes to `path`
+pub fn temp_write_path(path: &Path) -> PathBuf {
+    path.with_extension(".tmp")
 }
 
 /// Delete a file from the file system
@@ -203,8 +316,7 @@ pub async fn delete_file(path: &str) -> Result<(), FileSystemError> {
         return Err(FileSystemError::NotFound(format!("File not found: {}", path)));
     }
     
-    fs::remove_file(&path_buf).await?;
-    Ok(())
+    blocking(move || Ok(safe_fs::remove_file(&path_buf)?)).await
 }
 
 /// Get file metadata
@@ -222,7 +334,10 @@ pub async fn get_file_metadata(path: &str) -> Result<FileMetadata, FileSystemErr
         return Err(FileSystemError::NotFound(format!("Path not found: {}", path)));
     }
     
-    let metadata = fs::metadata(&path_buf).await?;
+    let metadata = {
+        let path_buf = path_buf.clone();
+        blocking(move || Ok(safe_fs::metadata(&path_buf)?)).await?
+    };
     let file_type = metadata.file_type();
     let is_file = file_type.is_file();
     let is_directory = file_type.is_dir();
@@ -281,36 +396,27 @@ pub async fn list_directory(path: &str) -> Result<Vec<DirectoryEntry>, FileSyste
         return Err(FileSystemError::InvalidPath(format!("Path is not a directory: {}", path)));
     }
     
-    let mut entries = Vec::new();
-    let mut dir_entries = fs::read_dir(&path_buf).await?;
+    // Read through the directory's own handle so it can't be swapped for a symlink
+    let dir_entries = {
+        let path_buf = path_buf.clone();
+        blocking(move || Ok(safe_fs::read_dir(&path_buf)?)).await?
+    };
     
-    while let Some(entry) = dir_entries.next_entry().await? {
-        let entry_path = entry.path();
-        let metadata = entry.metadata().await?;
-        let file_type = metadata.file_type();
-        let is_file = file_type.is_file();
-        let is_directory = file_type.is_dir();
-        
-        let name = entry_path
-            .file_name()
-            .and_then(|n| n.to_str())
-            .unwrap_or("")
-            .to_string();
-        
-        let modified = metadata
-            .modified()
-            .map(|t| DateTime::<Utc>::from(t))
-            .unwrap_or_else(|_| Utc::now());
-        
-        entries.push(DirectoryEntry {
-            name,
-            path: entry_path.to_string_lossy().to_string(),
-            is_file,
-            is_directory,
-            size: if is_file { Some(metadata.len()) } else { None },
-            modified,
-        });
-    }
+    let mut entries: Vec<DirectoryEntry> = dir_entries
+        .into_iter()
+        .map(|entry| {
+            let entry_path = path_buf.join(&entry.name);
+            DirectoryEntry {
+                name: entry.name.to_str().unwrap_or("").to_string(),
+                path: entry_path.to_string_lossy().to_string(),
+                is_file: entry.is_file,
+                is_directory: entry.is_dir,
+                size: if entry.is_file { Some(entry.len) } else { None },
+                modified: entry.modified.map(DateTime::<Utc>::from).unwrap_or_else(Utc::now),
+                denied: false,
+            }
+        })
+        .collect();
     
     // Sort: directories first, then files
     entries.sort_by(|a, b| {
@@ -334,8 +440,7 @@ pub async fn list_directory(path: &str) -> Result<Vec<DirectoryEntry>, FileSyste
 /// * `Err(FileSystemError)` - Error on failure
 pub async fn create_directory(path: &str) -> Result<(), FileSystemError> {
     let path_buf = PathBuf::from(path);
-    fs::create_dir_all(&path_buf).await?;
-    Ok(())
+    blocking(move || Ok(safe_fs::create_dir_all(&path_buf)?)).await
 }
 
 /// Delete a directory (recursive)
@@ -353,8 +458,7 @@ pub async fn delete_directory(path: &str) -> Result<(), FileSystemError> {
         return Err(FileSystemError::NotFound(format!("Directory not found: {}", path)));
     }
     
-    fs::remove_dir_all(&path_buf).await?;
-    Ok(())
+    blocking(move || Ok(safe_fs::remove_dir_all(&path_buf)?)).await
 }
 
 /// Check if a path exists
@@ -1,16 +1,59 @@
+mod audit;
+mod consent;
+mod deny_rules;
 mod filesystem;
 mod file_watcher;
+mod llm;
+mod process;
+mod quota;
+mod safe_fs;
 mod security;
+mod terminal;
 
+use audit::{AuditEntry, AuditLog, AuditOperation, AuditQuery, AUDIT_LOG_FILE};
+use consent::{
+    ConsentDecision, ConsentRequestId, ConsentState, PermissionRequest, PermissionResolution,
+    CONSENT_TIMEOUT, PERMISSION_REQUESTED, PERMISSION_RESOLVED, TRUSTED_CONSENT_WINDOWS,
+};
+use deny_rules::{DeniedEntryMode, DenyOverrides};
 use filesystem::{
     create_directory, delete_directory, delete_file, file_exists, get_file_metadata,
     list_directory, read_file, write_file, DirectoryEntry, FileMetadata, FileReadResult,
-    FileWriteRequest,
+    FileSystemError, FileWriteRequest,
+};
+use file_watcher::{
+    unwatch, unwatch_window, watch_directory, watch_file, FileWatcherState, SubscriptionId,
+    WatchStatus, WatchSubscription,
+};
+use llm::agent::{
+    run_agent, AgentRequest, AgentResult, AgentStep, ToolExecutor, ToolFuture, LLM_AGENT_STEP,
+};
+use llm::cache::{CacheOptions, CacheStats, LlmCache, LlmCall, CACHE_DIR};
+use llm::prompts::{render_prompt, RenderedPrompt, TemplateInfo};
+use llm::queue::{Priority, ProviderQueueStatus};
+use llm::resilience::{ProviderStatus, LLM_PROVIDER_STATUS};
+use llm::structured::{generate_structured, StructuredOutput, StructuredRequest};
+use llm::tokens::{
+    count_tokens, fit_budget, plan_budget, BudgetReport, BudgetRequest, FitResult, TokenCount,
+};
+use llm::tools::{ToolCall, ToolRegistry, LIST_DIRECTORY, READ_FILE, WRITE_FILE};
+use llm::{
+    check_health, AiRole, ChatRequest, GenerateRequest, LlmChunk, LlmConfig, LlmError, LlmHealth,
+    LlmPullProgress, LlmResponse, LlmState, ModelStatus, PullProgress, StreamChunk, LLM_CHUNK,
+    LLM_CONFIG_FILE, LLM_PULL_PROGRESS,
+};
+use process::{spawn_command, ProcessId, ProcessInfo, ProcessState, RunCommandRequest};
+use quota::{QuotaCharge, QuotaLimits, QuotaState, QuotaStatus, QUOTA_EXCEEDED};
+use security::{
+    validate_path, Actor, Caller, Capability, CapabilitySet, GrantScope, OperationContext,
+    PermissionGrant, SecurityManager, GRANTS_FILE,
 };
-use file_watcher::{unwatch, unwatch_all, watch_directory, watch_file, FileWatcherState};
-use security::{validate_path, SecurityManager};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::sync::Mutex;
-use tauri::{AppHandle, State};
+use tauri::{AppHandle, Emitter, Manager, RunEvent, State, Window, WindowEvent};
+use terminal::{create_terminal, TerminalId, TerminalInfo, TerminalOptions, TerminalState};
+use tokio::sync::broadcast::{error::RecvError, Receiver};
 
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
 #[tauri::command]
@@ -18,287 +61,1508 @@ fn greet(name: &str) -> String {
     format!("Hello, {}! You've been greeted from Rust!", name)
 }
 
+/// Who a command from a window acts for
+/// 
+/// Trusted windows act for the user and all other windows for a plugin; the
+/// frontend only chooses the task. AI actors come from `CommandToolExecutor`.
+fn caller_of(window: &Window, context: Option<OperationContext>) -> Caller {
+    let actor = if TRUSTED_CONSENT_WINDOWS.contains(&window.label()) {
+        Actor::User
+    } else {
+        Actor::Plugin
+    };
+    Caller {
+        actor,
+        task_id: context.and_then(|c| c.task_id),
+    }
+}
+
+/// Charge an operation against the acting party's quotas
+/// 
+/// Emits `quota-exceeded` when the charge pauses the actor.
+fn charge_quota(
+    app: &AppHandle,
+    quotas: &QuotaState,
+    caller: &Caller,
+    charge: QuotaCharge,
+) -> Result<(), FileSystemError> {
+    quotas
+        .charge(caller.actor, caller.task_id.as_deref(), charge)
+        .map_err(|breach| {
+            if breach.newly_paused {
+                let _ = app.emit(QUOTA_EXCEEDED, &breach);
+            }
+            FileSystemError::from(breach)
+        })
+}
+
+/// Check that the acting party may perform `capability` on a path
+/// 
+/// Returns the resolved path, which the operation must use instead of the
+/// path it was given.
+fn authorize(
+    security: &Mutex<SecurityManager>,
+    path: &Path,
+    capability: Capability,
+    caller: &Caller,
+) -> Result<String, FileSystemError> {
+    security
+        .lock()
+        .unwrap()
+        .check_access(path, capability, caller.actor)
+        .map(|resolved| resolved.to_string_lossy().to_string())
+}
+
 // File operations commands
+
+/// Read a file for `caller` (shared by the command and the AI file tools)
+async fn read_file_as(
+    path: String,
+    caller: Caller,
+    app: AppHandle,
+    security: State<'_, Mutex<SecurityManager>>,
+    audit: State<'_, AuditLog>,
+    quotas: State<'_, QuotaState>,
+) -> Result<FileReadResult, FileSystemError> {
+    let mut resolved_path = None;
+    let result = async {
+        // Validate path
+        let validated_path = validate_path(&path)?;
+        
+        // Check access for this operation (lock released before await)
+        let resolved = authorize(&security, &validated_path, Capability::Read, &caller)?;
+        resolved_path = Some(resolved.clone());
+        charge_quota(&app, &quotas, &caller, QuotaCharge::default())?;
+        
+        read_file(&resolved)
+            .await
+    }
+    .await
+    .map_err(|e| e.at(&path));
+    
+    audit.record_operation(
+        caller.actor,
+        AuditOperation::ReadFile,
+        resolved_path.as_deref().unwrap_or(&path),
+        result.as_ref().map(|r| Some(r.size)),
+    )?;
+    result
+}
+
 #[tauri::command]
 async fn read_file_command(
     path: String,
+    context: Option<OperationContext>,
+    window: Window,
+    app: AppHandle,
     security: State<'_, Mutex<SecurityManager>>,
-) -> Result<FileReadResult, String> {
-    // Validate path
-    let validated_path = validate_path(&path)
-        .map_err(|e| format!("Path validation failed: {}", e))?;
-    
-    // Check if path is allowed (lock released before await)
-    let is_allowed = {
-        let security_manager = security.lock().unwrap();
-        security_manager.is_path_allowed(&validated_path)
-    };
-    
-    if !is_allowed {
-        return Err(format!(
-            "Path not allowed. Please request permission for: {}",
-            path
-        ));
+    audit: State<'_, AuditLog>,
+    quotas: State<'_, QuotaState>,
+) -> Result<FileReadResult, FileSystemError> {
+    let caller = caller_of(&window, context);
+    read_file_as(path, caller, app, security, audit, quotas).await
+}
+
+/// Write a file for `caller` (shared by the command and the AI file tools)
+async fn write_file_as(
+    request: FileWriteRequest,
+    caller: Caller,
+    app: AppHandle,
+    security: State<'_, Mutex<SecurityManager>>,
+    audit: State<'_, AuditLog>,
+    quotas: State<'_, QuotaState>,
+    watcher: State<'_, FileWatcherState>,
+) -> Result<(), FileSystemError> {
+    let path = request.path.clone();
+    let bytes = request.content.len() as u64;
+    let mut resolved_path = None;
+    let result = async {
+        // Validate path
+        let validated_path = validate_path(&request.path)?;
+        
+        // Check access for this operation (lock released before await)
+        // Overwriting needs write access, a new file needs create access
+        let capability = if validated_path.exists() {
+            Capability::Write
+        } else {
+            Capability::Create
+        };
+        let resolved = authorize(&security, &validated_path, capability, &caller)?;
+        resolved_path = Some(resolved.clone());
+        let charge = QuotaCharge {
+            bytes_written: bytes,
+            files_created: u64::from(capability == Capability::Create),
+            ..QuotaCharge::default()
+        };
+        charge_quota(&app, &quotas, &caller, charge)?;
+        
+        // Tag the write so the watcher doesn't report our own save as an external change
+        watcher.expect_self_write(Path::new(&resolved), request.content.as_bytes());
+        
+        write_file(FileWriteRequest {
+            path: resolved,
+            ..request
+        })
+        .await
     }
+    .await
+    .map_err(|e| e.at(&path));
     
-    read_file(&path)
-        .await
-        .map_err(|e| format!("Failed to read file: {}", e))
+    audit.record_operation(
+        caller.actor,
+        AuditOperation::WriteFile,
+        resolved_path.as_deref().unwrap_or(&path),
+        result.as_ref().map(|_| Some(bytes)),
+    )?;
+    result
 }
 
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 async fn write_file_command(
     request: FileWriteRequest,
+    context: Option<OperationContext>,
+    window: Window,
+    app: AppHandle,
     security: State<'_, Mutex<SecurityManager>>,
-) -> Result<(), String> {
-    // Validate path
-    let validated_path = validate_path(&request.path)
-        .map_err(|e| format!("Path validation failed: {}", e))?;
-    
-    // Check if path is allowed (lock released before await)
-    let is_allowed = {
-        let security_manager = security.lock().unwrap();
-        security_manager.is_path_allowed(&validated_path)
-    };
-    
-    if !is_allowed {
-        return Err(format!(
-            "Path not allowed. Please request permission for: {}",
-            request.path
-        ));
-    }
-    
-    write_file(request)
-        .await
-        .map_err(|e| format!("Failed to write file: {}", e))
+    audit: State<'_, AuditLog>,
+    quotas: State<'_, QuotaState>,
+    watcher: State<'_, FileWatcherState>,
+) -> Result<(), FileSystemError> {
+    let caller = caller_of(&window, context);
+    write_file_as(request, caller, app, security, audit, quotas, watcher).await
 }
 
 #[tauri::command]
 async fn delete_file_command(
     path: String,
+    context: Option<OperationContext>,
+    window: Window,
+    app: AppHandle,
     security: State<'_, Mutex<SecurityManager>>,
-) -> Result<(), String> {
-    // Validate path
-    let validated_path = validate_path(&path)
-        .map_err(|e| format!("Path validation failed: {}", e))?;
-    
-    // Check if path is allowed (lock released before await)
-    let is_allowed = {
-        let security_manager = security.lock().unwrap();
-        security_manager.is_path_allowed(&validated_path)
-    };
-    
-    if !is_allowed {
-        return Err(format!(
-            "Path not allowed. Please request permission for: {}",
-            path
-        ));
+    audit: State<'_, AuditLog>,
+    quotas: State<'_, QuotaState>,
+) -> Result<(), FileSystemError> {
+    let caller = caller_of(&window, context);
+    let mut resolved_path = None;
+    let result = async {
+        // Validate path
+        let validated_path = validate_path(&path)?;
+        
+        // Check access for this operation (lock released before await)
+        let resolved = authorize(&security, &validated_path, Capability::Delete, &caller)?;
+        resolved_path = Some(resolved.clone());
+        charge_quota(
+            &app,
+            &quotas,
+            &caller,
+            QuotaCharge {
+                deletes: 1,
+                ..QuotaCharge::default()
+            },
+        )?;
+        
+        delete_file(&resolved)
+            .await
     }
+    .await
+    .map_err(|e| e.at(&path));
     
-    delete_file(&path)
-        .await
-        .map_err(|e| format!("Failed to delete file: {}", e))
+    audit.record_operation(
+        caller.actor,
+        AuditOperation::DeleteFile,
+        resolved_path.as_deref().unwrap_or(&path),
+        result.as_ref().map(|_| None),
+    )?;
+    result
 }
 
 #[tauri::command]
 async fn get_file_metadata_command(
     path: String,
+    context: Option<OperationContext>,
+    window: Window,
+    app: AppHandle,
     security: State<'_, Mutex<SecurityManager>>,
-) -> Result<FileMetadata, String> {
-    // Validate path
-    let validated_path = validate_path(&path)
-        .map_err(|e| format!("Path validation failed: {}", e))?;
-    
-    // Check if path is allowed (lock released before await)
-    let is_allowed = {
-        let security_manager = security.lock().unwrap();
-        security_manager.is_path_allowed(&validated_path)
-    };
-    
-    if !is_allowed {
-        return Err(format!(
-            "Path not allowed. Please request permission for: {}",
-            path
-        ));
+    audit: State<'_, AuditLog>,
+    quotas: State<'_, QuotaState>,
+) -> Result<FileMetadata, FileSystemError> {
+    let caller = caller_of(&window, context);
+    let mut resolved_path = None;
+    let result = async {
+        // Validate path
+        let validated_path = validate_path(&path)?;
+        
+        // Check access for this operation (lock released before await)
+        let resolved = authorize(&security, &validated_path, Capability::Read, &caller)?;
+        resolved_path = Some(resolved.clone());
+        charge_quota(&app, &quotas, &caller, QuotaCharge::default())?;
+        
+        get_file_metadata(&resolved)
+            .await
     }
+    .await
+    .map_err(|e| e.at(&path));
     
-    get_file_metadata(&path)
-        .await
-        .map_err(|e| format!("Failed to get file metadata: {}", e))
+    audit.record_operation(
+        caller.actor,
+        AuditOperation::GetMetadata,
+        resolved_path.as_deref().unwrap_or(&path),
+        result.as_ref().map(|_| None),
+    )?;
+    result
 }
 
 // Directory operations commands
-#[tauri::command]
-async fn list_directory_command(
+
+/// List a directory for `caller` (shared by the command and the AI file tools)
+async fn list_directory_as(
     path: String,
+    denied_entries: Option<DeniedEntryMode>,
+    caller: Caller,
+    app: AppHandle,
     security: State<'_, Mutex<SecurityManager>>,
-) -> Result<Vec<DirectoryEntry>, String> {
-    // Validate path
-    let validated_path = validate_path(&path)
-        .map_err(|e| format!("Path validation failed: {}", e))?;
-    
-    // Check if path is allowed (lock released before await)
-    let is_allowed = {
+    audit: State<'_, AuditLog>,
+    quotas: State<'_, QuotaState>,
+) -> Result<Vec<DirectoryEntry>, FileSystemError> {
+    let mut resolved_path = None;
+    let result = async {
+        // Validate path
+        let validated_path = validate_path(&path)?;
+        
+        // Check access for this operation (lock released before await)
+        let resolved = authorize(&security, &validated_path, Capability::Read, &caller)?;
+        resolved_path = Some(resolved.clone());
+        charge_quota(&app, &quotas, &caller, QuotaCharge::default())?;
+        
+        let mut entries = list_directory(&resolved)
+            .await?;
+        
+        // AI actors don't see protected files unless asked to; the user sees them marked
+        let mode = denied_entries.unwrap_or(if caller.actor == Actor::User {
+            DeniedEntryMode::Mark
+        } else {
+            DeniedEntryMode::Redact
+        });
         let security_manager = security.lock().unwrap();
-        security_manager.is_path_allowed(&validated_path)
-    };
-    
-    if !is_allowed {
-        return Err(format!(
-            "Path not allowed. Please request permission for: {}",
-            path
-        ));
+        for entry in entries.iter_mut() {
+            entry.denied = security_manager.is_denied(Path::new(&entry.path));
+        }
+        if mode == DeniedEntryMode::Redact {
+            entries.retain(|entry| !entry.denied);
+        }
+        
+        Ok::<_, FileSystemError>(entries)
     }
+    .await
+    .map_err(|e| e.at(&path));
     
-    list_directory(&path)
-        .await
-        .map_err(|e| format!("Failed to list directory: {}", e))
+    audit.record_operation(
+        caller.actor,
+        AuditOperation::ListDirectory,
+        resolved_path.as_deref().unwrap_or(&path),
+        result.as_ref().map(|_| None),
+    )?;
+    result
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn list_directory_command(
+    path: String,
+    denied_entries: Option<DeniedEntryMode>,
+    context: Option<OperationContext>,
+    window: Window,
+    app: AppHandle,
+    security: State<'_, Mutex<SecurityManager>>,
+    audit: State<'_, AuditLog>,
+    quotas: State<'_, QuotaState>,
+) -> Result<Vec<DirectoryEntry>, FileSystemError> {
+    let caller = caller_of(&window, context);
+    list_directory_as(path, denied_entries, caller, app, security, audit, quotas).await
 }
 
 #[tauri::command]
 async fn create_directory_command(
     path: String,
+    context: Option<OperationContext>,
+    window: Window,
+    app: AppHandle,
     security: State<'_, Mutex<SecurityManager>>,
-) -> Result<(), String> {
-    // Validate path
-    let validated_path = validate_path(&path)
-        .map_err(|e| format!("Path validation failed: {}", e))?;
-    
-    // Check if path is allowed (lock released before await)
-    let is_allowed = {
-        let security_manager = security.lock().unwrap();
-        security_manager.is_path_allowed(&validated_path)
-    };
-    
-    if !is_allowed {
-        return Err(format!(
-            "Path not allowed. Please request permission for: {}",
-            path
-        ));
+    audit: State<'_, AuditLog>,
+    quotas: State<'_, QuotaState>,
+) -> Result<(), FileSystemError> {
+    let caller = caller_of(&window, context);
+    let mut resolved_path = None;
+    let result = async {
+        // Validate path
+        let validated_path = validate_path(&path)?;
+        
+        // Check access for this operation (lock released before await)
+        let resolved = authorize(&security, &validated_path, Capability::Create, &caller)?;
+        resolved_path = Some(resolved.clone());
+        charge_quota(
+            &app,
+            &quotas,
+            &caller,
+            QuotaCharge {
+                files_created: 1,
+                ..QuotaCharge::default()
+            },
+        )?;
+        
+        create_directory(&resolved)
+            .await
     }
+    .await
+    .map_err(|e| e.at(&path));
     
-    create_directory(&path)
-        .await
-        .map_err(|e| format!("Failed to create directory: {}", e))
+    audit.record_operation(
+        caller.actor,
+        AuditOperation::CreateDirectory,
+        resolved_path.as_deref().unwrap_or(&path),
+        result.as_ref().map(|_| None),
+    )?;
+    result
 }
 
 #[tauri::command]
 async fn delete_directory_command(
     path: String,
+    context: Option<OperationContext>,
+    window: Window,
+    app: AppHandle,
     security: State<'_, Mutex<SecurityManager>>,
-) -> Result<(), String> {
-    // Validate path
-    let validated_path = validate_path(&path)
-        .map_err(|e| format!("Path validation failed: {}", e))?;
-    
-    // Check if path is allowed (lock released before await)
-    let is_allowed = {
-        let security_manager = security.lock().unwrap();
-        security_manager.is_path_allowed(&validated_path)
-    };
-    
-    if !is_allowed {
-        return Err(format!(
-            "Path not allowed. Please request permission for: {}",
-            path
-        ));
+    audit: State<'_, AuditLog>,
+    quotas: State<'_, QuotaState>,
+) -> Result<(), FileSystemError> {
+    let caller = caller_of(&window, context);
+    let mut resolved_path = None;
+    let result = async {
+        // Validate path
+        let validated_path = validate_path(&path)?;
+        
+        // Check access for this operation (lock released before await)
+        let resolved = authorize(&security, &validated_path, Capability::Delete, &caller)?;
+        resolved_path = Some(resolved.clone());
+        charge_quota(
+            &app,
+            &quotas,
+            &caller,
+            QuotaCharge {
+                deletes: 1,
+                ..QuotaCharge::default()
+            },
+        )?;
+        
+        delete_directory(&resolved)
+            .await
     }
+    .await
+    .map_err(|e| e.at(&path));
     
-    delete_directory(&path)
-        .await
-        .map_err(|e| format!("Failed to delete directory: {}", e))
+    audit.record_operation(
+        caller.actor,
+        AuditOperation::DeleteDirectory,
+        resolved_path.as_deref().unwrap_or(&path),
+        result.as_ref().map(|_| None),
+    )?;
+    result
 }
 
 #[tauri::command]
-async fn file_exists_command(path: String) -> Result<bool, String> {
+async fn file_exists_command(path: String) -> Result<bool, FileSystemError> {
     // Validate path (but don't require it to be allowed for existence check)
-    let _validated_path = validate_path(&path)
-        .map_err(|e| format!("Path validation failed: {}", e))?;
+    let _validated_path = validate_path(&path).map_err(|e| e.at(&path))?;
     
     file_exists(&path)
         .await
-        .map_err(|e| format!("Failed to check file existence: {}", e))
+        .map_err(|e| e.at(&path))
 }
 
 // File watching commands
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 async fn watch_file_command(
     path: String,
+    context: Option<OperationContext>,
     app: AppHandle,
+    window: Window,
+    security: State<'_, Mutex<SecurityManager>>,
+    audit: State<'_, AuditLog>,
+    quotas: State<'_, QuotaState>,
     state: State<'_, FileWatcherState>,
-) -> Result<(), String> {
-    watch_file(&path, app, &state)
-        .map_err(|e| format!("Failed to watch file: {}", e))
+) -> Result<WatchSubscription, FileSystemError> {
+    let caller = caller_of(&window, context);
+    let mut resolved_path = None;
+    let result = async {
+        let validated_path = validate_path(&path)?;
+        let resolved = authorize(&security, &validated_path, Capability::Watch, &caller)?;
+        resolved_path = Some(resolved.clone());
+        charge_quota(&app, &quotas, &caller, QuotaCharge::default())?;
+        
+        watch_file(&resolved, window.label(), app, &state)
+    }
+    .await
+    .map_err(|e| e.at(&path));
+    
+    audit.record_operation(
+        caller.actor,
+        AuditOperation::Watch,
+        resolved_path.as_deref().unwrap_or(&path),
+        result.as_ref().map(|_| None),
+    )?;
+    result
 }
 
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 async fn watch_directory_command(
     path: String,
     recursive: bool,
+    context: Option<OperationContext>,
     app: AppHandle,
+    window: Window,
+    security: State<'_, Mutex<SecurityManager>>,
+    audit: State<'_, AuditLog>,
+    quotas: State<'_, QuotaState>,
     state: State<'_, FileWatcherState>,
-) -> Result<(), String> {
-    watch_directory(&path, recursive, app, &state)
-        .map_err(|e| format!("Failed to watch directory: {}", e))
+) -> Result<WatchSubscription, FileSystemError> {
+    let caller = caller_of(&window, context);
+    let mut resolved_path = None;
+    let result = async {
+        let validated_path = validate_path(&path)?;
+        let resolved = authorize(&security, &validated_path, Capability::Watch, &caller)?;
+        resolved_path = Some(resolved.clone());
+        charge_quota(&app, &quotas, &caller, QuotaCharge::default())?;
+        
+        watch_directory(&resolved, recursive, window.label(), app, &state)
+    }
+    .await
+    .map_err(|e| e.at(&path));
+    
+    audit.record_operation(
+        caller.actor,
+        AuditOperation::Watch,
+        resolved_path.as_deref().unwrap_or(&path),
+        result.as_ref().map(|_| None),
+    )?;
+    result
 }
 
 #[tauri::command]
 async fn unwatch_command(
-    path: String,
+    subscription_id: SubscriptionId,
+    window: Window,
     state: State<'_, FileWatcherState>,
-) -> Result<(), String> {
-    unwatch(&path, &state)
-        .map_err(|e| format!("Failed to unwatch: {}", e))
+) -> Result<(), FileSystemError> {
+    unwatch(subscription_id, window.label(), &state)
 }
 
 #[tauri::command]
-async fn unwatch_all_command(state: State<'_, FileWatcherState>) -> Result<(), String> {
-    unwatch_all(&state);
+async fn unwatch_all_command(
+    window: Window,
+    state: State<'_, FileWatcherState>,
+) -> Result<(), FileSystemError> {
+    unwatch_window(window.label(), &state);
     Ok(())
 }
 
+#[tauri::command]
+async fn get_watch_status(
+    state: State<'_, FileWatcherState>,
+) -> Result<Vec<WatchStatus>, FileSystemError> {
+    Ok(state.status())
+}
+
+// Process commands
+
+/// Run a command in an allowed directory, streaming its output to this window
+/// 
+/// Emits `process-output` for each line and `process-exited` when it ends.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn run_command(
+    request: RunCommandRequest,
+    context: Option<OperationContext>,
+    app: AppHandle,
+    window: Window,
+    security: State<'_, Mutex<SecurityManager>>,
+    audit: State<'_, AuditLog>,
+    quotas: State<'_, QuotaState>,
+    processes: State<'_, ProcessState>,
+) -> Result<ProcessInfo, FileSystemError> {
+    let caller = caller_of(&window, context);
+    let path = request.cwd.clone();
+    let mut resolved_path = None;
+    let result = async {
+        let mut request = request;
+        let validated_path = validate_path(&request.cwd)?;
+        let cwd = authorize(&security, &validated_path, Capability::Execute, &caller)?;
+        resolved_path = Some(cwd.clone());
+        
+        // A program given as a path must be executable as well
+        if Path::new(&request.program).components().count() > 1 {
+            let program = Path::new(&cwd).join(&request.program);
+            request.program = authorize(&security, &program, Capability::Execute, &caller)?;
+        }
+        charge_quota(&app, &quotas, &caller, QuotaCharge::default())?;
+        
+        spawn_command(
+            request,
+            PathBuf::from(cwd),
+            caller.actor,
+            window.label(),
+            app.clone(),
+            &processes,
+        )
+    }
+    .await
+    .map_err(|e| e.at(&path));
+    
+    audit.record_operation(
+        caller.actor,
+        AuditOperation::RunCommand,
+        resolved_path.as_deref().unwrap_or(&path),
+        result.as_ref().map(|_| None),
+    )?;
+    result
+}
+
+/// Check that a window may control a process or terminal (its owner or a trusted window)
+fn check_owner(owner: Option<String>, what: &str, window: &Window) -> Result<(), FileSystemError> {
+    match owner {
+        Some(owner)
+            if owner == window.label() || TRUSTED_CONSENT_WINDOWS.contains(&window.label()) =>
+        {
+            Ok(())
+        }
+        Some(_) => Err(FileSystemError::PermissionDenied(format!(
+            "Window {} did not start {}",
+            window.label(),
+            what
+        ))),
+        None => Err(FileSystemError::NotFound(format!("No running {}", what))),
+    }
+}
+
+/// Ask a process to exit, killing it if it doesn't within a grace period
+#[tauri::command]
+async fn cancel_process(
+    id: ProcessId,
+    window: Window,
+    processes: State<'_, ProcessState>,
+) -> Result<(), FileSystemError> {
+    check_owner(processes.owner(id), &format!("process {}", id), &window)?;
+    processes.cancel(id)
+}
+
+/// Kill a process and everything it started immediately
+#[tauri::command]
+async fn kill_process(
+    id: ProcessId,
+    window: Window,
+    processes: State<'_, ProcessState>,
+) -> Result<(), FileSystemError> {
+    check_owner(processes.owner(id), &format!("process {}", id), &window)?;
+    processes.kill(id)
+}
+
+#[tauri::command]
+async fn list_processes(
+    processes: State<'_, ProcessState>,
+) -> Result<Vec<ProcessInfo>, FileSystemError> {
+    Ok(processes.list())
+}
+
+// Terminal commands
+
+/// Open a terminal session, streaming its output to this window
+/// 
+/// The working directory defaults to the project root. Emits `terminal-output`
+/// as the shell prints and `terminal-exited` when it exits.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn create_terminal_command(
+    options: Option<TerminalOptions>,
+    context: Option<OperationContext>,
+    app: AppHandle,
+    window: Window,
+    security: State<'_, Mutex<SecurityManager>>,
+    audit: State<'_, AuditLog>,
+    quotas: State<'_, QuotaState>,
+    terminals: State<'_, TerminalState>,
+) -> Result<TerminalInfo, FileSystemError> {
+    let caller = caller_of(&window, context);
+    let options = options.unwrap_or_default();
+    let cwd = match &options.cwd {
+        Some(cwd) => Some(cwd.clone()),
+        None => security
+            .lock()
+            .unwrap()
+            .project_root(Capability::Execute)
+            .map(|root| root.to_string_lossy().to_string()),
+    };
+    let path = cwd.clone().unwrap_or_default();
+    let mut resolved_path = None;
+    let result = async {
+        let cwd = cwd.ok_or_else(|| {
+            FileSystemError::InvalidInput(
+                "No project root allows running commands; pass a working directory".to_string(),
+            )
+        })?;
+        let validated_path = validate_path(&cwd)?;
+        let resolved = authorize(&security, &validated_path, Capability::Execute, &caller)?;
+        resolved_path = Some(resolved.clone());
+        charge_quota(&app, &quotas, &caller, QuotaCharge::default())?;
+        
+        create_terminal(options, PathBuf::from(resolved), window.label(), app.clone(), &terminals)
+    }
+    .await
+    .map_err(|e| e.at(&path));
+    
+    audit.record_operation(
+        caller.actor,
+        AuditOperation::OpenTerminal,
+        resolved_path.as_deref().unwrap_or(&path),
+        result.as_ref().map(|_| None),
+    )?;
+    result
+}
+
+#[tauri::command]
+async fn write_terminal(
+    id: TerminalId,
+    data: String,
+    window: Window,
+    terminals: State<'_, TerminalState>,
+) -> Result<(), FileSystemError> {
+    check_owner(terminals.owner(id), &format!("terminal {}", id), &window)?;
+    terminals.write(id, &data).await
+}
+
+#[tauri::command]
+async fn resize_terminal(
+    id: TerminalId,
+    cols: u16,
+    rows: u16,
+    window: Window,
+    terminals: State<'_, TerminalState>,
+) -> Result<(), FileSystemError> {
+    check_owner(terminals.owner(id), &format!("terminal {}", id), &window)?;
+    terminals.resize(id, cols, rows)
+}
+
+#[tauri::command]
+async fn kill_terminal(
+    id: TerminalId,
+    window: Window,
+    terminals: State<'_, TerminalState>,
+) -> Result<(), FileSystemError> {
+    check_owner(terminals.owner(id), &format!("terminal {}", id), &window)?;
+    terminals.kill(id)
+}
+
+/// Output kept for a session (to restore a terminal panel)
+#[tauri::command]
+async fn get_terminal_scrollback(
+    id: TerminalId,
+    window: Window,
+    terminals: State<'_, TerminalState>,
+) -> Result<String, FileSystemError> {
+    check_owner(terminals.owner(id), &format!("terminal {}", id), &window)?;
+    terminals.scrollback(id)
+}
+
+#[tauri::command]
+async fn list_terminals(
+    terminals: State<'_, TerminalState>,
+) -> Result<Vec<TerminalInfo>, FileSystemError> {
+    Ok(terminals.list())
+}
+
+// LLM commands
+
+/// Send a chunk of generated text to the window that asked for it
+fn emit_llm_chunk(app: &AppHandle, window_label: &str, request_id: &str, chunk: &StreamChunk) {
+    let chunk = LlmChunk {
+        request_id: request_id.to_string(),
+        chunk: chunk.clone(),
+    };
+    let _ = app.emit_to(window_label, LLM_CHUNK, chunk);
+}
+
+/// Complete a prompt with the provider configured for `role`
+/// 
+/// Text streams to this window as `llm-chunk` events keyed by `request_id`
+/// (chosen by the caller); the last chunk has `done` set, even on failure.
+/// With `cache` set the response is cached on disk and answered from there
+/// when the same request comes again; identical requests running at the same
+/// time share one model call either way. Calls wait for a free slot on the
+/// provider, `priority` (by default interactive, background for the
+/// implementation role) deciding the order; a preempted call starts over,
+/// announced by a chunk with `restart` set.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn llm_generate(
+    request_id: String,
+    mut request: GenerateRequest,
+    role: Option<AiRole>,
+    priority: Option<Priority>,
+    cache: Option<CacheOptions>,
+    app: AppHandle,
+    window: Window,
+    llm: State<'_, LlmState>,
+    llm_cache: State<'_, LlmCache>,
+) -> Result<LlmResponse, LlmError> {
+    request.model = llm.model_for(role, &request.model)?;
+    let priority = priority.unwrap_or(Priority::for_role(role));
+    let (provider_id, provider) = llm.queued_provider(role, &request_id, priority)?;
+    let cancel = llm.begin(&request_id)?;
+    let mut on_chunk = |chunk: &StreamChunk| {
+        emit_llm_chunk(&app, window.label(), &request_id, chunk)
+    };
+    let call = LlmCall::Generate(&request);
+    let result = llm_cache
+        .complete(&provider_id, provider.as_ref(), call, cache.as_ref(), &cancel, &mut on_chunk)
+        .await;
+    
+    llm.finish(&request_id);
+    if result.is_err() {
+        let done = StreamChunk {
+            done: true,
+            ..StreamChunk::default()
+        };
+        emit_llm_chunk(&app, window.label(), &request_id, &done);
+    }
+    result
+}
+
+/// Continue a conversation with the provider configured for `role`
+/// 
+/// Streams like `llm_generate`.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn llm_chat(
+    request_id: String,
+    mut request: ChatRequest,
+    role: Option<AiRole>,
+    priority: Option<Priority>,
+    cache: Option<CacheOptions>,
+    app: AppHandle,
+    window: Window,
+    llm: State<'_, LlmState>,
+    llm_cache: State<'_, LlmCache>,
+) -> Result<LlmResponse, LlmError> {
+    request.model = llm.model_for(role, &request.model)?;
+    let priority = priority.unwrap_or(Priority::for_role(role));
+    let (provider_id, provider) = llm.queued_provider(role, &request_id, priority)?;
+    let cancel = llm.begin(&request_id)?;
+    let mut on_chunk = |chunk: &StreamChunk| {
+        emit_llm_chunk(&app, window.label(), &request_id, chunk)
+    };
+    let call = LlmCall::Chat(&request);
+    let result = llm_cache
+        .complete(&provider_id, provider.as_ref(), call, cache.as_ref(), &cancel, &mut on_chunk)
+        .await;
+    
+    llm.finish(&request_id);
+    if result.is_err() {
+        let done = StreamChunk {
+            done: true,
+            ..StreamChunk::default()
+        };
+        emit_llm_chunk(&app, window.label(), &request_id, &done);
+    }
+    result
+}
+
+/// Ask the provider configured for `role` for JSON matching a schema
+/// 
+/// The reply is constrained by the provider's JSON mode where available,
+/// validated against `schema` and sent back with its problems up to
+/// `max_repairs` times. Streams like `llm_generate`; each repair attempt starts
+/// with a chunk that has `restart` set. Fails with `InvalidOutput` listing the
+/// last reply's problems when no attempt matched.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn llm_generate_structured(
+    request_id: String,
+    mut request: StructuredRequest,
+    role: Option<AiRole>,
+    priority: Option<Priority>,
+    app: AppHandle,
+    window: Window,
+    llm: State<'_, LlmState>,
+) -> Result<StructuredOutput<serde_json::Value>, LlmError> {
+    request.chat.model = llm.model_for(role, &request.chat.model)?;
+    let priority = priority.unwrap_or(Priority::for_role(role));
+    let (_, provider) = llm.queued_provider(role, &request_id, priority)?;
+    let cancel = llm.begin(&request_id)?;
+    let result = generate_structured(provider.as_ref(), request, &cancel, |chunk| {
+        emit_llm_chunk(&app, window.label(), &request_id, chunk)
+    })
+    .await;
+    
+    llm.finish(&request_id);
+    let done = StreamChunk {
+        done: true,
+        ..StreamChunk::default()
+    };
+    emit_llm_chunk(&app, window.label(), &request_id, &done);
+    result
+}
+
+/// Stop an in-flight LLM request (it then fails with `Cancelled`)
+/// 
+/// Requests still waiting in the queue leave it.
+#[tauri::command]
+async fn llm_cancel(request_id: String, llm: State<'_, LlmState>) -> Result<bool, LlmError> {
+    Ok(llm.cancel(&request_id))
+}
+
+/// Circuit breaker state of every configured provider
+/// 
+/// Changes are also sent to every window as `llm-provider-status` events.
+#[tauri::command]
+async fn llm_provider_status(llm: State<'_, LlmState>) -> Result<Vec<ProviderStatus>, LlmError> {
+    Ok(llm.provider_status())
+}
+
+/// Running and waiting model calls per provider, with estimated times
+#[tauri::command]
+async fn llm_queue_status(llm: State<'_, LlmState>) -> Result<Vec<ProviderQueueStatus>, LlmError> {
+    Ok(llm.queue_status())
+}
+
+/// LLM server settings; API keys are left out for windows that can't change them
+#[tauri::command]
+async fn llm_get_config(window: Window, llm: State<'_, LlmState>) -> Result<LlmConfig, LlmError> {
+    let mut config = llm.config();
+    if check_llm_admin(&window, "read API keys").is_err() {
+        for provider in config.providers.values_mut() {
+            provider.api_key = None;
+        }
+    }
+    Ok(config)
+}
+
+/// Check that a window may change LLM settings or models (trusted windows only)
+fn check_llm_admin(window: &Window, action: &str) -> Result<(), LlmError> {
+    if !TRUSTED_CONSENT_WINDOWS.contains(&window.label()) {
+        return Err(LlmError::PermissionDenied(format!(
+            "Window {} is not allowed to {}",
+            window.label(),
+            action
+        )));
+    }
+    Ok(())
+}
+
+/// Change the LLM server settings (trusted windows only)
+#[tauri::command]
+async fn llm_set_config(
+    config: LlmConfig,
+    window: Window,
+    llm: State<'_, LlmState>,
+    llm_cache: State<'_, LlmCache>,
+) -> Result<(), LlmError> {
+    check_llm_admin(&window, "change LLM settings")?;
+    let cache = config.cache;
+    llm.set_config(config)?;
+    llm_cache.set_config(cache);
+    Ok(())
+}
+
+/// Size and hit counts of the LLM response cache
+#[tauri::command]
+async fn llm_cache_stats(llm_cache: State<'_, LlmCache>) -> Result<CacheStats, LlmError> {
+    Ok(llm_cache.stats())
+}
+
+/// Drop every cached LLM response (trusted windows only)
+/// 
+/// # Returns
+/// The number of entries dropped
+#[tauri::command]
+async fn llm_cache_clear(
+    window: Window,
+    llm_cache: State<'_, LlmCache>,
+) -> Result<usize, LlmError> {
+    check_llm_admin(&window, "clear the LLM cache")?;
+    Ok(llm_cache.clear())
+}
+
+/// List the models a provider can run
+/// 
+/// `provider` is a provider id; the default provider is used when it is `None`.
+#[tauri::command]
+async fn llm_list_models(
+    provider: Option<String>,
+    llm: State<'_, LlmState>,
+) -> Result<Vec<ModelStatus>, LlmError> {
+    let (_, provider) = llm.provider_by_id(provider.as_deref())?;
+    provider.list_models().await
+}
+
+/// Report whether a provider's server is reachable
+/// 
+/// Never fails for a known provider; an unreachable server is reported in the result.
+#[tauri::command]
+async fn llm_health(
+    provider: Option<String>,
+    llm: State<'_, LlmState>,
+) -> Result<LlmHealth, LlmError> {
+    let (id, provider) = llm.provider_by_id(provider.as_deref())?;
+    Ok(check_health(&id, provider.as_ref()).await)
+}
+
+/// Load a model so the first request doesn't wait for it
+#[tauri::command]
+async fn llm_warm_up(
+    model: String,
+    provider: Option<String>,
+    llm: State<'_, LlmState>,
+) -> Result<(), LlmError> {
+    let (_, provider) = llm.provider_by_id(provider.as_deref())?;
+    provider.warm_up(&model).await
+}
+
+/// Download a model (trusted windows only)
+/// 
+/// Progress streams to this window as `llm-pull-progress` events keyed by
+/// `request_id`; `llm_cancel` stops the download.
+#[tauri::command]
+async fn llm_pull_model(
+    request_id: String,
+    model: String,
+    provider: Option<String>,
+    app: AppHandle,
+    window: Window,
+    llm: State<'_, LlmState>,
+) -> Result<(), LlmError> {
+    check_llm_admin(&window, "download models")?;
+    let (_, provider) = llm.provider_by_id(provider.as_deref())?;
+    let cancel = llm.begin(&request_id)?;
+    let mut on_progress = |progress: &PullProgress| {
+        let event = LlmPullProgress {
+            request_id: request_id.clone(),
+            model: model.clone(),
+            progress: progress.clone(),
+        };
+        let _ = app.emit_to(window.label(), LLM_PULL_PROGRESS, event);
+    };
+    let result = provider.pull_model(&model, &cancel, &mut on_progress).await;
+    
+    llm.finish(&request_id);
+    result
+}
+
+/// Delete a downloaded model (trusted windows only)
+#[tauri::command]
+async fn llm_delete_model(
+    model: String,
+    provider: Option<String>,
+    window: Window,
+    llm: State<'_, LlmState>,
+) -> Result<(), LlmError> {
+    check_llm_admin(&window, "delete models")?;
+    let (_, provider) = llm.provider_by_id(provider.as_deref())?;
+    provider.delete_model(&model).await
+}
+
+/// Count the tokens a text takes for a model (the role's model if `model` is empty)
+#[tauri::command]
+async fn llm_count_tokens(
+    model: String,
+    text: String,
+    role: Option<AiRole>,
+    llm: State<'_, LlmState>,
+) -> Result<TokenCount, LlmError> {
+    let model = llm.model_for(role, &model)?;
+    let provider = llm.provider(role)?;
+    count_tokens(provider.as_ref(), &model, text).await
+}
+
+/// Report how prompt inputs fit a model's context window, without changing them
+/// 
+/// Meant for showing the breakdown before a request is sent.
+#[tauri::command]
+async fn llm_plan_budget(
+    mut request: BudgetRequest,
+    role: Option<AiRole>,
+    llm: State<'_, LlmState>,
+) -> Result<BudgetReport, LlmError> {
+    request.model = llm.model_for(role, &request.model)?;
+    let provider = llm.provider(role)?;
+    plan_budget(provider.as_ref(), &request).await
+}
+
+/// Shorten prompt inputs to fit a model's context window
+/// 
+/// Inputs are truncated or summarized by the model as planned by
+/// `llm_plan_budget`; `llm_cancel` with `request_id` stops pending summaries.
+#[tauri::command]
+async fn llm_fit_budget(
+    request_id: String,
+    mut request: BudgetRequest,
+    role: Option<AiRole>,
+    priority: Option<Priority>,
+    llm: State<'_, LlmState>,
+) -> Result<FitResult, LlmError> {
+    request.model = llm.model_for(role, &request.model)?;
+    let priority = priority.unwrap_or(Priority::for_role(role));
+    let (_, provider) = llm.queued_provider(role, &request_id, priority)?;
+    let cancel = llm.begin(&request_id)?;
+    let result = fit_budget(provider.as_ref(), request, &cancel).await;
+    
+    llm.finish(&request_id);
+    result
+}
+
+/// Root of the open project (the most recently granted readable root)
+fn open_project_root(app: &AppHandle) -> Option<PathBuf> {
+    app.state::<Mutex<SecurityManager>>()
+        .lock()
+        .unwrap()
+        .project_root(Capability::Read)
+}
+
+/// List the prompt templates of a role (both roles when `role` is `None`)
+/// 
+/// Project templates come first, then the app's, then the built-in one;
+/// `active` marks the one in use and `error` explains files that can't be used.
+#[tauri::command]
+async fn list_prompt_templates(
+    role: Option<AiRole>,
+    app: AppHandle,
+    llm: State<'_, LlmState>,
+) -> Result<Vec<TemplateInfo>, LlmError> {
+    let dirs = llm.template_dirs(open_project_root(&app));
+    let roles = match role {
+        Some(role) => vec![role],
+        None => vec![AiRole::Overseer, AiRole::Implementation],
+    };
+    Ok(roles.into_iter().flat_map(|role| dirs.list(role)).collect())
+}
+
+/// Render a role's system prompt from its active template
+/// 
+/// Fills in `project_name`, `project_root`, `file_tree` (without denied
+/// files), `task`, `role`, `date` and any `variables` given; the result is
+/// what to send as the conversation's system message.
+#[tauri::command]
+async fn preview_prompt(
+    role: AiRole,
+    task: Option<String>,
+    variables: Option<HashMap<String, String>>,
+    app: AppHandle,
+    llm: State<'_, LlmState>,
+) -> Result<RenderedPrompt, LlmError> {
+    let dirs = llm.template_dirs(open_project_root(&app));
+    // Listing the project for the file tree is blocking work
+    tokio::task::spawn_blocking(move || {
+        let security = app.state::<Mutex<SecurityManager>>();
+        let is_denied = |path: &Path| security.lock().unwrap().is_denied(path);
+        render_prompt(&dirs, role, task.as_deref(), variables.unwrap_or_default(), is_denied)
+    })
+    .await
+    .map_err(|e| LlmError::InvalidInput(format!("Rendering the prompt failed: {}", e)))?
+}
+
+/// Actor an AI role's agent acts as
+fn actor_for_role(role: AiRole) -> Actor {
+    match role {
+        AiRole::Overseer => Actor::OverseerAi,
+        AiRole::Implementation => Actor::ImplementationAi,
+    }
+}
+
+/// Runs agent tool calls through the same guarded commands the frontend uses
+/// 
+/// Every call is validated, authorized, charged and audited for the caller's actor.
+struct CommandToolExecutor {
+    app: AppHandle,
+    caller: Caller,
+}
+
+impl CommandToolExecutor {
+    /// A call's `path` argument, with relative paths taken from the project root
+    fn path_argument(&self, call: &ToolCall) -> Result<String, FileSystemError> {
+        let path = call.arguments["path"]
+            .as_str()
+            .ok_or_else(|| FileSystemError::InvalidInput("Missing path".to_string()))?;
+        if Path::new(path).is_absolute() {
+            return Ok(path.to_string());
+        }
+        
+        let root = self
+            .app
+            .state::<Mutex<SecurityManager>>()
+            .lock()
+            .unwrap()
+            .project_root(Capability::Read)
+            .ok_or_else(|| {
+                FileSystemError::InvalidPath(format!("No project root to resolve {} against", path))
+            })?;
+        Ok(root.join(path).to_string_lossy().to_string())
+    }
+    
+    async fn run(&self, call: &ToolCall) -> Result<serde_json::Value, FileSystemError> {
+        let path = self.path_argument(call)?;
+        let caller = self.caller.clone();
+        let app = &self.app;
+        let value = match call.name.as_str() {
+            READ_FILE => {
+                let file = read_file_as(
+                    path,
+                    caller,
+                    app.clone(),
+                    app.state(),
+                    app.state(),
+                    app.state(),
+                )
+                .await?;
+                serde_json::json!(file)
+            }
+            LIST_DIRECTORY => {
+                let entries = list_directory_as(
+                    path,
+                    None,
+                    caller,
+                    app.clone(),
+                    app.state(),
+                    app.state(),
+                    app.state(),
+                )
+                .await?;
+                serde_json::json!(entries)
+            }
+            WRITE_FILE => {
+                let content = call.arguments["content"]
+                    .as_str()
+                    .ok_or_else(|| {
+                        FileSystemError::InvalidInput("content must be a string".to_string())
+                    })?
+                    .to_string();
+                let bytes = content.len();
+                let request = FileWriteRequest {
+                    path,
+                    content,
+                    create_if_not_exists: true,
+                    backup: false,
+                };
+                write_file_as(
+                    request,
+                    caller,
+                    app.clone(),
+                    app.state(),
+                    app.state(),
+                    app.state(),
+                    app.state(),
+                )
+                .await?;
+                serde_json::json!({ "bytes_written": bytes })
+            }
+            name => {
+                return Err(FileSystemError::InvalidInput(format!("Unknown tool: {}", name)));
+            }
+        };
+        Ok(value)
+    }
+}
+
+impl ToolExecutor for CommandToolExecutor {
+    fn execute<'a>(&'a self, call: &'a ToolCall) -> ToolFuture<'a> {
+        Box::pin(async move {
+            self.run(call)
+                .await
+                .map_err(|e| serde_json::to_value(&e).unwrap_or_default())
+        })
+    }
+}
+
+/// Let a role's model read, list and write files until it answers
+/// 
+/// Tool calls run as the role's actor (`overseer_ai` or `implementation_ai`)
+/// through the regular file commands, so permissions, deny rules, quotas
+/// (tracked under `task_id`) and the audit log all apply. Text streams as
+/// `llm-chunk` events and each tool call is reported as an `llm-agent-step`
+/// event, both keyed by `request_id`; the run stops after `max_steps` tool rounds.
+/// Trusted windows only.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn llm_agent_run(
+    request_id: String,
+    mut request: AgentRequest,
+    role: AiRole,
+    task_id: Option<String>,
+    priority: Option<Priority>,
+    app: AppHandle,
+    window: Window,
+    llm: State<'_, LlmState>,
+) -> Result<AgentResult, LlmError> {
+    // The agent acts as an AI actor, which may hold more than a plugin window
+    check_llm_admin(&window, "run agents")?;
+    request.model = llm.model_for(Some(role), &request.model)?;
+    let priority = priority.unwrap_or(Priority::for_role(Some(role)));
+    let (_, provider) = llm.queued_provider(Some(role), &request_id, priority)?;
+    let cancel = llm.begin(&request_id)?;
+    let executor = CommandToolExecutor {
+        app: app.clone(),
+        caller: Caller {
+            actor: actor_for_role(role),
+            task_id,
+        },
+    };
+    let result = run_agent(
+        provider.as_ref(),
+        &ToolRegistry::file_tools(),
+        &executor,
+        request,
+        &request_id,
+        &cancel,
+        |chunk| emit_llm_chunk(&app, window.label(), &request_id, chunk),
+        |step: &AgentStep| {
+            let _ = app.emit_to(window.label(), LLM_AGENT_STEP, step);
+        },
+    )
+    .await;
+    
+    llm.finish(&request_id);
+    let done = StreamChunk {
+        done: true,
+        ..StreamChunk::default()
+    };
+    emit_llm_chunk(&app, window.label(), &request_id, &done);
+    result
+}
+
 // Security commands
+
+/// Tell trusted windows how a permission request was decided
+fn emit_permission_resolved(app: &AppHandle, request: &PermissionRequest, decision: ConsentDecision) {
+    let payload = PermissionResolution {
+        id: request.id,
+        path: request.path.clone(),
+        decision,
+    };
+    for label in TRUSTED_CONSENT_WINDOWS {
+        let _ = app.emit_to(*label, PERMISSION_RESOLVED, &payload);
+    }
+}
+
+/// Ask the user for permission to access a path
+///
+/// Emits `permission-requested` to the trusted windows and waits until the
+/// user answers via `respond_path_permission` (or the request times out).
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 async fn request_path_permission(
     path: String,
+    scope: Option<GrantScope>,
+    reason: Option<String>,
+    app: AppHandle,
+    window: Window,
+    security: State<'_, Mutex<SecurityManager>>,
+    consent: State<'_, ConsentState>,
+    audit: State<'_, AuditLog>,
+) -> Result<bool, FileSystemError> {
+    // Validate and resolve the path first, so the user is shown where it really leads
+    let validated_path = validate_path(&path)?;
+    let resolved = security.lock().unwrap().resolve(&validated_path)?;
+    let scope = scope.unwrap_or(GrantScope::ReadWrite);
+    let actor = caller_of(&window, None).actor;
+    
+    // Nothing to ask if existing grants already cover the request
+    if security.lock().unwrap().is_granted(&resolved, scope) {
+        return Ok(true);
+    }
+    
+    // Identical pending requests share a single prompt
+    let (request, is_new, decision) =
+        consent.open(resolved, scope, reason, window.label(), actor);
+    if is_new {
+        let recorded = audit.record(
+            actor,
+            AuditOperation::PermissionRequest,
+            &request.path,
+            Some(format!("{:?} requested by window {}", scope, request.requested_by)),
+        );
+        // Never prompt for a request the log doesn't show
+        if let Err(e) = recorded {
+            consent.cancel(request.id);
+            return Err(e);
+        }
+        for label in TRUSTED_CONSENT_WINDOWS {
+            let _ = app.emit_to(*label, PERMISSION_REQUESTED, &request);
+        }
+    }
+    
+    let decision = match tokio::time::timeout(CONSENT_TIMEOUT, decision).await {
+        Ok(Ok(decision)) => decision,
+        // Timed out (or the request was dropped): treat as denied
+        _ => {
+            if consent.cancel(request.id).is_some() {
+                emit_permission_resolved(&app, &request, ConsentDecision::Denied);
+                audit.record(
+                    actor,
+                    AuditOperation::PermissionDeny,
+                    &request.path,
+                    Some("Timed out waiting for the user".to_string()),
+                )?;
+            }
+            ConsentDecision::Denied
+        }
+    };
+    
+    Ok(matches!(decision, ConsentDecision::Approved { .. }))
+}
+
+/// Answer a pending permission request (trusted windows only)
+///
+/// An approval may narrow the requested scope but never widen it.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn respond_path_permission(
+    request_id: ConsentRequestId,
+    approved: bool,
+    scope: Option<GrantScope>,
+    app: AppHandle,
+    window: Window,
     security: State<'_, Mutex<SecurityManager>>,
-) -> Result<bool, String> {
-    // Validate and normalize path first
-    let validated_path = validate_path(&path)
-        .map_err(|e| format!("Path validation failed: {}", e))?;
+    consent: State<'_, ConsentState>,
+    audit: State<'_, AuditLog>,
+) -> Result<(), FileSystemError> {
+    if !TRUSTED_CONSENT_WINDOWS.contains(&window.label()) {
+        return Err(FileSystemError::PermissionDenied(format!(
+            "Window {} is not allowed to answer permission requests",
+            window.label()
+        )));
+    }
     
-    // For now, automatically grant permission (in future, this could show a dialog)
-    let mut security_manager = security.lock().unwrap();
+    let request = consent
+        .get(request_id)?;
     
-    // Add the path itself
-    security_manager.add_allowed_path(validated_path.clone());
+    let decision = if approved {
+        let scope = scope.unwrap_or(request.scope);
+        if !scope.is_within(request.scope) {
+            return Err(FileSystemError::InvalidInput(format!(
+                "Approved scope {:?} is wider than requested scope {:?}",
+                scope, request.scope
+            )));
+        }
+        ConsentDecision::Approved { scope }
+    } else {
+        ConsentDecision::Denied
+    };
     
-    // Also try to canonicalize and add that version
-    if let Ok(canonical) = validated_path.canonicalize() {
-        security_manager.add_allowed_path(canonical);
+    // Hold the grants while waking the requester, so its next operation
+    // waits for the grant below
+    let mut security = security.lock().unwrap();
+    let Some(request) = consent.resolve(request_id, decision) else {
+        return Err(FileSystemError::Conflict(format!(
+            "Permission request {} was already answered or timed out",
+            request_id
+        )));
+    };
+    if let ConsentDecision::Approved { scope } = decision {
+        security.extend_grant(
+            PathBuf::from(&request.path),
+            scope.capabilities(),
+            request.reason.clone(),
+        )?;
     }
+    drop(security);
     
-    Ok(true)
+    let recorded = match decision {
+        // The decision is the user's; the detail says whom it was for
+        ConsentDecision::Approved { scope } => audit.record(
+            Actor::User,
+            AuditOperation::PermissionGrant,
+            &request.path,
+            Some(format!(
+                "{:?} approved for {:?} in window {}",
+                scope,
+                request.actor,
+                window.label()
+            )),
+        ),
+        ConsentDecision::Denied => audit.record(
+            Actor::User,
+            AuditOperation::PermissionDeny,
+            &request.path,
+            Some(format!("Denied for {:?} in window {}", request.actor, window.label())),
+        ),
+    };
+    emit_permission_resolved(&app, &request, decision);
+    recorded
+}
+
+#[tauri::command]
+async fn list_pending_permission_requests(
+    consent: State<'_, ConsentState>,
+) -> Result<Vec<PermissionRequest>, FileSystemError> {
+    Ok(consent.pending_requests())
 }
 
+/// Grant access to a path without a consent prompt (trusted windows only)
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 async fn add_allowed_path(
     path: String,
+    scope: Option<GrantScope>,
+    capabilities: Option<CapabilitySet>,
+    expires_at: Option<i64>,
+    reason: Option<String>,
+    window: Window,
     security: State<'_, Mutex<SecurityManager>>,
-) -> Result<(), String> {
-    let validated_path = validate_path(&path)
-        .map_err(|e| format!("Path validation failed: {}", e))?;
+    audit: State<'_, AuditLog>,
+) -> Result<PermissionGrant, FileSystemError> {
+    if !TRUSTED_CONSENT_WINDOWS.contains(&window.label()) {
+        return Err(FileSystemError::PermissionDenied(format!(
+            "Window {} is not allowed to grant permissions",
+            window.label()
+        )));
+    }
     
-    let mut security_manager = security.lock().unwrap();
-    security_manager.add_allowed_path(validated_path);
+    let validated_path = validate_path(&path)?;
     
-    Ok(())
+    // Expiry is given in seconds since the Unix epoch
+    let expires_at = match expires_at {
+        Some(secs) => Some(
+            chrono::DateTime::from_timestamp(secs, 0)
+                .ok_or_else(|| {
+                    FileSystemError::InvalidInput(format!("Invalid expiry timestamp: {}", secs))
+                })?,
+        ),
+        None => None,
+    };
+    
+    let grant = security
+        .lock()
+        .unwrap()
+        .add_grant(
+            validated_path,
+            // Explicit capabilities take precedence over the scope preset
+            capabilities.unwrap_or_else(|| scope.unwrap_or(GrantScope::ReadWrite).capabilities()),
+            expires_at,
+            reason,
+        )?;
+    
+    audit.record(
+        Actor::User,
+        AuditOperation::PermissionGrant,
+        &grant.path.to_string_lossy(),
+        Some(format!("{:?} added directly in window {}", grant.capabilities, window.label())),
+    )?;
+    Ok(grant)
 }
 
 #[tauri::command]
 async fn get_allowed_paths(
     security: State<'_, Mutex<SecurityManager>>,
-) -> Result<Vec<String>, String> {
+) -> Result<Vec<String>, FileSystemError> {
     let security_manager = security.lock().unwrap();
     let paths = security_manager
         .get_allowed_paths()
@@ -309,12 +1573,276 @@ async fn get_allowed_paths(
     Ok(paths)
 }
 
+#[tauri::command]
+async fn list_permission_grants(
+    security: State<'_, Mutex<SecurityManager>>,
+) -> Result<Vec<PermissionGrant>, FileSystemError> {
+    Ok(security.lock().unwrap().list_grants())
+}
+
+/// Revoke the grant for a path (trusted windows only)
+#[tauri::command]
+async fn revoke_permission_grant(
+    path: String,
+    window: Window,
+    security: State<'_, Mutex<SecurityManager>>,
+    audit: State<'_, AuditLog>,
+) -> Result<bool, FileSystemError> {
+    if !TRUSTED_CONSENT_WINDOWS.contains(&window.label()) {
+        return Err(FileSystemError::PermissionDenied(format!(
+            "Window {} is not allowed to revoke permissions",
+            window.label()
+        )));
+    }
+    
+    let revoked = security
+        .lock()
+        .unwrap()
+        .revoke_grant(Path::new(&path))?;
+    
+    if let Some(grant) = &revoked {
+        audit.record(
+            Actor::User,
+            AuditOperation::PermissionRevoke,
+            &grant.path.to_string_lossy(),
+            None,
+        )?;
+    }
+    Ok(revoked.is_some())
+}
+
+/// Read audit log entries matching a filter, oldest first
+/// 
+/// The `path` filter is resolved like the logged paths, so any spelling of a
+/// path (relative, through a symlink) finds its entries.
+#[tauri::command]
+async fn query_audit_log(
+    query: Option<AuditQuery>,
+    security: State<'_, Mutex<SecurityManager>>,
+    audit: State<'_, AuditLog>,
+) -> Result<Vec<AuditEntry>, FileSystemError> {
+    let mut query = query.unwrap_or_default();
+    if let Some(path) = &query.path {
+        let validated_path = validate_path(path)?;
+        let resolved = security.lock().unwrap().resolve(&validated_path)?;
+        query.path = Some(resolved.to_string_lossy().to_string());
+    }
+    
+    audit
+        .query(&query)
+        .await
+}
+
+#[tauri::command]
+async fn get_quota_status(quotas: State<'_, QuotaState>) -> Result<Vec<QuotaStatus>, FileSystemError> {
+    Ok(quotas.status())
+}
+
+/// Replace an actor's quota limits (trusted windows only)
+#[tauri::command]
+async fn set_quota_limits(
+    actor: Actor,
+    limits: QuotaLimits,
+    window: Window,
+    quotas: State<'_, QuotaState>,
+    audit: State<'_, AuditLog>,
+) -> Result<(), FileSystemError> {
+    if !TRUSTED_CONSENT_WINDOWS.contains(&window.label()) {
+        return Err(FileSystemError::PermissionDenied(format!(
+            "Window {} is not allowed to change quotas",
+            window.label()
+        )));
+    }
+    
+    quotas.set_limits(actor, limits);
+    audit.record(
+        Actor::User,
+        AuditOperation::PolicyChange,
+        "",
+        Some(format!("{:?} quota limits set to {:?}", actor, limits)),
+    )?;
+    Ok(())
+}
+
+/// Let an actor paused by a quota breach continue (trusted windows only)
+#[tauri::command]
+async fn resume_actor(
+    actor: Actor,
+    reset_usage: Option<bool>,
+    window: Window,
+    quotas: State<'_, QuotaState>,
+    audit: State<'_, AuditLog>,
+) -> Result<bool, FileSystemError> {
+    if !TRUSTED_CONSENT_WINDOWS.contains(&window.label()) {
+        return Err(FileSystemError::PermissionDenied(format!(
+            "Window {} is not allowed to resume actors",
+            window.label()
+        )));
+    }
+    
+    let reset_usage = reset_usage.unwrap_or(false);
+    let resumed = quotas.resume(actor, reset_usage);
+    audit.record(
+        Actor::User,
+        AuditOperation::PolicyChange,
+        "",
+        Some(format!("{:?} resumed (usage reset: {})", actor, reset_usage)),
+    )?;
+    Ok(resumed)
+}
+
+#[tauri::command]
+async fn get_actor_capabilities(
+    actor: Actor,
+    security: State<'_, Mutex<SecurityManager>>,
+) -> Result<CapabilitySet, FileSystemError> {
+    Ok(security.lock().unwrap().actor_capabilities(actor))
+}
+
+/// Set the capability ceiling for an actor (trusted windows only)
+#[tauri::command]
+async fn set_actor_capabilities(
+    actor: Actor,
+    capabilities: CapabilitySet,
+    window: Window,
+    security: State<'_, Mutex<SecurityManager>>,
+    audit: State<'_, AuditLog>,
+) -> Result<(), FileSystemError> {
+    if !TRUSTED_CONSENT_WINDOWS.contains(&window.label()) {
+        return Err(FileSystemError::PermissionDenied(format!(
+            "Window {} is not allowed to change actor capabilities",
+            window.label()
+        )));
+    }
+    
+    security
+        .lock()
+        .unwrap()
+        .set_actor_capabilities(actor, capabilities.clone())?;
+    
+    audit.record(
+        Actor::User,
+        AuditOperation::PolicyChange,
+        "",
+        Some(format!("{:?} capabilities set to {:?}", actor, capabilities)),
+    )?;
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_deny_overrides(
+    security: State<'_, Mutex<SecurityManager>>,
+) -> Result<HashMap<String, DenyOverrides>, FileSystemError> {
+    Ok(security
+        .lock()
+        .unwrap()
+        .deny_overrides()
+        .iter()
+        .map(|(project, overrides)| (project.to_string_lossy().to_string(), overrides.clone()))
+        .collect())
+}
+
+/// Set the deny-rule overrides for a project (trusted windows only)
+#[tauri::command]
+async fn set_deny_overrides(
+    project: String,
+    overrides: DenyOverrides,
+    window: Window,
+    security: State<'_, Mutex<SecurityManager>>,
+    audit: State<'_, AuditLog>,
+) -> Result<(), FileSystemError> {
+    if !TRUSTED_CONSENT_WINDOWS.contains(&window.label()) {
+        return Err(FileSystemError::PermissionDenied(format!(
+            "Window {} is not allowed to change deny rules",
+            window.label()
+        )));
+    }
+    
+    let validated_path = validate_path(&project)?;
+    security
+        .lock()
+        .unwrap()
+        .set_deny_overrides(validated_path, overrides.clone())?;
+    
+    audit.record(
+        Actor::User,
+        AuditOperation::PolicyChange,
+        &project,
+        Some(format!("Deny rules set to {:?}", overrides)),
+    )?;
+    Ok(())
+}
+
+/// Drop cached LLM responses that depend on files the watcher reports changed
+fn spawn_cache_invalidation(app: AppHandle) {
+    let mut changes = app.state::<FileWatcherState>().changes();
+    tauri::async_runtime::spawn(async move {
+        let cache = app.state::<LlmCache>();
+        loop {
+            match changes.recv().await {
+                Ok(event) => {
+                    cache.invalidate_changed(&event);
+                }
+                // Changes were missed, so any file-dependent entry may be stale
+                Err(RecvError::Lagged(_)) => {
+                    cache.invalidate_dependent();
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
+}
+
+/// Tell every window when a provider's circuit breaker opens or closes
+fn spawn_provider_status_events(app: AppHandle, mut changes: Receiver<ProviderStatus>) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            match changes.recv().await {
+                Ok(status) => {
+                    let _ = app.emit(LLM_PROVIDER_STATUS, &status);
+                }
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
         .manage(FileWatcherState::new())
-        .manage(Mutex::new(SecurityManager::new()))
+        .manage(ConsentState::new())
+        .manage(QuotaState::new())
+        .manage(ProcessState::new())
+        .manage(TerminalState::new())
+        .setup(|app| {
+            // Load persisted permission grants
+            let grants_path = app.path().app_config_dir()?.join(GRANTS_FILE);
+            app.manage(Mutex::new(SecurityManager::load(grants_path)?));
+            
+            let audit_path = app.path().app_data_dir()?.join(AUDIT_LOG_FILE);
+            app.manage(AuditLog::new(audit_path));
+            
+            let llm_config_path = app.path().app_config_dir()?.join(LLM_CONFIG_FILE);
+            let llm = LlmState::load(llm_config_path)?;
+            let cache_path = app.path().app_cache_dir()?.join(CACHE_DIR);
+            app.manage(LlmCache::open(cache_path, llm.config().cache));
+            let status_changes = llm.status_changes();
+            app.manage(llm);
+            spawn_cache_invalidation(app.handle().clone());
+            spawn_provider_status_events(app.handle().clone(), status_changes);
+            Ok(())
+        })
+        .on_window_event(|window, event| {
+            // Release watch subscriptions, processes and terminals owned by a closed window
+            if let WindowEvent::Destroyed = event {
+                unwatch_window(window.label(), &window.state::<FileWatcherState>());
+                window.state::<ProcessState>().kill_window(window.label());
+                window.state::<TerminalState>().kill_window(window.label());
+            }
+        })
         .invoke_handler(tauri::generate_handler![
             greet,
             read_file_command,
@@ -329,10 +1857,63 @@ pub fn run() {
             watch_directory_command,
             unwatch_command,
             unwatch_all_command,
+            get_watch_status,
+            run_command,
+            cancel_process,
+            kill_process,
+            list_processes,
+            create_terminal_command,
+            write_terminal,
+            resize_terminal,
+            kill_terminal,
+            get_terminal_scrollback,
+            list_terminals,
+            llm_generate,
+            llm_chat,
+            llm_generate_structured,
+            llm_cancel,
+            llm_queue_status,
+            llm_provider_status,
+            llm_get_config,
+            llm_set_config,
+            llm_list_models,
+            llm_health,
+            llm_warm_up,
+            llm_pull_model,
+            llm_delete_model,
+            llm_count_tokens,
+            llm_plan_budget,
+            llm_fit_budget,
+            llm_cache_stats,
+            llm_cache_clear,
+            list_prompt_templates,
+            preview_prompt,
+            llm_agent_run,
             request_path_permission,
+            respond_path_permission,
+            list_pending_permission_requests,
             add_allowed_path,
-            get_allowed_paths
+            get_allowed_paths,
+            list_permission_grants,
+            revoke_permission_grant,
+            query_audit_log,
+            get_quota_status,
+            set_quota_limits,
+            resume_actor,
+            get_actor_capabilities,
+            set_actor_capabilities,
+            get_deny_overrides,
+            set_deny_overrides
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            // Stop watchers and their event tasks, commands, terminals and LLM requests before exiting
+            if let RunEvent::Exit = event {
+                tauri::async_runtime::block_on(app.state::<FileWatcherState>().shutdown());
+                app.state::<ProcessState>().shutdown();
+                app.state::<TerminalState>().shutdown();
+                app.state::<LlmState>().cancel_all();
+            }
+        });
 }
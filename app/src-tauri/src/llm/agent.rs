@@ -0,0 +1,252 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::future::Future;
+use std::pin::Pin;
+use tokio_util::sync::CancellationToken;
+
+use super::tools::{parse_tool_calls, ToolCall, ToolRegistry};
+use super::{ChatMessage, ChatRequest, ChatRole, LlmError, LlmProvider, StreamChunk, Usage};
+
+/// Event reporting each executed tool call
+pub const LLM_AGENT_STEP: &str = "llm-agent-step";
+
+/// Tool rounds allowed when a request doesn't say
+const DEFAULT_MAX_STEPS: u32 = 10;
+
+/// Upper bound on `max_steps`
+const MAX_STEPS: u32 = 50;
+
+/// Tool results longer than this are cut before going back to the model
+const MAX_TOOL_RESULT_CHARS: usize = 32 * 1024;
+
+/// How the model is told about tools
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ToolMode {
+    /// The server's tool-calling API
+    Native,
+    /// A system prompt asking for JSON tool calls in the text
+    Json,
+}
+
+/// Agent run request
+#[derive(Debug, Clone, Deserialize)]
+pub struct AgentRequest {
+    /// Model to use (empty for the role's model)
+    #[serde(default)]
+    pub model: String,
+    pub messages: Vec<ChatMessage>,
+    /// Model options passed through as-is (temperature, num_ctx, ...)
+    #[serde(default)]
+    pub options: Option<Value>,
+    /// Tool rounds before the run is stopped (default 10, at most 50)
+    #[serde(default)]
+    pub max_steps: Option<u32>,
+    /// Defaults to `native`, switching to `json` if the model has no tool support
+    #[serde(default)]
+    pub tool_mode: Option<ToolMode>,
+}
+
+/// Payload of `LLM_AGENT_STEP`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentStep {
+    pub request_id: String,
+    /// Tool round the call belongs to, from 1
+    pub step: u32,
+    pub call: ToolCall,
+    pub ok: bool,
+    /// Tool output, or the error (`{ code, message, ... }`) the tool failed with
+    pub result: Value,
+}
+
+/// Why an agent run ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AgentStop {
+    /// The model answered without calling a tool
+    Answered,
+    /// The model still wanted tools after `max_steps` rounds
+    MaxSteps,
+}
+
+/// Result of an agent run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentResult {
+    /// The model's last reply
+    pub content: String,
+    pub stop: AgentStop,
+    /// Tool rounds run
+    pub steps: u32,
+    /// The conversation including tool calls and results
+    pub messages: Vec<ChatMessage>,
+    /// Tokens used across all model calls
+    pub usage: Usage,
+}
+
+/// Future returned by `ToolExecutor::execute`
+pub type ToolFuture<'a> = Pin<Box<dyn Future<Output = Result<Value, Value>> + Send + 'a>>;
+
+/// Runs tool calls on behalf of the agent
+pub trait ToolExecutor: Send + Sync {
+    /// Run a call the registry has validated
+    ///
+    /// # Returns
+    /// * `Ok(Value)` - The tool's output
+    /// * `Err(Value)` - The error, shown to the model so it can adjust
+    fn execute<'a>(&'a self, call: &'a ToolCall) -> ToolFuture<'a>;
+}
+
+/// Let a model use tools until it answers or runs out of steps
+///
+/// # Arguments
+/// * `provider` - Provider the model runs on
+/// * `registry` - Tools offered to the model
+/// * `executor` - Runs the calls
+/// * `request` - Model, conversation and limits (`model` must be set)
+/// * `request_id` - Id reported in step events
+/// * `cancel` - Stops the run when cancelled
+/// * `on_chunk` - Called with text as it is generated (never with `done` chunks)
+/// * `on_step` - Called after each tool call
+///
+/// # Returns
+/// * `Ok(AgentResult)` - The run ended with an answer or at the step limit
+/// * `Err(LlmError)` - A model call failed or the run was cancelled
+#[allow(clippy::too_many_arguments)]
+pub async fn run_agent(
+    provider: &dyn LlmProvider,
+    registry: &ToolRegistry,
+    executor: &dyn ToolExecutor,
+    request: AgentRequest,
+    request_id: &str,
+    cancel: &CancellationToken,
+    mut on_chunk: impl FnMut(&StreamChunk) + Send,
+    mut on_step: impl FnMut(&AgentStep) + Send,
+) -> Result<AgentResult, LlmError> {
+    let max_steps = request.max_steps.unwrap_or(DEFAULT_MAX_STEPS).clamp(1, MAX_STEPS);
+    let mut mode = request.tool_mode.unwrap_or(ToolMode::Native);
+    let mut messages = request.messages;
+    let mut usage = Usage::default();
+    let mut steps = 0;
+
+    loop {
+        let chat = ChatRequest {
+            model: request.model.clone(),
+            messages: with_tool_prompt(mode, registry, &messages),
+            options: request.options.clone(),
+            tools: match mode {
+                ToolMode::Native => registry.definitions().to_vec(),
+                ToolMode::Json => Vec::new(),
+            },
+            format: None,
+        };
+        let mut forward = |chunk: &StreamChunk| {
+            if !chunk.done {
+                on_chunk(chunk);
+            }
+        };
+        let response = match provider.chat(&chat, cancel, &mut forward).await {
+            // Models without tool support are rejected up front; describe the tools instead
+            Err(LlmError::Status { status: 400, message })
+                if mode == ToolMode::Native && message.contains("tools") =>
+            {
+                mode = ToolMode::Json;
+                continue;
+            }
+            result => result?,
+        };
+        usage = usage + response.usage;
+
+        let calls = if response.tool_calls.is_empty() {
+            parse_tool_calls(&response.content)
+        } else {
+            response.tool_calls
+        };
+        if calls.is_empty() || steps == max_steps {
+            let stop = if calls.is_empty() {
+                AgentStop::Answered
+            } else {
+                AgentStop::MaxSteps
+            };
+            messages.push(ChatMessage::new(ChatRole::Assistant, response.content.clone()));
+            return Ok(AgentResult {
+                content: response.content,
+                stop,
+                steps,
+                messages,
+                usage,
+            });
+        }
+
+        steps += 1;
+        messages.push(ChatMessage {
+            role: ChatRole::Assistant,
+            content: response.content,
+            // In JSON mode the calls are part of the text already
+            tool_calls: if mode == ToolMode::Native {
+                calls.clone()
+            } else {
+                Vec::new()
+            },
+            tool_call_id: None,
+        });
+
+        for call in calls {
+            if cancel.is_cancelled() {
+                return Err(LlmError::Cancelled);
+            }
+            let result = match registry.validate(&call) {
+                Ok(()) => executor.execute(&call).await,
+                Err(message) => Err(json!({ "code": "InvalidInput", "message": message })),
+            };
+            let (ok, result) = match result {
+                Ok(value) => (true, value),
+                Err(error) => (false, error),
+            };
+
+            let content = truncate(&result.to_string());
+            messages.push(match mode {
+                ToolMode::Native => ChatMessage {
+                    tool_call_id: Some(call.id.clone()),
+                    ..ChatMessage::new(ChatRole::Tool, content)
+                },
+                ToolMode::Json => ChatMessage::new(
+                    ChatRole::User,
+                    format!("Result of {}: {}", call.name, content),
+                ),
+            });
+            on_step(&AgentStep {
+                request_id: request_id.to_string(),
+                step: steps,
+                call,
+                ok,
+                result,
+            });
+        }
+    }
+}
+
+/// The conversation as sent to the model, with the tool prompt in JSON mode
+fn with_tool_prompt(
+    mode: ToolMode,
+    registry: &ToolRegistry,
+    messages: &[ChatMessage],
+) -> Vec<ChatMessage> {
+    let mut sent = Vec::with_capacity(messages.len() + 1);
+    if mode == ToolMode::Json {
+        sent.push(ChatMessage::new(ChatRole::System, registry.fallback_prompt()));
+    }
+    sent.extend_from_slice(messages);
+    sent
+}
+
+/// Cut a tool result to `MAX_TOOL_RESULT_CHARS`, saying so
+fn truncate(text: &str) -> String {
+    match text.char_indices().nth(MAX_TOOL_RESULT_CHARS) {
+        Some((cut, _)) => format!(
+            "{}\n[truncated: {} more characters]",
+            &text[..cut],
+            text[cut..].chars().count()
+        ),
+        None => text.to_string(),
+    }
+}
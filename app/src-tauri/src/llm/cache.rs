@@ -0,0 +1,934 @@
+// Response cache. Completed responses are stored as JSON files named by a
+// SHA-256 of the provider and the whole request (model, parameters, prompt).
+// Requests can name files the answer depends on; entries are dropped when the
+// watcher reports one changed, and checked against the files' metadata on use.
+// Identical requests running at the same time share one model call.
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::UNIX_EPOCH;
+use tokio::sync::{broadcast, watch};
+use tokio_util::sync::CancellationToken;
+
+use crate::file_watcher::{FileWatchEvent, FileWatchEventKind};
+
+use super::{
+    ChatRequest, GenerateRequest, LlmError, LlmFuture, LlmProvider, LlmResponse, OnChunk,
+    StreamChunk,
+};
+
+/// Directory of cached responses inside the app cache directory
+pub const CACHE_DIR: &str = "llm-cache";
+
+/// Default size limit of the cache on disk (256MB)
+const DEFAULT_MAX_BYTES: u64 = 256 * 1024 * 1024;
+
+/// Default time entries stay valid (one week)
+const DEFAULT_TTL_SECS: u64 = 7 * 24 * 60 * 60;
+
+/// Chunks buffered for callers sharing a running request
+const FLIGHT_CHANNEL_CAPACITY: usize = 1024;
+
+/// Cache limits
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheConfig {
+    /// Size limit on disk in bytes; the least recently used entries go first
+    pub max_bytes: u64,
+    /// How long entries stay valid, unless a request sets its own
+    pub ttl_secs: u64,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            max_bytes: DEFAULT_MAX_BYTES,
+            ttl_secs: DEFAULT_TTL_SECS,
+        }
+    }
+}
+
+/// Caching of one request (responses are only cached when this is given)
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct CacheOptions {
+    /// Files the answer depends on; the entry is dropped when one changes
+    #[serde(default)]
+    pub files: Vec<String>,
+    /// How long the entry stays valid (the configured TTL if unset)
+    #[serde(default)]
+    pub ttl_secs: Option<u64>,
+    /// Skip the lookup and replace the entry with a fresh response
+    #[serde(default)]
+    pub refresh: bool,
+}
+
+/// Cache statistics
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheStats {
+    pub entries: usize,
+    pub bytes: u64,
+    pub max_bytes: u64,
+    pub ttl_secs: u64,
+    /// Requests answered from the cache since the app started
+    pub hits: u64,
+    /// Cached requests that had to go to the model
+    pub misses: u64,
+    /// Requests that shared another identical request's model call
+    pub coalesced: u64,
+    /// Entries dropped because a file they depend on changed
+    pub invalidated: u64,
+    /// Responses that couldn't be written to the cache
+    pub write_errors: u64,
+    /// Why the last write failed
+    pub last_write_error: Option<String>,
+}
+
+/// A model call that can be cached
+#[derive(Debug, Clone, Copy)]
+pub enum LlmCall<'r> {
+    Generate(&'r GenerateRequest),
+    Chat(&'r ChatRequest),
+}
+
+impl LlmCall<'_> {
+    /// Cache key: SHA-256 over the provider, the kind of call and the whole request
+    pub fn key(&self, provider_id: &str) -> String {
+        let request = match self {
+            LlmCall::Generate(request) => json!({ "generate": request }),
+            LlmCall::Chat(request) => json!({ "chat": request }),
+        };
+        let identity = json!({ "provider": provider_id, "request": request });
+        format!("{:x}", Sha256::digest(identity.to_string().as_bytes()))
+    }
+
+    /// Send the request to `provider`
+    pub fn run<'a>(
+        &'a self,
+        provider: &'a dyn LlmProvider,
+        cancel: &'a CancellationToken,
+        on_chunk: &'a mut OnChunk<'a>,
+    ) -> LlmFuture<'a, LlmResponse> {
+        match self {
+            LlmCall::Generate(request) => provider.generate(request, cancel, on_chunk),
+            LlmCall::Chat(request) => provider.chat(request, cancel, on_chunk),
+        }
+    }
+}
+
+/// State of a file when a response was cached
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Dependency {
+    path: PathBuf,
+    /// `None` if the file didn't exist
+    size: Option<u64>,
+    /// Milliseconds since the Unix epoch
+    modified: Option<i64>,
+}
+
+impl Dependency {
+    fn snapshot(path: &Path) -> Self {
+        let metadata = std::fs::metadata(path).ok();
+        Self {
+            path: normalize(path),
+            size: metadata.as_ref().map(|metadata| metadata.len()),
+            modified: metadata
+                .and_then(|metadata| metadata.modified().ok())
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map(|duration| duration.as_millis() as i64),
+        }
+    }
+
+    fn is_current(&self) -> bool {
+        Self::snapshot(&self.path) == *self
+    }
+}
+
+/// A cache file
+#[derive(Debug, Serialize, Deserialize)]
+struct CacheRecord {
+    /// Milliseconds since the Unix epoch
+    created_at: i64,
+    expires_at: i64,
+    dependencies: Vec<Dependency>,
+    response: LlmResponse,
+}
+
+/// What the cache keeps in memory about an entry
+#[derive(Debug)]
+struct IndexEntry {
+    bytes: u64,
+    expires_at: i64,
+    last_used: i64,
+    dependencies: Vec<PathBuf>,
+}
+
+/// How a running request ended, for the callers sharing it
+#[derive(Debug, Clone)]
+enum FlightState {
+    Running,
+    Done(LlmResponse),
+    /// Failed or cancelled; each caller then makes its own request
+    Failed,
+}
+
+/// A model call shared by identical requests
+struct Flight {
+    /// Text generated so far, for callers joining late
+    content: Mutex<String>,
+    chunks: broadcast::Sender<StreamChunk>,
+    state: watch::Sender<FlightState>,
+}
+
+/// Ends a flight when the leading request finishes, fails or is dropped
+struct FlightGuard<'a> {
+    cache: &'a LlmCache,
+    key: &'a str,
+    flight: Arc<Flight>,
+}
+
+impl Drop for FlightGuard<'_> {
+    fn drop(&mut self) {
+        self.flight.state.send_if_modified(|state| match state {
+            FlightState::Running => {
+                *state = FlightState::Failed;
+                true
+            }
+            _ => false,
+        });
+        let mut flights = self.cache.flights.lock().unwrap();
+        if flights.get(self.key).is_some_and(|flight| Arc::ptr_eq(flight, &self.flight)) {
+            flights.remove(self.key);
+        }
+    }
+}
+
+/// On-disk response cache and the requests in flight (thread-safe)
+pub struct LlmCache {
+    dir: PathBuf,
+    config: Mutex<CacheConfig>,
+    index: Mutex<HashMap<String, IndexEntry>>,
+    flights: Mutex<HashMap<String, Arc<Flight>>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    coalesced: AtomicU64,
+    invalidated: AtomicU64,
+    write_errors: AtomicU64,
+    last_write_error: Mutex<Option<String>>,
+}
+
+impl LlmCache {
+    /// Open the cache in `dir`, dropping expired and unreadable entries
+    pub fn open(dir: PathBuf, config: CacheConfig) -> Self {
+        let mut index = HashMap::new();
+        let now = now_ms();
+        for entry in std::fs::read_dir(&dir).into_iter().flatten().flatten() {
+            let path = entry.path();
+            let Some(key) = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_suffix(".json"))
+            else {
+                continue;
+            };
+            let record = std::fs::read(&path)
+                .ok()
+                .and_then(|content| serde_json::from_slice::<CacheRecord>(&content).ok());
+            match record {
+                Some(record) if record.expires_at > now => {
+                    index.insert(
+                        key.to_string(),
+                        IndexEntry {
+                            bytes: entry.metadata().map(|metadata| metadata.len()).unwrap_or(0),
+                            expires_at: record.expires_at,
+                            last_used: record.created_at,
+                            dependencies: paths_of(&record.dependencies),
+                        },
+                    );
+                }
+                _ => {
+                    let _ = std::fs::remove_file(&path);
+                }
+            }
+        }
+
+        let cache = Self {
+            dir,
+            config: Mutex::new(config),
+            index: Mutex::new(index),
+            flights: Mutex::new(HashMap::new()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            coalesced: AtomicU64::new(0),
+            invalidated: AtomicU64::new(0),
+            write_errors: AtomicU64::new(0),
+            last_write_error: Mutex::new(None),
+        };
+        cache.evict();
+        cache
+    }
+
+    /// Change the limits, evicting entries over the new size limit
+    pub fn set_config(&self, config: CacheConfig) {
+        *self.config.lock().unwrap() = config;
+        self.evict();
+    }
+
+    /// Run a model call, answering from the cache or a running identical call if possible
+    ///
+    /// Without `options` the response isn't cached, but the call is still
+    /// shared with identical calls running at the same time. Answers that don't
+    /// come from the model are reported to `on_chunk` as one text chunk and a
+    /// final chunk, like a very fast stream.
+    ///
+    /// # Arguments
+    /// * `provider_id` - Id of the provider (part of the key)
+    /// * `provider` - Provider to call on a miss
+    /// * `call` - The request
+    /// * `options` - Whether and how to cache the response
+    /// * `cancel` - Stops this caller's request when cancelled
+    /// * `on_chunk` - Called with each chunk
+    pub async fn complete(
+        &self,
+        provider_id: &str,
+        provider: &dyn LlmProvider,
+        call: LlmCall<'_>,
+        options: Option<&CacheOptions>,
+        cancel: &CancellationToken,
+        on_chunk: &mut (dyn FnMut(&StreamChunk) + Send),
+    ) -> Result<LlmResponse, LlmError> {
+        let key = call.key(provider_id);
+        if options.is_some_and(|options| !options.refresh) {
+            if let Some(response) = self.lookup(&key) {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                replay(&response, on_chunk);
+                return Ok(response);
+            }
+        }
+
+        loop {
+            let (flight, leading) = {
+                let mut flights = self.flights.lock().unwrap();
+                match flights.get(&key) {
+                    Some(flight) => (flight.clone(), false),
+                    None => {
+                        let flight = Arc::new(Flight {
+                            content: Mutex::new(String::new()),
+                            chunks: broadcast::channel(FLIGHT_CHANNEL_CAPACITY).0,
+                            state: watch::channel(FlightState::Running).0,
+                        });
+                        flights.insert(key.clone(), flight.clone());
+                        (flight, true)
+                    }
+                }
+            };
+
+            if !leading {
+                if let Some(response) = follow(&flight, cancel, on_chunk).await? {
+                    self.coalesced.fetch_add(1, Ordering::Relaxed);
+                    return Ok(response);
+                }
+                // The shared call failed; try again (possibly leading this time)
+                continue;
+            }
+
+            let guard = FlightGuard {
+                cache: self,
+                key: &key,
+                flight,
+            };
+            // Taken before the call, so changes made while it runs make the entry stale
+            let dependencies: Vec<Dependency> = options
+                .map(|options| {
+                    options.files.iter().map(|path| Dependency::snapshot(Path::new(path))).collect()
+                })
+                .unwrap_or_default();
+            let mut publish = |chunk: &StreamChunk| {
+                if !chunk.done {
+                    let mut content = guard.flight.content.lock().unwrap();
+                    if chunk.restart {
+                        content.clear();
+                    }
+                    content.push_str(&chunk.content);
+                    let _ = guard.flight.chunks.send(chunk.clone());
+                }
+                on_chunk(chunk);
+            };
+            let result = call.run(provider, cancel, &mut publish).await;
+
+            if let Ok(response) = &result {
+                if let Some(options) = options {
+                    self.misses.fetch_add(1, Ordering::Relaxed);
+                    // The response is still good; the failure shows in the stats
+                    if let Err(e) = self.store(&key, options, dependencies, response) {
+                        self.write_errors.fetch_add(1, Ordering::Relaxed);
+                        *self.last_write_error.lock().unwrap() = Some(e.to_string());
+                    }
+                }
+                guard.flight.state.send_replace(FlightState::Done(response.clone()));
+            }
+            return result;
+        }
+    }
+
+    /// Drop entries depending on `path` or anything under it
+    ///
+    /// # Returns
+    /// The number of entries dropped
+    pub fn invalidate(&self, path: &Path) -> usize {
+        let path = normalize(path);
+        let removed = self.remove_where(|entry| {
+            entry
+                .dependencies
+                .iter()
+                .any(|dependency| dependency.starts_with(&path))
+        });
+        self.invalidated.fetch_add(removed as u64, Ordering::Relaxed);
+        removed
+    }
+
+    /// Drop entries a watcher event makes stale
+    ///
+    /// # Returns
+    /// The number of entries dropped
+    pub fn invalidate_changed(&self, event: &FileWatchEvent) -> usize {
+        // A directory's own timestamp changing says nothing about its files
+        if event.kind == FileWatchEventKind::Modified && event.is_dir == Some(true) {
+            return 0;
+        }
+        let removed = self.invalidate(Path::new(&event.path));
+        match &event.old_path {
+            Some(old_path) => removed + self.invalidate(Path::new(old_path)),
+            None => removed,
+        }
+    }
+
+    /// Drop every entry that depends on a file (when changes may have been missed)
+    pub fn invalidate_dependent(&self) -> usize {
+        let removed = self.remove_where(|entry| !entry.dependencies.is_empty());
+        self.invalidated.fetch_add(removed as u64, Ordering::Relaxed);
+        removed
+    }
+
+    /// Drop every entry
+    pub fn clear(&self) -> usize {
+        self.remove_where(|_| true)
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let config = *self.config.lock().unwrap();
+        let index = self.index.lock().unwrap();
+        CacheStats {
+            entries: index.len(),
+            bytes: index.values().map(|entry| entry.bytes).sum(),
+            max_bytes: config.max_bytes,
+            ttl_secs: config.ttl_secs,
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            coalesced: self.coalesced.load(Ordering::Relaxed),
+            invalidated: self.invalidated.load(Ordering::Relaxed),
+            write_errors: self.write_errors.load(Ordering::Relaxed),
+            last_write_error: self.last_write_error.lock().unwrap().clone(),
+        }
+    }
+
+    fn path_of(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+
+    /// A valid cached response (expired and stale entries are dropped)
+    fn lookup(&self, key: &str) -> Option<LlmResponse> {
+        let now = now_ms();
+        {
+            let mut index = self.index.lock().unwrap();
+            let entry = index.get_mut(key)?;
+            if entry.expires_at > now {
+                entry.last_used = now;
+            }
+        }
+
+        let record = std::fs::read(self.path_of(key))
+            .ok()
+            .and_then(|content| serde_json::from_slice::<CacheRecord>(&content).ok());
+        match record {
+            Some(record)
+                if record.expires_at > now
+                    && record.dependencies.iter().all(Dependency::is_current) =>
+            {
+                Some(LlmResponse {
+                    cached: true,
+                    ..record.response
+                })
+            }
+            _ => {
+                self.remove(key);
+                None
+            }
+        }
+    }
+
+    fn store(
+        &self,
+        key: &str,
+        options: &CacheOptions,
+        dependencies: Vec<Dependency>,
+        response: &LlmResponse,
+    ) -> std::io::Result<()> {
+        let ttl_secs = options.ttl_secs.unwrap_or(self.config.lock().unwrap().ttl_secs);
+        let now = now_ms();
+        let ttl_ms = i64::try_from(ttl_secs.saturating_mul(1000)).unwrap_or(i64::MAX);
+        let expires_at = now.saturating_add(ttl_ms);
+        let record = CacheRecord {
+            created_at: now,
+            expires_at,
+            dependencies,
+            response: response.clone(),
+        };
+        let content = serde_json::to_vec(&record)?;
+
+        std::fs::create_dir_all(&self.dir)?;
+        let path = self.path_of(key);
+        let temp_path = path.with_extension("json.tmp");
+        std::fs::write(&temp_path, &content)?;
+        std::fs::rename(&temp_path, &path)?;
+
+        self.index.lock().unwrap().insert(
+            key.to_string(),
+            IndexEntry {
+                bytes: content.len() as u64,
+                expires_at,
+                last_used: now,
+                dependencies: paths_of(&record.dependencies),
+            },
+        );
+        self.evict();
+        Ok(())
+    }
+
+    /// Drop expired entries, then the least recently used until under the size limit
+    fn evict(&self) {
+        let max_bytes = self.config.lock().unwrap().max_bytes;
+        let now = now_ms();
+        let mut index = self.index.lock().unwrap();
+        let mut doomed: Vec<String> = index
+            .iter()
+            .filter(|(_, entry)| entry.expires_at <= now)
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        let mut live: Vec<(&String, &IndexEntry)> =
+            index.iter().filter(|(_, entry)| entry.expires_at > now).collect();
+        let mut bytes: u64 = live.iter().map(|(_, entry)| entry.bytes).sum();
+        live.sort_by_key(|(_, entry)| entry.last_used);
+        for (key, entry) in live {
+            if bytes <= max_bytes {
+                break;
+            }
+            bytes -= entry.bytes;
+            doomed.push(key.clone());
+        }
+
+        for key in doomed {
+            index.remove(&key);
+            let _ = std::fs::remove_file(self.path_of(&key));
+        }
+    }
+
+    fn remove(&self, key: &str) {
+        self.index.lock().unwrap().remove(key);
+        let _ = std::fs::remove_file(self.path_of(key));
+    }
+
+    fn remove_where(&self, predicate: impl Fn(&IndexEntry) -> bool) -> usize {
+        let mut index = self.index.lock().unwrap();
+        let doomed: Vec<String> = index
+            .iter()
+            .filter(|(_, entry)| predicate(entry))
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in &doomed {
+            index.remove(key);
+            let _ = std::fs::remove_file(self.path_of(key));
+        }
+        doomed.len()
+    }
+}
+
+/// Share a running call's output
+///
+/// # Returns
+/// * `Ok(Some(response))` - The call finished
+/// * `Ok(None)` - The call failed; the caller should make its own
+/// * `Err(LlmError::Cancelled)` - This caller was cancelled
+async fn follow(
+    flight: &Flight,
+    cancel: &CancellationToken,
+    on_chunk: &mut (dyn FnMut(&StreamChunk) + Send),
+) -> Result<Option<LlmResponse>, LlmError> {
+    // Subscribe under the lock so no chunk is both in the snapshot and received
+    let (snapshot, mut chunks, mut state) = {
+        let content = flight.content.lock().unwrap();
+        (content.clone(), flight.chunks.subscribe(), flight.state.subscribe())
+    };
+    if !snapshot.is_empty() {
+        on_chunk(&StreamChunk {
+            content: snapshot,
+            ..StreamChunk::default()
+        });
+    }
+
+    loop {
+        let finished = {
+            let state = state.borrow_and_update();
+            match &*state {
+                FlightState::Running => None,
+                FlightState::Done(response) => Some(Some(response.clone())),
+                FlightState::Failed => Some(None),
+            }
+        };
+        if let Some(response) = finished {
+            // Every chunk was sent before the state changed
+            while let Ok(chunk) = chunks.try_recv() {
+                on_chunk(&chunk);
+            }
+            if let Some(response) = &response {
+                on_chunk(&done_chunk(response));
+            }
+            return Ok(response);
+        }
+
+        tokio::select! {
+            _ = cancel.cancelled() => return Err(LlmError::Cancelled),
+            chunk = chunks.recv() => {
+                // A lagging caller misses text, but still gets the full response
+                if let Ok(chunk) = chunk {
+                    on_chunk(&chunk);
+                }
+            }
+            changed = state.changed() => {
+                if changed.is_err() {
+                    return Ok(None);
+                }
+            }
+        }
+    }
+}
+
+/// Report a complete response as a text chunk and a final chunk
+fn replay(response: &LlmResponse, on_chunk: &mut (dyn FnMut(&StreamChunk) + Send)) {
+    if !response.content.is_empty() {
+        on_chunk(&StreamChunk {
+            content: response.content.clone(),
+            ..StreamChunk::default()
+        });
+    }
+    on_chunk(&done_chunk(response));
+}
+
+fn done_chunk(response: &LlmResponse) -> StreamChunk {
+    StreamChunk {
+        content: String::new(),
+        done: true,
+        finish_reason: response.finish_reason,
+        usage: Some(response.usage),
+        restart: false,
+    }
+}
+
+fn paths_of(dependencies: &[Dependency]) -> Vec<PathBuf> {
+    dependencies.iter().map(|dependency| dependency.path.clone()).collect()
+}
+
+/// Comparison form of a path (parent canonicalized, so it works for deleted files too)
+fn normalize(path: &Path) -> PathBuf {
+    if let Ok(path) = path.canonicalize() {
+        return path;
+    }
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => parent
+            .canonicalize()
+            .map(|parent| parent.join(name))
+            .unwrap_or_else(|_| path.to_path_buf()),
+        _ => path.to_path_buf(),
+    }
+}
+
+fn now_ms() -> i64 {
+    Utc::now().timestamp_millis()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::ollama::OllamaProvider;
+    use crate::llm::test_server::{Reply, TestServer, NDJSON};
+    use std::time::Duration;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("nexus-cache-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn request(prompt: &str) -> GenerateRequest {
+        GenerateRequest {
+            model: "test-model".to_string(),
+            prompt: prompt.to_string(),
+            system: None,
+            options: None,
+        }
+    }
+
+    /// A server answering every request with `Hello` in two pieces
+    async fn server(pause: Duration) -> TestServer {
+        TestServer::start(move |_, _| {
+            Reply::ok(NDJSON)
+                .send("{\"response\":\"Hel\"}\n")
+                .pause(pause)
+                .send("{\"response\":\"lo\"}\n")
+                .send("{\"response\":\"\",\"done\":true,\"done_reason\":\"stop\"}\n")
+        })
+        .await
+    }
+
+    fn options(files: &[&Path]) -> CacheOptions {
+        CacheOptions {
+            files: files.iter().map(|path| path.to_string_lossy().to_string()).collect(),
+            ..CacheOptions::default()
+        }
+    }
+
+    async fn complete(
+        cache: &LlmCache,
+        server: &TestServer,
+        prompt: &str,
+        options: Option<&CacheOptions>,
+    ) -> (LlmResponse, Vec<StreamChunk>) {
+        let provider = OllamaProvider::new(reqwest::Client::new(), &server.url);
+        let request = request(prompt);
+        let mut chunks = Vec::new();
+        let mut collect = |chunk: &StreamChunk| chunks.push(chunk.clone());
+        let call = LlmCall::Generate(&request);
+        let cancel = CancellationToken::new();
+        let response = cache
+            .complete("local", &provider, call, options, &cancel, &mut collect)
+            .await
+            .unwrap();
+        (response, chunks)
+    }
+
+    fn event(kind: FileWatchEventKind, path: &Path, is_dir: Option<bool>) -> FileWatchEvent {
+        FileWatchEvent {
+            kind,
+            path: path.to_string_lossy().to_string(),
+            old_path: None,
+            is_dir,
+            size: None,
+            modified: None,
+            content_hash: None,
+            watch_id: 1,
+            sequence: 0,
+            self_originated: false,
+        }
+    }
+
+    #[test]
+    fn key_covers_provider_kind_and_whole_request() {
+        let generate = request("Hi");
+        let key = LlmCall::Generate(&generate).key("local");
+        assert_eq!(key.len(), 64);
+        assert_eq!(key, LlmCall::Generate(&request("Hi")).key("local"));
+
+        assert_ne!(key, LlmCall::Generate(&generate).key("remote"));
+        assert_ne!(key, LlmCall::Generate(&request("Hi!")).key("local"));
+        let mut with_options = request("Hi");
+        with_options.options = Some(json!({ "temperature": 0 }));
+        assert_ne!(key, LlmCall::Generate(&with_options).key("local"));
+        let mut with_system = request("Hi");
+        with_system.system = Some(String::new());
+        assert_ne!(key, LlmCall::Generate(&with_system).key("local"));
+        // The same conversation asked as a chat is a different call
+        assert_ne!(key, LlmCall::Chat(&generate.to_chat()).key("local"));
+    }
+
+    #[tokio::test]
+    async fn repeated_requests_are_answered_from_disk() {
+        let dir = temp_dir("hit");
+        let server = server(Duration::ZERO).await;
+        let cache = LlmCache::open(dir.clone(), CacheConfig::default());
+        let options = options(&[]);
+
+        let (first, _) = complete(&cache, &server, "Hi", Some(&options)).await;
+        assert!(!first.cached);
+        let (second, chunks) = complete(&cache, &server, "Hi", Some(&options)).await;
+        assert!(second.cached);
+        assert_eq!(second.content, "Hello");
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].content, "Hello");
+        assert!(chunks[1].done);
+        assert_eq!(server.hits(), 1);
+
+        // Entries outlive the process; uncached and refreshed calls go to the model
+        let cache = LlmCache::open(dir.clone(), CacheConfig::default());
+        assert!(complete(&cache, &server, "Hi", Some(&options)).await.0.cached);
+        assert!(!complete(&cache, &server, "Hi", None).await.0.cached);
+        let refresh = CacheOptions {
+            refresh: true,
+            ..CacheOptions::default()
+        };
+        assert!(!complete(&cache, &server, "Hi", Some(&refresh)).await.0.cached);
+        assert_eq!(server.hits(), 3);
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.entries), (1, 1, 1));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn entries_expire_after_their_ttl() {
+        let dir = temp_dir("ttl");
+        let server = server(Duration::ZERO).await;
+        let config = CacheConfig {
+            ttl_secs: 0,
+            ..CacheConfig::default()
+        };
+        let cache = LlmCache::open(dir.clone(), config);
+
+        complete(&cache, &server, "Hi", Some(&options(&[]))).await;
+        assert!(!complete(&cache, &server, "Hi", Some(&options(&[]))).await.0.cached);
+        assert_eq!(cache.stats().entries, 0);
+
+        // A request's own TTL wins over the configured one
+        let lasting = CacheOptions {
+            ttl_secs: Some(60),
+            ..CacheOptions::default()
+        };
+        complete(&cache, &server, "Hi", Some(&lasting)).await;
+        assert!(complete(&cache, &server, "Hi", Some(&lasting)).await.0.cached);
+        assert_eq!(server.hits(), 3);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn least_recently_used_entries_go_over_the_size_limit() {
+        let dir = temp_dir("evict");
+        let server = server(Duration::ZERO).await;
+        let cache = LlmCache::open(dir.clone(), CacheConfig::default());
+        let options = options(&[]);
+        let pause = || tokio::time::sleep(Duration::from_millis(5));
+
+        complete(&cache, &server, "A", Some(&options)).await;
+        pause().await;
+        complete(&cache, &server, "B", Some(&options)).await;
+        pause().await;
+        // Room for exactly these two
+        let config = CacheConfig {
+            max_bytes: cache.stats().bytes,
+            ..CacheConfig::default()
+        };
+        cache.set_config(config);
+        assert_eq!(cache.stats().entries, 2);
+
+        // Using A makes B the least recently used
+        assert!(complete(&cache, &server, "A", Some(&options)).await.0.cached);
+        pause().await;
+        complete(&cache, &server, "C", Some(&options)).await;
+        let stats = cache.stats();
+        assert_eq!(stats.entries, 2);
+        assert!(stats.bytes <= stats.max_bytes);
+        assert!(complete(&cache, &server, "A", Some(&options)).await.0.cached);
+        assert!(complete(&cache, &server, "C", Some(&options)).await.0.cached);
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn identical_running_requests_share_one_call() {
+        let dir = temp_dir("coalesce");
+        let server = server(Duration::from_millis(200)).await;
+        let cache = LlmCache::open(dir.clone(), CacheConfig::default());
+
+        let ((leader, _), (follower, chunks)) = tokio::join!(
+            complete(&cache, &server, "Hi", None),
+            complete(&cache, &server, "Hi", None),
+        );
+        assert_eq!(leader.content, "Hello");
+        assert_eq!(follower.content, "Hello");
+        let text: String = chunks.iter().map(|chunk| chunk.content.as_str()).collect();
+        assert_eq!(text, "Hello");
+        assert!(chunks.last().unwrap().done);
+        assert_eq!(server.hits(), 1);
+        assert_eq!(cache.stats().coalesced, 1);
+
+        // Once it's over, the next identical request makes its own call
+        complete(&cache, &server, "Hi", None).await;
+        assert_eq!(server.hits(), 2);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn watcher_events_drop_dependent_entries() {
+        let dir = temp_dir("watch");
+        let project = dir.join("project");
+        std::fs::create_dir_all(project.join("src")).unwrap();
+        let file = project.join("src/main.rs");
+        std::fs::write(&file, "fn main() {}").unwrap();
+        let server = server(Duration::ZERO).await;
+        let cache = LlmCache::open(dir.join("cache"), CacheConfig::default());
+        let depends = options(&[&file]);
+        let cached = |response: (LlmResponse, Vec<StreamChunk>)| response.0.cached;
+
+        complete(&cache, &server, "Hi", Some(&depends)).await;
+        complete(&cache, &server, "Independent", Some(&options(&[]))).await;
+        let modified_dir = event(FileWatchEventKind::Modified, &project.join("src"), Some(true));
+        assert_eq!(cache.invalidate_changed(&modified_dir), 0);
+        let modified = event(FileWatchEventKind::Modified, &file, Some(false));
+        assert_eq!(cache.invalidate_changed(&modified), 1);
+        assert!(!cached(complete(&cache, &server, "Hi", Some(&depends)).await));
+
+        // The old side of a rename, and anything under a deleted directory
+        let mut renamed = event(FileWatchEventKind::Renamed, &project.join("lib.rs"), Some(false));
+        renamed.old_path = Some(file.to_string_lossy().to_string());
+        assert_eq!(cache.invalidate_changed(&renamed), 1);
+        complete(&cache, &server, "Hi", Some(&depends)).await;
+        let deleted = event(FileWatchEventKind::Deleted, &project, None);
+        assert_eq!(cache.invalidate_changed(&deleted), 1);
+
+        // A change the watcher missed is caught on use
+        complete(&cache, &server, "Hi", Some(&depends)).await;
+        std::fs::write(&file, "fn main() { run() }").unwrap();
+        assert!(!cached(complete(&cache, &server, "Hi", Some(&depends)).await));
+
+        let independent = complete(&cache, &server, "Independent", Some(&options(&[]))).await;
+        assert!(cached(independent));
+        assert_eq!(cache.stats().invalidated, 3);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn failed_writes_are_counted_not_returned() {
+        let dir = temp_dir("unwritable");
+        // A file where the cache directory should be
+        let path = dir.join("cache");
+        std::fs::write(&path, "").unwrap();
+        let server = server(Duration::ZERO).await;
+        let cache = LlmCache::open(path, CacheConfig::default());
+
+        let (response, _) = complete(&cache, &server, "Hi", Some(&options(&[]))).await;
+        assert_eq!(response.content, "Hello");
+        let stats = cache.stats();
+        assert_eq!((stats.entries, stats.write_errors), (0, 1));
+        assert!(stats.last_write_error.is_some());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
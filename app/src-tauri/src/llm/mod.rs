@@ -0,0 +1,1139 @@
+// LLM backends. Clients take their base URL explicitly and report chunks via a
+// callback, so they work without Tauri (e.g. against a local mock server);
+// `LlmState` holds the configuration and the in-flight requests for the commands.
+
+pub mod agent;
+pub mod cache;
+pub mod ollama;
+pub mod openai;
+pub mod prompts;
+pub mod queue;
+pub mod resilience;
+pub mod structured;
+pub mod tokens;
+pub mod tools;
+
+#[cfg(test)]
+mod test_server;
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+use tokio_util::sync::CancellationToken;
+
+use cache::CacheConfig;
+use ollama::OllamaProvider;
+use openai::OpenAiProvider;
+use prompts::{TemplateDirs, PROMPTS_DIR};
+use queue::{LlmQueue, Priority, ProviderQueueStatus, DEFAULT_MAX_CONCURRENT};
+use resilience::{CircuitBreaker, ProviderStatus, ResilienceConfig, ResilientProvider};
+use structured::OutputIssue;
+use tools::{ToolCall, ToolDefinition};
+
+/// Event carrying generated text (and a final chunk with `done` set)
+pub const LLM_CHUNK: &str = "llm-chunk";
+
+/// Event carrying model download progress
+pub const LLM_PULL_PROGRESS: &str = "llm-pull-progress";
+
+/// File name of the LLM configuration (inside the app config directory)
+pub const LLM_CONFIG_FILE: &str = "llm.json";
+
+/// Where Ollama listens by default
+pub const DEFAULT_OLLAMA_URL: &str = "http://localhost:11434";
+
+/// Id of the provider configured out of the box
+const DEFAULT_PROVIDER: &str = "ollama";
+
+/// Provider status updates buffered for the event forwarder
+const STATUS_CHANNEL_CAPACITY: usize = 64;
+
+/// How long a health probe may take
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Error from an LLM request
+#[derive(Debug)]
+pub enum LlmError {
+    /// The server could not be reached
+    Connection(String),
+    /// The server answered with an error status
+    Status { status: u16, message: String },
+    /// The model doesn't exist on the server
+    ModelNotFound(String),
+    /// The server sent something that isn't a valid response
+    InvalidResponse(String),
+    InvalidInput(String),
+    PermissionDenied(String),
+    /// Reading or writing the configuration failed
+    IoError(std::io::Error),
+    /// The provider's API has no such operation
+    Unsupported(String),
+    Cancelled,
+    /// The server stopped answering within the configured timeout
+    Timeout(String),
+    /// The provider's circuit breaker is open after repeated failures
+    ServerDown {
+        provider: String,
+        /// Time until a trial call is let through
+        retry_in_ms: Option<u64>,
+        last_error: Option<String>,
+    },
+    /// Structured output still didn't match its schema after the repair attempts
+    InvalidOutput {
+        attempts: u32,
+        /// Problems with the last reply
+        issues: Vec<OutputIssue>,
+    },
+}
+
+impl LlmError {
+    /// Stable code the frontend branches on
+    pub fn code(&self) -> &'static str {
+        match self {
+            LlmError::Connection(_) => "Connection",
+            LlmError::Status { .. } => "ServerError",
+            LlmError::ModelNotFound(_) => "ModelNotFound",
+            LlmError::InvalidResponse(_) => "InvalidResponse",
+            LlmError::InvalidInput(_) => "InvalidInput",
+            LlmError::PermissionDenied(_) => "PermissionDenied",
+            LlmError::IoError(_) => "Io",
+            LlmError::Unsupported(_) => "Unsupported",
+            LlmError::Cancelled => "Cancelled",
+            LlmError::Timeout(_) => "Timeout",
+            LlmError::ServerDown { .. } => "ServerDown",
+            LlmError::InvalidOutput { .. } => "InvalidOutput",
+        }
+    }
+
+    /// Whether repeating the same request unchanged may succeed
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            LlmError::Connection(_) | LlmError::Timeout(_) | LlmError::ServerDown { .. } => true,
+            LlmError::Status { status, .. } => *status == 429 || *status >= 500,
+            _ => false,
+        }
+    }
+}
+
+impl std::fmt::Display for LlmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LlmError::Connection(msg) => write!(f, "Could not reach the LLM server: {}", msg),
+            LlmError::Status { status, message } => {
+                write!(f, "LLM server error ({}): {}", status, message)
+            }
+            LlmError::ModelNotFound(model) => write!(f, "Model not found: {}", model),
+            LlmError::InvalidResponse(msg) => write!(f, "Invalid LLM response: {}", msg),
+            LlmError::InvalidInput(msg) => write!(f, "Invalid input: {}", msg),
+            LlmError::PermissionDenied(msg) => write!(f, "Permission denied: {}", msg),
+            LlmError::IoError(e) => write!(f, "IO error: {}", e),
+            LlmError::Unsupported(msg) => write!(f, "Not supported: {}", msg),
+            LlmError::Cancelled => write!(f, "Request cancelled"),
+            LlmError::Timeout(msg) => write!(f, "LLM server timed out: {}", msg),
+            LlmError::ServerDown {
+                provider,
+                retry_in_ms,
+                last_error,
+            } => {
+                write!(f, "LLM server {} is down", provider)?;
+                if let Some(error) = last_error {
+                    write!(f, " ({})", error)?;
+                }
+                match retry_in_ms {
+                    Some(ms) => write!(f, "; next attempt in {}s", ms.div_ceil(1000)),
+                    None => write!(f, "; checking whether it is back"),
+                }
+            }
+            LlmError::InvalidOutput { attempts, issues } => {
+                write!(f, "Model output didn't match the schema after {} attempts", attempts)?;
+                for (index, issue) in issues.iter().enumerate() {
+                    let separator = if index == 0 { ": " } else { "; " };
+                    write!(f, "{}{}", separator, issue)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for LlmError {}
+
+/// Serialized like `FileSystemError`: `{ code, message, path, retryable }`
+impl Serialize for LlmError {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut state = serializer.serialize_struct("LlmError", 4)?;
+        state.serialize_field("code", self.code())?;
+        state.serialize_field("message", &self.to_string())?;
+        state.serialize_field("path", &None::<String>)?;
+        state.serialize_field("retryable", &self.is_retryable())?;
+        state.end()
+    }
+}
+
+impl From<reqwest::Error> for LlmError {
+    fn from(error: reqwest::Error) -> Self {
+        if error.is_decode() {
+            LlmError::InvalidResponse(error.to_string())
+        } else {
+            LlmError::Connection(error.to_string())
+        }
+    }
+}
+
+/// Who wrote a chat message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChatRole {
+    System,
+    User,
+    Assistant,
+    Tool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub role: ChatRole,
+    pub content: String,
+    /// Tools the assistant called in this message
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCall>,
+    /// Call a `tool` message answers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+}
+
+impl ChatMessage {
+    pub fn new(role: ChatRole, content: impl Into<String>) -> Self {
+        Self {
+            role,
+            content: content.into(),
+            tool_calls: Vec::new(),
+            tool_call_id: None,
+        }
+    }
+}
+
+/// Single-prompt completion request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GenerateRequest {
+    /// Model to use (empty for the role's model)
+    #[serde(default)]
+    pub model: String,
+    pub prompt: String,
+    #[serde(default)]
+    pub system: Option<String>,
+    /// Model options passed through as-is (temperature, num_ctx, ...)
+    #[serde(default)]
+    pub options: Option<serde_json::Value>,
+}
+
+impl GenerateRequest {
+    /// The same request as a conversation (for providers without a completion endpoint)
+    pub fn to_chat(&self) -> ChatRequest {
+        let mut messages = Vec::new();
+        if let Some(system) = &self.system {
+            messages.push(ChatMessage::new(ChatRole::System, system.clone()));
+        }
+        messages.push(ChatMessage::new(ChatRole::User, self.prompt.clone()));
+        ChatRequest {
+            model: self.model.clone(),
+            messages,
+            options: self.options.clone(),
+            tools: Vec::new(),
+            format: None,
+        }
+    }
+}
+
+/// Chat completion request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatRequest {
+    /// Model to use (empty for the role's model)
+    #[serde(default)]
+    pub model: String,
+    pub messages: Vec<ChatMessage>,
+    /// Model options passed through as-is (temperature, num_ctx, ...)
+    #[serde(default)]
+    pub options: Option<serde_json::Value>,
+    /// Tools the model may call natively
+    #[serde(default)]
+    pub tools: Vec<ToolDefinition>,
+    /// Constrain the reply to JSON (the provider's JSON or grammar mode)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format: Option<ResponseFormat>,
+}
+
+/// Required form of a reply
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ResponseFormat {
+    /// Any JSON value
+    Json,
+    /// JSON matching a schema
+    JsonSchema { schema: serde_json::Value },
+}
+
+/// Why generation stopped, the same for every provider
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FinishReason {
+    /// The model finished (or hit a stop sequence)
+    Stop,
+    /// The token limit was reached
+    Length,
+    ToolCalls,
+    ContentFilter,
+    Other,
+}
+
+impl FinishReason {
+    /// Normalize a provider's stop reason (`stop`, `length`, `tool_calls`, ...)
+    pub fn from_wire(reason: &str) -> Self {
+        match reason {
+            "stop" | "eos" | "end_turn" | "stop_sequence" => FinishReason::Stop,
+            "length" | "max_tokens" => FinishReason::Length,
+            "tool_calls" | "function_call" | "tool_use" => FinishReason::ToolCalls,
+            "content_filter" => FinishReason::ContentFilter,
+            _ => FinishReason::Other,
+        }
+    }
+}
+
+/// Token counts reported by the server (either may be missing)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Usage {
+    pub prompt_tokens: Option<u64>,
+    pub completion_tokens: Option<u64>,
+}
+
+/// Totals over several requests (missing only if missing from all of them)
+impl std::ops::Add for Usage {
+    type Output = Usage;
+
+    fn add(self, other: Usage) -> Usage {
+        let add = |a: Option<u64>, b: Option<u64>| match (a, b) {
+            (None, None) => None,
+            (a, b) => Some(a.unwrap_or(0) + b.unwrap_or(0)),
+        };
+        Usage {
+            prompt_tokens: add(self.prompt_tokens, other.prompt_tokens),
+            completion_tokens: add(self.completion_tokens, other.completion_tokens),
+        }
+    }
+}
+
+/// One piece of a streamed response, normalized across providers
+/// 
+/// Text chunks carry `content`; the last chunk has `done` set along with the
+/// finish reason and usage the server reported.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StreamChunk {
+    /// Text generated since the previous chunk
+    pub content: String,
+    pub done: bool,
+    pub finish_reason: Option<FinishReason>,
+    pub usage: Option<Usage>,
+    /// The text sent so far is void: the request was preempted and starts over
+    #[serde(default)]
+    pub restart: bool,
+}
+
+/// Payload of `LLM_CHUNK`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LlmChunk {
+    pub request_id: String,
+    #[serde(flatten)]
+    pub chunk: StreamChunk,
+}
+
+/// Complete result of a request
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LlmResponse {
+    pub model: String,
+    pub content: String,
+    pub finish_reason: Option<FinishReason>,
+    pub usage: Usage,
+    /// Wall-clock time of the request
+    pub duration_ms: u64,
+    /// Native tool calls the model made
+    #[serde(default)]
+    pub tool_calls: Vec<ToolCall>,
+    /// Whether the response came from the cache
+    #[serde(default)]
+    pub cached: bool,
+}
+
+/// A model installed on (or served by) a provider
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ModelStatus {
+    pub name: String,
+    /// Size on disk in bytes
+    pub size: Option<u64>,
+    /// Quantization level (e.g. `Q4_K_M`)
+    pub quantization: Option<String>,
+    /// Parameter count as reported (e.g. `8.0B`)
+    pub parameter_size: Option<String>,
+    pub family: Option<String>,
+    /// Context window the model supports, in tokens
+    pub context_length: Option<u64>,
+    /// Whether the model is currently loaded (`None` if the provider can't tell)
+    pub loaded: Option<bool>,
+    pub modified_at: Option<String>,
+}
+
+/// Progress of a model download
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PullProgress {
+    /// What the server is doing (e.g. `pulling manifest`, `success`)
+    pub status: String,
+    /// Layer being downloaded
+    pub digest: Option<String>,
+    /// Bytes of the layer downloaded so far
+    pub completed: Option<u64>,
+    /// Size of the layer in bytes
+    pub total: Option<u64>,
+}
+
+/// Payload of `LLM_PULL_PROGRESS`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LlmPullProgress {
+    pub request_id: String,
+    pub model: String,
+    #[serde(flatten)]
+    pub progress: PullProgress,
+}
+
+/// Result of a health probe
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LlmHealth {
+    pub provider: String,
+    pub reachable: bool,
+    /// Server version, if the API reports one
+    pub version: Option<String>,
+    pub latency_ms: u64,
+    /// Why the server is unreachable
+    pub error: Option<String>,
+}
+
+/// Called with each chunk of a streamed response
+pub type OnChunk<'a> = dyn FnMut(&StreamChunk) + Send + 'a;
+
+/// Called with each download progress update
+pub type OnPullProgress<'a> = dyn FnMut(&PullProgress) + Send + 'a;
+
+/// Future returned by provider methods
+pub type LlmFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, LlmError>> + Send + 'a>>;
+
+/// An LLM server API
+/// 
+/// Implementations turn their wire format (NDJSON, SSE, ...) into
+/// `StreamChunk`s; the last chunk passed to `on_chunk` has `done` set.
+pub trait LlmProvider: Send + Sync {
+    /// Continue a conversation, reporting text as it is generated
+    /// 
+    /// # Arguments
+    /// * `request` - Model, messages and options
+    /// * `cancel` - Stops the request when cancelled
+    /// * `on_chunk` - Called with each chunk
+    /// 
+    /// # Returns
+    /// * `Ok(LlmResponse)` - The assistant's full reply
+    /// * `Err(LlmError)` - The request failed or was cancelled
+    fn chat<'a>(
+        &'a self,
+        request: &'a ChatRequest,
+        cancel: &'a CancellationToken,
+        on_chunk: &'a mut OnChunk<'a>,
+    ) -> LlmFuture<'a, LlmResponse>;
+
+    /// Complete a prompt, reporting text as it is generated
+    /// 
+    /// Sent as a conversation unless the provider has a completion endpoint.
+    fn generate<'a>(
+        &'a self,
+        request: &'a GenerateRequest,
+        cancel: &'a CancellationToken,
+        on_chunk: &'a mut OnChunk<'a>,
+    ) -> LlmFuture<'a, LlmResponse> {
+        Box::pin(async move {
+            let chat = request.to_chat();
+            self.chat(&chat, cancel, on_chunk).await
+        })
+    }
+
+    /// Models the server can run
+    fn list_models(&self) -> LlmFuture<'_, Vec<ModelStatus>>;
+
+    /// Check that the server answers (within `PROBE_TIMEOUT`)
+    /// 
+    /// # Returns
+    /// * `Ok(Option<String>)` - The server's version, if its API reports one
+    fn probe(&self) -> LlmFuture<'_, Option<String>>;
+
+    /// Load a model into memory so the first real request doesn't wait for it
+    fn warm_up<'a>(&'a self, model: &'a str) -> LlmFuture<'a, ()>;
+
+    /// Download a model, reporting progress as it goes
+    fn pull_model<'a>(
+        &'a self,
+        model: &'a str,
+        _cancel: &'a CancellationToken,
+        _on_progress: &'a mut OnPullProgress<'a>,
+    ) -> LlmFuture<'a, ()> {
+        Box::pin(async move {
+            Err(LlmError::Unsupported(format!(
+                "This provider can't download models ({})",
+                model
+            )))
+        })
+    }
+
+    /// Remove a downloaded model
+    fn delete_model<'a>(&'a self, model: &'a str) -> LlmFuture<'a, ()> {
+        Box::pin(async move {
+            Err(LlmError::Unsupported(format!(
+                "This provider can't delete models ({})",
+                model
+            )))
+        })
+    }
+}
+
+/// Probe a provider, reporting failure instead of returning it
+pub async fn check_health(id: &str, provider: &dyn LlmProvider) -> LlmHealth {
+    let started = Instant::now();
+    let result = provider.probe().await;
+    let latency_ms = started.elapsed().as_millis() as u64;
+    match result {
+        Ok(version) => LlmHealth {
+            provider: id.to_string(),
+            reachable: true,
+            version,
+            latency_ms,
+            error: None,
+        },
+        Err(e) => LlmHealth {
+            provider: id.to_string(),
+            reachable: false,
+            version: None,
+            latency_ms,
+            error: Some(e.to_string()),
+        },
+    }
+}
+
+/// AI role a request is made for (each can use a different provider)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AiRole {
+    Overseer,
+    Implementation,
+}
+
+/// API a provider speaks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ProviderKind {
+    /// Ollama's native API (`/api/generate`, `/api/chat`)
+    #[serde(rename = "ollama")]
+    Ollama,
+    /// `/chat/completions` (llama.cpp server, vLLM, LM Studio, Ollama's `/v1`, ...)
+    #[serde(rename = "openai_compatible")]
+    OpenAiCompatible,
+}
+
+/// A configured LLM server
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProviderConfig {
+    pub kind: ProviderKind,
+    /// Server URL; for OpenAI-compatible servers the API root (e.g. `http://localhost:8080/v1`)
+    pub base_url: String,
+    /// Sent as a bearer token (OpenAI-compatible only)
+    #[serde(default)]
+    pub api_key: Option<String>,
+    /// Model calls run at once; further calls wait in line (one if unset)
+    #[serde(default)]
+    pub max_concurrent: Option<usize>,
+}
+
+impl ProviderConfig {
+    /// Client for this server
+    fn provider(&self, http: reqwest::Client) -> Box<dyn LlmProvider> {
+        match self.kind {
+            ProviderKind::Ollama => Box::new(OllamaProvider::new(http, &self.base_url)),
+            ProviderKind::OpenAiCompatible => Box::new(OpenAiProvider::new(
+                http,
+                &self.base_url,
+                self.api_key.clone(),
+            )),
+        }
+    }
+}
+
+/// Provider and model used by a role
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoleConfig {
+    /// Provider id (the default provider if unset)
+    #[serde(default)]
+    pub provider: Option<String>,
+    /// Model used when a request doesn't name one
+    #[serde(default)]
+    pub model: Option<String>,
+}
+
+/// LLM settings
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LlmConfig {
+    /// Servers by id
+    pub providers: HashMap<String, ProviderConfig>,
+    /// Provider used by roles without their own
+    pub default_provider: String,
+    /// Provider and model per role
+    #[serde(default)]
+    pub roles: HashMap<AiRole, RoleConfig>,
+    /// Response cache limits
+    #[serde(default)]
+    pub cache: CacheConfig,
+    /// Timeouts, retries and circuit breaker limits
+    #[serde(default)]
+    pub resilience: ResilienceConfig,
+}
+
+impl Default for LlmConfig {
+    fn default() -> Self {
+        let ollama = ProviderConfig {
+            kind: ProviderKind::Ollama,
+            base_url: DEFAULT_OLLAMA_URL.to_string(),
+            api_key: None,
+            max_concurrent: None,
+        };
+        Self {
+            providers: HashMap::from([(DEFAULT_PROVIDER.to_string(), ollama)]),
+            default_provider: DEFAULT_PROVIDER.to_string(),
+            roles: HashMap::new(),
+            cache: CacheConfig::default(),
+            resilience: ResilienceConfig::default(),
+        }
+    }
+}
+
+impl LlmConfig {
+    /// Check that URLs are usable and every referenced provider exists
+    fn validate(&self) -> Result<(), LlmError> {
+        self.resilience.validate()?;
+        for (id, provider) in &self.providers {
+            let base_url = reqwest::Url::parse(&provider.base_url).map_err(|e| {
+                LlmError::InvalidInput(format!("Invalid base URL for {}: {}", id, e))
+            })?;
+            if !matches!(base_url.scheme(), "http" | "https") {
+                return Err(LlmError::InvalidInput(format!(
+                    "Unsupported URL scheme for {}: {}",
+                    id,
+                    base_url.scheme()
+                )));
+            }
+            if provider.max_concurrent == Some(0) {
+                return Err(LlmError::InvalidInput(format!(
+                    "max_concurrent for {} must be at least 1",
+                    id
+                )));
+            }
+        }
+
+        let role_providers = self.roles.values().filter_map(|role| role.provider.as_ref());
+        let referenced = std::iter::once(&self.default_provider).chain(role_providers);
+        for id in referenced {
+            if !self.providers.contains_key(id) {
+                return Err(LlmError::InvalidInput(format!("Unknown LLM provider: {}", id)));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Builds the response from normalized chunks while passing them on
+struct ChunkSink<'a> {
+    response: LlmResponse,
+    on_chunk: &'a mut OnChunk<'a>,
+    started: Instant,
+}
+
+impl<'a> ChunkSink<'a> {
+    fn new(model: &str, on_chunk: &'a mut OnChunk<'a>) -> Self {
+        Self {
+            response: LlmResponse {
+                model: model.to_string(),
+                ..LlmResponse::default()
+            },
+            on_chunk,
+            started: Instant::now(),
+        }
+    }
+
+    /// Add generated text
+    fn text(&mut self, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        self.response.content.push_str(text);
+        (self.on_chunk)(&StreamChunk {
+            content: text.to_string(),
+            ..StreamChunk::default()
+        });
+    }
+
+    /// Add a native tool call (given an id if the server sent none)
+    fn tool_call(&mut self, mut call: ToolCall) {
+        if call.id.is_empty() {
+            call.id = format!("call_{}", self.response.tool_calls.len());
+        }
+        self.response.tool_calls.push(call);
+    }
+
+    /// Send the final chunk and return the complete response
+    fn finish(self, finish_reason: Option<FinishReason>, usage: Usage) -> LlmResponse {
+        (self.on_chunk)(&StreamChunk {
+            content: String::new(),
+            done: true,
+            finish_reason,
+            usage: Some(usage),
+            restart: false,
+        });
+        LlmResponse {
+            finish_reason,
+            usage,
+            duration_ms: self.started.elapsed().as_millis() as u64,
+            ..self.response
+        }
+    }
+}
+
+/// Send a request, failing on an error status
+async fn send(
+    request: reqwest::RequestBuilder,
+    model: &str,
+    cancel: &CancellationToken,
+) -> Result<reqwest::Response, LlmError> {
+    let response = tokio::select! {
+        _ = cancel.cancelled() => return Err(LlmError::Cancelled),
+        response = request.send() => response?,
+    };
+    if !response.status().is_success() {
+        return Err(status_error(model, response).await);
+    }
+    Ok(response)
+}
+
+/// Turn an error response into an `LlmError`
+/// 
+/// Understands `{"error": "..."}` (Ollama) and `{"error": {"message": "..."}}`
+/// (OpenAI-compatible servers).
+async fn status_error(model: &str, response: reqwest::Response) -> LlmError {
+    let status = response.status().as_u16();
+    let body = response.text().await.unwrap_or_default();
+    let message = serde_json::from_str::<serde_json::Value>(&body)
+        .ok()
+        .and_then(|value| error_message(&value))
+        .unwrap_or(body);
+
+    let missing = message.contains("not found") || message.contains("does not exist");
+    if status == 404 && missing {
+        LlmError::ModelNotFound(model.to_string())
+    } else {
+        LlmError::Status { status, message }
+    }
+}
+
+/// Message of an `error` field in either provider's format
+fn error_message(value: &serde_json::Value) -> Option<String> {
+    let error = value.get("error")?;
+    match error.as_str() {
+        Some(message) => Some(message.to_string()),
+        None => error.get("message")?.as_str().map(str::to_string),
+    }
+}
+
+/// Read a response body line by line
+/// 
+/// `on_line` gets each line without its line ending and returns `true` to stop.
+/// 
+/// # Returns
+/// * `Ok(true)` - `on_line` stopped the stream
+/// * `Ok(false)` - The body ended
+async fn read_lines(
+    response: &mut reqwest::Response,
+    cancel: &CancellationToken,
+    mut on_line: impl FnMut(&[u8]) -> Result<bool, LlmError>,
+) -> Result<bool, LlmError> {
+    let mut buffer: Vec<u8> = Vec::new();
+    loop {
+        let chunk = tokio::select! {
+            _ = cancel.cancelled() => return Err(LlmError::Cancelled),
+            chunk = response.chunk() => chunk?,
+        };
+        let Some(chunk) = chunk else {
+            break;
+        };
+        buffer.extend_from_slice(&chunk);
+
+        while let Some(newline) = buffer.iter().position(|byte| *byte == b'\n') {
+            let line: Vec<u8> = buffer.drain(..=newline).collect();
+            if on_line(trim_line_ending(&line))? {
+                return Ok(true);
+            }
+        }
+    }
+
+    // The last line may lack a newline
+    if !buffer.is_empty() && on_line(trim_line_ending(&buffer))? {
+        return Ok(true);
+    }
+    Ok(false)
+}
+
+fn trim_line_ending(line: &[u8]) -> &[u8] {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+/// HTTP client shared by the providers
+fn http_client(config: &ResilienceConfig) -> reqwest::Client {
+    reqwest::Client::builder()
+        .connect_timeout(config.connect_timeout())
+        .build()
+        .expect("HTTP client configuration is valid")
+}
+
+/// LLM configuration, in-flight requests, the request queue and the
+/// providers' circuit breakers (thread-safe)
+pub struct LlmState {
+    config: Mutex<LlmConfig>,
+    config_path: PathBuf,
+    requests: Mutex<HashMap<String, CancellationToken>>,
+    queue: LlmQueue,
+    breakers: Mutex<HashMap<String, Arc<CircuitBreaker>>>,
+    status_changes: broadcast::Sender<ProviderStatus>,
+    http: Mutex<reqwest::Client>,
+}
+
+impl LlmState {
+    /// Load the configuration from disk (defaults if missing)
+    ///
+    /// # Returns
+    /// The state, or the error reading, parsing or validating an existing file
+    pub fn load(config_path: PathBuf) -> Result<Self, LlmError> {
+        let config = match std::fs::read_to_string(&config_path) {
+            Ok(content) => serde_json::from_str::<LlmConfig>(&content)
+                .map_err(|e| e.to_string())
+                .and_then(|config| config.validate().map(|_| config).map_err(|e| e.to_string()))
+                .map_err(|e| {
+                    LlmError::InvalidInput(format!(
+                        "Invalid LLM config {}: {}",
+                        config_path.to_string_lossy(),
+                        e
+                    ))
+                })?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => LlmConfig::default(),
+            Err(e) => return Err(LlmError::IoError(e)),
+        };
+        let http = http_client(&config.resilience);
+
+        Ok(Self {
+            config: Mutex::new(config),
+            config_path,
+            requests: Mutex::new(HashMap::new()),
+            queue: LlmQueue::new(),
+            breakers: Mutex::new(HashMap::new()),
+            status_changes: broadcast::channel(STATUS_CHANNEL_CAPACITY).0,
+            http: Mutex::new(http),
+        })
+    }
+
+    pub fn config(&self) -> LlmConfig {
+        self.config.lock().unwrap().clone()
+    }
+
+    /// Where role prompt templates are looked up (next to the configuration)
+    pub fn template_dirs(&self, project_root: Option<PathBuf>) -> TemplateDirs {
+        let config_dir = self.config_path.parent().unwrap_or(Path::new("."));
+        TemplateDirs {
+            app: config_dir.join(PROMPTS_DIR),
+            project_root,
+        }
+    }
+
+    /// Replace and persist the configuration
+    pub fn set_config(&self, config: LlmConfig) -> Result<(), LlmError> {
+        config.validate()?;
+        let content = serde_json::to_string_pretty(&config)
+            .map_err(|e| LlmError::InvalidInput(e.to_string()))?;
+        let save = || -> std::io::Result<()> {
+            if let Some(parent) = self.config_path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            let temp_path = self.config_path.with_extension("json.tmp");
+            std::fs::write(&temp_path, content)?;
+            std::fs::rename(&temp_path, &self.config_path)
+        };
+        save().map_err(LlmError::IoError)?;
+
+        let previous = std::mem::replace(&mut *self.config.lock().unwrap(), config.clone());
+        if previous.resilience.connect_timeout_ms != config.resilience.connect_timeout_ms {
+            *self.http.lock().unwrap() = http_client(&config.resilience);
+        }
+        // Servers may have moved; give every provider a fresh start
+        for breaker in self.breakers.lock().unwrap().values() {
+            breaker.reset();
+        }
+        Ok(())
+    }
+
+    /// Provider for a role (the default provider when `role` is `None` or has none)
+    pub fn provider(&self, role: Option<AiRole>) -> Result<Box<dyn LlmProvider>, LlmError> {
+        Ok(self.role_provider(role)?.1)
+    }
+
+    /// Provider for a role and its id
+    pub fn role_provider(
+        &self,
+        role: Option<AiRole>,
+    ) -> Result<(String, Box<dyn LlmProvider>), LlmError> {
+        let id = role.and_then(|role| self.config().roles.get(&role)?.provider.clone());
+        self.provider_by_id(id.as_deref())
+    }
+
+    /// Provider for a role whose chat and completion calls wait their turn
+    /// 
+    /// Each provider runs `max_concurrent` calls at once; see `queue`.
+    pub fn queued_provider(
+        &self,
+        role: Option<AiRole>,
+        request_id: &str,
+        priority: Priority,
+    ) -> Result<(String, Box<dyn LlmProvider>), LlmError> {
+        let (id, provider) = self.role_provider(role)?;
+        let max_concurrent = self
+            .config()
+            .providers
+            .get(&id)
+            .and_then(|provider| provider.max_concurrent)
+            .unwrap_or(DEFAULT_MAX_CONCURRENT);
+        let provider = self.queue.provider(&id, max_concurrent, provider, request_id, priority);
+        Ok((id, provider))
+    }
+
+    /// Queued and running model calls per provider
+    pub fn queue_status(&self) -> Vec<ProviderQueueStatus> {
+        self.queue.status()
+    }
+
+    /// Provider by id (the default provider when `id` is `None`)
+    /// 
+    /// # Returns
+    /// * `Ok((id, provider))` - The resolved id and its provider
+    /// * `Err(LlmError::InvalidInput)` - No provider has that id
+    pub fn provider_by_id(
+        &self,
+        id: Option<&str>,
+    ) -> Result<(String, Box<dyn LlmProvider>), LlmError> {
+        let config = self.config();
+        let id = id.unwrap_or(&config.default_provider);
+        let provider = config
+            .providers
+            .get(id)
+            .ok_or_else(|| LlmError::InvalidInput(format!("Unknown LLM provider: {}", id)))?;
+        let http = self.http.lock().unwrap().clone();
+        let provider = ResilientProvider::new(
+            provider.provider(http),
+            self.breaker(id),
+            config.resilience,
+        );
+        Ok((id.to_string(), Box::new(provider)))
+    }
+
+    /// Circuit breaker of a provider
+    fn breaker(&self, id: &str) -> Arc<CircuitBreaker> {
+        self.breakers
+            .lock()
+            .unwrap()
+            .entry(id.to_string())
+            .or_insert_with(|| Arc::new(CircuitBreaker::new(id, self.status_changes.clone())))
+            .clone()
+    }
+
+    /// Circuit breaker state of every configured provider
+    pub fn provider_status(&self) -> Vec<ProviderStatus> {
+        let mut ids: Vec<String> = self.config().providers.into_keys().collect();
+        ids.sort();
+        ids.iter().map(|id| self.breaker(id).status()).collect()
+    }
+
+    /// Receive provider status changes (for forwarding as events)
+    pub fn status_changes(&self) -> broadcast::Receiver<ProviderStatus> {
+        self.status_changes.subscribe()
+    }
+
+    /// Model for a request: the one it names, else the role's model
+    pub fn model_for(&self, role: Option<AiRole>, requested: &str) -> Result<String, LlmError> {
+        if !requested.is_empty() {
+            return Ok(requested.to_string());
+        }
+        role.and_then(|role| self.config().roles.get(&role)?.model.clone())
+            .ok_or_else(|| LlmError::InvalidInput("No model given or configured".to_string()))
+    }
+
+    /// Register an in-flight request under a caller-chosen id
+    pub fn begin(&self, request_id: &str) -> Result<CancellationToken, LlmError> {
+        let mut requests = self.requests.lock().unwrap();
+        if requests.contains_key(request_id) {
+            return Err(LlmError::InvalidInput(format!(
+                "Request {} is already running",
+                request_id
+            )));
+        }
+        let token = CancellationToken::new();
+        requests.insert(request_id.to_string(), token.clone());
+        Ok(token)
+    }
+
+    /// Forget a finished request
+    pub fn finish(&self, request_id: &str) {
+        self.requests.lock().unwrap().remove(request_id);
+    }
+
+    /// Cancel an in-flight request
+    ///
+    /// # Returns
+    /// * `true` - The request was running
+    pub fn cancel(&self, request_id: &str) -> bool {
+        match self.requests.lock().unwrap().remove(request_id) {
+            Some(token) => {
+                token.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Cancel every in-flight request
+    pub fn cancel_all(&self) {
+        for (_, token) in self.requests.lock().unwrap().drain() {
+            token.cancel();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_server::{Reply, TestServer, EVENT_STREAM, NDJSON};
+    use tokio::sync::mpsc;
+    use tokio::time::timeout;
+
+    /// How long a cancelled call may take to return
+    const CANCEL_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// State whose default provider is a stub of the given kind
+    fn state_for(server: &TestServer, kind: &str, name: &str) -> LlmState {
+        let dir = std::env::temp_dir().join(format!("nexus-llm-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let config_path = dir.join(LLM_CONFIG_FILE);
+        let config = serde_json::json!({
+            "providers": { "stub": { "kind": kind, "base_url": server.url } },
+            "default_provider": "stub",
+        });
+        std::fs::write(&config_path, config.to_string()).unwrap();
+        LlmState::load(config_path).unwrap()
+    }
+
+    /// Start a chat that streams one chunk and stalls, then cancel it by id
+    async fn cancel_mid_stream(state: &LlmState) -> Result<LlmResponse, LlmError> {
+        let token = state.begin("request-1").unwrap();
+        let provider = state.provider(None).unwrap();
+        let request = ChatRequest {
+            model: "test-model".to_string(),
+            messages: vec![ChatMessage::new(ChatRole::User, "Hi")],
+            options: None,
+            tools: Vec::new(),
+            format: None,
+        };
+        let (first_tx, mut first_rx) = mpsc::unbounded_channel();
+        let mut on_chunk = move |chunk: &StreamChunk| {
+            let _ = first_tx.send(chunk.content.clone());
+        };
+
+        let chat = provider.chat(&request, &token, &mut on_chunk);
+        let cancel = async {
+            assert_eq!(first_rx.recv().await.as_deref(), Some("Hi"));
+            assert!(state.cancel("request-1"));
+        };
+        let (result, ()) = timeout(CANCEL_TIMEOUT, async { tokio::join!(chat, cancel) })
+            .await
+            .expect("cancelled call returned");
+        // The id is free again
+        assert!(!state.cancel("request-1"));
+        result
+    }
+
+    #[tokio::test]
+    async fn cancel_stops_ollama_stream() {
+        let server = TestServer::start(|_, _| {
+            Reply::ok(NDJSON)
+                .send("{\"message\":{\"role\":\"assistant\",\"content\":\"Hi\"}}\n")
+                .hang()
+        })
+        .await;
+        let state = state_for(&server, "ollama", "cancel-ollama");
+
+        assert!(matches!(cancel_mid_stream(&state).await, Err(LlmError::Cancelled)));
+        assert_eq!(server.hits(), 1);
+    }
+
+    #[tokio::test]
+    async fn cancel_stops_openai_stream() {
+        let server = TestServer::start(|_, _| {
+            Reply::ok(EVENT_STREAM)
+                .send("data: {\"choices\":[{\"delta\":{\"content\":\"Hi\"}}]}\n\n")
+                .hang()
+        })
+        .await;
+        let state = state_for(&server, "openai_compatible", "cancel-openai");
+
+        assert!(matches!(cancel_mid_stream(&state).await, Err(LlmError::Cancelled)));
+        assert_eq!(server.hits(), 1);
+    }
+
+    #[tokio::test]
+    async fn cancel_all_stops_every_request() {
+        let missing = std::env::temp_dir().join("nexus-llm-missing.json");
+        let state = LlmState::load(missing).unwrap();
+        let first = state.begin("first").unwrap();
+        let second = state.begin("second").unwrap();
+        assert!(state.begin("first").is_err());
+
+        state.cancel_all();
+        assert!(first.is_cancelled() && second.is_cancelled());
+        assert!(!state.cancel("first"));
+    }
+
+    #[test]
+    fn invalid_config_is_reported() {
+        let dir = std::env::temp_dir().join(format!("nexus-llm-invalid-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let config_path = dir.join(LLM_CONFIG_FILE);
+
+        std::fs::write(&config_path, "{ not json").unwrap();
+        let error = LlmState::load(config_path.clone()).err().unwrap();
+        assert!(matches!(error, LlmError::InvalidInput(_)), "{:?}", error);
+
+        // Parses, but names a provider that isn't configured
+        std::fs::write(&config_path, r#"{ "default_provider": "missing" }"#).unwrap();
+        assert!(LlmState::load(config_path).is_err());
+    }
+}
@@ -0,0 +1,609 @@
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
+
+use super::tools::ToolCall;
+use super::{
+    read_lines, send, ChatMessage, ChatRequest, ChunkSink, FinishReason, GenerateRequest,
+    LlmError, LlmFuture, LlmProvider, LlmResponse, ModelStatus, OnChunk, OnPullProgress,
+    PullProgress, ResponseFormat, Usage, PROBE_TIMEOUT,
+};
+
+/// One line of Ollama's newline-delimited JSON stream
+#[derive(Debug, Default, Deserialize)]
+struct StreamLine {
+    /// Generated text (`/api/generate`)
+    #[serde(default)]
+    response: Option<String>,
+    /// Generated message (`/api/chat`)
+    #[serde(default)]
+    message: Option<StreamMessage>,
+    #[serde(default)]
+    done: bool,
+    #[serde(default)]
+    done_reason: Option<String>,
+    #[serde(default)]
+    prompt_eval_count: Option<u64>,
+    #[serde(default)]
+    eval_count: Option<u64>,
+    /// Error reported after streaming started
+    #[serde(default)]
+    error: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct StreamMessage {
+    #[serde(default)]
+    content: String,
+    #[serde(default)]
+    tool_calls: Vec<StreamToolCall>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamToolCall {
+    #[serde(default)]
+    id: Option<String>,
+    function: StreamFunction,
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamFunction {
+    name: String,
+    #[serde(default)]
+    arguments: Value,
+}
+
+/// Response of `/api/tags` (installed models) and `/api/ps` (loaded models)
+#[derive(Debug, Default, Deserialize)]
+struct ModelList {
+    #[serde(default)]
+    models: Vec<ListedModel>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ListedModel {
+    name: String,
+    #[serde(default)]
+    size: Option<u64>,
+    #[serde(default)]
+    modified_at: Option<String>,
+    #[serde(default)]
+    details: ModelDetails,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ModelDetails {
+    #[serde(default)]
+    family: Option<String>,
+    #[serde(default)]
+    parameter_size: Option<String>,
+    #[serde(default)]
+    quantization_level: Option<String>,
+}
+
+/// Response of `/api/show`
+#[derive(Debug, Default, Deserialize)]
+struct ShowResponse {
+    /// GGUF metadata, e.g. `general.architecture` and `llama.context_length`
+    #[serde(default)]
+    model_info: serde_json::Map<String, serde_json::Value>,
+}
+
+impl ShowResponse {
+    fn context_length(&self) -> Option<u64> {
+        let architecture = self.model_info.get("general.architecture")?.as_str()?;
+        self.model_info
+            .get(&format!("{}.context_length", architecture))?
+            .as_u64()
+    }
+}
+
+/// One line of `/api/pull`'s stream
+#[derive(Debug, Default, Deserialize)]
+struct PullLine {
+    #[serde(default)]
+    status: String,
+    #[serde(default)]
+    digest: Option<String>,
+    #[serde(default)]
+    completed: Option<u64>,
+    #[serde(default)]
+    total: Option<u64>,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+/// `/api/show` requests sent at once when listing models
+const SHOW_CONCURRENCY: usize = 4;
+
+/// Text a stream line contributes
+type TextOf = for<'a> fn(&'a StreamLine) -> Option<&'a str>;
+
+/// Provider for Ollama's native API
+#[derive(Clone)]
+pub struct OllamaProvider {
+    http: reqwest::Client,
+    base_url: String,
+}
+
+impl OllamaProvider {
+    /// Provider for the server at `base_url` (e.g. `http://localhost:11434`)
+    pub fn new(http: reqwest::Client, base_url: &str) -> Self {
+        Self {
+            http,
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    /// Context length of a model (`None` if the server doesn't say)
+    async fn context_length(&self, model: &str) -> Option<u64> {
+        let request = self.http.post(self.url("/api/show")).json(&json!({ "model": model }));
+        let response = send(request, model, &CancellationToken::new()).await.ok()?;
+        let show: ShowResponse = response.json().await.ok()?;
+        show.context_length()
+    }
+
+    /// Context lengths of models, in the same order, looked up a few at a time
+    async fn context_lengths(&self, models: &[ListedModel]) -> Vec<Option<u64>> {
+        let permits = Arc::new(Semaphore::new(SHOW_CONCURRENCY));
+        let mut lookups = JoinSet::new();
+        for (index, model) in models.iter().enumerate() {
+            let provider = self.clone();
+            let permits = permits.clone();
+            let name = model.name.clone();
+            lookups.spawn(async move {
+                let _permit = permits.acquire_owned().await;
+                (index, provider.context_length(&name).await)
+            });
+        }
+
+        let mut lengths = vec![None; models.len()];
+        while let Some(lookup) = lookups.join_next().await {
+            if let Ok((index, length)) = lookup {
+                lengths[index] = length;
+            }
+        }
+        lengths
+    }
+
+    async fn models(&self) -> Result<Vec<ModelStatus>, LlmError> {
+        let never = CancellationToken::new();
+        let installed: ModelList = send(self.http.get(self.url("/api/tags")), "", &never)
+            .await?
+            .json()
+            .await?;
+        // Only used to mark loaded models, so failures are ignored
+        let loaded = match send(self.http.get(self.url("/api/ps")), "", &never).await {
+            Ok(response) => response.json::<ModelList>().await.unwrap_or_default(),
+            Err(_) => ModelList::default(),
+        };
+        let loaded: Vec<String> = loaded.models.into_iter().map(|model| model.name).collect();
+
+        let context_lengths = self.context_lengths(&installed.models).await;
+        let models = installed
+            .models
+            .into_iter()
+            .zip(context_lengths)
+            .map(|(model, context_length)| ModelStatus {
+                context_length,
+                loaded: Some(loaded.contains(&model.name)),
+                size: model.size,
+                quantization: model.details.quantization_level,
+                parameter_size: model.details.parameter_size,
+                family: model.details.family,
+                modified_at: model.modified_at,
+                name: model.name,
+            })
+            .collect();
+        Ok(models)
+    }
+
+    async fn pull<'a>(
+        &self,
+        model: &str,
+        cancel: &CancellationToken,
+        on_progress: &'a mut OnPullProgress<'a>,
+    ) -> Result<(), LlmError> {
+        let request = self
+            .http
+            .post(self.url("/api/pull"))
+            .json(&json!({ "model": model, "stream": true }));
+        let mut response = send(request, model, cancel).await?;
+
+        let finished = read_lines(&mut response, cancel, |line| {
+            if line.iter().all(u8::is_ascii_whitespace) {
+                return Ok(false);
+            }
+            let line: PullLine = serde_json::from_slice(line)
+                .map_err(|e| LlmError::InvalidResponse(e.to_string()))?;
+            if let Some(message) = line.error {
+                return Err(LlmError::Status { status: 500, message });
+            }
+            let done = line.status == "success";
+            on_progress(&PullProgress {
+                status: line.status,
+                digest: line.digest,
+                completed: line.completed,
+                total: line.total,
+            });
+            Ok(done)
+        })
+        .await?;
+
+        if !finished {
+            return Err(LlmError::InvalidResponse(
+                "Download ended before it was complete".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Send a streaming request and collect the streamed text
+    async fn stream<'a>(
+        &self,
+        path: &str,
+        model: &str,
+        body: serde_json::Value,
+        cancel: &CancellationToken,
+        on_chunk: &'a mut OnChunk<'a>,
+        text_of: TextOf,
+    ) -> Result<LlmResponse, LlmError> {
+        let request = self.http.post(self.url(path)).json(&body);
+        let mut response = send(request, model, cancel).await?;
+
+        let mut sink = ChunkSink::new(model, on_chunk);
+        let mut last: Option<StreamLine> = None;
+        let finished = read_lines(&mut response, cancel, |line| {
+            if line.iter().all(u8::is_ascii_whitespace) {
+                return Ok(false);
+            }
+            let mut line: StreamLine = serde_json::from_slice(line)
+                .map_err(|e| LlmError::InvalidResponse(e.to_string()))?;
+            if let Some(message) = line.error {
+                // Failures after the response started (e.g. the model crashed)
+                return Err(LlmError::Status { status: 500, message });
+            }
+
+            if let Some(text) = text_of(&line) {
+                sink.text(text);
+            }
+            if let Some(message) = line.message.as_mut() {
+                for call in message.tool_calls.drain(..) {
+                    sink.tool_call(ToolCall {
+                        id: call.id.unwrap_or_default(),
+                        name: call.function.name,
+                        arguments: call.function.arguments,
+                        arguments_error: None,
+                    });
+                }
+            }
+            let done = line.done;
+            last = Some(line);
+            Ok(done)
+        })
+        .await?;
+
+        let Some(last) = last.filter(|_| finished) else {
+            return Err(LlmError::InvalidResponse(
+                "Stream ended before the response was complete".to_string(),
+            ));
+        };
+        let usage = Usage {
+            prompt_tokens: last.prompt_eval_count,
+            completion_tokens: last.eval_count,
+        };
+        let finish_reason = last.done_reason.as_deref().map(FinishReason::from_wire);
+        Ok(sink.finish(finish_reason, usage))
+    }
+}
+
+/// Messages in Ollama's format (tool call arguments are JSON objects)
+fn wire_messages(messages: &[ChatMessage]) -> Vec<Value> {
+    messages
+        .iter()
+        .map(|message| {
+            let mut wire = json!({ "role": message.role, "content": message.content });
+            if !message.tool_calls.is_empty() {
+                let calls: Vec<Value> = message
+                    .tool_calls
+                    .iter()
+                    .map(|call| {
+                        json!({ "function": { "name": call.name, "arguments": call.arguments } })
+                    })
+                    .collect();
+                wire["tool_calls"] = Value::from(calls);
+            }
+            wire
+        })
+        .collect()
+}
+
+impl LlmProvider for OllamaProvider {
+    fn chat<'a>(
+        &'a self,
+        request: &'a ChatRequest,
+        cancel: &'a CancellationToken,
+        on_chunk: &'a mut OnChunk<'a>,
+    ) -> LlmFuture<'a, LlmResponse> {
+        let mut body = json!({
+            "model": request.model,
+            "messages": wire_messages(&request.messages),
+            "options": request.options,
+            "stream": true,
+        });
+        if !request.tools.is_empty() {
+            let tools: Vec<Value> = request.tools.iter().map(|tool| tool.to_wire()).collect();
+            body["tools"] = Value::from(tools);
+        }
+        match &request.format {
+            Some(ResponseFormat::Json) => body["format"] = Value::from("json"),
+            Some(ResponseFormat::JsonSchema { schema }) => body["format"] = schema.clone(),
+            None => {}
+        }
+        Box::pin(self.stream("/api/chat", &request.model, body, cancel, on_chunk, |line| {
+            line.message.as_ref().map(|message| message.content.as_str())
+        }))
+    }
+
+    /// Uses `/api/generate` so the model's own prompt template applies
+    fn generate<'a>(
+        &'a self,
+        request: &'a GenerateRequest,
+        cancel: &'a CancellationToken,
+        on_chunk: &'a mut OnChunk<'a>,
+    ) -> LlmFuture<'a, LlmResponse> {
+        let body = json!({
+            "model": request.model,
+            "prompt": request.prompt,
+            "system": request.system,
+            "options": request.options,
+            "stream": true,
+        });
+        Box::pin(self.stream("/api/generate", &request.model, body, cancel, on_chunk, |line| {
+            line.response.as_deref()
+        }))
+    }
+
+    fn list_models(&self) -> LlmFuture<'_, Vec<ModelStatus>> {
+        Box::pin(self.models())
+    }
+
+    fn probe(&self) -> LlmFuture<'_, Option<String>> {
+        #[derive(Deserialize)]
+        struct Version {
+            version: String,
+        }
+
+        Box::pin(async move {
+            let request = self.http.get(self.url("/api/version")).timeout(PROBE_TIMEOUT);
+            let version: Version = send(request, "", &CancellationToken::new())
+                .await?
+                .json()
+                .await?;
+            Ok(Some(version.version))
+        })
+    }
+
+    /// A request without a prompt only loads the model
+    fn warm_up<'a>(&'a self, model: &'a str) -> LlmFuture<'a, ()> {
+        Box::pin(async move {
+            let request = self
+                .http
+                .post(self.url("/api/generate"))
+                .json(&json!({ "model": model, "stream": false }));
+            send(request, model, &CancellationToken::new()).await?;
+            Ok(())
+        })
+    }
+
+    fn pull_model<'a>(
+        &'a self,
+        model: &'a str,
+        cancel: &'a CancellationToken,
+        on_progress: &'a mut OnPullProgress<'a>,
+    ) -> LlmFuture<'a, ()> {
+        Box::pin(self.pull(model, cancel, on_progress))
+    }
+
+    fn delete_model<'a>(&'a self, model: &'a str) -> LlmFuture<'a, ()> {
+        Box::pin(async move {
+            let request = self
+                .http
+                .delete(self.url("/api/delete"))
+                .json(&json!({ "model": model }));
+            send(request, model, &CancellationToken::new()).await?;
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::test_server::{Reply, TestServer, NDJSON};
+    use crate::llm::{ChatRole, StreamChunk};
+    use std::time::{Duration, Instant};
+
+    /// A line of a chat stream carrying text
+    fn text_line(text: &str) -> String {
+        format!("{}\n", json!({ "message": { "role": "assistant", "content": text } }))
+    }
+
+    /// The last line of a chat stream
+    fn done_line(reason: &str) -> String {
+        let line = json!({
+            "message": { "role": "assistant", "content": "" },
+            "done": true,
+            "done_reason": reason,
+            "prompt_eval_count": 4,
+            "eval_count": 2,
+        });
+        format!("{}\n", line)
+    }
+
+    fn request() -> ChatRequest {
+        ChatRequest {
+            model: "test-model".to_string(),
+            messages: vec![ChatMessage::new(ChatRole::User, "Hi")],
+            options: None,
+            tools: Vec::new(),
+            format: None,
+        }
+    }
+
+    /// Chat with a stub that answers every request with `reply`
+    async fn chat(
+        reply: impl Fn() -> Reply + Send + Sync + 'static,
+    ) -> (TestServer, Result<LlmResponse, LlmError>, Vec<StreamChunk>) {
+        let server = TestServer::start(move |_, _| reply()).await;
+        let provider = OllamaProvider::new(reqwest::Client::new(), &server.url);
+        let mut chunks = Vec::new();
+        let mut collect = |chunk: &StreamChunk| chunks.push(chunk.clone());
+        let result = provider
+            .chat(&request(), &CancellationToken::new(), &mut collect)
+            .await;
+        (server, result, chunks)
+    }
+
+    #[tokio::test]
+    async fn streams_lines_until_done() {
+        let (server, result, chunks) = chat(|| {
+            Reply::ok(NDJSON)
+                .send(&text_line("Hel"))
+                .send(&text_line("lo"))
+                .send(&done_line("stop"))
+        })
+        .await;
+
+        let response = result.unwrap();
+        assert_eq!(response.content, "Hello");
+        assert_eq!(response.finish_reason, Some(FinishReason::Stop));
+        assert_eq!(response.usage.prompt_tokens, Some(4));
+        assert_eq!(response.usage.completion_tokens, Some(2));
+        let texts: Vec<&str> = chunks.iter().map(|chunk| chunk.content.as_str()).collect();
+        assert_eq!(texts, ["Hel", "lo", ""]);
+        assert!(chunks.last().unwrap().done);
+
+        let (path, body) = &server.requests()[0];
+        assert_eq!(path, "/api/chat");
+        assert_eq!(body["model"], "test-model");
+        assert_eq!(body["stream"], true);
+    }
+
+    #[tokio::test]
+    async fn accepts_last_line_without_newline() {
+        let (_server, result, _) = chat(|| {
+            Reply::ok(NDJSON)
+                .send(&text_line("Hi"))
+                .send(done_line("length").trim_end())
+        })
+        .await;
+
+        let response = result.unwrap();
+        assert_eq!(response.content, "Hi");
+        assert_eq!(response.finish_reason, Some(FinishReason::Length));
+    }
+
+    #[tokio::test]
+    async fn rejects_stream_cut_off_before_done() {
+        let (_server, result, chunks) = chat(|| Reply::ok(NDJSON).send(&text_line("Hi"))).await;
+
+        assert!(matches!(result, Err(LlmError::InvalidResponse(_))));
+        assert!(chunks.iter().all(|chunk| !chunk.done));
+    }
+
+    #[tokio::test]
+    async fn fails_on_error_line_mid_stream() {
+        let (_server, result, _) = chat(|| {
+            Reply::ok(NDJSON)
+                .send(&text_line("Hi"))
+                .send("{\"error\":\"model runner crashed\"}\n")
+        })
+        .await;
+
+        match result {
+            Err(LlmError::Status { status: 500, message }) => {
+                assert_eq!(message, "model runner crashed");
+            }
+            other => panic!("expected a status error, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn reports_missing_model() {
+        let (_server, result, _) =
+            chat(|| Reply::status(404, r#"{"error":"model 'test-model' not found"}"#)).await;
+
+        assert!(matches!(result, Err(LlmError::ModelNotFound(model)) if model == "test-model"));
+    }
+
+    #[tokio::test]
+    async fn lists_models_with_concurrent_show_calls() {
+        const SHOW_DELAY: Duration = Duration::from_millis(400);
+        let server = TestServer::start(|_, path| match path {
+            "/api/tags" => {
+                let models: Vec<Value> =
+                    (0..4).map(|n| json!({ "name": format!("model-{}", n) })).collect();
+                Reply::ok("application/json").send(&json!({ "models": models }).to_string())
+            }
+            "/api/ps" => Reply::ok("application/json").send(r#"{"models":[{"name":"model-2"}]}"#),
+            _ => Reply::ok("application/json").pause(SHOW_DELAY).send(
+                r#"{"model_info":{"general.architecture":"llama","llama.context_length":8192}}"#,
+            ),
+        })
+        .await;
+        let provider = OllamaProvider::new(reqwest::Client::new(), &server.url);
+
+        let started = Instant::now();
+        let models = provider.list_models().await.unwrap();
+        // One after another this would take four times as long
+        assert!(started.elapsed() < SHOW_DELAY * 2, "took {:?}", started.elapsed());
+
+        let names: Vec<&str> = models.iter().map(|model| model.name.as_str()).collect();
+        assert_eq!(names, ["model-0", "model-1", "model-2", "model-3"]);
+        assert!(models.iter().all(|model| model.context_length == Some(8192)));
+        let loaded: Vec<bool> = models.iter().map(|model| model.loaded == Some(true)).collect();
+        assert_eq!(loaded, [false, false, true, false]);
+        let mut shown: Vec<String> = server
+            .requests()
+            .into_iter()
+            .filter(|(path, _)| path == "/api/show")
+            .map(|(_, body)| body["model"].as_str().unwrap().to_string())
+            .collect();
+        shown.sort();
+        assert_eq!(shown, names);
+    }
+
+    #[tokio::test]
+    async fn generate_streams_response_text() {
+        let server = TestServer::start(|_, _| {
+            Reply::ok(NDJSON)
+                .send("{\"response\":\"4\"}\n")
+                .send("{\"response\":\"2\"}\n")
+                .send("{\"response\":\"\",\"done\":true,\"done_reason\":\"stop\"}\n")
+        })
+        .await;
+        let provider = OllamaProvider::new(reqwest::Client::new(), &server.url);
+        let request = GenerateRequest {
+            model: "test-model".to_string(),
+            prompt: "6 * 7?".to_string(),
+            system: None,
+            options: None,
+        };
+        let mut ignore = |_: &StreamChunk| {};
+
+        let response = provider
+            .generate(&request, &CancellationToken::new(), &mut ignore)
+            .await
+            .unwrap();
+        assert_eq!(response.content, "42");
+        assert_eq!(server.requests()[0].0, "/api/generate");
+    }
+}
@@ -0,0 +1,531 @@
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio_util::sync::CancellationToken;
+
+use super::tools::{parse_arguments, ToolCall};
+use super::{
+    error_message, read_lines, send, ChatMessage, ChatRequest, ChatRole, ChunkSink,
+    FinishReason, LlmError, LlmFuture, LlmProvider, LlmResponse, ModelStatus, OnChunk,
+    ResponseFormat, Usage, PROBE_TIMEOUT,
+};
+
+/// Data of one server-sent event
+#[derive(Debug, Default, Deserialize)]
+struct CompletionChunk {
+    #[serde(default)]
+    choices: Vec<Choice>,
+    /// Sent in the last event when `stream_options.include_usage` is honored
+    #[serde(default)]
+    usage: Option<Usage>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Choice {
+    #[serde(default)]
+    delta: Delta,
+    #[serde(default)]
+    finish_reason: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Delta {
+    #[serde(default)]
+    content: Option<String>,
+    #[serde(default)]
+    tool_calls: Vec<ToolCallDelta>,
+}
+
+/// Fragment of a tool call; fragments with the same index belong together
+#[derive(Debug, Default, Deserialize)]
+struct ToolCallDelta {
+    #[serde(default)]
+    index: usize,
+    #[serde(default)]
+    id: Option<String>,
+    #[serde(default)]
+    function: FunctionDelta,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct FunctionDelta {
+    #[serde(default)]
+    name: Option<String>,
+    /// Piece of the JSON-encoded arguments
+    #[serde(default)]
+    arguments: Option<String>,
+}
+
+/// Tool call assembled from fragments
+#[derive(Debug, Default)]
+struct PartialCall {
+    id: String,
+    name: String,
+    arguments: String,
+}
+
+impl PartialCall {
+    fn into_call(self) -> ToolCall {
+        let (arguments, arguments_error) = parse_arguments(self.arguments);
+        ToolCall {
+            id: self.id,
+            name: self.name,
+            arguments,
+            arguments_error,
+        }
+    }
+}
+
+/// Response of `/models`
+#[derive(Debug, Default, Deserialize)]
+struct ModelList {
+    #[serde(default)]
+    data: Vec<ListedModel>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ListedModel {
+    id: String,
+    /// Context length (vLLM)
+    #[serde(default)]
+    max_model_len: Option<u64>,
+    /// Model metadata (llama.cpp server)
+    #[serde(default)]
+    meta: Option<ModelMeta>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ModelMeta {
+    #[serde(default)]
+    n_ctx_train: Option<u64>,
+    /// Size on disk in bytes
+    #[serde(default)]
+    size: Option<u64>,
+}
+
+/// Provider for OpenAI-compatible servers (llama.cpp server, vLLM, LM Studio, ...)
+pub struct OpenAiProvider {
+    http: reqwest::Client,
+    base_url: String,
+    api_key: Option<String>,
+}
+
+impl OpenAiProvider {
+    /// Provider for the API rooted at `base_url` (e.g. `http://localhost:8080/v1`)
+    pub fn new(http: reqwest::Client, base_url: &str, api_key: Option<String>) -> Self {
+        Self {
+            http,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key,
+        }
+    }
+
+    /// Request to an API path, authenticated if a key is configured
+    fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        let request = self.http.request(method, format!("{}{}", self.base_url, path));
+        match &self.api_key {
+            Some(api_key) => request.bearer_auth(api_key),
+            None => request,
+        }
+    }
+
+    async fn models(&self) -> Result<Vec<ModelStatus>, LlmError> {
+        let request = self.request(reqwest::Method::GET, "/models");
+        let list: ModelList = send(request, "", &CancellationToken::new())
+            .await?
+            .json()
+            .await?;
+        let models = list
+            .data
+            .into_iter()
+            .map(|model| {
+                let meta = model.meta.unwrap_or_default();
+                ModelStatus {
+                    name: model.id,
+                    size: meta.size,
+                    context_length: model.max_model_len.or(meta.n_ctx_train),
+                    ..ModelStatus::default()
+                }
+            })
+            .collect();
+        Ok(models)
+    }
+
+    async fn stream_chat<'a>(
+        &self,
+        request: &ChatRequest,
+        cancel: &CancellationToken,
+        on_chunk: &'a mut OnChunk<'a>,
+    ) -> Result<LlmResponse, LlmError> {
+        // Options are top-level fields here (temperature, max_tokens, ...); they go in
+        // first so they can't replace the fields set below
+        let mut body = match &request.options {
+            Some(Value::Object(options)) => Value::Object(options.clone()),
+            _ => json!({}),
+        };
+        body["model"] = Value::from(request.model.as_str());
+        body["messages"] = Value::from(wire_messages(&request.messages));
+        body["stream"] = Value::Bool(true);
+        body["stream_options"] = json!({ "include_usage": true });
+        if !request.tools.is_empty() {
+            let tools: Vec<Value> = request.tools.iter().map(|tool| tool.to_wire()).collect();
+            body["tools"] = Value::from(tools);
+        }
+        match &request.format {
+            Some(ResponseFormat::Json) => {
+                body["response_format"] = json!({ "type": "json_object" });
+            }
+            Some(ResponseFormat::JsonSchema { schema }) => {
+                body["response_format"] = json!({
+                    "type": "json_schema",
+                    "json_schema": { "name": "response", "schema": schema },
+                });
+            }
+            None => {}
+        }
+
+        let http_request = self
+            .request(reqwest::Method::POST, "/chat/completions")
+            .json(&body);
+        let mut response = send(http_request, &request.model, cancel).await?;
+
+        let mut sink = ChunkSink::new(&request.model, on_chunk);
+        let mut finish_reason: Option<FinishReason> = None;
+        let mut usage = Usage::default();
+        let mut calls: Vec<PartialCall> = Vec::new();
+        // `data:` lines of the current event (an event ends at a blank line)
+        let mut data = String::new();
+        let mut dispatch = |data: &str| -> Result<bool, LlmError> {
+            if data.is_empty() {
+                return Ok(false);
+            }
+            if data == "[DONE]" {
+                return Ok(true);
+            }
+            let value: serde_json::Value = serde_json::from_str(data)
+                .map_err(|e| LlmError::InvalidResponse(e.to_string()))?;
+            if let Some(message) = error_message(&value) {
+                // Failures after the response started
+                return Err(LlmError::Status { status: 500, message });
+            }
+            let chunk: CompletionChunk = serde_json::from_value(value)
+                .map_err(|e| LlmError::InvalidResponse(e.to_string()))?;
+
+            for choice in chunk.choices {
+                if let Some(text) = &choice.delta.content {
+                    sink.text(text);
+                }
+                for fragment in choice.delta.tool_calls {
+                    if calls.len() <= fragment.index {
+                        calls.resize_with(fragment.index + 1, PartialCall::default);
+                    }
+                    let call = &mut calls[fragment.index];
+                    if let Some(id) = fragment.id {
+                        call.id = id;
+                    }
+                    if let Some(name) = fragment.function.name {
+                        call.name.push_str(&name);
+                    }
+                    if let Some(arguments) = fragment.function.arguments {
+                        call.arguments.push_str(&arguments);
+                    }
+                }
+                if let Some(reason) = &choice.finish_reason {
+                    finish_reason = Some(FinishReason::from_wire(reason));
+                }
+            }
+            if let Some(reported) = chunk.usage {
+                usage = reported;
+            }
+            Ok(false)
+        };
+
+        let finished = read_lines(&mut response, cancel, |line| {
+            let line = String::from_utf8_lossy(line);
+            if line.is_empty() {
+                let done = dispatch(&data)?;
+                data.clear();
+                return Ok(done);
+            }
+            // Other fields (`event:`, `id:`, comments) carry nothing we need
+            if let Some(value) = line.strip_prefix("data:") {
+                if !data.is_empty() {
+                    data.push('\n');
+                }
+                data.push_str(value.strip_prefix(' ').unwrap_or(value));
+            }
+            Ok(false)
+        })
+        .await?;
+        let finished = finished || dispatch(&data)?;
+
+        // Some servers close the stream without `[DONE]`
+        if !finished && finish_reason.is_none() {
+            return Err(LlmError::InvalidResponse(
+                "Stream ended before the response was complete".to_string(),
+            ));
+        }
+        for call in calls.into_iter().filter(|call| !call.name.is_empty()) {
+            sink.tool_call(call.into_call());
+        }
+        Ok(sink.finish(finish_reason, usage))
+    }
+}
+
+/// Messages in the OpenAI format (tool call arguments are JSON strings)
+fn wire_messages(messages: &[ChatMessage]) -> Vec<Value> {
+    messages
+        .iter()
+        .map(|message| {
+            let mut wire = json!({ "role": message.role, "content": message.content });
+            if !message.tool_calls.is_empty() {
+                let calls: Vec<Value> = message
+                    .tool_calls
+                    .iter()
+                    .map(|call| {
+                        // Invalid arguments go back as sent, so the model sees its mistake
+                        let arguments = match (&call.arguments, &call.arguments_error) {
+                            (Value::String(raw), Some(_)) => raw.clone(),
+                            (arguments, _) => arguments.to_string(),
+                        };
+                        json!({
+                            "id": call.id,
+                            "type": "function",
+                            "function": { "name": call.name, "arguments": arguments },
+                        })
+                    })
+                    .collect();
+                wire["tool_calls"] = Value::from(calls);
+            }
+            if let Some(id) = &message.tool_call_id {
+                wire["tool_call_id"] = Value::from(id.as_str());
+            }
+            wire
+        })
+        .collect()
+}
+
+impl LlmProvider for OpenAiProvider {
+    fn chat<'a>(
+        &'a self,
+        request: &'a ChatRequest,
+        cancel: &'a CancellationToken,
+        on_chunk: &'a mut OnChunk<'a>,
+    ) -> LlmFuture<'a, LlmResponse> {
+        Box::pin(self.stream_chat(request, cancel, on_chunk))
+    }
+
+    fn list_models(&self) -> LlmFuture<'_, Vec<ModelStatus>> {
+        Box::pin(self.models())
+    }
+
+    /// The API has no version endpoint; listing models shows the server answers
+    fn probe(&self) -> LlmFuture<'_, Option<String>> {
+        Box::pin(async move {
+            let request = self.request(reqwest::Method::GET, "/models").timeout(PROBE_TIMEOUT);
+            send(request, "", &CancellationToken::new()).await?;
+            Ok(None)
+        })
+    }
+
+    /// Servers that load models on demand do so for a one-token completion
+    fn warm_up<'a>(&'a self, model: &'a str) -> LlmFuture<'a, ()> {
+        Box::pin(async move {
+            let request = ChatRequest {
+                model: model.to_string(),
+                messages: vec![ChatMessage::new(ChatRole::User, "Hi")],
+                options: Some(json!({ "max_tokens": 1 })),
+                tools: Vec::new(),
+                format: None,
+            };
+            let mut ignore = |_: &_| {};
+            self.stream_chat(&request, &CancellationToken::new(), &mut ignore)
+                .await?;
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::test_server::{Reply, TestServer, EVENT_STREAM};
+    use crate::llm::tools::ToolRegistry;
+    use crate::llm::StreamChunk;
+
+    /// A server-sent event carrying `data`
+    fn event(data: &str) -> String {
+        format!("data: {}\n\n", data)
+    }
+
+    /// An event with one choice holding a text delta
+    fn text_event(text: &str) -> String {
+        event(&json!({ "choices": [{ "delta": { "content": text } }] }).to_string())
+    }
+
+    /// An event ending the choice
+    fn finish_event(reason: &str) -> String {
+        event(&json!({ "choices": [{ "delta": {}, "finish_reason": reason }] }).to_string())
+    }
+
+    fn request(options: Option<Value>) -> ChatRequest {
+        ChatRequest {
+            model: "test-model".to_string(),
+            messages: vec![ChatMessage::new(ChatRole::User, "Hi")],
+            options,
+            tools: Vec::new(),
+            format: None,
+        }
+    }
+
+    /// Chat with a stub that answers every request with `reply`
+    async fn chat(
+        reply: impl Fn() -> Reply + Send + Sync + 'static,
+        request: ChatRequest,
+    ) -> (TestServer, Result<LlmResponse, LlmError>, Vec<StreamChunk>) {
+        let server = TestServer::start(move |_, _| reply()).await;
+        let provider = OpenAiProvider::new(reqwest::Client::new(), &server.url, None);
+        let mut chunks = Vec::new();
+        let mut collect = |chunk: &StreamChunk| chunks.push(chunk.clone());
+        let result = provider
+            .chat(&request, &CancellationToken::new(), &mut collect)
+            .await;
+        (server, result, chunks)
+    }
+
+    #[tokio::test]
+    async fn streams_events_until_done() {
+        let usage = json!({
+            "choices": [],
+            "usage": { "prompt_tokens": 3, "completion_tokens": 2 },
+        });
+        let (server, result, chunks) = chat(
+            move || {
+                Reply::ok(EVENT_STREAM)
+                    .send(&text_event("Hel"))
+                    .send(&text_event("lo"))
+                    .send(&finish_event("stop"))
+                    .send(&event(&usage.to_string()))
+                    .send(&event("[DONE]"))
+            },
+            request(None),
+        )
+        .await;
+
+        let response = result.unwrap();
+        assert_eq!(response.content, "Hello");
+        assert_eq!(response.finish_reason, Some(FinishReason::Stop));
+        assert_eq!(response.usage.prompt_tokens, Some(3));
+        assert_eq!(response.usage.completion_tokens, Some(2));
+        let texts: Vec<&str> = chunks.iter().map(|chunk| chunk.content.as_str()).collect();
+        assert_eq!(texts, ["Hel", "lo", ""]);
+        assert!(chunks.last().unwrap().done);
+        assert_eq!(server.requests()[0].0, "/chat/completions");
+    }
+
+    #[tokio::test]
+    async fn accepts_finished_stream_without_done() {
+        let (_server, result, _) = chat(
+            || {
+                Reply::ok(EVENT_STREAM)
+                    .send(&text_event("Hi"))
+                    .send(&finish_event("length"))
+            },
+            request(None),
+        )
+        .await;
+
+        let response = result.unwrap();
+        assert_eq!(response.content, "Hi");
+        assert_eq!(response.finish_reason, Some(FinishReason::Length));
+    }
+
+    #[tokio::test]
+    async fn rejects_stream_cut_off_before_finish() {
+        let (_server, result, _) =
+            chat(|| Reply::ok(EVENT_STREAM).send(&text_event("Hi")), request(None)).await;
+
+        assert!(matches!(result, Err(LlmError::InvalidResponse(_))));
+    }
+
+    #[tokio::test]
+    async fn fails_on_error_event_mid_stream() {
+        let (_server, result, chunks) = chat(
+            || {
+                Reply::ok(EVENT_STREAM)
+                    .send(&text_event("Hi"))
+                    .send(&event(r#"{"error":{"message":"model crashed"}}"#))
+            },
+            request(None),
+        )
+        .await;
+
+        match result {
+            Err(LlmError::Status { status: 500, message }) => assert_eq!(message, "model crashed"),
+            other => panic!("expected a status error, got {:?}", other),
+        }
+        assert!(chunks.iter().all(|chunk| !chunk.done));
+    }
+
+    #[tokio::test]
+    async fn options_do_not_replace_request_fields() {
+        let options = json!({
+            "model": "other-model",
+            "stream": false,
+            "messages": [],
+            "stream_options": null,
+            "temperature": 0.2,
+        });
+        let (server, result, _) = chat(
+            || Reply::ok(EVENT_STREAM).send(&finish_event("stop")),
+            request(Some(options)),
+        )
+        .await;
+
+        result.unwrap();
+        let body = &server.requests()[0].1;
+        assert_eq!(body["model"], "test-model");
+        assert_eq!(body["stream"], true);
+        assert_eq!(body["messages"].as_array().unwrap().len(), 1);
+        assert_eq!(body["stream_options"]["include_usage"], true);
+        assert_eq!(body["temperature"], 0.2);
+    }
+
+    #[tokio::test]
+    async fn reports_invalid_tool_arguments() {
+        let call = |id: Option<&str>, arguments: &str| {
+            let mut fragment = json!({ "index": 0, "function": { "arguments": arguments } });
+            if let Some(id) = id {
+                fragment["id"] = Value::from(id);
+                fragment["function"]["name"] = Value::from("read_file");
+            }
+            event(&json!({ "choices": [{ "delta": { "tool_calls": [fragment] } }] }).to_string())
+        };
+        let (first, second) = (call(Some("call_a"), "{\"path\": "), call(None, "\"a.txt\""));
+        let (_server, result, _) = chat(
+            move || {
+                Reply::ok(EVENT_STREAM)
+                    .send(&first)
+                    .send(&second)
+                    .send(&finish_event("tool_calls"))
+            },
+            request(None),
+        )
+        .await;
+
+        let response = result.unwrap();
+        let call = &response.tool_calls[0];
+        assert_eq!(call.id, "call_a");
+        assert_eq!(call.arguments, Value::from("{\"path\": \"a.txt\""));
+        assert!(call.arguments_error.is_some());
+        let error = ToolRegistry::file_tools().validate(call).unwrap_err();
+        assert!(error.contains("not valid JSON"), "{}", error);
+
+        // The arguments go back to the model as it sent them
+        let message = ChatMessage {
+            tool_calls: response.tool_calls.clone(),
+            ..ChatMessage::new(ChatRole::Assistant, "")
+        };
+        let wire = wire_messages(&[message]);
+        assert_eq!(wire[0]["tool_calls"][0]["function"]["arguments"], "{\"path\": \"a.txt\"");
+    }
+}
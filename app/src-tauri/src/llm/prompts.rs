@@ -0,0 +1,774 @@
+// Role prompt templates. Each role's system prompt comes from the first of
+// `<project>/.nexus-overseer/prompts/<role>.md`, `<app config>/prompts/<role>.md`
+// and the built-in template, so teams can tune prompts per project or machine.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use super::{AiRole, LlmError};
+
+/// Directory of prompt templates inside the app config directory
+pub const PROMPTS_DIR: &str = "prompts";
+
+/// Directory of prompt templates inside a project
+pub const PROJECT_PROMPTS_DIR: &str = ".nexus-overseer/prompts";
+
+/// Variables every template can use (empty when unknown)
+pub const BUILTIN_VARIABLES: [&str; 6] =
+    ["role", "project_name", "project_root", "file_tree", "task", "date"];
+
+/// Directories left out of `file_tree`
+const IGNORED_DIRS: [&str; 8] =
+    [".git", "node_modules", "target", "dist", "build", ".next", "__pycache__", ".venv"];
+
+/// How deep `file_tree` goes below the project root
+const MAX_TREE_DEPTH: usize = 4;
+
+/// Entries listed in `file_tree` before the rest is summarized
+const MAX_TREE_ENTRIES: usize = 300;
+
+const BUILTIN_OVERSEER: &str = include_str!("../../prompts/overseer.md");
+const BUILTIN_IMPLEMENTATION: &str = include_str!("../../prompts/implementation.md");
+
+/// Where a template was loaded from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TemplateSource {
+    /// Compiled into the app
+    Builtin,
+    /// The app config directory
+    App,
+    /// The project's `.nexus-overseer/prompts`
+    Project,
+}
+
+/// Part of a parsed template
+#[derive(Debug, Clone)]
+enum Node {
+    Text(String),
+    /// `{{name}}`
+    Variable { name: String, line: usize },
+    /// `{{#name}}...{{/name}}`, or `{{^name}}...{{/name}}` when inverted
+    Section {
+        name: String,
+        inverted: bool,
+        line: usize,
+        children: Vec<Node>,
+    },
+}
+
+/// A role's prompt template
+#[derive(Debug, Clone)]
+pub struct PromptTemplate {
+    pub role: AiRole,
+    pub source: TemplateSource,
+    /// File the template was read from (`None` for built-in templates)
+    pub path: Option<PathBuf>,
+    /// `version` from the front matter
+    pub version: Option<String>,
+    /// `description` from the front matter
+    pub description: Option<String>,
+    /// First 12 hex digits of the file's SHA-256, identifying the exact text
+    pub hash: String,
+    nodes: Vec<Node>,
+}
+
+/// A template as listed for the UI
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TemplateInfo {
+    pub role: AiRole,
+    pub source: TemplateSource,
+    pub path: Option<String>,
+    pub version: Option<String>,
+    pub description: Option<String>,
+    /// Empty if the file couldn't be read
+    pub hash: String,
+    /// Whether this is the template the role uses
+    pub active: bool,
+    /// Why the file can't be used (read or syntax error)
+    pub error: Option<String>,
+}
+
+/// A rendered system prompt
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RenderedPrompt {
+    pub content: String,
+    /// The template it was rendered from
+    pub template: TemplateInfo,
+    /// Variables the template uses, with the values they got
+    pub variables: HashMap<String, String>,
+}
+
+impl PromptTemplate {
+    /// Parse a template file
+    ///
+    /// An optional front matter block (`---` lines around `key: value` pairs)
+    /// sets `version` and `description`; other keys are ignored.
+    ///
+    /// # Returns
+    /// * `Err(String)` - The template has a syntax error (with its line)
+    pub fn parse(
+        role: AiRole,
+        source: TemplateSource,
+        path: Option<PathBuf>,
+        text: &str,
+    ) -> Result<Self, String> {
+        let hash = format!("{:x}", Sha256::digest(text.as_bytes()));
+        let (front_matter, body, body_line) = split_front_matter(text);
+        let mut version = None;
+        let mut description = None;
+        for line in front_matter.lines() {
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim().trim_matches('"').to_string();
+            match key.trim() {
+                "version" => version = Some(value),
+                "description" => description = Some(value),
+                _ => {}
+            }
+        }
+
+        Ok(Self {
+            role,
+            source,
+            path,
+            version,
+            description,
+            hash: hash[..12].to_string(),
+            nodes: parse_nodes(body, body_line)?,
+        })
+    }
+
+    /// The template compiled into the app
+    pub fn builtin(role: AiRole) -> Self {
+        let text = match role {
+            AiRole::Overseer => BUILTIN_OVERSEER,
+            AiRole::Implementation => BUILTIN_IMPLEMENTATION,
+        };
+        Self::parse(role, TemplateSource::Builtin, None, text)
+            .expect("built-in templates are valid")
+    }
+
+    /// Names of the variables the template uses
+    pub fn variables(&self) -> Vec<String> {
+        fn collect(nodes: &[Node], names: &mut Vec<String>) {
+            for node in nodes {
+                let name = match node {
+                    Node::Text(_) => continue,
+                    Node::Variable { name, .. } => name,
+                    Node::Section { name, children, .. } => {
+                        collect(children, names);
+                        name
+                    }
+                };
+                if !names.contains(name) {
+                    names.push(name.clone());
+                }
+            }
+        }
+
+        let mut names = Vec::new();
+        collect(&self.nodes, &mut names);
+        names
+    }
+
+    /// Fill in the template
+    ///
+    /// `{{name}}` is replaced by the variable's value; `{{#name}}...{{/name}}`
+    /// is kept only if the value isn't empty and `{{^name}}...{{/name}}` only if
+    /// it is. Using a variable missing from `variables` is an error, so typos
+    /// don't silently produce empty text.
+    pub fn render(&self, variables: &HashMap<String, String>) -> Result<String, LlmError> {
+        fn render_nodes(
+            nodes: &[Node],
+            variables: &HashMap<String, String>,
+            out: &mut String,
+        ) -> Result<(), (String, usize)> {
+            for node in nodes {
+                match node {
+                    Node::Text(text) => out.push_str(text),
+                    Node::Variable { name, line } => {
+                        let value = variables.get(name).ok_or((name.clone(), *line))?;
+                        out.push_str(value);
+                    }
+                    Node::Section {
+                        name,
+                        inverted,
+                        line,
+                        children,
+                    } => {
+                        let value = variables.get(name).ok_or((name.clone(), *line))?;
+                        if value.trim().is_empty() == *inverted {
+                            render_nodes(children, variables, out)?;
+                        }
+                    }
+                }
+            }
+            Ok(())
+        }
+
+        let mut out = String::new();
+        render_nodes(&self.nodes, variables, &mut out).map_err(|(name, line)| {
+            LlmError::InvalidInput(format!(
+                "Unknown variable {} on line {} of the {} template",
+                name,
+                line,
+                self.describe()
+            ))
+        })?;
+        Ok(out.trim().to_string())
+    }
+
+    pub fn info(&self, active: bool) -> TemplateInfo {
+        TemplateInfo {
+            role: self.role,
+            source: self.source,
+            path: self.path.as_ref().map(|path| path.to_string_lossy().to_string()),
+            version: self.version.clone(),
+            description: self.description.clone(),
+            hash: self.hash.clone(),
+            active,
+            error: None,
+        }
+    }
+
+    /// E.g. `project overseer`
+    fn describe(&self) -> String {
+        let source = match self.source {
+            TemplateSource::Builtin => "built-in",
+            TemplateSource::App => "app",
+            TemplateSource::Project => "project",
+        };
+        format!("{} {}", source, role_name(self.role))
+    }
+}
+
+/// File name stem of a role's template
+fn role_name(role: AiRole) -> &'static str {
+    match role {
+        AiRole::Overseer => "overseer",
+        AiRole::Implementation => "implementation",
+    }
+}
+
+/// Split `---` front matter from the body
+///
+/// # Returns
+/// The front matter (empty if there is none), the body and the line the body starts on
+fn split_front_matter(text: &str) -> (&str, &str, usize) {
+    let Some(rest) = text.strip_prefix("---\n").or_else(|| text.strip_prefix("---\r\n")) else {
+        return ("", text, 1);
+    };
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if line.trim_end() == "---" {
+            let front_matter = &rest[..offset];
+            let body = &rest[offset + line.len()..];
+            return (front_matter, body, front_matter.lines().count() + 3);
+        }
+        offset += line.len();
+    }
+    // No closing line: not front matter
+    ("", text, 1)
+}
+
+/// Parse template text into nodes
+///
+/// Section and comment tags (`{{! ...}}`) alone on a line take the whole line
+/// with them, so they don't leave blank lines behind.
+fn parse_nodes(body: &str, first_line: usize) -> Result<Vec<Node>, String> {
+    // Open sections: (name, inverted, line, nodes before the section)
+    let mut stack: Vec<(String, bool, usize, Vec<Node>)> = Vec::new();
+    let mut nodes: Vec<Node> = Vec::new();
+    let mut text = String::new();
+    let mut rest = body;
+    let mut line = first_line;
+    // Whether `text` begins at the start of a line
+    let mut text_starts_line = true;
+
+    while let Some(start) = rest.find("{{") {
+        let (before, after) = rest.split_at(start);
+        line += before.matches('\n').count();
+        let end = after
+            .find("}}")
+            .ok_or_else(|| format!("Unclosed {{{{ on line {}", line))?;
+        let tag = after[2..end].trim();
+        let mut remainder = &after[end + 2..];
+        text.push_str(before);
+
+        let kind = tag.chars().next().filter(|c| matches!(c, '#' | '^' | '/' | '!'));
+        let name = match kind {
+            Some(_) => tag[1..].trim(),
+            None => tag,
+        };
+        if kind != Some('!') && !is_variable_name(name) {
+            return Err(format!("Invalid tag {{{{{}}}}} on line {}", tag, line));
+        }
+
+        // A tag on a line of its own
+        let line_start = text.rfind('\n').map(|i| i + 1);
+        let rest_of_line = remainder.find('\n').map_or(remainder, |i| &remainder[..i]);
+        let standalone = kind.is_some()
+            && (line_start.is_some() || text_starts_line)
+            && text[line_start.unwrap_or(0)..].trim().is_empty()
+            && rest_of_line.trim().is_empty();
+        text_starts_line = standalone;
+        if standalone {
+            let line_start = line_start.unwrap_or(0);
+            text.truncate(line_start);
+            remainder = remainder.find('\n').map_or("", |i| &remainder[i + 1..]);
+        }
+        if !text.is_empty() {
+            nodes.push(Node::Text(std::mem::take(&mut text)));
+        }
+
+        match kind {
+            Some('#') | Some('^') => {
+                let outer = std::mem::take(&mut nodes);
+                stack.push((name.to_string(), kind == Some('^'), line, outer));
+            }
+            Some('/') => {
+                let (open, inverted, open_line, outer) = stack
+                    .pop()
+                    .ok_or_else(|| format!("{{{{/{}}}}} without a section on line {}", name, line))?;
+                if open != name {
+                    return Err(format!(
+                        "{{{{/{}}}}} on line {} closes {{{{#{}}}}} from line {}",
+                        name, line, open, open_line
+                    ));
+                }
+                let children = std::mem::replace(&mut nodes, outer);
+                nodes.push(Node::Section {
+                    name: open,
+                    inverted,
+                    line: open_line,
+                    children,
+                });
+            }
+            Some(_) => {}
+            None => nodes.push(Node::Variable {
+                name: name.to_string(),
+                line,
+            }),
+        }
+        // Newlines inside the tag and the one a standalone tag took
+        line += after[..after.len() - remainder.len()].matches('\n').count();
+        rest = remainder;
+    }
+
+    if let Some((name, _, open_line, _)) = stack.pop() {
+        return Err(format!("{{{{#{}}}}} on line {} is never closed", name, open_line));
+    }
+    text.push_str(rest);
+    if !text.is_empty() {
+        nodes.push(Node::Text(text));
+    }
+    Ok(nodes)
+}
+
+fn is_variable_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Load a template file
+///
+/// # Returns
+/// * `Ok(None)` - There is no such file
+/// * `Err((path, error))` - The file exists but can't be used
+fn load_file(
+    role: AiRole,
+    source: TemplateSource,
+    dir: &Path,
+) -> Result<Option<PromptTemplate>, (PathBuf, String)> {
+    let path = dir.join(format!("{}.md", role_name(role)));
+    let text = match std::fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err((path, e.to_string())),
+    };
+    match PromptTemplate::parse(role, source, Some(path.clone()), &text) {
+        Ok(template) => Ok(Some(template)),
+        Err(error) => Err((path, error)),
+    }
+}
+
+/// Where templates are looked up
+#[derive(Debug, Clone)]
+pub struct TemplateDirs {
+    /// `<app config>/prompts`
+    pub app: PathBuf,
+    /// The project root, if a project is open
+    pub project_root: Option<PathBuf>,
+}
+
+impl TemplateDirs {
+    /// Template directories, most specific first
+    fn layers(&self) -> Vec<(TemplateSource, PathBuf)> {
+        let project = self.project_root.as_ref().map(|root| root.join(PROJECT_PROMPTS_DIR));
+        project
+            .map(|dir| (TemplateSource::Project, dir))
+            .into_iter()
+            .chain([(TemplateSource::App, self.app.clone())])
+            .collect()
+    }
+
+    /// Every template of a role, most specific first
+    ///
+    /// Files that exist but can't be used are listed with their error.
+    pub fn list(&self, role: AiRole) -> Vec<TemplateInfo> {
+        let mut found = Vec::new();
+        for (source, dir) in self.layers() {
+            match load_file(role, source, &dir) {
+                Ok(Some(template)) => found.push(template.info(false)),
+                Ok(None) => {}
+                Err((path, error)) => found.push(TemplateInfo {
+                    role,
+                    source,
+                    path: Some(path.to_string_lossy().to_string()),
+                    version: None,
+                    description: None,
+                    hash: String::new(),
+                    active: false,
+                    error: Some(error),
+                }),
+            }
+        }
+        found.push(PromptTemplate::builtin(role).info(false));
+
+        // An invalid file isn't skipped, so then no template is active
+        if let Some(first) = found.first_mut().filter(|info| info.error.is_none()) {
+            first.active = true;
+        }
+        found
+    }
+
+    /// The template a role uses: the project's, else the app's, else the built-in one
+    ///
+    /// # Returns
+    /// * `Err(LlmError::InvalidInput)` - The most specific template file is invalid
+    ///   (reported rather than silently skipped)
+    pub fn active(&self, role: AiRole) -> Result<PromptTemplate, LlmError> {
+        for (source, dir) in self.layers() {
+            match load_file(role, source, &dir) {
+                Ok(Some(template)) => return Ok(template),
+                Ok(None) => {}
+                Err((path, error)) => {
+                    return Err(LlmError::InvalidInput(format!(
+                        "Invalid prompt template {}: {}",
+                        path.display(),
+                        error
+                    )));
+                }
+            }
+        }
+        Ok(PromptTemplate::builtin(role))
+    }
+}
+
+/// Render a role's active template
+///
+/// # Arguments
+/// * `dirs` - Where to look for templates
+/// * `role` - Role whose prompt to render
+/// * `task` - Task text for `{{task}}`
+/// * `extra` - Further variables (these win over the built-in ones)
+/// * `is_denied` - Paths left out of `{{file_tree}}` (e.g. deny rules for AI actors)
+pub fn render_prompt(
+    dirs: &TemplateDirs,
+    role: AiRole,
+    task: Option<&str>,
+    extra: HashMap<String, String>,
+    is_denied: impl Fn(&Path) -> bool,
+) -> Result<RenderedPrompt, LlmError> {
+    let template = dirs.active(role)?;
+    let used = template.variables();
+    let root = dirs.project_root.as_deref();
+
+    let mut variables: HashMap<String, String> = HashMap::new();
+    for name in BUILTIN_VARIABLES {
+        let value = match name {
+            "role" => role_name(role).to_string(),
+            "project_name" => root
+                .and_then(Path::file_name)
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            "project_root" => root.map(|root| root.to_string_lossy().to_string()).unwrap_or_default(),
+            // Walking the project is the expensive part, so only when used
+            "file_tree" => match root {
+                Some(root) if used.iter().any(|name| name == "file_tree") => {
+                    file_tree(root, &is_denied)
+                }
+                _ => String::new(),
+            },
+            "task" => task.unwrap_or_default().to_string(),
+            "date" => chrono::Local::now().format("%Y-%m-%d").to_string(),
+            _ => String::new(),
+        };
+        variables.insert(name.to_string(), value);
+    }
+    variables.extend(extra);
+
+    let content = template.render(&variables)?;
+    variables.retain(|name, _| used.contains(name));
+    Ok(RenderedPrompt {
+        content,
+        template: template.info(true),
+        variables,
+    })
+}
+
+/// Indented listing of a project (directories end in `/`)
+///
+/// Skips `IGNORED_DIRS` and denied paths, stops `MAX_TREE_DEPTH` levels down
+/// and after `MAX_TREE_ENTRIES` entries.
+pub fn file_tree(root: &Path, is_denied: &impl Fn(&Path) -> bool) -> String {
+    fn walk(
+        dir: &Path,
+        depth: usize,
+        is_denied: &impl Fn(&Path) -> bool,
+        lines: &mut Vec<String>,
+        skipped: &mut usize,
+    ) {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return;
+        };
+        let mut entries: Vec<(String, PathBuf, bool)> = entries
+            .filter_map(Result::ok)
+            .map(|entry| {
+                let is_dir = entry.file_type().map(|kind| kind.is_dir()).unwrap_or(false);
+                (entry.file_name().to_string_lossy().to_string(), entry.path(), is_dir)
+            })
+            .filter(|(name, path, is_dir)| {
+                (!*is_dir || !IGNORED_DIRS.contains(&name.as_str())) && !is_denied(path)
+            })
+            .collect();
+        // Directories first, then by name
+        entries.sort_by(|a, b| b.2.cmp(&a.2).then_with(|| a.0.cmp(&b.0)));
+
+        for (name, path, is_dir) in entries {
+            if lines.len() >= MAX_TREE_ENTRIES {
+                *skipped += 1;
+                continue;
+            }
+            let indent = "  ".repeat(depth);
+            if is_dir {
+                lines.push(format!("{}{}/", indent, name));
+                if depth + 1 < MAX_TREE_DEPTH {
+                    walk(&path, depth + 1, is_denied, lines, skipped);
+                }
+            } else {
+                lines.push(format!("{}{}", indent, name));
+            }
+        }
+    }
+
+    let mut lines = Vec::new();
+    let mut skipped = 0;
+    walk(root, 0, is_denied, &mut lines, &mut skipped);
+    if skipped > 0 {
+        lines.push(format!("... ({} more entries)", skipped));
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::deny_rules::DenyRules;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir()
+            .join(format!("nexus-prompts-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn template(text: &str) -> PromptTemplate {
+        PromptTemplate::parse(AiRole::Overseer, TemplateSource::App, None, text).unwrap()
+    }
+
+    fn vars(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    fn write(dir: &Path, text: &str) {
+        std::fs::create_dir_all(dir).unwrap();
+        std::fs::write(dir.join("overseer.md"), text).unwrap();
+    }
+
+    #[test]
+    fn variables_are_substituted() {
+        let template = template("Hello {{ name }}, you work on {{project}}.");
+        let rendered = template
+            .render(&vars(&[("name", "Ada"), ("project", "nexus")]))
+            .unwrap();
+        assert_eq!(rendered, "Hello Ada, you work on nexus.");
+        assert_eq!(template.variables(), ["name", "project"]);
+    }
+
+    #[test]
+    fn sections_follow_whether_the_value_is_empty() {
+        let template = template(
+            "Start\n{{#task}}\nTask: {{task}}\n{{/task}}\n{{^task}}\nNo task\n{{/task}}\nEnd",
+        );
+        let with_task = template.render(&vars(&[("task", "fix it")])).unwrap();
+        assert_eq!(with_task, "Start\nTask: fix it\nEnd");
+        let without = template.render(&vars(&[("task", "  ")])).unwrap();
+        assert_eq!(without, "Start\nNo task\nEnd");
+    }
+
+    #[test]
+    fn unknown_variables_and_bad_syntax_are_errors() {
+        let error = template("Line one\n{{missing}}").render(&HashMap::new()).unwrap_err();
+        assert!(error.to_string().contains("missing on line 2"), "{}", error);
+
+        let parse = |text| PromptTemplate::parse(AiRole::Overseer, TemplateSource::App, None, text);
+        assert!(parse("{{#task}} never closed").unwrap_err().contains("never closed"));
+        assert!(parse("{{#a}}{{/b}}").unwrap_err().contains("closes"));
+        assert!(parse("{{not a name}}").unwrap_err().contains("Invalid tag"));
+    }
+
+    #[test]
+    fn front_matter_sets_the_version() {
+        let template = template("---\nversion: \"2.1\"\ndescription: Terse\n---\nBody {{x}}");
+        assert_eq!(template.version.as_deref(), Some("2.1"));
+        assert_eq!(template.description.as_deref(), Some("Terse"));
+        let error = template.render(&HashMap::new()).unwrap_err();
+        // Line numbers count the front matter
+        assert!(error.to_string().contains("line 5"), "{}", error);
+        assert_eq!(template.render(&vars(&[("x", "text")])).unwrap(), "Body text");
+    }
+
+    #[test]
+    fn project_templates_win_over_app_over_builtin() {
+        let base = temp_dir("layers");
+        let project_root = base.join("project");
+        std::fs::create_dir_all(&project_root).unwrap();
+        let dirs = TemplateDirs {
+            app: base.join("app"),
+            project_root: Some(project_root.clone()),
+        };
+        let builtin = PromptTemplate::builtin(AiRole::Overseer);
+
+        let active = dirs.active(AiRole::Overseer).unwrap();
+        assert_eq!(active.source, TemplateSource::Builtin);
+        assert_eq!(active.hash, builtin.hash);
+
+        write(&dirs.app, "---\nversion: app-1\n---\nApp prompt");
+        assert_eq!(dirs.active(AiRole::Overseer).unwrap().source, TemplateSource::App);
+
+        write(&project_root.join(PROJECT_PROMPTS_DIR), "---\nversion: project-3\n---\nProject");
+        let active = dirs.active(AiRole::Overseer).unwrap();
+        assert_eq!(active.source, TemplateSource::Project);
+        assert_eq!(active.version.as_deref(), Some("project-3"));
+
+        // Other roles still fall through to their built-in template
+        let implementation = dirs.active(AiRole::Implementation).unwrap();
+        assert_eq!(implementation.source, TemplateSource::Builtin);
+
+        let _ = std::fs::remove_dir_all(&base);
+    }
+
+    #[test]
+    fn listing_marks_the_version_in_use() {
+        let base = temp_dir("list");
+        let project_root = base.join("project");
+        let dirs = TemplateDirs {
+            app: base.join("app"),
+            project_root: Some(project_root.clone()),
+        };
+        write(&dirs.app, "---\nversion: app-1\n---\nApp prompt");
+        write(&project_root.join(PROJECT_PROMPTS_DIR), "---\nversion: project-3\n---\nProject");
+
+        let listed = dirs.list(AiRole::Overseer);
+        let summary: Vec<(TemplateSource, Option<&str>, bool)> = listed
+            .iter()
+            .map(|info| (info.source, info.version.as_deref(), info.active))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (TemplateSource::Project, Some("project-3"), true),
+                (TemplateSource::App, Some("app-1"), false),
+                (TemplateSource::Builtin, Some("1"), false),
+            ]
+        );
+        let rendered = render_prompt(&dirs, AiRole::Overseer, None, HashMap::new(), |_| false)
+            .unwrap();
+        assert_eq!(rendered.template.version.as_deref(), Some("project-3"));
+        assert_eq!(rendered.content, "Project");
+
+        // A broken project template is reported, not skipped for the app's
+        write(&project_root.join(PROJECT_PROMPTS_DIR), "{{#task}}");
+        let listed = dirs.list(AiRole::Overseer);
+        assert!(listed[0].error.is_some());
+        assert!(listed.iter().all(|info| !info.active));
+        assert!(dirs.active(AiRole::Overseer).is_err());
+
+        let _ = std::fs::remove_dir_all(&base);
+    }
+
+    #[test]
+    fn file_tree_leaves_out_denied_and_ignored_files() {
+        let base = temp_dir("tree");
+        let root = base.join("app");
+        for dir in ["src", ".ssh", "node_modules/pkg", "config"] {
+            std::fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for file in [
+            "src/main.rs",
+            ".env",
+            ".env.example",
+            ".ssh/id_rsa",
+            "node_modules/pkg/index.js",
+            "config/server.pem",
+            "config/app.toml",
+        ] {
+            std::fs::write(root.join(file), "").unwrap();
+        }
+        let dirs = TemplateDirs {
+            app: base.join("prompts"),
+            project_root: Some(root.clone()),
+        };
+        write(&dirs.app, "{{project_name}}:\n{{file_tree}}");
+        let rules = DenyRules::new();
+
+        let rendered = render_prompt(
+            &dirs,
+            AiRole::Overseer,
+            None,
+            HashMap::new(),
+            |path| rules.is_denied(path),
+        )
+        .unwrap();
+        assert_eq!(
+            rendered.content,
+            "app:\nconfig/\n  app.toml\nsrc/\n  main.rs\n.env.example"
+        );
+        assert_eq!(rendered.variables.len(), 2);
+
+        let _ = std::fs::remove_dir_all(&base);
+    }
+
+    #[test]
+    fn extra_variables_win_over_builtin_ones() {
+        let base = temp_dir("extra");
+        let dirs = TemplateDirs {
+            app: base.join("prompts"),
+            project_root: None,
+        };
+        write(&dirs.app, "{{role}} / {{task}} / {{project_name}}");
+        let extra = vars(&[("role", "reviewer")]);
+
+        let rendered = render_prompt(&dirs, AiRole::Overseer, Some("ship"), extra, |_| false)
+            .unwrap();
+        assert_eq!(rendered.content, "reviewer / ship /");
+        assert_eq!(rendered.variables["task"], "ship");
+
+        let _ = std::fs::remove_dir_all(&base);
+    }
+}
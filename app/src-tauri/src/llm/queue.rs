@@ -0,0 +1,695 @@
+// Request scheduling. Each provider runs a limited number of model calls at
+// once (one by default: a local server usually has a single GPU); the rest
+// wait, interactive calls ahead of background ones and otherwise first come,
+// first served. An interactive call that has to wait preempts the most recently
+// started background call, which is cancelled and starts over once it gets a
+// slot again.
+
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::sync::oneshot;
+use tokio_util::sync::CancellationToken;
+
+use super::cache::LlmCall;
+use super::{
+    AiRole, ChatRequest, GenerateRequest, LlmError, LlmFuture, LlmProvider, LlmResponse,
+    ModelStatus, OnChunk, OnPullProgress, StreamChunk,
+};
+
+/// Model calls a provider runs at once unless configured otherwise
+pub const DEFAULT_MAX_CONCURRENT: usize = 1;
+
+/// Weight of the latest call in the running average of call durations
+const AVERAGE_WEIGHT: f64 = 0.3;
+
+/// Scheduling class of a request
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    /// Batch work (implementation tasks); yields to interactive requests
+    Background,
+    /// Someone is waiting for the answer (overseer chat)
+    Interactive,
+}
+
+impl Priority {
+    /// Priority of a role's requests unless they set one
+    pub fn for_role(role: Option<AiRole>) -> Self {
+        match role {
+            Some(AiRole::Implementation) => Priority::Background,
+            _ => Priority::Interactive,
+        }
+    }
+}
+
+/// A queued or running model call
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueueEntry {
+    pub request_id: String,
+    pub priority: Priority,
+    /// Time since the call started (running) or was queued (waiting)
+    pub elapsed_ms: u64,
+    /// Estimated time until the call finishes (running) or starts (waiting);
+    /// unknown until a call on the provider has completed
+    pub eta_ms: Option<u64>,
+    /// Cancelled to make room for an interactive call; it queues again
+    pub preempted: bool,
+}
+
+/// Queue of one provider
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProviderQueueStatus {
+    pub provider: String,
+    pub max_concurrent: usize,
+    pub running: Vec<QueueEntry>,
+    /// In the order they will start
+    pub waiting: Vec<QueueEntry>,
+    /// Running average of call durations
+    pub average_ms: Option<u64>,
+}
+
+/// A call holding a slot
+struct Running {
+    id: u64,
+    request_id: String,
+    priority: Priority,
+    started: Instant,
+    /// Cancels the call (but not its request) when preempted
+    call: CancellationToken,
+    preempted: bool,
+}
+
+/// A call waiting for a slot
+struct Waiting {
+    id: u64,
+    /// Position among all requests (requests keep theirs across calls)
+    order: u64,
+    request_id: String,
+    priority: Priority,
+    queued: Instant,
+    call: CancellationToken,
+    ready: oneshot::Sender<()>,
+}
+
+#[derive(Default)]
+struct ProviderQueue {
+    max_concurrent: usize,
+    running: Vec<Running>,
+    waiting: Vec<Waiting>,
+    average_ms: Option<f64>,
+}
+
+impl ProviderQueue {
+    /// Start waiting calls while there are free slots
+    fn dispatch(&mut self) {
+        while self.running.len() < self.max_concurrent {
+            let next = self
+                .waiting
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, waiting)| (Reverse(waiting.priority), waiting.order))
+                .map(|(index, _)| index);
+            let Some(index) = next else {
+                break;
+            };
+            let waiting = self.waiting.remove(index);
+            // The caller is gone if it can't be told
+            if waiting.ready.send(()).is_ok() {
+                self.running.push(Running {
+                    id: waiting.id,
+                    request_id: waiting.request_id,
+                    priority: waiting.priority,
+                    started: Instant::now(),
+                    call: waiting.call,
+                    preempted: false,
+                });
+            }
+        }
+    }
+
+    /// Cancel the newest background call for each interactive call without a slot
+    fn preempt(&mut self) {
+        let interactive = self
+            .waiting
+            .iter()
+            .filter(|waiting| waiting.priority == Priority::Interactive)
+            .count();
+        let preempted = self.running.iter().filter(|running| running.preempted).count();
+        if interactive <= preempted {
+            return;
+        }
+        let victim = self
+            .running
+            .iter_mut()
+            .filter(|running| running.priority == Priority::Background && !running.preempted)
+            .max_by_key(|running| running.started);
+        if let Some(victim) = victim {
+            victim.preempted = true;
+            victim.call.cancel();
+        }
+    }
+
+    fn status(&self, provider: &str) -> ProviderQueueStatus {
+        let now = Instant::now();
+        let average = self.average_ms;
+        let remaining = |running: &Running| {
+            let elapsed = now.duration_since(running.started).as_millis() as f64;
+            match running.preempted {
+                true => 0.0,
+                false => average.map_or(0.0, |average| (average - elapsed).max(0.0)),
+            }
+        };
+
+        // When each slot frees up; slots beyond the limit (after it was
+        // lowered) go away with the calls that finish first
+        let mut slots: Vec<f64> = self.running.iter().map(remaining).collect();
+        slots.sort_by(f64::total_cmp);
+        let excess = slots.len().saturating_sub(self.max_concurrent);
+        slots.drain(..excess);
+        slots.resize(self.max_concurrent, 0.0);
+
+        let mut waiting: Vec<&Waiting> = self.waiting.iter().collect();
+        waiting.sort_by_key(|waiting| (Reverse(waiting.priority), waiting.order));
+        let waiting = waiting
+            .into_iter()
+            .map(|waiting| {
+                let eta = average.and_then(|average| {
+                    let slot = slots.iter_mut().min_by(|a, b| a.total_cmp(b))?;
+                    let starts = *slot;
+                    *slot += average;
+                    Some(starts as u64)
+                });
+                QueueEntry {
+                    request_id: waiting.request_id.clone(),
+                    priority: waiting.priority,
+                    elapsed_ms: now.duration_since(waiting.queued).as_millis() as u64,
+                    eta_ms: eta,
+                    preempted: false,
+                }
+            })
+            .collect();
+
+        let running = self
+            .running
+            .iter()
+            .map(|running| QueueEntry {
+                request_id: running.request_id.clone(),
+                priority: running.priority,
+                elapsed_ms: now.duration_since(running.started).as_millis() as u64,
+                eta_ms: average.map(|_| remaining(running) as u64),
+                preempted: running.preempted,
+            })
+            .collect();
+
+        ProviderQueueStatus {
+            provider: provider.to_string(),
+            max_concurrent: self.max_concurrent,
+            running,
+            waiting,
+            average_ms: average.map(|average| average as u64),
+        }
+    }
+}
+
+#[derive(Default)]
+struct QueueInner {
+    providers: HashMap<String, ProviderQueue>,
+    next_id: u64,
+}
+
+impl QueueInner {
+    fn next_id(&mut self) -> u64 {
+        self.next_id += 1;
+        self.next_id
+    }
+}
+
+/// Model calls waiting for or holding a provider slot (thread-safe, cheap to clone)
+#[derive(Clone, Default)]
+pub struct LlmQueue {
+    inner: Arc<Mutex<QueueInner>>,
+}
+
+impl LlmQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Wrap a provider so every model call of a request waits its turn
+    ///
+    /// Chat and completion calls are queued; listing, probing and managing
+    /// models are not. The request keeps its place in line across its calls.
+    ///
+    /// # Arguments
+    /// * `provider_id` - Queue to use
+    /// * `max_concurrent` - Calls the provider runs at once
+    /// * `provider` - The provider to wrap
+    /// * `request_id` - Shown in the status
+    /// * `priority` - Scheduling class of the calls
+    pub fn provider(
+        &self,
+        provider_id: &str,
+        max_concurrent: usize,
+        provider: Box<dyn LlmProvider>,
+        request_id: &str,
+        priority: Priority,
+    ) -> Box<dyn LlmProvider> {
+        let order = self.inner.lock().unwrap().next_id();
+        Box::new(QueuedProvider {
+            inner: provider,
+            queue: self.clone(),
+            ticket: Ticket {
+                provider_id: provider_id.to_string(),
+                max_concurrent,
+                request_id: request_id.to_string(),
+                priority,
+                order,
+            },
+        })
+    }
+
+    /// Queue depth, running calls and estimates per provider
+    pub fn status(&self) -> Vec<ProviderQueueStatus> {
+        let inner = self.inner.lock().unwrap();
+        let mut status: Vec<ProviderQueueStatus> = inner
+            .providers
+            .iter()
+            .map(|(id, queue)| queue.status(id))
+            .collect();
+        status.sort_by(|a, b| a.provider.cmp(&b.provider));
+        status
+    }
+
+    /// Wait for a slot
+    ///
+    /// # Arguments
+    /// * `call` - Cancelled if the call is preempted
+    /// * `cancel` - The request's token
+    async fn acquire(
+        &self,
+        ticket: &Ticket,
+        call: &CancellationToken,
+        cancel: &CancellationToken,
+    ) -> Result<Slot, LlmError> {
+        let (ready, slot) = {
+            let mut inner = self.inner.lock().unwrap();
+            let id = inner.next_id();
+            let queue = inner.providers.entry(ticket.provider_id.clone()).or_default();
+            queue.max_concurrent = ticket.max_concurrent;
+            let (ready, waiting) = oneshot::channel();
+            queue.waiting.push(Waiting {
+                id,
+                order: ticket.order,
+                request_id: ticket.request_id.clone(),
+                priority: ticket.priority,
+                queued: Instant::now(),
+                call: call.clone(),
+                ready,
+            });
+            queue.dispatch();
+            if ticket.priority == Priority::Interactive {
+                queue.preempt();
+            }
+            let slot = Slot {
+                queue: self.clone(),
+                provider_id: ticket.provider_id.clone(),
+                id,
+                completed: false,
+            };
+            (waiting, slot)
+        };
+
+        // Dropping the slot leaves the line
+        tokio::select! {
+            _ = cancel.cancelled() => Err(LlmError::Cancelled),
+            started = ready => started.map(|_| slot).map_err(|_| LlmError::Cancelled),
+        }
+    }
+}
+
+/// A place in line or a running call; frees it when dropped
+struct Slot {
+    queue: LlmQueue,
+    provider_id: String,
+    id: u64,
+    /// Whether the call ran to completion (only those count toward the average)
+    completed: bool,
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        let mut inner = self.queue.inner.lock().unwrap();
+        let Some(queue) = inner.providers.get_mut(&self.provider_id) else {
+            return;
+        };
+        match queue.running.iter().position(|running| running.id == self.id) {
+            Some(index) => {
+                let running = queue.running.remove(index);
+                if self.completed {
+                    let duration = running.started.elapsed().as_millis() as f64;
+                    queue.average_ms = Some(match queue.average_ms {
+                        Some(average) => average + AVERAGE_WEIGHT * (duration - average),
+                        None => duration,
+                    });
+                }
+            }
+            None => queue.waiting.retain(|waiting| waiting.id != self.id),
+        }
+        queue.dispatch();
+    }
+}
+
+/// Where a request's calls go in line
+#[derive(Debug, Clone)]
+struct Ticket {
+    provider_id: String,
+    max_concurrent: usize,
+    request_id: String,
+    priority: Priority,
+    order: u64,
+}
+
+/// A provider whose model calls wait for a slot
+struct QueuedProvider {
+    inner: Box<dyn LlmProvider>,
+    queue: LlmQueue,
+    ticket: Ticket,
+}
+
+impl QueuedProvider {
+    /// Run a call in a slot, starting over when it is preempted
+    ///
+    /// The text streamed before a preemption is void; a chunk with `restart`
+    /// set tells `on_chunk` so.
+    fn run<'a>(
+        &'a self,
+        call: LlmCall<'a>,
+        cancel: &'a CancellationToken,
+        on_chunk: &'a mut OnChunk<'a>,
+    ) -> LlmFuture<'a, LlmResponse> {
+        Box::pin(async move {
+            loop {
+                let token = cancel.child_token();
+                let mut slot = self.queue.acquire(&self.ticket, &token, cancel).await?;
+                let result = call.run(self.inner.as_ref(), &token, &mut *on_chunk).await;
+
+                let preempted = token.is_cancelled() && !cancel.is_cancelled();
+                if preempted && matches!(result, Err(LlmError::Cancelled)) {
+                    drop(slot);
+                    on_chunk(&StreamChunk {
+                        restart: true,
+                        ..StreamChunk::default()
+                    });
+                    continue;
+                }
+                slot.completed = result.is_ok();
+                return result;
+            }
+        })
+    }
+}
+
+impl LlmProvider for QueuedProvider {
+    fn chat<'a>(
+        &'a self,
+        request: &'a ChatRequest,
+        cancel: &'a CancellationToken,
+        on_chunk: &'a mut OnChunk<'a>,
+    ) -> LlmFuture<'a, LlmResponse> {
+        self.run(LlmCall::Chat(request), cancel, on_chunk)
+    }
+
+    fn generate<'a>(
+        &'a self,
+        request: &'a GenerateRequest,
+        cancel: &'a CancellationToken,
+        on_chunk: &'a mut OnChunk<'a>,
+    ) -> LlmFuture<'a, LlmResponse> {
+        self.run(LlmCall::Generate(request), cancel, on_chunk)
+    }
+
+    fn list_models(&self) -> LlmFuture<'_, Vec<ModelStatus>> {
+        self.inner.list_models()
+    }
+
+    fn probe(&self) -> LlmFuture<'_, Option<String>> {
+        self.inner.probe()
+    }
+
+    fn warm_up<'a>(&'a self, model: &'a str) -> LlmFuture<'a, ()> {
+        self.inner.warm_up(model)
+    }
+
+    fn pull_model<'a>(
+        &'a self,
+        model: &'a str,
+        cancel: &'a CancellationToken,
+        on_progress: &'a mut OnPullProgress<'a>,
+    ) -> LlmFuture<'a, ()> {
+        self.inner.pull_model(model, cancel, on_progress)
+    }
+
+    fn delete_model<'a>(&'a self, model: &'a str) -> LlmFuture<'a, ()> {
+        self.inner.delete_model(model)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::{ChatMessage, ChatRole};
+    use std::time::Duration;
+    use tokio::sync::Semaphore;
+    use tokio::task::JoinHandle;
+
+    /// Provider whose calls stream `partial`, then wait for a permit (or cancellation)
+    #[derive(Clone)]
+    struct Gated {
+        /// Prompts of the calls started so far, in order
+        started: Arc<Mutex<Vec<String>>>,
+        permits: Arc<Semaphore>,
+    }
+
+    impl Gated {
+        fn new() -> Self {
+            Self {
+                started: Arc::new(Mutex::new(Vec::new())),
+                permits: Arc::new(Semaphore::new(0)),
+            }
+        }
+
+        fn started(&self) -> Vec<String> {
+            self.started.lock().unwrap().clone()
+        }
+
+        /// Let one running call finish
+        fn release(&self) {
+            self.permits.add_permits(1);
+        }
+    }
+
+    impl LlmProvider for Gated {
+        fn chat<'a>(
+            &'a self,
+            request: &'a ChatRequest,
+            cancel: &'a CancellationToken,
+            on_chunk: &'a mut OnChunk<'a>,
+        ) -> LlmFuture<'a, LlmResponse> {
+            Box::pin(async move {
+                let prompt = request.messages[0].content.clone();
+                self.started.lock().unwrap().push(prompt.clone());
+                on_chunk(&StreamChunk {
+                    content: "partial".to_string(),
+                    ..StreamChunk::default()
+                });
+                tokio::select! {
+                    _ = cancel.cancelled() => Err(LlmError::Cancelled),
+                    permit = self.permits.acquire() => {
+                        permit.unwrap().forget();
+                        Ok(LlmResponse {
+                            content: prompt,
+                            ..LlmResponse::default()
+                        })
+                    }
+                }
+            })
+        }
+
+        fn list_models(&self) -> LlmFuture<'_, Vec<ModelStatus>> {
+            Box::pin(async { Ok(Vec::new()) })
+        }
+
+        fn probe(&self) -> LlmFuture<'_, Option<String>> {
+            Box::pin(async { Ok(None) })
+        }
+
+        fn warm_up<'a>(&'a self, _model: &'a str) -> LlmFuture<'a, ()> {
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    type Outcome = (Result<LlmResponse, LlmError>, Vec<StreamChunk>);
+
+    /// Start a request named `name` on a provider with one slot
+    fn spawn(
+        queue: &LlmQueue,
+        gated: &Gated,
+        name: &str,
+        priority: Priority,
+        cancel: CancellationToken,
+    ) -> JoinHandle<Outcome> {
+        let provider = queue.provider("stub", 1, Box::new(gated.clone()), name, priority);
+        let request = ChatRequest {
+            model: "test-model".to_string(),
+            messages: vec![ChatMessage::new(ChatRole::User, name)],
+            options: None,
+            tools: Vec::new(),
+            format: None,
+        };
+        tokio::spawn(async move {
+            let mut chunks = Vec::new();
+            let mut collect = |chunk: &StreamChunk| chunks.push(chunk.clone());
+            let result = provider.chat(&request, &cancel, &mut collect).await;
+            (result, chunks)
+        })
+    }
+
+    /// Wait until the provider's queue looks like `running` and `waiting`
+    async fn wait_for(queue: &LlmQueue, running: &[&str], waiting: &[&str]) {
+        let names = |entries: &[QueueEntry]| -> Vec<String> {
+            entries.iter().map(|entry| entry.request_id.clone()).collect()
+        };
+        let expected = (
+            running.iter().map(|name| name.to_string()).collect::<Vec<_>>(),
+            waiting.iter().map(|name| name.to_string()).collect::<Vec<_>>(),
+        );
+        let mut current = (Vec::new(), Vec::new());
+        for _ in 0..500 {
+            let status = queue.status();
+            current = status
+                .first()
+                .map(|status| (names(&status.running), names(&status.waiting)))
+                .unwrap_or_default();
+            if current == expected {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("queue is {:?}, expected {:?}", current, expected);
+    }
+
+    async fn finish(handle: JoinHandle<Outcome>) -> Outcome {
+        tokio::time::timeout(Duration::from_secs(5), handle).await.unwrap().unwrap()
+    }
+
+    #[tokio::test]
+    async fn interactive_calls_go_ahead_of_background_ones() {
+        let queue = LlmQueue::new();
+        let gated = Gated::new();
+        let cancel = CancellationToken::new();
+        let start = |name, priority| spawn(&queue, &gated, name, priority, cancel.clone());
+
+        let first = start("first", Priority::Interactive);
+        wait_for(&queue, &["first"], &[]).await;
+        let handles = [
+            first,
+            start("batch-1", Priority::Background),
+            start("batch-2", Priority::Background),
+            start("chat", Priority::Interactive),
+        ];
+        wait_for(&queue, &["first"], &["chat", "batch-1", "batch-2"]).await;
+
+        // Only one call runs at a time, so the slot decides who goes next
+        for _ in &handles {
+            gated.release();
+        }
+        for handle in handles {
+            assert!(finish(handle).await.0.is_ok());
+        }
+        assert_eq!(gated.started(), ["first", "chat", "batch-1", "batch-2"]);
+        assert!(queue.status()[0].average_ms.is_some());
+    }
+
+    #[tokio::test]
+    async fn preempted_background_calls_start_over() {
+        let queue = LlmQueue::new();
+        let gated = Gated::new();
+        let cancel = CancellationToken::new();
+
+        let batch = spawn(&queue, &gated, "batch", Priority::Background, cancel.clone());
+        wait_for(&queue, &["batch"], &[]).await;
+        let chat = spawn(&queue, &gated, "chat", Priority::Interactive, cancel.clone());
+        // The background call gives up its slot and queues again
+        wait_for(&queue, &["chat"], &["batch"]).await;
+        assert_eq!(gated.started(), ["batch", "chat"]);
+
+        gated.release();
+        assert_eq!(finish(chat).await.0.unwrap().content, "chat");
+        gated.release();
+        let (result, chunks) = finish(batch).await;
+        assert_eq!(result.unwrap().content, "batch");
+        assert_eq!(gated.started(), ["batch", "chat", "batch"]);
+        // The text streamed before the preemption is voided
+        let flow: Vec<(&str, bool)> = chunks
+            .iter()
+            .map(|chunk| (chunk.content.as_str(), chunk.restart))
+            .collect();
+        assert_eq!(flow, [("partial", false), ("", true), ("partial", false)]);
+    }
+
+    #[tokio::test]
+    async fn interactive_calls_do_not_preempt_each_other() {
+        let queue = LlmQueue::new();
+        let gated = Gated::new();
+        let cancel = CancellationToken::new();
+
+        let first = spawn(&queue, &gated, "first", Priority::Interactive, cancel.clone());
+        wait_for(&queue, &["first"], &[]).await;
+        let second = spawn(&queue, &gated, "second", Priority::Interactive, cancel.clone());
+        wait_for(&queue, &["first"], &["second"]).await;
+        assert!(!queue.status()[0].running[0].preempted);
+
+        gated.release();
+        gated.release();
+        assert!(finish(first).await.0.is_ok());
+        assert!(finish(second).await.0.is_ok());
+        assert_eq!(gated.started(), ["first", "second"]);
+    }
+
+    #[tokio::test]
+    async fn cancelled_and_dropped_callers_free_their_place() {
+        let queue = LlmQueue::new();
+        let gated = Gated::new();
+        let running_cancel = CancellationToken::new();
+        let waiting_cancel = CancellationToken::new();
+        let cancel = CancellationToken::new();
+
+        let start = |name, cancel: &CancellationToken| {
+            spawn(&queue, &gated, name, Priority::Background, cancel.clone())
+        };
+
+        let running = start("running", &running_cancel);
+        wait_for(&queue, &["running"], &[]).await;
+        let cancelled = start("cancelled", &waiting_cancel);
+        let dropped = start("dropped", &cancel);
+        let next = start("next", &cancel);
+        wait_for(&queue, &["running"], &["cancelled", "dropped", "next"]).await;
+
+        waiting_cancel.cancel();
+        assert!(matches!(finish(cancelled).await.0, Err(LlmError::Cancelled)));
+        dropped.abort();
+        wait_for(&queue, &["running"], &["next"]).await;
+
+        // Cancelling the running call hands its slot on without a release
+        running_cancel.cancel();
+        assert!(matches!(finish(running).await.0, Err(LlmError::Cancelled)));
+        wait_for(&queue, &["next"], &[]).await;
+        gated.release();
+        assert!(finish(next).await.0.is_ok());
+        assert_eq!(gated.started(), ["running", "next"]);
+        wait_for(&queue, &[], &[]).await;
+    }
+}
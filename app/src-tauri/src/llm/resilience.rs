@@ -0,0 +1,796 @@
+// Resilience policy for calls to LLM servers. Every provider is wrapped so its
+// calls time out when the server stops answering, transient failures are
+// retried with exponential backoff and jitter, and a per-provider circuit
+// breaker fails calls fast with `ServerDown` after repeated failures until a
+// cooldown has passed and a trial call gets through.
+
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::RandomState;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+use tokio_util::sync::CancellationToken;
+
+use super::cache::LlmCall;
+use super::{
+    ChatRequest, GenerateRequest, LlmError, LlmFuture, LlmProvider, LlmResponse, ModelStatus,
+    OnChunk, OnPullProgress, PullProgress, StreamChunk,
+};
+
+/// Event carrying a provider's circuit breaker state when it changes
+pub const LLM_PROVIDER_STATUS: &str = "llm-provider-status";
+
+/// No activity seen yet (see `with_read_timeout`)
+const NO_ACTIVITY: u64 = u64::MAX;
+
+/// Timeouts, retries and circuit breaker limits for calls to LLM servers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ResilienceConfig {
+    /// How long to wait for a connection
+    pub connect_timeout_ms: u64,
+    /// How long to wait for the first chunk of a reply, or for a model to load
+    pub first_chunk_timeout_ms: u64,
+    /// How long the server may go quiet in the middle of a reply, and how
+    /// long other calls (listing, deleting models) may take
+    pub read_timeout_ms: u64,
+    /// Retries after the first attempt
+    pub max_retries: u32,
+    /// Delay before the first retry; doubled for each further one
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+    /// Consecutive failures that mark the server down
+    pub failure_threshold: u32,
+    /// How long calls fail fast before a trial call is let through
+    pub cooldown_ms: u64,
+}
+
+impl Default for ResilienceConfig {
+    fn default() -> Self {
+        Self {
+            connect_timeout_ms: 10_000,
+            // Loading a large model from disk takes minutes on some machines
+            first_chunk_timeout_ms: 300_000,
+            read_timeout_ms: 60_000,
+            max_retries: 2,
+            initial_backoff_ms: 500,
+            max_backoff_ms: 8_000,
+            failure_threshold: 3,
+            cooldown_ms: 30_000,
+        }
+    }
+}
+
+impl ResilienceConfig {
+    /// Check that the limits are usable
+    pub fn validate(&self) -> Result<(), LlmError> {
+        let timeouts = [
+            ("connect_timeout_ms", self.connect_timeout_ms),
+            ("first_chunk_timeout_ms", self.first_chunk_timeout_ms),
+            ("read_timeout_ms", self.read_timeout_ms),
+        ];
+        for (name, value) in timeouts {
+            if value == 0 {
+                return Err(LlmError::InvalidInput(format!("{} must be positive", name)));
+            }
+        }
+        if self.failure_threshold == 0 {
+            return Err(LlmError::InvalidInput(
+                "failure_threshold must be at least 1".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn connect_timeout(&self) -> Duration {
+        Duration::from_millis(self.connect_timeout_ms)
+    }
+
+    /// Delay before retry number `attempt` (from 0): exponential, capped, with
+    /// the upper half jittered so clients that failed together don't retry together
+    fn backoff(&self, attempt: u32) -> Duration {
+        let base = self
+            .initial_backoff_ms
+            .saturating_mul(1u64 << attempt.min(32))
+            .min(self.max_backoff_ms);
+        let half = base / 2;
+        let jitter = RandomState::new().build_hasher().finish() % (half + 1);
+        Duration::from_millis(base - half + jitter)
+    }
+}
+
+/// State of a provider's circuit breaker
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    /// Calls go through
+    Closed,
+    /// The server is considered down; calls fail with `ServerDown`
+    Open,
+    /// A trial call is checking whether the server is back
+    HalfOpen,
+}
+
+/// Payload of `LLM_PROVIDER_STATUS` and entry of `llm_provider_status`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProviderStatus {
+    pub provider: String,
+    pub state: CircuitState,
+    pub consecutive_failures: u32,
+    /// Message of the most recent failure
+    pub last_error: Option<String>,
+    /// Time until a trial call is let through (open breakers only)
+    pub retry_in_ms: Option<u64>,
+}
+
+enum Circuit {
+    Closed,
+    Open { until: Instant },
+    HalfOpen,
+}
+
+struct BreakerState {
+    circuit: Circuit,
+    failures: u32,
+    last_error: Option<String>,
+}
+
+/// Circuit breaker of one provider (thread-safe)
+pub struct CircuitBreaker {
+    provider: String,
+    state: Mutex<BreakerState>,
+    changes: broadcast::Sender<ProviderStatus>,
+}
+
+impl CircuitBreaker {
+    /// # Arguments
+    /// * `provider` - Provider id (reported in statuses and errors)
+    /// * `changes` - Where state changes are published
+    pub fn new(provider: &str, changes: broadcast::Sender<ProviderStatus>) -> Self {
+        Self {
+            provider: provider.to_string(),
+            state: Mutex::new(BreakerState {
+                circuit: Circuit::Closed,
+                failures: 0,
+                last_error: None,
+            }),
+            changes,
+        }
+    }
+
+    pub fn status(&self) -> ProviderStatus {
+        self.describe(&self.state.lock().unwrap())
+    }
+
+    /// Close the breaker (after the provider's settings changed)
+    pub fn reset(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.failures = 0;
+        state.last_error = None;
+        self.transition(&mut state, Circuit::Closed);
+    }
+
+    /// Whether a call may go out now
+    ///
+    /// # Returns
+    /// * `Ok(())` - Go ahead (as the trial call if the cooldown just ended)
+    /// * `Err(LlmError::ServerDown)` - The breaker is open
+    fn check(&self) -> Result<(), LlmError> {
+        let mut state = self.state.lock().unwrap();
+        match state.circuit {
+            Circuit::Closed => Ok(()),
+            Circuit::Open { until } if Instant::now() >= until => {
+                self.transition(&mut state, Circuit::HalfOpen);
+                Ok(())
+            }
+            Circuit::Open { .. } | Circuit::HalfOpen => Err(self.down_error(&state)),
+        }
+    }
+
+    /// Update the breaker with how a call ended
+    fn record<T>(&self, result: &Result<T, LlmError>, config: &ResilienceConfig) {
+        let mut state = self.state.lock().unwrap();
+        match result.as_ref().err().map(is_server_failure) {
+            // The server answered
+            None | Some(Some(false)) => {
+                state.failures = 0;
+                state.last_error = None;
+                self.transition(&mut state, Circuit::Closed);
+            }
+            Some(Some(true)) => {
+                let error = result.as_ref().err().map(ToString::to_string);
+                state.failures = state.failures.saturating_add(1);
+                state.last_error = error;
+                let trips = match state.circuit {
+                    Circuit::Closed => state.failures >= config.failure_threshold,
+                    Circuit::Open { .. } | Circuit::HalfOpen => true,
+                };
+                if trips {
+                    let until = Instant::now() + Duration::from_millis(config.cooldown_ms);
+                    self.transition(&mut state, Circuit::Open { until });
+                }
+            }
+            // Says nothing about the server; let the next call be the trial
+            Some(None) => {
+                if let Circuit::HalfOpen = state.circuit {
+                    let until = Instant::now();
+                    self.transition(&mut state, Circuit::Open { until });
+                }
+            }
+        }
+    }
+
+    /// Change the circuit, publishing the new status if the state changed
+    fn transition(&self, state: &mut BreakerState, circuit: Circuit) {
+        let changed = !matches!(
+            (&state.circuit, &circuit),
+            (Circuit::Closed, Circuit::Closed) | (Circuit::HalfOpen, Circuit::HalfOpen)
+        );
+        state.circuit = circuit;
+        if changed {
+            // No receivers is fine
+            let _ = self.changes.send(self.describe(state));
+        }
+    }
+
+    fn describe(&self, state: &BreakerState) -> ProviderStatus {
+        let (circuit, retry_in_ms) = match state.circuit {
+            Circuit::Closed => (CircuitState::Closed, None),
+            Circuit::Open { until } => {
+                let remaining = until.saturating_duration_since(Instant::now());
+                (CircuitState::Open, Some(remaining.as_millis() as u64))
+            }
+            Circuit::HalfOpen => (CircuitState::HalfOpen, None),
+        };
+        ProviderStatus {
+            provider: self.provider.clone(),
+            state: circuit,
+            consecutive_failures: state.failures,
+            last_error: state.last_error.clone(),
+            retry_in_ms,
+        }
+    }
+
+    fn down_error(&self, state: &BreakerState) -> LlmError {
+        let status = self.describe(state);
+        LlmError::ServerDown {
+            provider: status.provider,
+            retry_in_ms: status.retry_in_ms,
+            last_error: status.last_error,
+        }
+    }
+}
+
+/// Whether an error means the server is down
+///
+/// # Returns
+/// * `Some(true)` - Unreachable, timed out or failing (5xx)
+/// * `Some(false)` - The server answered (the request itself was the problem)
+/// * `None` - Nothing is known about the server
+fn is_server_failure(error: &LlmError) -> Option<bool> {
+    match error {
+        LlmError::Connection(_) | LlmError::Timeout(_) => Some(true),
+        LlmError::Status { status, .. } => Some(*status >= 500),
+        LlmError::ModelNotFound(_) | LlmError::InvalidResponse(_) => Some(false),
+        _ => None,
+    }
+}
+
+/// A provider whose calls follow the resilience policy
+pub struct ResilientProvider {
+    inner: Box<dyn LlmProvider>,
+    breaker: Arc<CircuitBreaker>,
+    config: ResilienceConfig,
+}
+
+impl ResilientProvider {
+    pub fn new(
+        inner: Box<dyn LlmProvider>,
+        breaker: Arc<CircuitBreaker>,
+        config: ResilienceConfig,
+    ) -> Self {
+        Self {
+            inner,
+            breaker,
+            config,
+        }
+    }
+
+    /// Whether to try a failed call again
+    ///
+    /// Only transient failures are retried, and chat calls only while nothing
+    /// has been streamed: the caller would otherwise see the text twice.
+    fn should_retry(&self, error: &LlmError, attempt: u32, streamed: bool) -> bool {
+        attempt < self.config.max_retries
+            && error.is_retryable()
+            && !matches!(error, LlmError::ServerDown { .. })
+            && !streamed
+    }
+
+    /// Run a chat or completion call with timeouts and retries
+    fn run<'a>(
+        &'a self,
+        call: LlmCall<'a>,
+        cancel: &'a CancellationToken,
+        on_chunk: &'a mut OnChunk<'a>,
+    ) -> LlmFuture<'a, LlmResponse> {
+        Box::pin(async move {
+            let mut attempt = 0;
+            loop {
+                self.breaker.check()?;
+                let started = Instant::now();
+                let activity = AtomicU64::new(NO_ACTIVITY);
+                // Anything the caller has seen (text, or any other chunk before the
+                // last) counts as streamed, so the call isn't repeated
+                let mut forward = |chunk: &StreamChunk| {
+                    if !chunk.done {
+                        activity.store(started.elapsed().as_millis() as u64, Ordering::Relaxed);
+                    }
+                    on_chunk(chunk);
+                };
+                let result = with_read_timeout(
+                    call.run(self.inner.as_ref(), cancel, &mut forward),
+                    started,
+                    &activity,
+                    &self.config,
+                )
+                .await;
+                self.breaker.record(&result, &self.config);
+
+                let streamed = activity.load(Ordering::Relaxed) != NO_ACTIVITY;
+                match result {
+                    Err(e) if self.should_retry(&e, attempt, streamed) => {
+                        backoff(&self.config, attempt, Some(cancel)).await?;
+                        attempt += 1;
+                    }
+                    result => return result,
+                }
+            }
+        })
+    }
+
+    /// Run a call that is safe to repeat, with a timeout and retries
+    async fn retry<'a, T, F>(&'a self, timeout: Duration, mut call: F) -> Result<T, LlmError>
+    where
+        F: FnMut() -> LlmFuture<'a, T>,
+    {
+        let mut attempt = 0;
+        loop {
+            self.breaker.check()?;
+            let result = with_timeout(call(), timeout).await;
+            self.breaker.record(&result, &self.config);
+            match result {
+                Err(e) if self.should_retry(&e, attempt, false) => {
+                    backoff(&self.config, attempt, None).await?;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn read_timeout(&self) -> Duration {
+        Duration::from_millis(self.config.read_timeout_ms)
+    }
+}
+
+impl LlmProvider for ResilientProvider {
+    fn chat<'a>(
+        &'a self,
+        request: &'a ChatRequest,
+        cancel: &'a CancellationToken,
+        on_chunk: &'a mut OnChunk<'a>,
+    ) -> LlmFuture<'a, LlmResponse> {
+        self.run(LlmCall::Chat(request), cancel, on_chunk)
+    }
+
+    fn generate<'a>(
+        &'a self,
+        request: &'a GenerateRequest,
+        cancel: &'a CancellationToken,
+        on_chunk: &'a mut OnChunk<'a>,
+    ) -> LlmFuture<'a, LlmResponse> {
+        self.run(LlmCall::Generate(request), cancel, on_chunk)
+    }
+
+    fn list_models(&self) -> LlmFuture<'_, Vec<ModelStatus>> {
+        Box::pin(self.retry(self.read_timeout(), || self.inner.list_models()))
+    }
+
+    /// Probes always go out (that's how a down server is noticed coming back)
+    /// and are never retried, so they report the server as it is now
+    fn probe(&self) -> LlmFuture<'_, Option<String>> {
+        Box::pin(async move {
+            let result = self.inner.probe().await;
+            self.breaker.record(&result, &self.config);
+            result
+        })
+    }
+
+    fn warm_up<'a>(&'a self, model: &'a str) -> LlmFuture<'a, ()> {
+        let timeout = Duration::from_millis(self.config.first_chunk_timeout_ms);
+        Box::pin(self.retry(timeout, move || self.inner.warm_up(model)))
+    }
+
+    /// Not retried: progress already reported would be reported again
+    fn pull_model<'a>(
+        &'a self,
+        model: &'a str,
+        cancel: &'a CancellationToken,
+        on_progress: &'a mut OnPullProgress<'a>,
+    ) -> LlmFuture<'a, ()> {
+        Box::pin(async move {
+            self.breaker.check()?;
+            let started = Instant::now();
+            let activity = AtomicU64::new(NO_ACTIVITY);
+            let mut forward = |progress: &PullProgress| {
+                activity.store(started.elapsed().as_millis() as u64, Ordering::Relaxed);
+                on_progress(progress);
+            };
+            let config = ResilienceConfig {
+                first_chunk_timeout_ms: self.config.read_timeout_ms,
+                ..self.config
+            };
+            let result = with_read_timeout(
+                self.inner.pull_model(model, cancel, &mut forward),
+                started,
+                &activity,
+                &config,
+            )
+            .await;
+            self.breaker.record(&result, &self.config);
+            result
+        })
+    }
+
+    /// Not retried: a repeat after a lost reply would fail with `ModelNotFound`
+    fn delete_model<'a>(&'a self, model: &'a str) -> LlmFuture<'a, ()> {
+        Box::pin(async move {
+            self.breaker.check()?;
+            let result = with_timeout(self.inner.delete_model(model), self.read_timeout()).await;
+            self.breaker.record(&result, &self.config);
+            result
+        })
+    }
+}
+
+/// Fail with `Timeout` if `call` takes longer than `timeout`
+async fn with_timeout<T>(
+    call: impl Future<Output = Result<T, LlmError>>,
+    timeout: Duration,
+) -> Result<T, LlmError> {
+    tokio::time::timeout(timeout, call).await.unwrap_or_else(|_| {
+        Err(LlmError::Timeout(format!("no reply within {}ms", timeout.as_millis())))
+    })
+}
+
+/// Fail with `Timeout` if the server goes quiet
+///
+/// The first activity may take `first_chunk_timeout_ms` (the model may have
+/// to load), later gaps `read_timeout_ms`.
+///
+/// # Arguments
+/// * `started` - When the call started
+/// * `activity` - Milliseconds from `started` to the latest activity, or `NO_ACTIVITY`
+async fn with_read_timeout<T>(
+    call: impl Future<Output = Result<T, LlmError>>,
+    started: Instant,
+    activity: &AtomicU64,
+    config: &ResilienceConfig,
+) -> Result<T, LlmError> {
+    let deadline = || match activity.load(Ordering::Relaxed) {
+        NO_ACTIVITY => started + Duration::from_millis(config.first_chunk_timeout_ms),
+        last => started + Duration::from_millis(last + config.read_timeout_ms),
+    };
+    tokio::pin!(call);
+    loop {
+        let wait_until = deadline();
+        tokio::select! {
+            result = &mut call => return result,
+            _ = tokio::time::sleep_until(wait_until.into()) => {
+                // Activity since the sleep started moves the deadline
+                if Instant::now() < deadline() {
+                    continue;
+                }
+                let message = match activity.load(Ordering::Relaxed) {
+                    NO_ACTIVITY => format!(
+                        "no reply within {}ms",
+                        config.first_chunk_timeout_ms
+                    ),
+                    _ => format!("server went quiet for {}ms", config.read_timeout_ms),
+                };
+                return Err(LlmError::Timeout(message));
+            }
+        }
+    }
+}
+
+/// Wait before retry number `attempt`
+///
+/// # Returns
+/// * `Err(LlmError::Cancelled)` - `cancel` was cancelled while waiting
+async fn backoff(
+    config: &ResilienceConfig,
+    attempt: u32,
+    cancel: Option<&CancellationToken>,
+) -> Result<(), LlmError> {
+    let delay = tokio::time::sleep(config.backoff(attempt));
+    match cancel {
+        Some(cancel) => tokio::select! {
+            _ = cancel.cancelled() => Err(LlmError::Cancelled),
+            _ = delay => Ok(()),
+        },
+        None => {
+            delay.await;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::ollama::OllamaProvider;
+    use crate::llm::test_server::{Reply, TestServer, NDJSON};
+    use crate::llm::{ChatMessage, ChatRole};
+
+    /// Fast limits so the tests don't wait on real backoffs and cooldowns
+    fn config() -> ResilienceConfig {
+        ResilienceConfig {
+            first_chunk_timeout_ms: 2_000,
+            read_timeout_ms: 2_000,
+            max_retries: 2,
+            initial_backoff_ms: 1,
+            max_backoff_ms: 1,
+            failure_threshold: 2,
+            cooldown_ms: 200,
+            ..ResilienceConfig::default()
+        }
+    }
+
+    fn request() -> ChatRequest {
+        ChatRequest {
+            model: "test-model".to_string(),
+            messages: vec![ChatMessage::new(ChatRole::User, "Hi")],
+            options: None,
+            tools: Vec::new(),
+            format: None,
+        }
+    }
+
+    /// A complete chat stream
+    fn answer() -> Reply {
+        Reply::ok(NDJSON)
+            .send("{\"message\":{\"role\":\"assistant\",\"content\":\"Hi\"}}\n")
+            .send("{\"done\":true,\"done_reason\":\"stop\"}\n")
+    }
+
+    fn server_error() -> Reply {
+        Reply::status(503, r#"{"error":"overloaded"}"#)
+    }
+
+    /// The server at `server` behind a fresh breaker
+    fn resilient(server: &TestServer, config: ResilienceConfig) -> ResilientProvider {
+        let (changes, _) = broadcast::channel(16);
+        resilient_with(server, config, Arc::new(CircuitBreaker::new("stub", changes)))
+    }
+
+    fn resilient_with(
+        server: &TestServer,
+        config: ResilienceConfig,
+        breaker: Arc<CircuitBreaker>,
+    ) -> ResilientProvider {
+        let inner = OllamaProvider::new(reqwest::Client::new(), &server.url);
+        ResilientProvider::new(Box::new(inner), breaker, config)
+    }
+
+    /// Chat, returning the result and the text of every chunk forwarded
+    async fn chat(provider: &dyn LlmProvider) -> (Result<LlmResponse, LlmError>, Vec<String>) {
+        let mut texts = Vec::new();
+        let mut collect = |chunk: &StreamChunk| {
+            if !chunk.done {
+                texts.push(chunk.content.clone());
+            }
+        };
+        let result = provider
+            .chat(&request(), &CancellationToken::new(), &mut collect)
+            .await;
+        (result, texts)
+    }
+
+    #[tokio::test]
+    async fn retries_server_errors_then_succeeds() {
+        let server = TestServer::start(|n, _| if n < 2 { server_error() } else { answer() }).await;
+        let provider = resilient(&server, ResilienceConfig { failure_threshold: 5, ..config() });
+
+        let (result, texts) = chat(&provider).await;
+        assert_eq!(result.unwrap().content, "Hi");
+        assert_eq!(texts, ["Hi"]);
+        assert_eq!(server.hits(), 3);
+        assert_eq!(provider.breaker.status().state, CircuitState::Closed);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_retries() {
+        let server = TestServer::start(|_, _| server_error()).await;
+        let provider = resilient(&server, ResilienceConfig { failure_threshold: 5, ..config() });
+
+        let (result, _) = chat(&provider).await;
+        assert!(matches!(result, Err(LlmError::Status { status: 503, .. })));
+        assert_eq!(server.hits(), 3);
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_once_text_streamed() {
+        let server = TestServer::start(|_, _| {
+            Reply::ok(NDJSON)
+                .send("{\"message\":{\"role\":\"assistant\",\"content\":\"Hi\"}}\n")
+                .send("{\"error\":\"model runner crashed\"}\n")
+        })
+        .await;
+        let provider = resilient(&server, config());
+
+        let (result, texts) = chat(&provider).await;
+        assert!(matches!(result, Err(LlmError::Status { status: 500, .. })));
+        assert_eq!(texts, ["Hi"]);
+        assert_eq!(server.hits(), 1);
+    }
+
+    /// Provider that forwards one chunk without text, then fails
+    struct FailsAfterChunk;
+
+    impl LlmProvider for FailsAfterChunk {
+        fn chat<'a>(
+            &'a self,
+            _request: &'a ChatRequest,
+            _cancel: &'a CancellationToken,
+            on_chunk: &'a mut OnChunk<'a>,
+        ) -> LlmFuture<'a, LlmResponse> {
+            Box::pin(async move {
+                on_chunk(&StreamChunk {
+                    restart: true,
+                    ..StreamChunk::default()
+                });
+                Err(LlmError::Status { status: 503, message: "overloaded".to_string() })
+            })
+        }
+
+        fn list_models(&self) -> LlmFuture<'_, Vec<ModelStatus>> {
+            Box::pin(async { Ok(Vec::new()) })
+        }
+
+        fn probe(&self) -> LlmFuture<'_, Option<String>> {
+            Box::pin(async { Ok(None) })
+        }
+
+        fn warm_up<'a>(&'a self, _model: &'a str) -> LlmFuture<'a, ()> {
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_once_any_chunk_forwarded() {
+        let (changes, _) = broadcast::channel(16);
+        let provider = ResilientProvider::new(
+            Box::new(FailsAfterChunk),
+            Arc::new(CircuitBreaker::new("stub", changes)),
+            config(),
+        );
+
+        let mut forwarded = 0;
+        let mut count = |_: &StreamChunk| forwarded += 1;
+        let result = provider
+            .chat(&request(), &CancellationToken::new(), &mut count)
+            .await;
+        assert!(matches!(result, Err(LlmError::Status { status: 503, .. })));
+        assert_eq!(forwarded, 1);
+    }
+
+    #[tokio::test]
+    async fn breaker_opens_after_threshold() {
+        let server = TestServer::start(|_, _| server_error()).await;
+        let provider = resilient(&server, ResilienceConfig { max_retries: 0, ..config() });
+
+        for _ in 0..2 {
+            let (result, _) = chat(&provider).await;
+            assert!(matches!(result, Err(LlmError::Status { status: 503, .. })));
+        }
+        let (result, _) = chat(&provider).await;
+        match result {
+            Err(LlmError::ServerDown { provider, retry_in_ms, last_error }) => {
+                assert_eq!(provider, "stub");
+                assert!(retry_in_ms.is_some());
+                assert!(last_error.is_some());
+            }
+            other => panic!("expected ServerDown, got {:?}", other),
+        }
+        // The open breaker kept the third call from reaching the server
+        assert_eq!(server.hits(), 2);
+        assert_eq!(provider.breaker.status().state, CircuitState::Open);
+    }
+
+    #[tokio::test]
+    async fn trial_call_closes_breaker() {
+        let server = TestServer::start(|n, _| if n < 2 { server_error() } else { answer() }).await;
+        let (changes, mut statuses) = broadcast::channel(16);
+        let breaker = Arc::new(CircuitBreaker::new("stub", changes));
+        let config = ResilienceConfig { max_retries: 0, ..config() };
+        let provider = resilient_with(&server, config, breaker.clone());
+
+        for _ in 0..2 {
+            chat(&provider).await.0.unwrap_err();
+        }
+        assert_eq!(breaker.status().state, CircuitState::Open);
+        tokio::time::sleep(Duration::from_millis(config.cooldown_ms + 50)).await;
+
+        let (result, _) = chat(&provider).await;
+        assert_eq!(result.unwrap().content, "Hi");
+        assert_eq!(breaker.status().state, CircuitState::Closed);
+        assert_eq!(breaker.status().consecutive_failures, 0);
+
+        let mut states = Vec::new();
+        while let Ok(status) = statuses.try_recv() {
+            states.push(status.state);
+        }
+        assert_eq!(states, [CircuitState::Open, CircuitState::HalfOpen, CircuitState::Closed]);
+    }
+
+    #[tokio::test]
+    async fn failed_trial_reopens_breaker() {
+        let server = TestServer::start(|_, _| server_error()).await;
+        let provider = resilient(&server, ResilienceConfig { max_retries: 0, ..config() });
+
+        for _ in 0..2 {
+            chat(&provider).await.0.unwrap_err();
+        }
+        tokio::time::sleep(Duration::from_millis(config().cooldown_ms + 50)).await;
+
+        let (result, _) = chat(&provider).await;
+        assert!(matches!(result, Err(LlmError::Status { status: 503, .. })));
+        assert_eq!(server.hits(), 3);
+        assert_eq!(provider.breaker.status().state, CircuitState::Open);
+    }
+
+    #[tokio::test]
+    async fn times_out_waiting_for_first_chunk() {
+        let server = TestServer::start(|_, _| Reply::ok(NDJSON).hang()).await;
+        let config = ResilienceConfig {
+            first_chunk_timeout_ms: 200,
+            max_retries: 0,
+            ..config()
+        };
+        let provider = resilient(&server, config);
+
+        let started = Instant::now();
+        let (result, _) = chat(&provider).await;
+        match result {
+            Err(LlmError::Timeout(message)) => assert!(message.contains("no reply"), "{}", message),
+            other => panic!("expected a timeout, got {:?}", other),
+        }
+        assert!(started.elapsed() < Duration::from_millis(config.read_timeout_ms));
+    }
+
+    #[tokio::test]
+    async fn times_out_when_stream_goes_quiet() {
+        let server = TestServer::start(|_, _| {
+            Reply::ok(NDJSON)
+                .send("{\"message\":{\"role\":\"assistant\",\"content\":\"Hi\"}}\n")
+                .hang()
+        })
+        .await;
+        let config = ResilienceConfig { read_timeout_ms: 200, ..config() };
+        let provider = resilient(&server, config);
+
+        let (result, texts) = chat(&provider).await;
+        match result {
+            Err(LlmError::Timeout(message)) => assert!(message.contains("quiet"), "{}", message),
+            other => panic!("expected a timeout, got {:?}", other),
+        }
+        // Text was streamed, so the timeout isn't retried
+        assert_eq!(texts, ["Hi"]);
+        assert_eq!(server.hits(), 1);
+    }
+}
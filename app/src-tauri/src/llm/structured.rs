@@ -0,0 +1,399 @@
+use jsonschema::Validator;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+use tokio_util::sync::CancellationToken;
+
+use super::{
+    ChatMessage, ChatRequest, ChatRole, LlmError, LlmProvider, ResponseFormat, StreamChunk, Usage,
+};
+
+/// Repair rounds allowed when a request doesn't say
+const DEFAULT_MAX_REPAIRS: u32 = 2;
+
+/// Upper bound on `max_repairs`
+const MAX_REPAIRS: u32 = 5;
+
+/// Problems quoted back to the model and kept in `InvalidOutput`
+const MAX_ISSUES: usize = 20;
+
+/// Structured generation request
+#[derive(Debug, Clone, Deserialize)]
+pub struct StructuredRequest {
+    /// Model, conversation and options (`tools` and `format` are ignored)
+    #[serde(flatten)]
+    pub chat: ChatRequest,
+    /// JSON schema the reply must match
+    pub schema: Value,
+    /// Times an invalid reply is sent back with its problems (default 2, at most 5)
+    #[serde(default)]
+    pub max_repairs: Option<u32>,
+    /// Use the provider's JSON mode to constrain the reply (default true)
+    #[serde(default)]
+    pub constrained: Option<bool>,
+}
+
+/// Result of structured generation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StructuredOutput<T> {
+    /// The parsed and validated reply
+    pub value: T,
+    /// The reply's text
+    pub content: String,
+    /// Model calls made (1 if the first reply was valid)
+    pub attempts: u32,
+    /// Tokens used across all model calls
+    pub usage: Usage,
+}
+
+/// Why a reply was rejected
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutputIssue {
+    /// JSON pointer to the offending value (empty for the reply as a whole)
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for OutputIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{}: {}", self.path, self.message)
+        }
+    }
+}
+
+/// Ask a model for JSON matching a schema and deserialize it
+///
+/// The provider's JSON mode constrains the reply where it has one (servers
+/// that reject a schema get plain JSON mode, then none). Replies that don't
+/// parse, validate or deserialize are sent back with their problems until one
+/// passes or the repairs run out.
+///
+/// # Arguments
+/// * `provider` - Provider the model runs on
+/// * `request` - Model, conversation, schema and limits (`model` must be set)
+/// * `cancel` - Stops generation when cancelled
+/// * `on_chunk` - Called with text as it is generated (never with `done` chunks);
+///   a chunk with `restart` set starts each repair attempt
+///
+/// # Returns
+/// * `Ok(StructuredOutput<T>)` - A reply matched the schema and deserialized
+/// * `Err(LlmError)` - `InvalidInput` for a bad schema, `InvalidOutput` when
+///   the repairs ran out, or the failure of a model call
+pub async fn generate_structured<T: DeserializeOwned>(
+    provider: &dyn LlmProvider,
+    request: StructuredRequest,
+    cancel: &CancellationToken,
+    mut on_chunk: impl FnMut(&StreamChunk) + Send,
+) -> Result<StructuredOutput<T>, LlmError> {
+    let validator = jsonschema::validator_for(&request.schema)
+        .map_err(|e| LlmError::InvalidInput(format!("Invalid JSON schema: {}", e)))?;
+    let max_repairs = request.max_repairs.unwrap_or(DEFAULT_MAX_REPAIRS).min(MAX_REPAIRS);
+    let mut format = match request.constrained.unwrap_or(true) {
+        true => Some(ResponseFormat::JsonSchema { schema: request.schema.clone() }),
+        false => None,
+    };
+    let mut messages = with_schema_prompt(&request.schema, request.chat.messages);
+    let mut usage = Usage::default();
+    let mut attempts = 0;
+
+    loop {
+        let chat = ChatRequest {
+            model: request.chat.model.clone(),
+            messages: messages.clone(),
+            options: request.chat.options.clone(),
+            tools: Vec::new(),
+            format: format.clone(),
+        };
+        let mut forward = |chunk: &StreamChunk| {
+            if !chunk.done {
+                on_chunk(chunk);
+            }
+        };
+        let response = match provider.chat(&chat, cancel, &mut forward).await {
+            // Servers without schema support reject the request up front; loosen the constraint
+            Err(LlmError::Status { status: 400, .. }) if format.is_some() => {
+                format = match format {
+                    Some(ResponseFormat::JsonSchema { .. }) => Some(ResponseFormat::Json),
+                    _ => None,
+                };
+                continue;
+            }
+            result => result?,
+        };
+        attempts += 1;
+        usage = usage + response.usage;
+
+        let issues = match check(&validator, &response.content) {
+            Ok(value) => match serde_json::from_value(value) {
+                Ok(value) => {
+                    return Ok(StructuredOutput {
+                        value,
+                        content: response.content,
+                        attempts,
+                        usage,
+                    });
+                }
+                Err(e) => vec![OutputIssue { path: String::new(), message: e.to_string() }],
+            },
+            Err(issues) => issues,
+        };
+        if attempts > max_repairs {
+            return Err(LlmError::InvalidOutput { attempts, issues });
+        }
+
+        messages.push(ChatMessage::new(ChatRole::Assistant, response.content));
+        messages.push(ChatMessage::new(ChatRole::User, repair_prompt(&issues)));
+        on_chunk(&StreamChunk {
+            restart: true,
+            ..StreamChunk::default()
+        });
+    }
+}
+
+/// The conversation with the schema added to its system prompt
+fn with_schema_prompt(schema: &Value, mut messages: Vec<ChatMessage>) -> Vec<ChatMessage> {
+    let prompt = format!(
+        "Reply with a single JSON value matching this JSON schema, without code fences \
+         or any other text:\n{}",
+        serde_json::to_string_pretty(schema).unwrap_or_else(|_| schema.to_string())
+    );
+    match messages.first_mut() {
+        Some(first) if first.role == ChatRole::System => {
+            first.content = format!("{}\n\n{}", first.content, prompt);
+        }
+        _ => messages.insert(0, ChatMessage::new(ChatRole::System, prompt)),
+    }
+    messages
+}
+
+/// Message asking the model to fix its last reply
+fn repair_prompt(issues: &[OutputIssue]) -> String {
+    let mut prompt = String::from("That reply doesn't match the schema:\n");
+    for issue in issues {
+        prompt.push_str(&format!("- {}\n", issue));
+    }
+    prompt.push_str("Reply again with only the corrected JSON.");
+    prompt
+}
+
+/// Parse a reply and validate it against the schema
+fn check(validator: &Validator, content: &str) -> Result<Value, Vec<OutputIssue>> {
+    let value: Value = serde_json::from_str(extract_json(content)).map_err(|e| {
+        vec![OutputIssue {
+            path: String::new(),
+            message: format!("Not valid JSON: {}", e),
+        }]
+    })?;
+    let issues: Vec<OutputIssue> = validator
+        .iter_errors(&value)
+        .take(MAX_ISSUES)
+        .map(|error| OutputIssue {
+            path: error.instance_path.to_string(),
+            message: error.to_string(),
+        })
+        .collect();
+    if issues.is_empty() {
+        Ok(value)
+    } else {
+        Err(issues)
+    }
+}
+
+/// The JSON in a reply, without code fences or text around it
+fn extract_json(content: &str) -> &str {
+    let content = content.trim();
+    let start = content.find(['{', '[']);
+    let end = content.rfind(['}', ']']);
+    match (start, end) {
+        (Some(start), Some(end)) if start < end => &content[start..=end],
+        // A scalar, or no JSON at all; let the parser report it
+        _ => content
+            .trim_start_matches("```json")
+            .trim_matches('`')
+            .trim(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::ollama::OllamaProvider;
+    use crate::llm::test_server::{Reply, TestServer, NDJSON};
+    use serde_json::json;
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Person {
+        name: String,
+        age: u32,
+    }
+
+    fn schema() -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "name": { "type": "string" },
+                "age": { "type": "integer", "minimum": 0 },
+            },
+            "required": ["name", "age"],
+        })
+    }
+
+    /// A chat stream answering with `content` in one piece
+    fn reply(content: &str) -> Reply {
+        let text = json!({ "message": { "role": "assistant", "content": content } });
+        let done = json!({
+            "message": { "role": "assistant", "content": "" },
+            "done": true,
+            "done_reason": "stop",
+            "prompt_eval_count": 4,
+            "eval_count": 2,
+        });
+        Reply::ok(NDJSON).send(&format!("{}\n{}\n", text, done))
+    }
+
+    fn request(max_repairs: Option<u32>) -> StructuredRequest {
+        StructuredRequest {
+            chat: ChatRequest {
+                model: "test-model".to_string(),
+                messages: vec![ChatMessage::new(ChatRole::User, "Who wrote the first program?")],
+                options: None,
+                tools: Vec::new(),
+                format: None,
+            },
+            schema: schema(),
+            max_repairs,
+            constrained: None,
+        }
+    }
+
+    async fn generate(
+        server: &TestServer,
+        request: StructuredRequest,
+    ) -> (Result<StructuredOutput<Person>, LlmError>, Vec<StreamChunk>) {
+        let provider = OllamaProvider::new(reqwest::Client::new(), &server.url);
+        let mut chunks = Vec::new();
+        let collect = |chunk: &StreamChunk| chunks.push(chunk.clone());
+        let result = generate_structured(&provider, request, &CancellationToken::new(), collect)
+            .await;
+        (result, chunks)
+    }
+
+    const VALID: &str = r#"{"name": "Ada Lovelace", "age": 36}"#;
+
+    #[tokio::test]
+    async fn valid_first_reply_takes_one_attempt() {
+        let server = TestServer::start(|_, _| reply(VALID)).await;
+
+        let (result, chunks) = generate(&server, request(None)).await;
+        let output = result.unwrap();
+        assert_eq!(output.value, Person { name: "Ada Lovelace".to_string(), age: 36 });
+        assert_eq!(output.content, VALID);
+        assert_eq!(output.attempts, 1);
+        assert_eq!(output.usage.prompt_tokens, Some(4));
+        assert!(chunks.iter().all(|chunk| !chunk.done && !chunk.restart));
+
+        let (_, body) = &server.requests()[0];
+        assert_eq!(body["format"], schema());
+        assert_eq!(body["messages"][0]["role"], "system");
+        assert!(body["messages"][0]["content"].as_str().unwrap().contains("\"required\""));
+    }
+
+    #[tokio::test]
+    async fn invalid_reply_is_sent_back_and_repaired() {
+        let server = TestServer::start(|n, _| match n {
+            0 => reply(r#"{"name": "Ada Lovelace"}"#),
+            _ => reply(VALID),
+        })
+        .await;
+
+        let (result, chunks) = generate(&server, request(None)).await;
+        let output = result.unwrap();
+        assert_eq!(output.attempts, 2);
+        assert_eq!(output.usage.completion_tokens, Some(4));
+        let flow: Vec<(&str, bool)> = chunks
+            .iter()
+            .map(|chunk| (chunk.content.as_str(), chunk.restart))
+            .collect();
+        assert_eq!(flow, [(r#"{"name": "Ada Lovelace"}"#, false), ("", true), (VALID, false)]);
+
+        // The repair request quotes the bad reply and what is wrong with it
+        let (_, body) = &server.requests()[1];
+        let messages = body["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 4);
+        assert_eq!(messages[2]["role"], "assistant");
+        assert_eq!(messages[3]["role"], "user");
+        assert!(messages[3]["content"].as_str().unwrap().contains("\"age\" is a required"));
+    }
+
+    #[tokio::test]
+    async fn gives_up_when_repairs_run_out() {
+        let server = TestServer::start(|_, _| reply("I'm not sure, sorry.")).await;
+
+        let (result, chunks) = generate(&server, request(Some(1))).await;
+        match result {
+            Err(LlmError::InvalidOutput { attempts, issues }) => {
+                assert_eq!(attempts, 2);
+                assert!(issues[0].message.starts_with("Not valid JSON"), "{:?}", issues);
+            }
+            other => panic!("expected InvalidOutput, got {:?}", other),
+        }
+        assert_eq!(server.hits(), 2);
+        assert_eq!(chunks.iter().filter(|chunk| chunk.restart).count(), 1);
+    }
+
+    #[tokio::test]
+    async fn rejected_formats_are_loosened_to_json_then_none() {
+        let server = TestServer::start(|n, _| match n {
+            0 | 1 => Reply::status(400, r#"{"error":"invalid format"}"#),
+            _ => reply(&format!("```json\n{}\n```", VALID)),
+        })
+        .await;
+
+        let (result, _) = generate(&server, request(None)).await;
+        let output = result.unwrap();
+        // Rejected requests aren't attempts
+        assert_eq!(output.attempts, 1);
+        let formats: Vec<Value> = server
+            .requests()
+            .into_iter()
+            .map(|(_, body)| body["format"].clone())
+            .collect();
+        assert_eq!(formats, [schema(), json!("json"), Value::Null]);
+
+        // Without a constraint a 400 is an ordinary failure
+        let server = TestServer::start(|_, _| Reply::status(400, r#"{"error":"bad"}"#)).await;
+        let mut unconstrained = request(None);
+        unconstrained.constrained = Some(false);
+        let (result, _) = generate(&server, unconstrained).await;
+        assert!(matches!(result, Err(LlmError::Status { status: 400, .. })));
+        assert_eq!(server.hits(), 1);
+    }
+
+    #[tokio::test]
+    async fn invalid_schema_is_rejected_before_any_call() {
+        let server = TestServer::start(|_, _| reply(VALID)).await;
+        let mut bad = request(None);
+        bad.schema = json!({ "type": "no-such-type" });
+
+        let (result, _) = generate(&server, bad).await;
+        assert!(matches!(result, Err(LlmError::InvalidInput(_))));
+        assert_eq!(server.hits(), 0);
+    }
+
+    #[test]
+    fn json_is_extracted_from_fences_and_prose() {
+        assert_eq!(extract_json("```json\n{\"a\": 1}\n```"), "{\"a\": 1}");
+        assert_eq!(extract_json("```\n[1, 2]\n```"), "[1, 2]");
+        assert_eq!(
+            extract_json("Sure! Here it is: {\"a\": {\"b\": 2}} Let me know."),
+            "{\"a\": {\"b\": 2}}"
+        );
+        assert_eq!(extract_json("  {\"a\": 1}  "), "{\"a\": 1}");
+        assert_eq!(extract_json("```json\n42\n```"), "42");
+        assert_eq!(extract_json("\"text\""), "\"text\"");
+    }
+}
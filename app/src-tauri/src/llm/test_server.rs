@@ -0,0 +1,172 @@
+// Scripted local HTTP server for the provider tests. Each connection gets the
+// reply the script returns for it, written piece by piece so tests can stream
+// NDJSON or server-sent events, stall mid-stream or fail with a status.
+
+use serde_json::Value;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Content types of the two streaming formats
+pub const NDJSON: &str = "application/x-ndjson";
+pub const EVENT_STREAM: &str = "text/event-stream";
+
+enum Part {
+    Data(String),
+    Pause(Duration),
+    /// Keep the connection open without sending anything more
+    Hang,
+}
+
+/// Reply to one request
+pub struct Reply {
+    status: u16,
+    content_type: &'static str,
+    parts: Vec<Part>,
+}
+
+impl Reply {
+    /// `200 OK` with a body sent in the given pieces
+    pub fn ok(content_type: &'static str) -> Self {
+        Self {
+            status: 200,
+            content_type,
+            parts: Vec::new(),
+        }
+    }
+
+    /// An error status with a JSON body
+    pub fn status(status: u16, body: &str) -> Self {
+        Self {
+            status,
+            content_type: "application/json",
+            parts: vec![Part::Data(body.to_string())],
+        }
+    }
+
+    /// Send a piece of the body
+    pub fn send(mut self, data: &str) -> Self {
+        self.parts.push(Part::Data(data.to_string()));
+        self
+    }
+
+    /// Wait before sending the next piece
+    pub fn pause(mut self, duration: Duration) -> Self {
+        self.parts.push(Part::Pause(duration));
+        self
+    }
+
+    /// Stop sending but leave the connection open
+    pub fn hang(mut self) -> Self {
+        self.parts.push(Part::Hang);
+        self
+    }
+}
+
+/// A running stub server
+pub struct TestServer {
+    pub url: String,
+    requests: Arc<Mutex<Vec<(String, Value)>>>,
+}
+
+impl TestServer {
+    /// Serve replies from `script`, which gets the 0-based number and the path of
+    /// each request
+    pub async fn start(script: impl Fn(usize, &str) -> Reply + Send + Sync + 'static) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let script = Arc::new(script);
+
+        let received = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let received = received.clone();
+                let script = script.clone();
+                tokio::spawn(async move {
+                    let Some(request) = read_request(&mut stream).await else {
+                        return;
+                    };
+                    let path = request.0.clone();
+                    let number = {
+                        let mut received = received.lock().unwrap();
+                        received.push(request);
+                        received.len() - 1
+                    };
+                    let _ = write_reply(stream, script(number, &path)).await;
+                });
+            }
+        });
+
+        Self { url, requests }
+    }
+
+    /// Requests received so far: path and JSON body (`Null` without one)
+    pub fn requests(&self) -> Vec<(String, Value)> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// Number of requests received so far
+    pub fn hits(&self) -> usize {
+        self.requests.lock().unwrap().len()
+    }
+}
+
+/// Read a request's path and body
+async fn read_request(stream: &mut TcpStream) -> Option<(String, Value)> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 4096];
+    let header_end = loop {
+        match stream.read(&mut chunk).await {
+            Ok(0) | Err(_) => return None,
+            Ok(read) => buffer.extend_from_slice(&chunk[..read]),
+        }
+        if let Some(end) = buffer.windows(4).position(|window| window == b"\r\n\r\n") {
+            break end + 4;
+        }
+    };
+
+    let head = String::from_utf8_lossy(&buffer[..header_end]).to_string();
+    let path = head.split_whitespace().nth(1)?.to_string();
+    let length: usize = head
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.trim().parse().ok())
+        .unwrap_or(0);
+    while buffer.len() < header_end + length {
+        match stream.read(&mut chunk).await {
+            Ok(0) | Err(_) => return None,
+            Ok(read) => buffer.extend_from_slice(&chunk[..read]),
+        }
+    }
+
+    let body = &buffer[header_end..header_end + length];
+    Some((path, serde_json::from_slice(body).unwrap_or_default()))
+}
+
+/// Write a reply; the body ends when the connection closes
+async fn write_reply(mut stream: TcpStream, reply: Reply) -> std::io::Result<()> {
+    let head = format!(
+        "HTTP/1.1 {} Stub\r\nContent-Type: {}\r\nConnection: close\r\n\r\n",
+        reply.status, reply.content_type
+    );
+    stream.write_all(head.as_bytes()).await?;
+    for part in reply.parts {
+        match part {
+            Part::Data(data) => {
+                stream.write_all(data.as_bytes()).await?;
+                stream.flush().await?;
+            }
+            Part::Pause(duration) => tokio::time::sleep(duration).await,
+            Part::Hang => {
+                // Wait for the client to give up and close its end
+                let mut rest = Vec::new();
+                let _ = stream.read_to_end(&mut rest).await;
+                return Ok(());
+            }
+        }
+    }
+    stream.shutdown().await
+}
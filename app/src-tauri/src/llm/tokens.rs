@@ -0,0 +1,655 @@
+use serde::{Deserialize, Serialize};
+use tiktoken_rs::{cl100k_base_singleton, o200k_base_singleton, CoreBPE};
+use tokio_util::sync::CancellationToken;
+
+use super::{GenerateRequest, LlmError, LlmProvider, ModelStatus, StreamChunk};
+
+/// Context window assumed when neither the request nor the server gives one
+pub const DEFAULT_CONTEXT_LENGTH: u64 = 4096;
+
+/// Tokens kept free for the reply when a request doesn't say
+const DEFAULT_RESERVE_OUTPUT: u64 = 1024;
+
+/// Formatting tokens each input costs on top of its text (role, separators)
+const INPUT_OVERHEAD: u64 = 4;
+
+/// Inputs that would get fewer tokens than this are dropped instead of cut
+const MIN_ALLOTMENT: u64 = 64;
+
+/// Room left for the instructions when a summary is requested
+const SUMMARY_PROMPT_TOKENS: u64 = 256;
+
+/// Appended to truncated inputs
+const TRUNCATION_MARKER: &str = "\n[truncated]";
+
+/// Tokenizer family of a model
+///
+/// GPT models are counted exactly with their own encoding. Other families are
+/// counted with the closest tiktoken encoding and scaled by how many more
+/// tokens their tokenizer typically produces, rounding up so estimates err on
+/// the side of fitting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ModelFamily {
+    /// GPT-4o, GPT-4.1, o-series (`o200k_base`)
+    Gpt4o,
+    /// GPT-4, GPT-3.5 (`cl100k_base`)
+    Gpt,
+    Llama3,
+    /// Llama 2 and Code Llama (32K SentencePiece vocabulary)
+    Llama,
+    Mistral,
+    Qwen,
+    Gemma,
+    Phi,
+    DeepSeek,
+    Other,
+}
+
+impl ModelFamily {
+    /// Family of a model, from its name or the family the server reports
+    ///
+    /// # Arguments
+    /// * `model` - Model name (e.g. `llama3.1:8b`, `gpt-4o-mini`)
+    /// * `family` - `ModelStatus::family`, if known (e.g. `qwen2`)
+    pub fn detect(model: &str, family: Option<&str>) -> Self {
+        let from_name = Self::from_name(model);
+        match (from_name, family) {
+            (ModelFamily::Other, Some(family)) => Self::from_name(family),
+            (detected, _) => detected,
+        }
+    }
+
+    fn from_name(name: &str) -> Self {
+        // Drop registry prefixes such as `hf.co/org/`
+        let name = name.rsplit('/').next().unwrap_or(name).to_lowercase();
+        let starts = |prefixes: &[&str]| prefixes.iter().any(|prefix| name.starts_with(prefix));
+        if starts(&["gpt-4o", "gpt-4.1", "gpt-5", "chatgpt-4o", "o1", "o3", "o4"]) {
+            ModelFamily::Gpt4o
+        } else if starts(&["gpt-4", "gpt-3.5", "gpt-35"]) {
+            ModelFamily::Gpt
+        } else if starts(&["llama3", "llama-3", "llama4", "llama-4", "meta-llama-3"]) {
+            ModelFamily::Llama3
+        } else if starts(&["llama", "codellama", "meta-llama", "vicuna"]) {
+            ModelFamily::Llama
+        } else if starts(&["mistral", "mixtral", "codestral", "devstral", "ministral"]) {
+            ModelFamily::Mistral
+        } else if starts(&["qwen", "qwq"]) {
+            ModelFamily::Qwen
+        } else if starts(&["gemma", "codegemma"]) {
+            ModelFamily::Gemma
+        } else if starts(&["phi"]) {
+            ModelFamily::Phi
+        } else if starts(&["deepseek"]) {
+            ModelFamily::DeepSeek
+        } else {
+            ModelFamily::Other
+        }
+    }
+
+    /// Whether counts for this family are exact rather than estimated
+    pub fn is_exact(self) -> bool {
+        matches!(self, ModelFamily::Gpt4o | ModelFamily::Gpt)
+    }
+
+    fn encoding(self) -> &'static CoreBPE {
+        match self {
+            ModelFamily::Gpt4o | ModelFamily::Gemma => o200k_base_singleton(),
+            _ => cl100k_base_singleton(),
+        }
+    }
+
+    /// Tokens the family's tokenizer produces per token of `encoding()`
+    fn scale(self) -> f64 {
+        match self {
+            ModelFamily::Gpt4o | ModelFamily::Gpt => 1.0,
+            ModelFamily::Llama3 | ModelFamily::Qwen => 1.05,
+            ModelFamily::Gemma => 1.1,
+            ModelFamily::DeepSeek => 1.15,
+            ModelFamily::Llama | ModelFamily::Mistral | ModelFamily::Phi => 1.25,
+            ModelFamily::Other => 1.3,
+        }
+    }
+
+    /// Tokens `text` takes for this family
+    pub fn count(self, text: &str) -> u64 {
+        let tokens = self.encoding().encode_ordinary(text).len();
+        (tokens as f64 * self.scale()).ceil() as u64
+    }
+
+    /// The longest prefix of `text` that takes at most `max_tokens` tokens
+    pub fn truncate(self, text: &str, max_tokens: u64) -> &str {
+        let bpe = self.encoding();
+        let tokens = bpe.encode_ordinary(text);
+        let keep = ((max_tokens as f64) / self.scale()).floor() as usize;
+        if keep >= tokens.len() {
+            return text;
+        }
+
+        // Tokens decode to the original bytes, so the prefix's length is the sum of theirs
+        let length: usize = bpe
+            ._decode_native_and_split(tokens[..keep].to_vec())
+            .map(|bytes| bytes.len())
+            .sum();
+        // A token can end inside a multi-byte character
+        let mut cut = length.min(text.len());
+        while !text.is_char_boundary(cut) {
+            cut -= 1;
+        }
+        &text[..cut]
+    }
+}
+
+/// How an input is shortened when it doesn't fit
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FitStrategy {
+    /// Keep the beginning
+    #[default]
+    Truncate,
+    /// Have the model summarize it
+    Summarize,
+}
+
+/// A piece of a prompt (a file, the task, the conversation so far, ...)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BudgetInput {
+    /// Label shown in the breakdown (e.g. a file path)
+    pub name: String,
+    pub content: String,
+    /// Required inputs are never shortened
+    #[serde(default)]
+    pub required: bool,
+    #[serde(default)]
+    pub strategy: FitStrategy,
+}
+
+/// Inputs to fit into a model's context window
+#[derive(Debug, Clone, Deserialize)]
+pub struct BudgetRequest {
+    /// Model to budget for (empty for the role's model)
+    #[serde(default)]
+    pub model: String,
+    pub inputs: Vec<BudgetInput>,
+    /// Context window in tokens (the server's value for the model if unset)
+    #[serde(default)]
+    pub context_length: Option<u64>,
+    /// Tokens kept free for the reply (default 1024, at most a quarter of the window)
+    #[serde(default)]
+    pub reserve_output: Option<u64>,
+}
+
+/// What happens to an input
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BudgetAction {
+    Kept,
+    Truncated,
+    Summarized,
+    /// Too little room was left to keep a useful part
+    Dropped,
+}
+
+/// Budget of one input
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BudgetItem {
+    pub name: String,
+    /// Tokens the input takes as given
+    pub tokens: u64,
+    /// Tokens it may take in the request
+    pub allotted: u64,
+    pub action: BudgetAction,
+}
+
+/// How a request's context window is spent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BudgetReport {
+    pub model: String,
+    pub family: ModelFamily,
+    /// Whether counts are exact rather than estimated
+    pub exact: bool,
+    pub context_length: u64,
+    pub reserved_output: u64,
+    /// Tokens left for inputs after the output reserve
+    pub available: u64,
+    /// Tokens the inputs take after fitting, including per-input overhead
+    pub used: u64,
+    pub remaining: u64,
+    /// `false` if the required inputs alone exceed `available`
+    pub fits: bool,
+    /// One item per input, in request order
+    pub items: Vec<BudgetItem>,
+}
+
+/// An input after fitting
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FittedInput {
+    pub name: String,
+    /// The content to send (empty if dropped)
+    pub content: String,
+}
+
+/// Result of fitting inputs into a context window
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FitResult {
+    pub report: BudgetReport,
+    /// One per input, in request order
+    pub inputs: Vec<FittedInput>,
+}
+
+/// Token count of a text
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenCount {
+    pub model: String,
+    pub family: ModelFamily,
+    pub exact: bool,
+    pub tokens: u64,
+}
+
+/// What the provider reports about a model (`None` if it can't be listed)
+async fn model_status(provider: &dyn LlmProvider, model: &str) -> Option<ModelStatus> {
+    let models = provider.list_models().await.ok()?;
+    models.into_iter().find(|status| status.name == model)
+}
+
+/// Family of a model, using the family the provider reports if the name doesn't tell
+async fn family_of(provider: &dyn LlmProvider, model: &str) -> ModelFamily {
+    match ModelFamily::detect(model, None) {
+        ModelFamily::Other => {
+            let status = model_status(provider, model).await;
+            ModelFamily::detect(model, status.as_ref().and_then(|s| s.family.as_deref()))
+        }
+        family => family,
+    }
+}
+
+/// Count the tokens of a text for a model
+pub async fn count_tokens(
+    provider: &dyn LlmProvider,
+    model: &str,
+    text: String,
+) -> Result<TokenCount, LlmError> {
+    let family = family_of(provider, model).await;
+    let tokens = blocking(move || family.count(&text)).await?;
+    Ok(TokenCount {
+        model: model.to_string(),
+        family,
+        exact: family.is_exact(),
+        tokens,
+    })
+}
+
+/// Work out how inputs fit a model's context window without changing them
+///
+/// # Arguments
+/// * `provider` - Provider the model runs on (asked for the context length and family)
+/// * `request` - Inputs and limits (`model` must be set)
+///
+/// # Returns
+/// * `Ok(BudgetReport)` - The breakdown; `fits` is `false` if required inputs don't fit
+pub async fn plan_budget(
+    provider: &dyn LlmProvider,
+    request: &BudgetRequest,
+) -> Result<BudgetReport, LlmError> {
+    let model = request.model.as_str();
+    let detected = ModelFamily::detect(model, None);
+    let status = if request.context_length.is_none() || detected == ModelFamily::Other {
+        model_status(provider, model).await
+    } else {
+        None
+    };
+    let family = ModelFamily::detect(model, status.as_ref().and_then(|s| s.family.as_deref()));
+    let context_length = request
+        .context_length
+        .or(status.and_then(|status| status.context_length))
+        .unwrap_or(DEFAULT_CONTEXT_LENGTH);
+
+    let contents: Vec<String> = request.inputs.iter().map(|input| input.content.clone()).collect();
+    let tokens = blocking(move || {
+        contents.iter().map(|content| family.count(content)).collect::<Vec<u64>>()
+    })
+    .await?;
+
+    let reserved_output = request
+        .reserve_output
+        .unwrap_or(DEFAULT_RESERVE_OUTPUT)
+        .min(context_length / 4);
+    Ok(allot(
+        model,
+        family,
+        context_length,
+        reserved_output,
+        &request.inputs,
+        &tokens,
+    ))
+}
+
+/// Shorten inputs so they fit a model's context window
+///
+/// Inputs are cut or summarized as `plan_budget` decides; summaries are
+/// generated by `provider` and cut to their allotment if the model overshoots.
+///
+/// # Returns
+/// * `Ok(FitResult)` - The inputs to send and the breakdown
+/// * `Err(LlmError)` - A summary failed or the request was cancelled
+pub async fn fit_budget(
+    provider: &dyn LlmProvider,
+    request: BudgetRequest,
+    cancel: &CancellationToken,
+) -> Result<FitResult, LlmError> {
+    let report = plan_budget(provider, &request).await?;
+    let family = report.family;
+
+    let mut inputs = Vec::with_capacity(request.inputs.len());
+    for (input, item) in request.inputs.into_iter().zip(&report.items) {
+        let content = match item.action {
+            BudgetAction::Kept => input.content,
+            BudgetAction::Dropped => String::new(),
+            BudgetAction::Truncated => {
+                let allotted = item.allotted;
+                blocking(move || cut(family, &input.content, allotted)).await?
+            }
+            BudgetAction::Summarized => {
+                // The text to summarize has to fit the window along with the summary
+                let room = report.context_length.saturating_sub(item.allotted + SUMMARY_PROMPT_TOKENS);
+                let content = blocking(move || cut(family, &input.content, room)).await?;
+                let summary =
+                    summarize(provider, &report.model, content, item.allotted, cancel).await?;
+                let allotted = item.allotted;
+                blocking(move || cut(family, &summary, allotted)).await?
+            }
+        };
+        inputs.push(FittedInput {
+            name: input.name,
+            content,
+        });
+    }
+    Ok(FitResult { report, inputs })
+}
+
+/// Split the window between inputs
+///
+/// Required inputs are kept whole. The rest share what is left evenly, smaller
+/// inputs first so the room they don't need goes to the larger ones.
+fn allot(
+    model: &str,
+    family: ModelFamily,
+    context_length: u64,
+    reserved_output: u64,
+    inputs: &[BudgetInput],
+    tokens: &[u64],
+) -> BudgetReport {
+    let available = context_length.saturating_sub(reserved_output);
+    let required: u64 = inputs
+        .iter()
+        .zip(tokens)
+        .filter(|(input, _)| input.required)
+        .map(|(_, tokens)| tokens + INPUT_OVERHEAD)
+        .sum();
+
+    let mut allotted: Vec<u64> = inputs
+        .iter()
+        .zip(tokens)
+        .map(|(input, tokens)| if input.required { *tokens } else { 0 })
+        .collect();
+    let mut optional: Vec<usize> = (0..inputs.len()).filter(|&i| !inputs[i].required).collect();
+    optional.sort_by_key(|&i| tokens[i]);
+
+    let mut left = available.saturating_sub(required);
+    let mut remaining_inputs = optional.len() as u64;
+    for i in optional {
+        let share = (left / remaining_inputs).saturating_sub(INPUT_OVERHEAD);
+        remaining_inputs -= 1;
+        let grant = tokens[i].min(share);
+        if grant < tokens[i] && grant < MIN_ALLOTMENT {
+            continue;
+        }
+        allotted[i] = grant;
+        left = left.saturating_sub(grant + INPUT_OVERHEAD);
+    }
+
+    let items: Vec<BudgetItem> = inputs
+        .iter()
+        .enumerate()
+        .map(|(i, input)| BudgetItem {
+            name: input.name.clone(),
+            tokens: tokens[i],
+            allotted: allotted[i],
+            action: if allotted[i] >= tokens[i] {
+                BudgetAction::Kept
+            } else if allotted[i] == 0 {
+                BudgetAction::Dropped
+            } else if input.strategy == FitStrategy::Summarize {
+                BudgetAction::Summarized
+            } else {
+                BudgetAction::Truncated
+            },
+        })
+        .collect();
+    let used: u64 = items
+        .iter()
+        .filter(|item| item.action != BudgetAction::Dropped)
+        .map(|item| item.allotted + INPUT_OVERHEAD)
+        .sum();
+
+    BudgetReport {
+        model: model.to_string(),
+        family,
+        exact: family.is_exact(),
+        context_length,
+        reserved_output,
+        available,
+        used,
+        remaining: available.saturating_sub(used),
+        fits: required <= available,
+        items,
+    }
+}
+
+/// Cut a text to `max_tokens`, marking the cut
+fn cut(family: ModelFamily, text: &str, max_tokens: u64) -> String {
+    if family.count(text) <= max_tokens {
+        return text.to_string();
+    }
+    let marker = family.count(TRUNCATION_MARKER);
+    let kept = family.truncate(text, max_tokens.saturating_sub(marker));
+    format!("{}{}", kept, TRUNCATION_MARKER)
+}
+
+/// Ask the model for a summary of roughly `max_tokens` tokens
+async fn summarize(
+    provider: &dyn LlmProvider,
+    model: &str,
+    content: String,
+    max_tokens: u64,
+    cancel: &CancellationToken,
+) -> Result<String, LlmError> {
+    // About three words per four tokens of English
+    let words = (max_tokens * 3 / 4).max(1);
+    let request = GenerateRequest {
+        model: model.to_string(),
+        prompt: format!(
+            "Summarize the following text in at most {} words. Keep file paths, names, \
+             numbers and code identifiers exactly as written. Reply with only the summary.\n\n{}",
+            words, content
+        ),
+        system: None,
+        options: None,
+    };
+    let mut ignore = |_: &StreamChunk| {};
+    let response = provider.generate(&request, cancel, &mut ignore).await?;
+    Ok(response.content.trim().to_string())
+}
+
+/// Run tokenizer work off the async runtime (encoding large files takes a while)
+async fn blocking<T: Send + 'static>(
+    work: impl FnOnce() -> T + Send + 'static,
+) -> Result<T, LlmError> {
+    tokio::task::spawn_blocking(work)
+        .await
+        .map_err(|e| LlmError::InvalidResponse(format!("Token counting failed: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::ollama::OllamaProvider;
+    use crate::llm::test_server::{Reply, TestServer};
+
+    /// Encodes as `t`, `ik`, `token`, ` is`, ` great`, `!` in `cl100k_base`
+    const SAMPLE: &str = "tiktoken is great!";
+
+    fn input(name: &str, required: bool) -> BudgetInput {
+        BudgetInput {
+            name: name.to_string(),
+            content: String::new(),
+            required,
+            strategy: FitStrategy::Truncate,
+        }
+    }
+
+    #[test]
+    fn exact_families_match_tiktoken() {
+        assert_eq!(ModelFamily::Gpt.count(SAMPLE), 6);
+        assert_eq!(ModelFamily::Gpt.count("hello world"), 2);
+        assert_eq!(ModelFamily::Gpt4o.count("hello world"), 2);
+        assert_eq!(ModelFamily::Gpt.count(""), 0);
+        assert!(ModelFamily::Gpt.is_exact() && ModelFamily::Gpt4o.is_exact());
+        assert!(!ModelFamily::Llama3.is_exact());
+    }
+
+    #[test]
+    fn estimates_scale_up_and_round_up() {
+        // 6 cl100k tokens times each family's factor
+        assert_eq!(ModelFamily::Llama3.count(SAMPLE), 7);
+        assert_eq!(ModelFamily::Qwen.count(SAMPLE), 7);
+        assert_eq!(ModelFamily::DeepSeek.count(SAMPLE), 7);
+        assert_eq!(ModelFamily::Mistral.count(SAMPLE), 8);
+        assert_eq!(ModelFamily::Other.count(SAMPLE), 8);
+        assert_eq!(ModelFamily::Mistral.count(""), 0);
+    }
+
+    #[test]
+    fn families_are_detected_from_names() {
+        let detect = |model: &str| ModelFamily::detect(model, None);
+        assert_eq!(detect("gpt-4o-mini"), ModelFamily::Gpt4o);
+        assert_eq!(detect("o3-mini"), ModelFamily::Gpt4o);
+        assert_eq!(detect("gpt-4-turbo"), ModelFamily::Gpt);
+        assert_eq!(detect("llama3.1:8b"), ModelFamily::Llama3);
+        assert_eq!(detect("codellama:13b"), ModelFamily::Llama);
+        assert_eq!(detect("hf.co/org/Mistral-7B-GGUF"), ModelFamily::Mistral);
+        assert_eq!(detect("my-finetune"), ModelFamily::Other);
+        // The server's family decides only when the name doesn't
+        assert_eq!(ModelFamily::detect("my-finetune", Some("qwen2")), ModelFamily::Qwen);
+        assert_eq!(ModelFamily::detect("phi3", Some("llama")), ModelFamily::Phi);
+    }
+
+    #[test]
+    fn truncate_keeps_whole_tokens() {
+        assert_eq!(ModelFamily::Gpt.truncate(SAMPLE, 3), "tiktoken");
+        assert_eq!(ModelFamily::Gpt.truncate(SAMPLE, 4), "tiktoken is");
+        assert_eq!(ModelFamily::Gpt.truncate(SAMPLE, 6), SAMPLE);
+        assert_eq!(ModelFamily::Gpt.truncate(SAMPLE, 100), SAMPLE);
+        assert_eq!(ModelFamily::Gpt.truncate(SAMPLE, 0), "");
+        // 5 Mistral tokens are 4 cl100k tokens
+        assert_eq!(ModelFamily::Mistral.truncate(SAMPLE, 5), "tiktoken is");
+    }
+
+    #[test]
+    fn truncate_stops_at_character_boundaries() {
+        let text = "日本語のテキストを切り詰める";
+        for max_tokens in 0..=ModelFamily::Gpt.count(text) {
+            let kept = ModelFamily::Gpt.truncate(text, max_tokens);
+            assert!(text.starts_with(kept));
+            assert!(ModelFamily::Gpt.count(kept) <= max_tokens, "{} > {}", kept, max_tokens);
+        }
+    }
+
+    #[test]
+    fn cut_marks_the_cut_and_stays_within_the_limit() {
+        let text = "word ".repeat(200);
+        let cut_text = cut(ModelFamily::Gpt, &text, 50);
+        assert!(cut_text.ends_with(TRUNCATION_MARKER));
+        assert!(ModelFamily::Gpt.count(&cut_text) <= 50);
+        assert_eq!(cut(ModelFamily::Gpt, SAMPLE, 6), SAMPLE);
+    }
+
+    #[test]
+    fn allot_keeps_required_inputs_and_shares_the_rest() {
+        let inputs = [input("task", true), input("small", false), input("large", false)];
+        let report = allot("gpt-4o", ModelFamily::Gpt4o, 1000, 100, &inputs, &[100, 50, 2000]);
+
+        assert_eq!(report.available, 900);
+        // Required: 100 + 4 overhead, leaving 796. The small input takes its 50
+        // (+4); the large one gets the remaining 742 less its overhead.
+        let allotted: Vec<u64> = report.items.iter().map(|item| item.allotted).collect();
+        assert_eq!(allotted, [100, 50, 738]);
+        let actions: Vec<BudgetAction> = report.items.iter().map(|item| item.action).collect();
+        assert_eq!(actions, [BudgetAction::Kept, BudgetAction::Kept, BudgetAction::Truncated]);
+        assert_eq!(report.used, 900);
+        assert_eq!(report.remaining, 0);
+        assert!(report.fits);
+    }
+
+    #[test]
+    fn allot_splits_evenly_between_large_inputs() {
+        let mut summarized = input("b", false);
+        summarized.strategy = FitStrategy::Summarize;
+        let inputs = [input("a", false), summarized];
+        let report = allot("m", ModelFamily::Other, 1000, 0, &inputs, &[5000, 5000]);
+
+        // 500 each, less 4 overhead; the second gets the first's leftover of 0
+        let allotted: Vec<u64> = report.items.iter().map(|item| item.allotted).collect();
+        assert_eq!(allotted, [496, 496]);
+        assert_eq!(report.items[0].action, BudgetAction::Truncated);
+        assert_eq!(report.items[1].action, BudgetAction::Summarized);
+        assert_eq!(report.used, 1000);
+    }
+
+    #[test]
+    fn allot_drops_inputs_left_with_too_little_room() {
+        let inputs = [input("task", true), input("file", false)];
+        let report = allot("m", ModelFamily::Gpt, 300, 0, &inputs, &[250, 1000]);
+
+        // 46 tokens are left, under the minimum worth keeping
+        assert_eq!(report.items[1].action, BudgetAction::Dropped);
+        assert_eq!(report.items[1].allotted, 0);
+        assert_eq!(report.used, 254);
+        assert_eq!(report.remaining, 46);
+        assert!(report.fits);
+    }
+
+    #[test]
+    fn allot_reports_required_inputs_that_do_not_fit() {
+        let inputs = [input("task", true), input("file", false)];
+        let report = allot("m", ModelFamily::Gpt, 100, 0, &inputs, &[200, 10]);
+
+        assert!(!report.fits);
+        assert_eq!(report.items[0].action, BudgetAction::Kept);
+        assert_eq!(report.items[1].action, BudgetAction::Dropped);
+        assert_eq!(report.remaining, 0);
+    }
+
+    #[tokio::test]
+    async fn plan_budget_caps_the_output_reserve() {
+        // Neither the context length nor the family has to be looked up
+        let server = TestServer::start(|_, _| Reply::status(500, "{}")).await;
+        let provider = OllamaProvider::new(reqwest::Client::new(), &server.url);
+        let mut task = input("task", true);
+        task.content = SAMPLE.to_string();
+        let request = BudgetRequest {
+            model: "gpt-4".to_string(),
+            inputs: vec![task],
+            context_length: Some(2000),
+            reserve_output: Some(1500),
+        };
+
+        let report = plan_budget(&provider, &request).await.unwrap();
+        assert_eq!(report.family, ModelFamily::Gpt);
+        assert_eq!(report.reserved_output, 500);
+        assert_eq!(report.available, 1500);
+        assert_eq!(report.items[0].tokens, 6);
+        assert_eq!(report.used, 10);
+        assert_eq!(server.hits(), 0);
+    }
+}
@@ -0,0 +1,264 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/// Names of the built-in file tools
+pub const READ_FILE: &str = "read_file";
+pub const LIST_DIRECTORY: &str = "list_directory";
+pub const WRITE_FILE: &str = "write_file";
+
+/// A function the model may call, described by a JSON schema
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolDefinition {
+    pub name: String,
+    pub description: String,
+    /// JSON schema of the arguments object
+    pub parameters: Value,
+}
+
+impl ToolDefinition {
+    /// The `tools` entry both Ollama and OpenAI-compatible servers expect
+    pub fn to_wire(&self) -> Value {
+        json!({
+            "type": "function",
+            "function": {
+                "name": self.name,
+                "description": self.description,
+                "parameters": self.parameters,
+            },
+        })
+    }
+}
+
+/// A tool call made by the model
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolCall {
+    /// Id the result is reported under (generated when the server gives none)
+    pub id: String,
+    pub name: String,
+    /// Arguments object (the raw text when the model sent invalid JSON)
+    pub arguments: Value,
+    /// Why the arguments couldn't be parsed as JSON
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arguments_error: Option<String>,
+}
+
+/// Tools offered to the model
+#[derive(Debug, Clone, Default)]
+pub struct ToolRegistry {
+    tools: Vec<ToolDefinition>,
+}
+
+impl ToolRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registry with `read_file`, `list_directory` and `write_file`
+    pub fn file_tools() -> Self {
+        let path = json!({
+            "type": "string",
+            "description": "Absolute path, or a path relative to the project root",
+        });
+        let mut registry = Self::new();
+        registry.register(ToolDefinition {
+            name: READ_FILE.to_string(),
+            description: "Read a UTF-8 text file".to_string(),
+            parameters: json!({
+                "type": "object",
+                "properties": { "path": path },
+                "required": ["path"],
+            }),
+        });
+        registry.register(ToolDefinition {
+            name: LIST_DIRECTORY.to_string(),
+            description: "List the files and directories in a directory".to_string(),
+            parameters: json!({
+                "type": "object",
+                "properties": { "path": path },
+                "required": ["path"],
+            }),
+        });
+        registry.register(ToolDefinition {
+            name: WRITE_FILE.to_string(),
+            description: "Create or overwrite a text file with the given content".to_string(),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "path": path,
+                    "content": { "type": "string", "description": "The complete new file content" },
+                },
+                "required": ["path", "content"],
+            }),
+        });
+        registry
+    }
+
+    /// Add a tool, replacing one with the same name
+    pub fn register(&mut self, tool: ToolDefinition) {
+        self.tools.retain(|existing| existing.name != tool.name);
+        self.tools.push(tool);
+    }
+
+    pub fn definitions(&self) -> &[ToolDefinition] {
+        &self.tools
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ToolDefinition> {
+        self.tools.iter().find(|tool| tool.name == name)
+    }
+
+    /// Check a call against its tool's schema
+    ///
+    /// Checks that the tool exists, the arguments parsed as a JSON object, required
+    /// properties are present and declared properties have the declared JSON type.
+    ///
+    /// # Returns
+    /// * `Err(String)` - What is wrong, worded for the model
+    pub fn validate(&self, call: &ToolCall) -> Result<(), String> {
+        let tool = self
+            .get(&call.name)
+            .ok_or_else(|| format!("Unknown tool: {}", call.name))?;
+        if let Some(error) = &call.arguments_error {
+            return Err(format!("Arguments of {} are not valid JSON: {}", call.name, error));
+        }
+        let arguments = call
+            .arguments
+            .as_object()
+            .ok_or_else(|| format!("Arguments of {} must be a JSON object", call.name))?;
+
+        let required = tool.parameters["required"].as_array().cloned().unwrap_or_default();
+        for name in required.iter().filter_map(Value::as_str) {
+            if !arguments.contains_key(name) {
+                return Err(format!("Missing argument for {}: {}", call.name, name));
+            }
+        }
+
+        let properties = tool.parameters["properties"].as_object();
+        for (name, value) in arguments {
+            let Some(expected) = properties
+                .and_then(|properties| properties.get(name))
+                .and_then(|property| property["type"].as_str())
+            else {
+                continue;
+            };
+            if !has_json_type(value, expected) {
+                return Err(format!(
+                    "Argument {} of {} must be of type {}",
+                    name, call.name, expected
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// System prompt describing the tools for models without native tool calling
+    pub fn fallback_prompt(&self) -> String {
+        let tools: Vec<Value> = self
+            .tools
+            .iter()
+            .map(|tool| {
+                json!({
+                    "name": tool.name,
+                    "description": tool.description,
+                    "parameters": tool.parameters,
+                })
+            })
+            .collect();
+        format!(
+            "You can use these tools:\n{}\n\n\
+             To call a tool, reply with only a JSON object of the form \
+             {{\"tool\": \"<name>\", \"arguments\": {{...}}}}. \
+             The result will be sent back to you. \
+             When you have the final answer, reply in plain text without a tool call.",
+            serde_json::to_string_pretty(&tools).unwrap_or_default()
+        )
+    }
+}
+
+/// Whether a value has a JSON schema type
+fn has_json_type(value: &Value, expected: &str) -> bool {
+    match expected {
+        "string" => value.is_string(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "number" => value.is_number(),
+        "boolean" => value.is_boolean(),
+        "array" => value.is_array(),
+        "object" => value.is_object(),
+        "null" => value.is_null(),
+        _ => true,
+    }
+}
+
+/// Find tool calls written as JSON in a model's text (the fallback format)
+///
+/// Accepts `{"tool": ..., "arguments": {...}}` and the `name`/`parameters`
+/// spellings, alone or in an array, bare or inside code fences or tags such as
+/// `<tool_call>`. Calls get the ids `call_0`, `call_1`, ...
+pub fn parse_tool_calls(text: &str) -> Vec<ToolCall> {
+    let mut calls = Vec::new();
+    let mut offset = 0;
+    while let Some(start) = text[offset..].find(['{', '[']).map(|i| offset + i) {
+        let mut values = serde_json::Deserializer::from_str(&text[start..]).into_iter::<Value>();
+        match values.next() {
+            Some(Ok(value)) => {
+                let found = match &value {
+                    Value::Array(items) => items.iter().filter_map(as_tool_call).collect(),
+                    value => as_tool_call(value).into_iter().collect::<Vec<_>>(),
+                };
+                if found.is_empty() {
+                    // Not a call; look for one nested inside
+                    offset = start + 1;
+                } else {
+                    calls.extend(found);
+                    offset = start + values.byte_offset();
+                }
+            }
+            _ => offset = start + 1,
+        }
+    }
+
+    for (index, call) in calls.iter_mut().enumerate() {
+        call.id = format!("call_{}", index);
+    }
+    calls
+}
+
+/// A tool call in the fallback format, if `value` is one
+fn as_tool_call(value: &Value) -> Option<ToolCall> {
+    let object = value.as_object()?;
+    let name = object
+        .get("tool")
+        .or_else(|| object.get("name"))?
+        .as_str()?
+        .to_string();
+    let arguments = object
+        .get("arguments")
+        .or_else(|| object.get("parameters"))
+        .cloned()
+        .unwrap_or_else(|| json!({}));
+    // Some models send the arguments as a JSON string
+    let (arguments, arguments_error) = match arguments {
+        Value::String(text) => parse_arguments(text),
+        arguments => (arguments, None),
+    };
+    Some(ToolCall {
+        id: String::new(),
+        name,
+        arguments,
+        arguments_error,
+    })
+}
+
+/// Parse JSON-encoded tool call arguments
+///
+/// # Returns
+/// * The arguments and no error, or the raw text and why it isn't valid JSON
+pub fn parse_arguments(text: String) -> (Value, Option<String>) {
+    if text.trim().is_empty() {
+        return (json!({}), None);
+    }
+    match serde_json::from_str(&text) {
+        Ok(arguments) => (arguments, None),
+        Err(e) => (Value::String(text), Some(e.to_string())),
+    }
+}
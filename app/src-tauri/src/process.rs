@@ -0,0 +1,528 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io;
+use std::path::PathBuf;
+use std::process::{ExitStatus, Stdio};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter};
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::process::{Child, Command};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use crate::filesystem::FileSystemError;
+use crate::security::Actor;
+
+/// Event names for process output and termination
+pub const PROCESS_OUTPUT: &str = "process-output";
+pub const PROCESS_EXITED: &str = "process-exited";
+
+/// Identifier of a running command
+pub type ProcessId = u64;
+
+/// Timeout used when a request doesn't set one
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+/// Longest timeout a request may ask for
+const MAX_TIMEOUT: Duration = Duration::from_secs(2 * 60 * 60);
+
+/// How long a cancelled process may take to exit before it is killed
+const KILL_GRACE: Duration = Duration::from_secs(5);
+
+/// How long to wait for output still buffered when the process exits
+const OUTPUT_DRAIN_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Output without a newline is emitted in pieces of at most this size
+const MAX_LINE_BYTES: usize = 16 * 1024;
+
+/// Environment variables passed through to commands (everything else is dropped)
+pub const ENV_ALLOW_LIST: &[&str] = &[
+    "PATH",
+    "HOME",
+    "USER",
+    "LOGNAME",
+    "SHELL",
+    "LANG",
+    "LC_ALL",
+    "LC_CTYPE",
+    "TERM",
+    "TZ",
+    "TMPDIR",
+    "TEMP",
+    "TMP",
+    "SYSTEMROOT",
+    "USERPROFILE",
+    "APPDATA",
+    "LOCALAPPDATA",
+    "PROGRAMDATA",
+    "CARGO_HOME",
+    "RUSTUP_HOME",
+    "RUSTUP_TOOLCHAIN",
+    "RUST_BACKTRACE",
+    "RUST_LOG",
+    "CARGO_TERM_COLOR",
+    "NODE_ENV",
+    "NVM_DIR",
+    "CI",
+    "NO_COLOR",
+    "FORCE_COLOR",
+];
+
+/// Whether an environment variable may be passed to a command
+pub(crate) fn is_env_allowed(name: &str) -> bool {
+    ENV_ALLOW_LIST.iter().any(|allowed| {
+        if cfg!(windows) {
+            allowed.eq_ignore_ascii_case(name)
+        } else {
+            *allowed == name
+        }
+    })
+}
+
+/// A command to run
+#[derive(Debug, Clone, Deserialize)]
+pub struct RunCommandRequest {
+    /// Program name (looked up on `PATH`) or path relative to `cwd`
+    pub program: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Working directory (must grant the execute capability)
+    pub cwd: String,
+    /// Extra environment variables (names must be on the allow-list)
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    /// Timeout in milliseconds (defaults to 10 minutes)
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+    /// Run in new user and network namespaces (Linux only)
+    #[serde(default)]
+    pub sandbox: bool,
+}
+
+/// Which output stream a chunk came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+/// Process output payload (one line, or part of a very long line)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcessOutput {
+    pub id: ProcessId,
+    pub stream: OutputStream,
+    pub data: String,
+}
+
+/// Why a process ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExitReason {
+    /// The process exited on its own
+    Exited,
+    TimedOut,
+    Cancelled,
+    Killed,
+    /// Waiting for the process failed
+    Failed,
+}
+
+/// Process termination payload
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcessExit {
+    pub id: ProcessId,
+    pub reason: ExitReason,
+    /// Exit code (`None` if the process was ended by a signal)
+    pub code: Option<i32>,
+    /// Signal that ended the process (Unix only)
+    pub signal: Option<i32>,
+    pub success: bool,
+    pub duration_ms: u64,
+    pub error: Option<String>,
+}
+
+/// A running command
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcessInfo {
+    pub id: ProcessId,
+    pub program: String,
+    pub args: Vec<String>,
+    pub cwd: String,
+    pub actor: Actor,
+    /// Window that started the command (receives its events)
+    pub window: String,
+    #[serde(with = "chrono::serde::ts_milliseconds")]
+    pub started_at: DateTime<Utc>,
+    pub sandboxed: bool,
+}
+
+/// How to stop a process
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StopRequest {
+    /// Ask the process to exit, killing it after `KILL_GRACE`
+    Cancel,
+    /// Kill the process immediately
+    Kill,
+}
+
+struct ProcessEntry {
+    info: ProcessInfo,
+    pid: Option<u32>,
+    stop: mpsc::UnboundedSender<StopRequest>,
+}
+
+type Processes = Arc<Mutex<HashMap<ProcessId, ProcessEntry>>>;
+
+/// Running commands (thread-safe)
+pub struct ProcessState {
+    processes: Processes,
+    next_id: AtomicU64,
+}
+
+impl ProcessState {
+    pub fn new() -> Self {
+        Self {
+            processes: Arc::new(Mutex::new(HashMap::new())),
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    /// Running commands, oldest first
+    pub fn list(&self) -> Vec<ProcessInfo> {
+        let mut processes: Vec<ProcessInfo> = self
+            .processes
+            .lock()
+            .unwrap()
+            .values()
+            .map(|entry| entry.info.clone())
+            .collect();
+        processes.sort_by_key(|info| info.id);
+        processes
+    }
+
+    /// Window that started a process
+    pub fn owner(&self, id: ProcessId) -> Option<String> {
+        self.processes
+            .lock()
+            .unwrap()
+            .get(&id)
+            .map(|entry| entry.info.window.clone())
+    }
+
+    fn request_stop(&self, id: ProcessId, request: StopRequest) -> Result<(), FileSystemError> {
+        let processes = self.processes.lock().unwrap();
+        let entry = processes
+            .get(&id)
+            .ok_or_else(|| FileSystemError::NotFound(format!("No running process {}", id)))?;
+        // The supervisor only stops listening once the process has exited
+        let _ = entry.stop.send(request);
+        Ok(())
+    }
+
+    /// Ask a process to exit (it is killed if it doesn't within a grace period)
+    pub fn cancel(&self, id: ProcessId) -> Result<(), FileSystemError> {
+        self.request_stop(id, StopRequest::Cancel)
+    }
+
+    /// Kill a process and its children immediately
+    pub fn kill(&self, id: ProcessId) -> Result<(), FileSystemError> {
+        self.request_stop(id, StopRequest::Kill)
+    }
+
+    /// Kill every process started by a window
+    pub fn kill_window(&self, window_label: &str) {
+        for entry in self.processes.lock().unwrap().values() {
+            if entry.info.window == window_label {
+                let _ = entry.stop.send(StopRequest::Kill);
+            }
+        }
+    }
+
+    /// Kill every process right away
+    ///
+    /// Called when the application exits, when supervisor tasks may no longer run.
+    pub fn shutdown(&self) {
+        for entry in self.processes.lock().unwrap().drain().map(|(_, entry)| entry) {
+            if let Some(pid) = entry.pid {
+                signal_group(pid, true);
+            }
+        }
+    }
+}
+
+/// Start a command and stream its output to the starting window
+///
+/// The caller must already have checked the working directory (and a program
+/// given as a path) for the execute capability.
+///
+/// # Arguments
+/// * `request` - The command to run
+/// * `cwd` - Resolved working directory
+/// * `actor` - Who runs the command
+/// * `window_label` - Window that receives the output and exit events
+/// * `app` - App handle for emitting events
+/// * `state` - Process state
+///
+/// # Returns
+/// * `Ok(ProcessInfo)` - The command was started
+/// * `Err(FileSystemError)` - The request is invalid or the program could not be started
+pub fn spawn_command(
+    request: RunCommandRequest,
+    cwd: PathBuf,
+    actor: Actor,
+    window_label: &str,
+    app: AppHandle,
+    state: &ProcessState,
+) -> Result<ProcessInfo, FileSystemError> {
+    if let Some(name) = request.env.keys().find(|name| !is_env_allowed(name)) {
+        return Err(FileSystemError::InvalidInput(format!(
+            "Environment variable {} is not on the allow-list",
+            name
+        )));
+    }
+    if request.sandbox && !cfg!(target_os = "linux") {
+        return Err(FileSystemError::InvalidInput(
+            "The process sandbox is only available on Linux".to_string(),
+        ));
+    }
+
+    let timeout = request
+        .timeout_ms
+        .map(Duration::from_millis)
+        .unwrap_or(DEFAULT_TIMEOUT)
+        .min(MAX_TIMEOUT);
+
+    let mut command = Command::new(&request.program);
+    command
+        .args(&request.args)
+        .current_dir(&cwd)
+        .env_clear()
+        .envs(std::env::vars_os().filter(|(name, _)| name.to_str().is_some_and(is_env_allowed)))
+        .envs(&request.env)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    // Own process group, so stopping the command also stops what it spawned
+    #[cfg(unix)]
+    command.process_group(0);
+    #[cfg(target_os = "linux")]
+    if request.sandbox {
+        sandbox(&mut command);
+    }
+
+    let mut child = command.spawn().map_err(|e| match e.kind() {
+        io::ErrorKind::NotFound => {
+            FileSystemError::NotFound(format!("Program not found: {}", request.program))
+        }
+        _ => FileSystemError::from(e),
+    })?;
+
+    let id = state.next_id.fetch_add(1, Ordering::Relaxed);
+    let info = ProcessInfo {
+        id,
+        program: request.program,
+        args: request.args,
+        cwd: cwd.to_string_lossy().to_string(),
+        actor,
+        window: window_label.to_string(),
+        started_at: Utc::now(),
+        sandboxed: request.sandbox,
+    };
+
+    let (stop_tx, stop_rx) = mpsc::unbounded_channel();
+    let pid = child.id();
+    state.processes.lock().unwrap().insert(
+        id,
+        ProcessEntry {
+            info: info.clone(),
+            pid,
+            stop: stop_tx,
+        },
+    );
+
+    let stdout = child
+        .stdout
+        .take()
+        .map(|reader| spawn_output(reader, id, OutputStream::Stdout, window_label, &app));
+    let stderr = child
+        .stderr
+        .take()
+        .map(|reader| spawn_output(reader, id, OutputStream::Stderr, window_label, &app));
+
+    let processes = state.processes.clone();
+    let window = window_label.to_string();
+    tokio::spawn(async move {
+        let started = Instant::now();
+        let (reason, status) = supervise(&mut child, timeout, stop_rx).await;
+
+        // Output still in the pipes belongs before the exit event
+        for task in [stdout, stderr].into_iter().flatten() {
+            let _ = tokio::time::timeout(OUTPUT_DRAIN_TIMEOUT, task).await;
+        }
+        processes.lock().unwrap().remove(&id);
+
+        let exit = match status {
+            Ok(status) => ProcessExit {
+                id,
+                reason,
+                code: status.code(),
+                signal: exit_signal(&status),
+                success: status.success(),
+                duration_ms: started.elapsed().as_millis() as u64,
+                error: None,
+            },
+            Err(e) => ProcessExit {
+                id,
+                reason: ExitReason::Failed,
+                code: None,
+                signal: None,
+                success: false,
+                duration_ms: started.elapsed().as_millis() as u64,
+                error: Some(e.to_string()),
+            },
+        };
+        let _ = app.emit_to(window.as_str(), PROCESS_EXITED, exit);
+    });
+
+    Ok(info)
+}
+
+/// Wait for a process to exit, time out, or be stopped
+async fn supervise(
+    child: &mut Child,
+    timeout: Duration,
+    mut stop_rx: mpsc::UnboundedReceiver<StopRequest>,
+) -> (ExitReason, io::Result<ExitStatus>) {
+    let (reason, force) = tokio::select! {
+        status = child.wait() => return (ExitReason::Exited, status),
+        _ = tokio::time::sleep(timeout) => (ExitReason::TimedOut, false),
+        Some(request) = stop_rx.recv() => match request {
+            StopRequest::Cancel => (ExitReason::Cancelled, false),
+            StopRequest::Kill => (ExitReason::Killed, true),
+        },
+    };
+
+    // Graceful termination needs signals (Unix only)
+    if !force && cfg!(unix) {
+        if let Some(pid) = child.id() {
+            signal_group(pid, false);
+            // A kill request during the grace period cuts it short
+            let grace = tokio::select! {
+                status = child.wait() => Some(status),
+                _ = tokio::time::sleep(KILL_GRACE) => None,
+                Some(StopRequest::Kill) = stop_rx.recv() => None,
+            };
+            if let Some(status) = grace {
+                return (reason, status);
+            }
+        }
+    }
+
+    if let Some(pid) = child.id() {
+        signal_group(pid, true);
+    }
+    let _ = child.start_kill();
+    (reason, child.wait().await)
+}
+
+/// Emit a process's output stream line by line (until it closes)
+fn spawn_output<R: AsyncRead + Send + Unpin + 'static>(
+    reader: R,
+    id: ProcessId,
+    stream: OutputStream,
+    window_label: &str,
+    app: &AppHandle,
+) -> JoinHandle<()> {
+    let window_label = window_label.to_string();
+    let app = app.clone();
+    tokio::spawn(async move { forward_output(reader, id, stream, &window_label, &app).await })
+}
+
+async fn forward_output<R: AsyncRead + Unpin>(
+    reader: R,
+    id: ProcessId,
+    stream: OutputStream,
+    window_label: &str,
+    app: &AppHandle,
+) {
+    let emit = |line: &[u8]| {
+        let payload = ProcessOutput {
+            id,
+            stream,
+            data: String::from_utf8_lossy(line).into_owned(),
+        };
+        let _ = app.emit_to(window_label, PROCESS_OUTPUT, payload);
+    };
+
+    let mut reader = BufReader::new(reader);
+    let mut line = Vec::new();
+    loop {
+        let available = match reader.fill_buf().await {
+            Ok(available) if !available.is_empty() => available,
+            _ => break,
+        };
+        let (len, complete) = match available.iter().position(|byte| *byte == b'\n') {
+            Some(newline) => (newline + 1, true),
+            None => (available.len(), false),
+        };
+        line.extend_from_slice(&available[..len]);
+        reader.consume(len);
+
+        if complete || line.len() >= MAX_LINE_BYTES {
+            emit(&line);
+            line.clear();
+        }
+    }
+    if !line.is_empty() {
+        emit(&line);
+    }
+}
+
+/// Signal a process group: terminate, or kill when `force` is set
+#[cfg(unix)]
+fn signal_group(pid: u32, force: bool) {
+    let signal = if force { libc::SIGKILL } else { libc::SIGTERM };
+    // The command leads its own group, whose id is its pid
+    unsafe {
+        libc::kill(-(pid as libc::pid_t), signal);
+    }
+}
+
+/// Without process groups only the direct child can be stopped (by the caller)
+#[cfg(not(unix))]
+fn signal_group(_pid: u32, _force: bool) {}
+
+#[cfg(unix)]
+fn exit_signal(status: &ExitStatus) -> Option<i32> {
+    use std::os::unix::process::ExitStatusExt;
+    status.signal()
+}
+
+#[cfg(not(unix))]
+fn exit_signal(_status: &ExitStatus) -> Option<i32> {
+    None
+}
+
+/// Run the command in new user and network namespaces
+///
+/// The command keeps the caller's view of the filesystem but has no network
+/// access, and can't gain privileges through setuid binaries.
+#[cfg(target_os = "linux")]
+fn sandbox(command: &mut Command) {
+    unsafe {
+        command.pre_exec(|| {
+            if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            if libc::unshare(libc::CLONE_NEWUSER | libc::CLONE_NEWNET | libc::CLONE_NEWIPC) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+}
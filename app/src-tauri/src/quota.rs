@@ -0,0 +1,446 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use crate::filesystem::FileSystemError;
+use crate::security::Actor;
+
+/// Event emitted when an actor breaches a quota and is paused
+pub const QUOTA_EXCEEDED: &str = "quota-exceeded";
+
+/// Window over which operations per second are measured
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+/// Limits applied to one actor (`None` = unlimited)
+///
+/// Byte, create and delete limits cap the actor's total across all its tasks
+/// until it is resumed with a usage reset.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuotaLimits {
+    pub max_bytes_written: Option<u64>,
+    pub max_files_created: Option<u64>,
+    pub max_deletes: Option<u64>,
+    pub max_ops_per_second: Option<u32>,
+}
+
+impl QuotaLimits {
+    /// Limits an actor starts with
+    ///
+    /// The user is unlimited; automated actors get limits that stop a runaway
+    /// loop without getting in the way of normal work.
+    pub fn default_for(actor: Actor) -> Self {
+        match actor {
+            Actor::User => Self::default(),
+            Actor::OverseerAi | Actor::ImplementationAi | Actor::Plugin => Self {
+                max_bytes_written: Some(100 * 1024 * 1024),
+                max_files_created: Some(500),
+                max_deletes: Some(50),
+                max_ops_per_second: Some(20),
+            },
+        }
+    }
+}
+
+/// Which limit was breached
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QuotaKind {
+    BytesWritten,
+    FilesCreated,
+    Deletes,
+    OpsPerSecond,
+    /// The actor was already paused by an earlier breach
+    Paused,
+}
+
+/// What an operation consumes
+#[derive(Debug, Clone, Copy, Default)]
+pub struct QuotaCharge {
+    pub bytes_written: u64,
+    pub files_created: u64,
+    pub deletes: u64,
+}
+
+/// Resources an actor has used across all its tasks
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Usage {
+    pub bytes_written: u64,
+    pub files_created: u64,
+    pub deletes: u64,
+}
+
+/// Payload of `QUOTA_EXCEEDED`, also the error of a rejected charge
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuotaBreach {
+    pub actor: Actor,
+    pub task_id: Option<String>,
+    pub kind: QuotaKind,
+    pub message: String,
+    /// This breach paused the actor (only then is the event emitted)
+    #[serde(skip)]
+    pub newly_paused: bool,
+}
+
+impl From<QuotaBreach> for FileSystemError {
+    fn from(breach: QuotaBreach) -> Self {
+        FileSystemError::QuotaExceeded(breach.message)
+    }
+}
+
+/// Resource usage of one task
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TaskUsage {
+    pub actor: Actor,
+    pub task_id: Option<String>,
+    pub bytes_written: u64,
+    pub files_created: u64,
+    pub deletes: u64,
+}
+
+/// Quota configuration and usage of one actor
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuotaStatus {
+    pub actor: Actor,
+    pub limits: QuotaLimits,
+    pub paused: bool,
+    /// Usage the limits are checked against
+    pub total: Usage,
+    pub tasks: Vec<TaskUsage>,
+}
+
+type TaskKey = (Actor, Option<String>);
+
+#[derive(Default)]
+struct QuotaInner {
+    limits: HashMap<Actor, QuotaLimits>,
+    usage: HashMap<TaskKey, TaskUsage>,
+    totals: HashMap<Actor, Usage>,
+    recent_ops: HashMap<Actor, VecDeque<Instant>>,
+    paused: HashSet<Actor>,
+}
+
+/// Per-actor quotas and rate limits for file operations (thread-safe)
+pub struct QuotaState {
+    inner: Mutex<QuotaInner>,
+}
+
+impl QuotaState {
+    pub fn new() -> Self {
+        Self {
+            inner: Mutex::new(QuotaInner::default()),
+        }
+    }
+
+    /// Charge an operation against the actor's quotas
+    ///
+    /// Nothing is charged if a limit would be exceeded. Breaching a byte,
+    /// create or delete limit pauses the actor until `resume` is called; going
+    /// over the rate limit only rejects this operation.
+    ///
+    /// # Returns
+    /// * `Ok(())` - The operation may proceed
+    /// * `Err(QuotaBreach)` - A limit was exceeded or the actor is paused
+    pub fn charge(
+        &self,
+        actor: Actor,
+        task_id: Option<&str>,
+        charge: QuotaCharge,
+    ) -> Result<(), QuotaBreach> {
+        let mut guard = self.inner.lock().unwrap();
+        let inner = &mut *guard;
+        let task_id = task_id.map(str::to_string);
+        let breach = |kind: QuotaKind, message: String, newly_paused: bool| QuotaBreach {
+            actor,
+            task_id: task_id.clone(),
+            kind,
+            message,
+            newly_paused,
+        };
+
+        if inner.paused.contains(&actor) {
+            return Err(breach(
+                QuotaKind::Paused,
+                format!("{:?} is paused after exceeding a quota", actor),
+                false,
+            ));
+        }
+
+        let limits = inner
+            .limits
+            .get(&actor)
+            .copied()
+            .unwrap_or_else(|| QuotaLimits::default_for(actor));
+
+        // Rate limit over a sliding window
+        let now = Instant::now();
+        let recent = inner.recent_ops.entry(actor).or_default();
+        while recent.front().is_some_and(|t| now.duration_since(*t) > RATE_WINDOW) {
+            recent.pop_front();
+        }
+        if let Some(max) = limits.max_ops_per_second.filter(|max| recent.len() >= *max as usize) {
+            return Err(breach(
+                QuotaKind::OpsPerSecond,
+                format!(
+                    "{:?} exceeded its rate limit (more than {} operations per second); \
+                     try again shortly",
+                    actor, max
+                ),
+                false,
+            ));
+        }
+
+        let total = inner.totals.get(&actor).copied().unwrap_or_default();
+        let exceeded = [
+            (
+                QuotaKind::BytesWritten,
+                limits.max_bytes_written,
+                total.bytes_written + charge.bytes_written,
+                "bytes written",
+            ),
+            (
+                QuotaKind::FilesCreated,
+                limits.max_files_created,
+                total.files_created + charge.files_created,
+                "files created",
+            ),
+            (
+                QuotaKind::Deletes,
+                limits.max_deletes,
+                total.deletes + charge.deletes,
+                "deletes",
+            ),
+        ]
+        .into_iter()
+        .find_map(|(kind, max, total, what)| {
+            max.filter(|max| total > *max)
+                .map(|max| (kind, format!("more than {} {}", max, what)))
+        });
+
+        if let Some((kind, limit)) = exceeded {
+            inner.paused.insert(actor);
+            let task = task_id
+                .as_deref()
+                .map(|id| format!(" in task {}", id))
+                .unwrap_or_default();
+            return Err(breach(
+                kind,
+                format!("{:?} exceeded its quota ({}){}; paused", actor, limit, task),
+                true,
+            ));
+        }
+
+        inner.recent_ops.entry(actor).or_default().push_back(now);
+        let total = inner.totals.entry(actor).or_default();
+        total.bytes_written += charge.bytes_written;
+        total.files_created += charge.files_created;
+        total.deletes += charge.deletes;
+        let usage = inner
+            .usage
+            .entry((actor, task_id.clone()))
+            .or_insert_with(|| TaskUsage {
+                actor,
+                task_id: task_id.clone(),
+                ..TaskUsage::default()
+            });
+        usage.bytes_written += charge.bytes_written;
+        usage.files_created += charge.files_created;
+        usage.deletes += charge.deletes;
+        Ok(())
+    }
+
+    /// Let a paused actor continue, optionally clearing its usage
+    ///
+    /// # Returns
+    /// * `true` - The actor was paused
+    pub fn resume(&self, actor: Actor, reset_usage: bool) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        if reset_usage {
+            inner.usage.retain(|(usage_actor, _), _| *usage_actor != actor);
+            inner.totals.remove(&actor);
+        }
+        inner.recent_ops.remove(&actor);
+        inner.paused.remove(&actor)
+    }
+
+    /// Replace the limits for an actor
+    pub fn set_limits(&self, actor: Actor, limits: QuotaLimits) {
+        self.inner.lock().unwrap().limits.insert(actor, limits);
+    }
+
+    /// Limits, pause state and task usage of every actor
+    pub fn status(&self) -> Vec<QuotaStatus> {
+        let inner = self.inner.lock().unwrap();
+        [Actor::User, Actor::OverseerAi, Actor::ImplementationAi, Actor::Plugin]
+            .into_iter()
+            .map(|actor| {
+                let mut tasks: Vec<TaskUsage> = inner
+                    .usage
+                    .values()
+                    .filter(|usage| usage.actor == actor)
+                    .cloned()
+                    .collect();
+                tasks.sort_by(|a, b| a.task_id.cmp(&b.task_id));
+                QuotaStatus {
+                    actor,
+                    limits: inner
+                        .limits
+                        .get(&actor)
+                        .copied()
+                        .unwrap_or_else(|| QuotaLimits::default_for(actor)),
+                    paused: inner.paused.contains(&actor),
+                    total: inner.totals.get(&actor).copied().unwrap_or_default(),
+                    tasks,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Quotas where the plugin has the given limits
+    fn quotas(limits: QuotaLimits) -> QuotaState {
+        let quotas = QuotaState::new();
+        quotas.set_limits(Actor::Plugin, limits);
+        quotas
+    }
+
+    fn writes(bytes: u64) -> QuotaCharge {
+        QuotaCharge {
+            bytes_written: bytes,
+            ..QuotaCharge::default()
+        }
+    }
+
+    fn status(quotas: &QuotaState, actor: Actor) -> QuotaStatus {
+        quotas.status().into_iter().find(|status| status.actor == actor).unwrap()
+    }
+
+    #[test]
+    fn byte_limit_pauses_the_actor() {
+        let quotas = quotas(QuotaLimits {
+            max_bytes_written: Some(100),
+            ..QuotaLimits::default()
+        });
+
+        quotas.charge(Actor::Plugin, None, writes(60)).unwrap();
+        quotas.charge(Actor::Plugin, None, writes(40)).unwrap();
+        let breach = quotas.charge(Actor::Plugin, None, writes(1)).unwrap_err();
+        assert_eq!(breach.kind, QuotaKind::BytesWritten);
+        assert!(breach.newly_paused);
+
+        // Paused: even free operations fail, without another event
+        let breach = quotas.charge(Actor::Plugin, None, QuotaCharge::default()).unwrap_err();
+        assert_eq!(breach.kind, QuotaKind::Paused);
+        assert!(!breach.newly_paused);
+        // The rejected charge was not counted
+        assert_eq!(status(&quotas, Actor::Plugin).total.bytes_written, 100);
+    }
+
+    #[test]
+    fn create_and_delete_limits_are_separate() {
+        let quotas = quotas(QuotaLimits {
+            max_files_created: Some(2),
+            max_deletes: Some(1),
+            ..QuotaLimits::default()
+        });
+        let creates = QuotaCharge {
+            files_created: 1,
+            ..QuotaCharge::default()
+        };
+        let deletes = QuotaCharge {
+            deletes: 1,
+            ..QuotaCharge::default()
+        };
+
+        quotas.charge(Actor::Plugin, None, creates).unwrap();
+        quotas.charge(Actor::Plugin, None, deletes).unwrap();
+        quotas.charge(Actor::Plugin, None, creates).unwrap();
+        let breach = quotas.charge(Actor::Plugin, None, deletes).unwrap_err();
+        assert_eq!(breach.kind, QuotaKind::Deletes);
+
+        quotas.resume(Actor::Plugin, false);
+        let breach = quotas.charge(Actor::Plugin, None, creates).unwrap_err();
+        assert_eq!(breach.kind, QuotaKind::FilesCreated);
+    }
+
+    #[test]
+    fn new_tasks_do_not_reset_the_actor_total() {
+        let quotas = quotas(QuotaLimits {
+            max_bytes_written: Some(100),
+            ..QuotaLimits::default()
+        });
+
+        quotas.charge(Actor::Plugin, Some("a"), writes(60)).unwrap();
+        let breach = quotas.charge(Actor::Plugin, Some("b"), writes(60)).unwrap_err();
+        assert_eq!(breach.kind, QuotaKind::BytesWritten);
+        assert_eq!(breach.task_id.as_deref(), Some("b"));
+
+        let status = status(&quotas, Actor::Plugin);
+        assert_eq!(status.total.bytes_written, 60);
+        assert_eq!(status.tasks.len(), 1);
+        assert_eq!(status.tasks[0].task_id.as_deref(), Some("a"));
+    }
+
+    #[test]
+    fn rate_limit_rejects_without_pausing() {
+        let quotas = quotas(QuotaLimits {
+            max_ops_per_second: Some(3),
+            ..QuotaLimits::default()
+        });
+
+        for _ in 0..3 {
+            quotas.charge(Actor::Plugin, None, QuotaCharge::default()).unwrap();
+        }
+        let breach = quotas.charge(Actor::Plugin, None, QuotaCharge::default()).unwrap_err();
+        assert_eq!(breach.kind, QuotaKind::OpsPerSecond);
+        assert!(!breach.newly_paused);
+        assert!(!status(&quotas, Actor::Plugin).paused);
+
+        // Room again once the window has passed
+        std::thread::sleep(RATE_WINDOW + Duration::from_millis(50));
+        quotas.charge(Actor::Plugin, None, QuotaCharge::default()).unwrap();
+    }
+
+    #[test]
+    fn resume_lifts_the_pause_and_optionally_the_usage() {
+        let quotas = quotas(QuotaLimits {
+            max_bytes_written: Some(100),
+            ..QuotaLimits::default()
+        });
+        quotas.charge(Actor::Plugin, Some("a"), writes(100)).unwrap();
+        quotas.charge(Actor::Plugin, Some("a"), writes(1)).unwrap_err();
+
+        // Resuming keeps the usage, so the next write breaches again
+        assert!(quotas.resume(Actor::Plugin, false));
+        assert!(!quotas.resume(Actor::Plugin, false));
+        quotas.charge(Actor::Plugin, Some("a"), writes(0)).unwrap();
+        quotas.charge(Actor::Plugin, Some("a"), writes(1)).unwrap_err();
+
+        assert!(quotas.resume(Actor::Plugin, true));
+        quotas.charge(Actor::Plugin, Some("a"), writes(100)).unwrap();
+        let status = status(&quotas, Actor::Plugin);
+        assert!(!status.paused);
+        assert_eq!(status.total.bytes_written, 100);
+    }
+
+    #[test]
+    fn actors_have_separate_quotas() {
+        let quotas = quotas(QuotaLimits {
+            max_deletes: Some(0),
+            ..QuotaLimits::default()
+        });
+        let delete = QuotaCharge {
+            deletes: 1,
+            ..QuotaCharge::default()
+        };
+
+        quotas.charge(Actor::Plugin, None, delete).unwrap_err();
+        quotas.charge(Actor::User, None, delete).unwrap();
+        quotas.charge(Actor::OverseerAi, None, delete).unwrap();
+        assert!(status(&quotas, Actor::Plugin).paused);
+        assert!(!status(&quotas, Actor::OverseerAi).paused);
+    }
+}
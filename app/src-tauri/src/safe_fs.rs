@@ -0,0 +1,490 @@
+// Filesystem operations on paths already checked by `SecurityManager::resolve`.
+// Paths are reached openat-style from the root refusing symlinks, so a component
+// swapped between the check and the use fails instead of escaping the allowed roots.
+
+use std::ffi::{OsStr, OsString};
+use std::fs::{File, Metadata};
+use std::io;
+use std::path::Path;
+use std::time::SystemTime;
+
+/// A directory entry, described without following it if it is a symlink
+#[derive(Debug, Clone)]
+pub struct DirEntry {
+    pub name: OsString,
+    pub is_file: bool,
+    pub is_dir: bool,
+    pub len: u64,
+    pub modified: Option<SystemTime>,
+}
+
+/// Error for a path that changed (e.g. became a symlink) after it was checked
+fn path_changed(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        format!("Path changed after it was checked: {}", path.to_string_lossy()),
+    )
+}
+
+/// Split a path into its parent directory and final component
+fn split(path: &Path) -> io::Result<(&Path, &OsStr)> {
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => Ok((parent, name)),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Path has no parent directory: {}", path.to_string_lossy()),
+        )),
+    }
+}
+
+#[cfg(unix)]
+mod imp {
+    use super::{path_changed, split, DirEntry};
+    use std::ffi::{CStr, CString, OsStr, OsString};
+    use std::fs::{File, Metadata};
+    use std::io::{self, Read, Write};
+    use std::os::fd::{AsRawFd, FromRawFd};
+    use std::os::unix::ffi::{OsStrExt, OsStringExt};
+    use std::path::{Component, Path};
+    use std::time::{Duration, SystemTime};
+
+    fn c_name(name: &OsStr) -> io::Result<CString> {
+        CString::new(name.as_bytes())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Path contains a NUL byte"))
+    }
+
+    fn check(result: libc::c_int, path: &Path) -> io::Result<libc::c_int> {
+        if result >= 0 {
+            return Ok(result);
+        }
+        let error = io::Error::last_os_error();
+        // O_NOFOLLOW reports a symlink as ELOOP (or ENOTDIR with O_DIRECTORY)
+        match error.raw_os_error() {
+            Some(libc::ELOOP) => Err(path_changed(path)),
+            _ => Err(error),
+        }
+    }
+
+    /// Open `name` inside `dir` without following a symlink
+    fn open_at(
+        dir: &File,
+        name: &OsStr,
+        flags: libc::c_int,
+        mode: libc::mode_t,
+        path: &Path,
+    ) -> io::Result<File> {
+        let c_name = c_name(name)?;
+        let fd = unsafe {
+            libc::openat(
+                dir.as_raw_fd(),
+                c_name.as_ptr(),
+                flags | libc::O_NOFOLLOW | libc::O_CLOEXEC,
+                mode as libc::c_uint,
+            )
+        };
+        let fd = check(fd, path)?;
+        Ok(unsafe { File::from_raw_fd(fd) })
+    }
+
+    /// Walk from the root to `path`, optionally creating missing directories
+    fn walk_dirs(path: &Path, create: bool) -> io::Result<File> {
+        let mut components = path.components();
+        if components.next() != Some(Component::RootDir) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Expected an absolute path: {}", path.to_string_lossy()),
+            ));
+        }
+
+        let mut dir = File::open("/")?;
+        for component in components {
+            let Component::Normal(name) = component else {
+                return Err(path_changed(path));
+            };
+            let flags = libc::O_RDONLY | libc::O_DIRECTORY;
+            dir = match open_at(&dir, name, flags, 0, path) {
+                Err(e) if create && e.kind() == io::ErrorKind::NotFound => {
+                    let c_name = c_name(name)?;
+                    let result = unsafe { libc::mkdirat(dir.as_raw_fd(), c_name.as_ptr(), 0o777) };
+                    if let Err(e) = check(result, path) {
+                        if e.kind() != io::ErrorKind::AlreadyExists {
+                            return Err(e);
+                        }
+                    }
+                    open_at(&dir, name, flags, 0, path)?
+                }
+                // A directory swapped for a symlink fails O_DIRECTORY before O_NOFOLLOW
+                Err(e) if e.raw_os_error() == Some(libc::ENOTDIR) => {
+                    let is_link = stat_at(&dir, name, path)
+                        .is_ok_and(|stat| stat.st_mode & libc::S_IFMT == libc::S_IFLNK);
+                    return Err(if is_link { path_changed(path) } else { e });
+                }
+                other => other?,
+            };
+        }
+        Ok(dir)
+    }
+
+    /// Names in an open directory, except `.` and `..`
+    fn entry_names(dir: &File) -> io::Result<Vec<OsString>> {
+        // fdopendir takes ownership of the descriptor it is given
+        let fd = unsafe { libc::dup(dir.as_raw_fd()) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let stream = unsafe { libc::fdopendir(fd) };
+        if stream.is_null() {
+            let error = io::Error::last_os_error();
+            unsafe { libc::close(fd) };
+            return Err(error);
+        }
+
+        let mut names = Vec::new();
+        loop {
+            let entry = unsafe { libc::readdir(stream) };
+            if entry.is_null() {
+                break;
+            }
+            let name = unsafe { CStr::from_ptr((*entry).d_name.as_ptr()) }.to_bytes();
+            if name != b"." && name != b".." {
+                names.push(OsString::from_vec(name.to_vec()));
+            }
+        }
+        unsafe { libc::closedir(stream) };
+        Ok(names)
+    }
+
+    /// Status of `name` inside `dir`, describing a symlink rather than its target
+    fn stat_at(dir: &File, name: &OsStr, path: &Path) -> io::Result<libc::stat> {
+        let c_name = c_name(name)?;
+        let mut stat = unsafe { std::mem::zeroed::<libc::stat>() };
+        let result = unsafe {
+            libc::fstatat(dir.as_raw_fd(), c_name.as_ptr(), &mut stat, libc::AT_SYMLINK_NOFOLLOW)
+        };
+        check(result, path)?;
+        Ok(stat)
+    }
+
+    fn is_dir(stat: &libc::stat) -> bool {
+        stat.st_mode & libc::S_IFMT == libc::S_IFDIR
+    }
+
+    fn unlink_at(dir: &File, name: &OsStr, flags: libc::c_int, path: &Path) -> io::Result<()> {
+        let c_name = c_name(name)?;
+        let result = unsafe { libc::unlinkat(dir.as_raw_fd(), c_name.as_ptr(), flags) };
+        check(result, path).map(|_| ())
+    }
+
+    /// Remove directory `name` inside `parent` and everything in it
+    ///
+    /// Each level is opened from its parent's handle refusing symlinks, so an
+    /// entry swapped for a link is unlinked or fails instead of being followed.
+    fn remove_tree_at(parent: &File, name: &OsStr, path: &Path) -> io::Result<()> {
+        let dir = open_at(parent, name, libc::O_RDONLY | libc::O_DIRECTORY, 0, path)?;
+        for entry in entry_names(&dir)? {
+            let stat = match stat_at(&dir, &entry, path) {
+                Ok(stat) => stat,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            };
+            if is_dir(&stat) {
+                remove_tree_at(&dir, &entry, path)?;
+            } else {
+                unlink_at(&dir, &entry, 0, path)?;
+            }
+        }
+        unlink_at(parent, name, libc::AT_REMOVEDIR, path)
+    }
+
+    pub fn read_dir(path: &Path) -> io::Result<Vec<DirEntry>> {
+        let dir = walk_dirs(path, false)?;
+        let mut entries = Vec::new();
+        for name in entry_names(&dir)? {
+            let stat = match stat_at(&dir, &name, path) {
+                Ok(stat) => stat,
+                // Removed since it was listed
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            };
+            let modified = u64::try_from(stat.st_mtime).ok().map(|secs| {
+                SystemTime::UNIX_EPOCH + Duration::new(secs, stat.st_mtime_nsec as u32)
+            });
+            entries.push(DirEntry {
+                name,
+                is_file: stat.st_mode & libc::S_IFMT == libc::S_IFREG,
+                is_dir: is_dir(&stat),
+                len: stat.st_size as u64,
+                modified,
+            });
+        }
+        Ok(entries)
+    }
+
+    pub fn create_dir_all(path: &Path) -> io::Result<()> {
+        walk_dirs(path, true).map(|_| ())
+    }
+
+    pub fn metadata(path: &Path) -> io::Result<Metadata> {
+        open(path)?.metadata()
+    }
+
+    pub fn open(path: &Path) -> io::Result<File> {
+        let (parent, name) = split(path)?;
+        let dir = walk_dirs(parent, false)?;
+        // Non-blocking so opening a FIFO can't hang; no effect on regular files
+        open_at(&dir, name, libc::O_RDONLY | libc::O_NONBLOCK, 0, path)
+    }
+
+    pub fn write_atomic(
+        path: &Path,
+        temp_name: &OsStr,
+        contents: &[u8],
+        create_parents: bool,
+        backup_name: Option<&OsStr>,
+    ) -> io::Result<()> {
+        let (parent, name) = split(path)?;
+        let dir = walk_dirs(parent, create_parents)?;
+        let create_flags = libc::O_WRONLY | libc::O_CREAT | libc::O_TRUNC;
+
+        if let Some(backup_name) = backup_name {
+            match open_at(&dir, name, libc::O_RDONLY | libc::O_NONBLOCK, 0, path) {
+                Ok(mut current) => {
+                    let mut existing = Vec::new();
+                    current.read_to_end(&mut existing)?;
+                    open_at(&dir, backup_name, create_flags, 0o666, path)?.write_all(&existing)?;
+                }
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        // Write to temp file, then rename within the same directory handle
+        open_at(&dir, temp_name, create_flags, 0o666, path)?.write_all(contents)?;
+        let c_temp = c_name(temp_name)?;
+        let c_target = c_name(name)?;
+        let result = unsafe {
+            libc::renameat(dir.as_raw_fd(), c_temp.as_ptr(), dir.as_raw_fd(), c_target.as_ptr())
+        };
+        check(result, path).map(|_| ())
+    }
+
+    pub fn remove_file(path: &Path) -> io::Result<()> {
+        let (parent, name) = split(path)?;
+        let dir = walk_dirs(parent, false)?;
+        // unlinkat removes the directory entry itself and never follows it
+        unlink_at(&dir, name, 0, path)
+    }
+
+    pub fn remove_dir_all(path: &Path) -> io::Result<()> {
+        let (parent, name) = split(path)?;
+        let parent = walk_dirs(parent, false)?;
+        remove_tree_at(&parent, name, path)
+    }
+}
+
+#[cfg(not(unix))]
+mod imp {
+    use super::DirEntry;
+    use std::ffi::OsStr;
+    use std::fs::{File, Metadata};
+    use std::io;
+    use std::path::Path;
+
+    pub fn read_dir(path: &Path) -> io::Result<Vec<DirEntry>> {
+        if !std::fs::symlink_metadata(path)?.is_dir() {
+            return Err(super::path_changed(path));
+        }
+        let mut entries = Vec::new();
+        for entry in std::fs::read_dir(path)? {
+            let entry = entry?;
+            // DirEntry::metadata doesn't follow symlinks
+            let metadata = entry.metadata()?;
+            entries.push(DirEntry {
+                name: entry.file_name(),
+                is_file: metadata.is_file(),
+                is_dir: metadata.is_dir(),
+                len: metadata.len(),
+                modified: metadata.modified().ok(),
+            });
+        }
+        Ok(entries)
+    }
+
+    pub fn metadata(path: &Path) -> io::Result<Metadata> {
+        std::fs::symlink_metadata(path)
+    }
+
+    pub fn create_dir_all(path: &Path) -> io::Result<()> {
+        std::fs::create_dir_all(path)
+    }
+
+    pub fn open(path: &Path) -> io::Result<File> {
+        File::open(path)
+    }
+
+    pub fn write_atomic(
+        path: &Path,
+        temp_name: &OsStr,
+        contents: &[u8],
+        create_parents: bool,
+        backup_name: Option<&OsStr>,
+    ) -> io::Result<()> {
+        let (parent, _) = super::split(path)?;
+        if create_parents {
+            std::fs::create_dir_all(parent)?;
+        }
+        if let Some(backup_name) = backup_name {
+            if path.exists() {
+                std::fs::copy(path, parent.join(backup_name))?;
+            }
+        }
+        let temp_path = parent.join(temp_name);
+        std::fs::write(&temp_path, contents)?;
+        std::fs::rename(&temp_path, path)
+    }
+
+    pub fn remove_file(path: &Path) -> io::Result<()> {
+        std::fs::remove_file(path)
+    }
+
+    pub fn remove_dir_all(path: &Path) -> io::Result<()> {
+        std::fs::remove_dir_all(path)
+    }
+}
+
+/// List a directory reached without following symlinks
+///
+/// Entries are described as they are: a symlink inside the directory is
+/// reported as neither a file nor a directory.
+pub fn read_dir(path: &Path) -> io::Result<Vec<DirEntry>> {
+    imp::read_dir(path)
+}
+
+/// Metadata of a file or directory, without following symlinks
+pub fn metadata(path: &Path) -> io::Result<Metadata> {
+    imp::metadata(path)
+}
+
+/// Create a directory and its missing parents without following symlinks
+pub fn create_dir_all(path: &Path) -> io::Result<()> {
+    imp::create_dir_all(path)
+}
+
+/// Open a file read-only without following symlinks
+pub fn open(path: &Path) -> io::Result<File> {
+    imp::open(path)
+}
+
+/// Atomically replace a file's contents via a temp file in the same directory
+///
+/// # Arguments
+/// * `path` - The file to write
+/// * `temp_name` - File name of the temp file (in the same directory)
+/// * `contents` - New contents
+/// * `create_parents` - Create missing parent directories
+/// * `backup_name` - If set, copy existing contents to this file name first
+pub fn write_atomic(
+    path: &Path,
+    temp_name: &OsStr,
+    contents: &[u8],
+    create_parents: bool,
+    backup_name: Option<&OsStr>,
+) -> io::Result<()> {
+    imp::write_atomic(path, temp_name, contents, create_parents, backup_name)
+}
+
+/// Remove a file without following symlinks
+pub fn remove_file(path: &Path) -> io::Result<()> {
+    imp::remove_file(path)
+}
+
+/// Remove a directory and its contents without following symlinks
+pub fn remove_dir_all(path: &Path) -> io::Result<()> {
+    imp::remove_dir_all(path)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::security::{Actor, Capability, GrantScope, SecurityManager};
+    use std::os::unix::fs::symlink;
+    use std::path::PathBuf;
+
+    /// A fresh directory holding `project/` and a sibling `outside/`
+    fn dirs(name: &str) -> (PathBuf, PathBuf) {
+        let base = std::env::temp_dir()
+            .join(format!("nexus-safe-fs-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&base);
+        std::fs::create_dir_all(base.join("project")).unwrap();
+        std::fs::create_dir_all(base.join("outside")).unwrap();
+        let base = base.canonicalize().unwrap();
+        (base.join("project"), base.join("outside"))
+    }
+
+    #[test]
+    fn directory_swapped_for_symlink_after_resolving_is_refused() {
+        let (project, outside) = dirs("swap");
+        std::fs::create_dir_all(project.join("src")).unwrap();
+        std::fs::write(project.join("src/main.rs"), "fn main() {}").unwrap();
+        std::fs::write(outside.join("main.rs"), "secret").unwrap();
+
+        let mut manager = SecurityManager::load(project.join("grants.json")).unwrap();
+        let capabilities = GrantScope::ReadWrite.capabilities();
+        manager.add_grant(project.clone(), capabilities, None, None).unwrap();
+        let resolved = manager
+            .check_access(&project.join("src/main.rs"), Capability::Read, Actor::User)
+            .unwrap();
+
+        // Swap the checked directory for a link out of the project
+        std::fs::rename(project.join("src"), project.join("src-old")).unwrap();
+        symlink(&outside, project.join("src")).unwrap();
+
+        let error = open(&resolved).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::PermissionDenied);
+        let error = write_atomic(&resolved, OsStr::new(".main.rs.tmp"), b"x", false, None)
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::PermissionDenied);
+        assert!(remove_file(&resolved).is_err());
+        assert_eq!(std::fs::read_to_string(outside.join("main.rs")).unwrap(), "secret");
+    }
+
+    #[test]
+    fn final_symlink_is_not_opened() {
+        let (project, outside) = dirs("final");
+        std::fs::write(outside.join("secret.txt"), "secret").unwrap();
+        symlink(outside.join("secret.txt"), project.join("link.txt")).unwrap();
+
+        let error = open(&project.join("link.txt")).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::PermissionDenied);
+    }
+
+    #[test]
+    fn remove_dir_all_leaves_symlink_targets_alone() {
+        let (project, outside) = dirs("remove");
+        std::fs::write(outside.join("keep.txt"), "keep").unwrap();
+        let tree = project.join("tree");
+        std::fs::create_dir_all(tree.join("nested")).unwrap();
+        std::fs::write(tree.join("nested/file.txt"), "gone").unwrap();
+        symlink(&outside, tree.join("nested/escape")).unwrap();
+        symlink(outside.join("keep.txt"), tree.join("file-link")).unwrap();
+
+        remove_dir_all(&tree).unwrap();
+
+        assert!(!tree.exists());
+        assert_eq!(std::fs::read_to_string(outside.join("keep.txt")).unwrap(), "keep");
+    }
+
+    #[test]
+    fn read_dir_reports_symlinks_as_they_are() {
+        let (project, outside) = dirs("list");
+        std::fs::write(project.join("file.txt"), "file").unwrap();
+        symlink(&outside, project.join("link")).unwrap();
+
+        let entries = read_dir(&project).unwrap();
+        let link = entries.iter().find(|entry| entry.name == "link").unwrap();
+        assert!(!link.is_dir && !link.is_file);
+        let file = entries.iter().find(|entry| entry.name == "file.txt").unwrap();
+        assert!(file.is_file);
+        assert_eq!(file.len, 4);
+    }
+}
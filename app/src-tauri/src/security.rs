@@ -1,88 +1,546 @@
-use std::collections::HashSet;
-use std::path::{Path, PathBuf};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::ffi::OsString;
+use std::path::{Component, Path, PathBuf};
+use crate::deny_rules::{DenyOverrides, DenyRules};
 use crate::filesystem::FileSystemError;
 
+/// File name of the persisted permission grants (inside the app config directory)
+pub const GRANTS_FILE: &str = "permission-grants.json";
+
+/// Maximum number of symlinks followed while resolving one path
+const MAX_SYMLINK_HOPS: usize = 40;
+
+/// Version of the on-disk grant store format
+/// 
+/// Version 1 stored a single `scope` per grant; version 2 stores capability sets
+/// and per-actor policy.
+const GRANT_STORE_VERSION: u32 = 2;
+
+/// A single operation that can be allowed on a path
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    Read,
+    /// Modify existing files
+    Write,
+    /// Create new files and directories
+    Create,
+    Delete,
+    Watch,
+    Execute,
+}
+
+impl Capability {
+    pub const ALL: [Capability; 6] = [
+        Capability::Read,
+        Capability::Write,
+        Capability::Create,
+        Capability::Delete,
+        Capability::Watch,
+        Capability::Execute,
+    ];
+}
+
+impl std::fmt::Display for Capability {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Capability::Read => write!(f, "read"),
+            Capability::Write => write!(f, "write"),
+            Capability::Create => write!(f, "create"),
+            Capability::Delete => write!(f, "delete"),
+            Capability::Watch => write!(f, "watch"),
+            Capability::Execute => write!(f, "execute"),
+        }
+    }
+}
+
+/// Set of capabilities held by a grant or allowed to an actor
+pub type CapabilitySet = BTreeSet<Capability>;
+
+/// Named capability presets used when asking the user for access
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GrantScope {
+    /// Read and watch
+    ReadOnly,
+    /// Everything except execute
+    ReadWrite,
+    /// Read, write, create and watch, but never delete
+    NoDelete,
+    /// Read, write, create, watch and execute, but never delete
+    ReadWriteExecute,
+    /// Every capability, including execute
+    Full,
+}
+
+impl GrantScope {
+    /// Capabilities this preset grants
+    pub fn capabilities(&self) -> CapabilitySet {
+        use Capability::*;
+        match self {
+            GrantScope::ReadOnly => [Read, Watch].into(),
+            GrantScope::ReadWrite => [Read, Write, Create, Delete, Watch].into(),
+            GrantScope::NoDelete => [Read, Write, Create, Watch].into(),
+            GrantScope::ReadWriteExecute => [Read, Write, Create, Watch, Execute].into(),
+            GrantScope::Full => Capability::ALL.into(),
+        }
+    }
+    
+    /// Whether this scope grants nothing beyond `other`
+    pub fn is_within(&self, other: GrantScope) -> bool {
+        self.capabilities().is_subset(&other.capabilities())
+    }
+}
+
+/// Who is performing an operation
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Actor {
+    #[default]
+    User,
+    OverseerAi,
+    ImplementationAi,
+    Plugin,
+}
+
+impl Actor {
+    /// Capabilities an actor may use before any policy is configured
+    /// 
+    /// The Implementation AI can edit files and run commands but not delete files;
+    /// it runs commands only where a grant allows execute (`read_write_execute` or
+    /// `full`). Plugins can only read and watch.
+    pub fn default_capabilities(&self) -> CapabilitySet {
+        use Capability::*;
+        match self {
+            Actor::User => Capability::ALL.into(),
+            Actor::OverseerAi => [Read, Write, Create, Delete, Watch].into(),
+            Actor::ImplementationAi => [Read, Write, Create, Watch, Execute].into(),
+            Actor::Plugin => [Read, Watch].into(),
+        }
+    }
+}
+
+/// Task an operation belongs to (passed by the frontend with each command)
+/// 
+/// The acting party is never taken from the frontend; see `Caller`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OperationContext {
+    /// Task the operation belongs to (quota usage is also reported per task)
+    #[serde(default)]
+    pub task_id: Option<String>,
+}
+
+/// Who an operation is performed for, as established by the backend
+/// 
+/// Commands derive the actor from the calling window; AI tool calls use the
+/// actor of the agent's role.
+#[derive(Debug, Clone, Default)]
+pub struct Caller {
+    pub actor: Actor,
+    pub task_id: Option<String>,
+}
+
+/// A persisted permission grant for a path and everything beneath it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PermissionGrant {
+    pub path: PathBuf,
+    pub capabilities: CapabilitySet,
+    #[serde(default, with = "chrono::serde::ts_seconds_option")]
+    pub expires_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub reason: Option<String>,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub granted_at: DateTime<Utc>,
+}
+
+impl PermissionGrant {
+    /// Whether the grant has passed its expiry time
+    pub fn is_expired(&self) -> bool {
+        self.expires_at.is_some_and(|expires| expires <= Utc::now())
+    }
+}
+
+/// On-disk format of the grant store
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct GrantStore {
+    version: u32,
+    grants: Vec<PermissionGrant>,
+    /// Capability ceiling per actor (actors not listed use their defaults)
+    #[serde(default)]
+    actor_policy: HashMap<Actor, CapabilitySet>,
+    /// Deny-rule overrides per project root
+    #[serde(default)]
+    deny_overrides: HashMap<PathBuf, DenyOverrides>,
+}
+
+/// Upgrade a version 1 store (one `scope` per grant) to capability sets
+fn migrate_grant_store(mut value: serde_json::Value) -> serde_json::Value {
+    if value.get("version").and_then(|v| v.as_u64()) == Some(1) {
+        if let Some(grants) = value.get_mut("grants").and_then(|g| g.as_array_mut()) {
+            for grant in grants.iter_mut().filter_map(|g| g.as_object_mut()) {
+                let scope = grant
+                    .remove("scope")
+                    .and_then(|scope| serde_json::from_value::<GrantScope>(scope).ok())
+                    .unwrap_or(GrantScope::ReadWrite);
+                grant.insert(
+                    "capabilities".to_string(),
+                    serde_json::json!(scope.capabilities()),
+                );
+            }
+        }
+        value["version"] = serde_json::json!(GRANT_STORE_VERSION);
+    }
+    value
+}
+
 /// Security manager for path validation and permission checking
+/// 
+/// An operation is allowed when the most specific grant covering the path
+/// holds the capability *and* the acting party's policy permits it. AI actors
+/// are additionally kept away from sensitive files matched by the deny rules.
 pub struct SecurityManager {
-    allowed_paths: HashSet<PathBuf>,
+    grants: HashMap<PathBuf, PermissionGrant>,
+    actor_policy: HashMap<Actor, CapabilitySet>,
+    deny_rules: DenyRules,
+    /// Where grants are persisted
+    store_path: PathBuf,
 }
 
 impl SecurityManager {
-    pub fn new() -> Self {
-        Self {
-            allowed_paths: HashSet::new(),
+    /// Load persisted grants from `store_path`, dropping expired ones
+    /// 
+    /// A missing store starts with no grants; later changes are saved back to
+    /// `store_path`.
+    /// 
+    /// # Returns
+    /// The manager, or the error reading or parsing an existing store (including
+    /// invalid deny-rule overrides)
+    pub fn load(store_path: PathBuf) -> Result<Self, FileSystemError> {
+        let mut manager = Self {
+            grants: HashMap::new(),
+            actor_policy: HashMap::new(),
+            deny_rules: DenyRules::new(),
+            store_path: store_path.clone(),
+        };
+        
+        let store = match std::fs::read_to_string(&store_path) {
+            Ok(content) => serde_json::from_str::<serde_json::Value>(&content)
+                .and_then(|value| serde_json::from_value::<GrantStore>(migrate_grant_store(value)))
+                .map_err(|e| {
+                    FileSystemError::EncodingError(format!(
+                        "Invalid permission grant store {:?}: {}",
+                        store_path, e
+                    ))
+                })?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => GrantStore::default(),
+            Err(e) => return Err(e.into()),
+        };
+        
+        for grant in store.grants.into_iter().filter(|g| !g.is_expired()) {
+            manager.grants.insert(grant.path.clone(), grant);
         }
+        manager.actor_policy = store.actor_policy;
+        manager.deny_rules = DenyRules::with_overrides(store.deny_overrides)?;
+        
+        Ok(manager)
     }
     
-    /// Add an allowed path
-    pub fn add_allowed_path(&mut self, path: PathBuf) {
-        // Add both canonicalized and non-canonicalized versions
-        // Try to canonicalize first
-        if let Ok(canonical) = path.canonicalize() {
-            self.allowed_paths.insert(canonical.clone());
-            // Also add the original if it's different
-            if canonical != path {
-                self.allowed_paths.insert(path);
-            }
-        } else {
-            // If canonicalization fails, add as-is (path may not exist yet)
-            self.allowed_paths.insert(path);
+    /// Write all grants and the actor policy to the store file
+    fn save(&self) -> Result<(), FileSystemError> {
+        let store_path = &self.store_path;
+        
+        let mut grants: Vec<PermissionGrant> = self.grants.values().cloned().collect();
+        grants.sort_by(|a, b| a.path.cmp(&b.path));
+        let store = GrantStore {
+            version: GRANT_STORE_VERSION,
+            grants,
+            actor_policy: self.actor_policy.clone(),
+            deny_overrides: self.deny_rules.overrides().clone(),
+        };
+        let content = serde_json::to_string_pretty(&store)
+            .map_err(|e| FileSystemError::EncodingError(e.to_string()))?;
+        
+        if let Some(parent) = store_path.parent() {
+            std::fs::create_dir_all(parent)?;
         }
+        
+        // Atomic write: write to temp file, then rename
+        let temp_path = store_path.with_extension("json.tmp");
+        std::fs::write(&temp_path, content)?;
+        std::fs::rename(&temp_path, store_path)?;
+        Ok(())
+    }
+    
+    /// Grant capabilities on a path (and everything beneath it)
+    /// 
+    /// Replaces any existing grant for the same path and persists the change.
+    pub fn add_grant(
+        &mut self,
+        path: PathBuf,
+        capabilities: CapabilitySet,
+        expires_at: Option<DateTime<Utc>>,
+        reason: Option<String>,
+    ) -> Result<PermissionGrant, FileSystemError> {
+        // Store the canonical path when it exists (path may not exist yet)
+        let path = path.canonicalize().unwrap_or(path);
+        let grant = PermissionGrant {
+            path: path.clone(),
+            capabilities,
+            expires_at,
+            reason,
+            granted_at: Utc::now(),
+        };
+        self.grants.insert(path, grant.clone());
+        self.save()?;
+        Ok(grant)
     }
     
-    /// Remove an allowed path
-    pub fn remove_allowed_path(&mut self, path: &Path) {
-        if let Ok(canonical) = path.canonicalize() {
-            self.allowed_paths.remove(&canonical);
+    /// Add capabilities to the grant on a path, keeping those it already has
+    /// 
+    /// Creates the grant if the path has none (or only an expired one). The
+    /// result doesn't expire; a new `reason` replaces the old one.
+    pub fn extend_grant(
+        &mut self,
+        path: PathBuf,
+        capabilities: CapabilitySet,
+        reason: Option<String>,
+    ) -> Result<PermissionGrant, FileSystemError> {
+        let path = path.canonicalize().unwrap_or(path);
+        let (mut capabilities, mut reason) = (capabilities, reason);
+        if let Some(existing) = self.grants.get(&path).filter(|g| !g.is_expired()) {
+            capabilities.extend(existing.capabilities.iter().copied());
+            reason = reason.or_else(|| existing.reason.clone());
         }
+        self.add_grant(path, capabilities, None, reason)
     }
     
-    /// Check if a path is allowed
-    pub fn is_path_allowed(&self, path: &Path) -> bool {
-        // Normalize path string for comparison (case-insensitive on Windows)
-        let path_str = self.normalize_path_string(path);
+    /// Revoke the grant for a path
+    /// 
+    /// # Returns
+    /// * `Ok(Some(grant))` - The revoked grant
+    /// * `Ok(None)` - No grant existed for the path
+    pub fn revoke_grant(
+        &mut self,
+        path: &Path,
+    ) -> Result<Option<PermissionGrant>, FileSystemError> {
+        let key = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        let removed = self.grants.remove(&key).or_else(|| self.grants.remove(path));
+        if removed.is_some() {
+            self.save()?;
+        }
+        Ok(removed)
+    }
+    
+    /// Capabilities an actor may use anywhere
+    pub fn actor_capabilities(&self, actor: Actor) -> CapabilitySet {
+        self.actor_policy
+            .get(&actor)
+            .cloned()
+            .unwrap_or_else(|| actor.default_capabilities())
+    }
+    
+    /// Replace the capability ceiling for an actor and persist the change
+    pub fn set_actor_capabilities(
+        &mut self,
+        actor: Actor,
+        capabilities: CapabilitySet,
+    ) -> Result<(), FileSystemError> {
+        self.actor_policy.insert(actor, capabilities);
+        self.save()
+    }
+    
+    /// Whether a resolved path matches the deny rules
+    pub fn is_denied(&self, path: &Path) -> bool {
+        self.deny_rules.is_denied(path)
+    }
+    
+    /// Get the deny-rule overrides of every project
+    pub fn deny_overrides(&self) -> &HashMap<PathBuf, DenyOverrides> {
+        self.deny_rules.overrides()
+    }
+    
+    /// Replace the deny-rule overrides for a project root and persist the change
+    pub fn set_deny_overrides(
+        &mut self,
+        project: PathBuf,
+        overrides: DenyOverrides,
+    ) -> Result<(), FileSystemError> {
+        let project = project.canonicalize().unwrap_or(project);
+        self.deny_rules.set_overrides(project, overrides)?;
+        self.save()
+    }
+    
+    /// Resolve a path component by component, following symlinks
+    /// 
+    /// Once the walk has entered an allowed root, every intermediate directory
+    /// (including symlink targets) must stay inside the allowed roots, so a
+    /// symlink inside a project pointing at `/etc` is rejected. Components that
+    /// don't exist yet are appended as-is.
+    /// 
+    /// # Returns
+    /// * `Ok(PathBuf)` - The resolved path, free of symlinks and `.`/`..`
+    /// * `Err(FileSystemError)` - Symlink escape, symlink loop or I/O error
+    pub fn resolve(&self, path: &Path) -> Result<PathBuf, FileSystemError> {
+        let mut resolved = PathBuf::new();
+        let mut pending: VecDeque<OsString> = VecDeque::new();
+        let mut hops = 0;
+        let mut entered_root = false;
         
-        // Check each allowed path
-        for allowed_path in &self.allowed_paths {
-            let allowed_str = self.normalize_path_string(allowed_path);
-            
-            // Exact match
-            if path_str == allowed_str {
-                return true;
+        for component in path.components() {
+            match component {
+                Component::Prefix(_) | Component::RootDir => resolved.push(component),
+                other => pending.push_back(other.as_os_str().to_os_string()),
             }
-            
-            // Check if path starts with allowed path (path is within allowed directory)
-            // Ensure it's a proper prefix (not just a substring)
-            if path_str.starts_with(&allowed_str) {
-                // Check that the next character is a path separator (or end of string)
-                // This prevents C:\foo from matching C:\foobar
-                let remaining = &path_str[allowed_str.len()..];
-                if remaining.is_empty() || remaining.starts_with('\\') || remaining.starts_with('/') {
-                    return true;
-                }
-            }
-            
-            // Also check canonicalized versions (for existing paths)
-            if let (Ok(canonical_path), Ok(canonical_allowed)) = 
-                (path.canonicalize(), allowed_path.canonicalize()) 
-            {
-                let canonical_path_str = self.normalize_path_string(&canonical_path);
-                let canonical_allowed_str = self.normalize_path_string(&canonical_allowed);
-                
-                if canonical_path_str == canonical_allowed_str {
-                    return true;
+        }
+        
+        while let Some(name) = pending.pop_front() {
+            match Path::new(&name).components().next() {
+                Some(Component::CurDir) | None => continue,
+                // Only reachable from symlink targets; `resolved` is real so popping is safe
+                Some(Component::ParentDir) => {
+                    resolved.pop();
                 }
-                
-                if canonical_path_str.starts_with(&canonical_allowed_str) {
-                    let remaining = &canonical_path_str[canonical_allowed_str.len()..];
-                    if remaining.is_empty() || remaining.starts_with('\\') || remaining.starts_with('/') {
-                        return true;
+                _ => {
+                    let candidate = resolved.join(&name);
+                    match std::fs::symlink_metadata(&candidate) {
+                        Ok(metadata) if metadata.file_type().is_symlink() => {
+                            hops += 1;
+                            if hops > MAX_SYMLINK_HOPS {
+                                return Err(FileSystemError::InvalidPath(format!(
+                                    "Too many symlinks resolving: {}",
+                                    path.to_string_lossy()
+                                )));
+                            }
+                            
+                            // Splice the target in place of the link
+                            let target = std::fs::read_link(&candidate)?;
+                            for component in target.components().rev() {
+                                match component {
+                                    Component::Prefix(_) | Component::RootDir => {}
+                                    other => pending.push_front(other.as_os_str().to_os_string()),
+                                }
+                            }
+                            if target.has_root() {
+                                resolved = target
+                                    .components()
+                                    .take_while(|c| matches!(c, Component::Prefix(_) | Component::RootDir))
+                                    .collect();
+                            }
+                            continue;
+                        }
+                        Ok(_) => resolved = candidate,
+                        Err(e) if e.kind() == std::io::ErrorKind::NotFound => resolved = candidate,
+                        Err(e) => return Err(e.into()),
                     }
                 }
             }
+            
+            if self.is_within_roots(&resolved) {
+                entered_root = true;
+            } else if entered_root {
+                return Err(FileSystemError::PermissionDenied(format!(
+                    "Path escapes the allowed directories via a symlink: {}",
+                    path.to_string_lossy()
+                )));
+            }
         }
         
-        false
+        Ok(resolved)
+    }
+    
+    /// Whether a resolved path lies inside any active grant
+    fn is_within_roots(&self, path: &Path) -> bool {
+        self.grants
+            .values()
+            .any(|grant| !grant.is_expired() && self.path_covers(&grant.path, path))
+    }
+    
+    /// Check whether `actor` may perform an operation needing `capability` on a path
+    /// 
+    /// The most specific (longest) active grant covering the path decides,
+    /// so a read-only grant on a subdirectory narrows a read-write parent.
+    /// 
+    /// # Returns
+    /// * `Ok(PathBuf)` - Access allowed; the resolved path the operation must use
+    /// * `Err(FileSystemError::PermissionDenied)` - Actor policy, deny rule, missing grant or grant forbids it
+    pub fn check_access(
+        &self,
+        path: &Path,
+        capability: Capability,
+        actor: Actor,
+    ) -> Result<PathBuf, FileSystemError> {
+        if !self.actor_capabilities(actor).contains(&capability) {
+            return Err(FileSystemError::PermissionDenied(format!(
+                "{:?} is not allowed {} access: {}",
+                actor,
+                capability,
+                path.to_string_lossy()
+            )));
+        }
+        
+        let resolved = self.resolve(path)?;
+        if actor != Actor::User && self.is_denied(&resolved) {
+            return Err(FileSystemError::PermissionDenied(format!(
+                "Protected file is off limits to {:?}: {}",
+                actor,
+                path.to_string_lossy()
+            )));
+        }
+        
+        match self.find_grant(&resolved) {
+            Some(grant) if grant.capabilities.contains(&capability) => Ok(resolved),
+            Some(grant) => Err(FileSystemError::PermissionDenied(format!(
+                "Grant for {} does not allow {} access to: {}",
+                grant.path.to_string_lossy(),
+                capability,
+                path.to_string_lossy()
+            ))),
+            None => Err(FileSystemError::PermissionDenied(format!(
+                "Path not allowed. Please request permission for: {}",
+                path.to_string_lossy()
+            ))),
+        }
+    }
+    
+    /// Check whether existing grants already give every capability in `scope` to a path
+    pub fn is_granted(&self, path: &Path, scope: GrantScope) -> bool {
+        scope
+            .capabilities()
+            .into_iter()
+            .all(|capability| self.check_access(path, capability, Actor::User).is_ok())
+    }
+    
+    /// Find the most specific active grant covering a path
+    fn find_grant(&self, path: &Path) -> Option<&PermissionGrant> {
+        self.grants
+            .values()
+            .filter(|grant| !grant.is_expired() && self.path_covers(&grant.path, path))
+            .max_by_key(|grant| grant.path.as_os_str().len())
+    }
+    
+    /// Check if resolved `path` is `allowed_path` or lies within it
+    /// 
+    /// `path` must come from `resolve`, so a plain prefix check can't be fooled
+    /// by symlinks; the grant path is also tried in canonical form.
+    fn path_covers(&self, allowed_path: &Path, path: &Path) -> bool {
+        // Normalize path string for comparison (case-insensitive on Windows)
+        let path_str = self.normalize_path_string(path);
+        let covers = |allowed_path: &Path| {
+            let allowed_str = self.normalize_path_string(allowed_path);
+            
+            // Ensure it's a proper prefix (not just a substring)
+            // This prevents C:\foo from matching C:\foobar
+            path_str.strip_prefix(&allowed_str).is_some_and(|remaining| {
+                remaining.is_empty() || remaining.starts_with('\\') || remaining.starts_with('/')
+            })
+        };
+        
+        covers(allowed_path)
+            || allowed_path
+                .canonicalize()
+                .is_ok_and(|canonical| covers(&canonical))
     }
     
     /// Normalize path string for comparison (handles Windows case-insensitivity and separators)
@@ -109,88 +567,247 @@ impl SecurityManager {
         }
     }
     
-    /// Get all allowed paths
+    /// The project root: the most recently granted active root allowing `capability`
+    /// 
+    /// Used as the default working directory when none is given.
+    pub fn project_root(&self, capability: Capability) -> Option<PathBuf> {
+        self.grants
+            .values()
+            .filter(|grant| !grant.is_expired() && grant.capabilities.contains(&capability))
+            .max_by_key(|grant| grant.granted_at)
+            .map(|grant| grant.path.clone())
+    }
+    
+    /// Get all allowed paths (paths of active grants)
     pub fn get_allowed_paths(&self) -> Vec<PathBuf> {
-        self.allowed_paths.iter().cloned().collect()
+        self.grants
+            .values()
+            .filter(|grant| !grant.is_expired())
+            .map(|grant| grant.path.clone())
+            .collect()
     }
-}
-
-/// Normalize a path (resolve . and .. components)
-/// 
-/// # Arguments
-/// * `path` - The path to normalize
-/// 
-/// # Returns
-/// * `Ok(PathBuf)` - Normalized path
-/// * `Err(FileSystemError)` - Error on failure
-pub fn normalize_path(path: &str) -> Result<PathBuf, FileSystemError> {
-    let path_buf = PathBuf::from(path);
     
-    // Try to canonicalize (resolves symlinks and .. components)
-    match path_buf.canonicalize() {
-        Ok(canonical) => Ok(canonical),
-        Err(_) => {
-            // If canonicalization fails (path doesn't exist), resolve manually
-            let mut normalized = PathBuf::new();
-            for component in path_buf.components() {
-                match component {
-                    std::path::Component::Prefix(_) | std::path::Component::RootDir => {
-                        normalized.push(component);
-                    }
-                    std::path::Component::CurDir => {
-                        // Skip .
-                    }
-                    std::path::Component::ParentDir => {
-                        // Go up one level
-                        normalized.pop();
-                    }
-                    std::path::Component::Normal(name) => {
-                        normalized.push(name);
-                    }
-                }
-            }
-            Ok(normalized)
-        }
+    /// Get all active grants with their metadata, sorted by path
+    pub fn list_grants(&self) -> Vec<PermissionGrant> {
+        let mut grants: Vec<PermissionGrant> = self
+            .grants
+            .values()
+            .filter(|grant| !grant.is_expired())
+            .cloned()
+            .collect();
+        grants.sort_by(|a, b| a.path.cmp(&b.path));
+        grants
     }
 }
 
 /// Validate a path for security (prevent path traversal attacks)
 /// 
+/// The check is lexical: symlinks are left in place for `SecurityManager::resolve`,
+/// which checks every component they lead through.
+/// 
 /// # Arguments
 /// * `path` - The path to validate
 /// 
 /// # Returns
-/// * `Ok(PathBuf)` - Validated and normalized path
+/// * `Ok(PathBuf)` - The path made absolute, without `.` components
 /// * `Err(FileSystemError)` - Error on failure
 pub fn validate_path(path: &str) -> Result<PathBuf, FileSystemError> {
     let path_buf = PathBuf::from(path);
     
-    // Check for path traversal attempts
+    // Check for path traversal attempts (real `..` components, not names like `foo..bar.rs`)
     let path_str = path_buf.to_string_lossy();
-    if path_str.contains("..") || path_str.contains("//") {
+    let has_parent_dir = path_buf.components().any(|c| c == Component::ParentDir);
+    if has_parent_dir || path_str.contains("//") {
         return Err(FileSystemError::InvalidPath(format!(
             "Path contains invalid components: {}",
             path
         )));
     }
     
-    // Normalize the path
-    normalize_path(path)
+    // Relative paths are taken from the working directory, without following symlinks
+    Ok(std::path::absolute(&path_buf)?)
 }
 
-/// Check if a path is within an allowed directory
-/// 
-/// # Arguments
-/// * `path` - The path to check
-/// * `allowed_base` - The allowed base directory
-/// 
-/// # Returns
-/// * `Ok(bool)` - True if path is within allowed directory
-/// * `Err(FileSystemError)` - Error on failure
-pub fn is_path_within_allowed(path: &Path, allowed_base: &Path) -> Result<bool, FileSystemError> {
-    let normalized_path = normalize_path(&path.to_string_lossy())?;
-    let normalized_base = normalize_path(&allowed_base.to_string_lossy())?;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    /// A fresh directory and a manager without grants that stores them beside it
+    fn manager(name: &str) -> (SecurityManager, PathBuf) {
+        let base = std::env::temp_dir()
+            .join(format!("nexus-security-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&base);
+        std::fs::create_dir_all(base.join("project")).unwrap();
+        let base = base.canonicalize().unwrap();
+        let manager = SecurityManager::load(base.join(GRANTS_FILE)).unwrap();
+        (manager, base.join("project"))
+    }
+    
+    fn grant(manager: &mut SecurityManager, path: &Path, scope: GrantScope) {
+        manager.add_grant(path.to_path_buf(), scope.capabilities(), None, None).unwrap();
+    }
+    
+    #[test]
+    fn most_specific_grant_wins() {
+        let (mut manager, project) = manager("specific");
+        std::fs::create_dir_all(project.join("docs")).unwrap();
+        std::fs::create_dir_all(project.join("src")).unwrap();
+        grant(&mut manager, &project, GrantScope::ReadWrite);
+        grant(&mut manager, &project.join("docs"), GrantScope::ReadOnly);
+        grant(&mut manager, &project.join("src"), GrantScope::Full);
+        
+        let write = |path: PathBuf| manager.check_access(&path, Capability::Write, Actor::User);
+        assert!(write(project.join("a.txt")).is_ok());
+        assert!(write(project.join("docs/a.txt")).is_err());
+        assert!(manager
+            .check_access(&project.join("docs/a.txt"), Capability::Read, Actor::User)
+            .is_ok());
+        // A narrower path can widen its parent as well as narrow it
+        assert!(manager
+            .check_access(&project.join("src/build.sh"), Capability::Execute, Actor::User)
+            .is_ok());
+        assert!(manager
+            .check_access(&project.join("a.sh"), Capability::Execute, Actor::User)
+            .is_err());
+    }
+    
+    #[test]
+    fn ungranted_paths_are_refused() {
+        let (mut manager, project) = manager("ungranted");
+        grant(&mut manager, &project, GrantScope::Full);
+        
+        let outside = project.parent().unwrap().join("other.txt");
+        let sibling = PathBuf::from(format!("{}-sibling/a.txt", project.to_string_lossy()));
+        assert!(manager.check_access(&outside, Capability::Read, Actor::User).is_err());
+        assert!(manager.check_access(&sibling, Capability::Read, Actor::User).is_err());
+    }
+    
+    #[test]
+    fn actors_are_limited_to_their_default_capabilities() {
+        let (mut manager, project) = manager("actors");
+        grant(&mut manager, &project, GrantScope::Full);
+        let file = project.join("a.txt");
+        let allowed = |actor: Actor, capability: Capability| {
+            manager.check_access(&file, capability, actor).is_ok()
+        };
+        
+        for capability in Capability::ALL {
+            assert!(allowed(Actor::User, capability), "user {:?}", capability);
+        }
+        assert!(allowed(Actor::OverseerAi, Capability::Delete));
+        assert!(!allowed(Actor::OverseerAi, Capability::Execute));
+        assert!(allowed(Actor::ImplementationAi, Capability::Execute));
+        assert!(!allowed(Actor::ImplementationAi, Capability::Delete));
+        assert!(allowed(Actor::Plugin, Capability::Read));
+        assert!(allowed(Actor::Plugin, Capability::Watch));
+        assert!(!allowed(Actor::Plugin, Capability::Write));
+        assert!(!allowed(Actor::Plugin, Capability::Create));
+    }
+    
+    #[test]
+    fn actor_policy_replaces_the_defaults() {
+        let (mut manager, project) = manager("policy");
+        grant(&mut manager, &project, GrantScope::Full);
+        manager
+            .set_actor_capabilities(Actor::Plugin, [Capability::Read, Capability::Write].into())
+            .unwrap();
+        let file = project.join("a.txt");
+        
+        assert!(manager.check_access(&file, Capability::Write, Actor::Plugin).is_ok());
+        assert!(manager.check_access(&file, Capability::Watch, Actor::Plugin).is_err());
+        
+        // The policy is saved with the grants
+        let reloaded = SecurityManager::load(manager.store_path.clone()).unwrap();
+        assert!(reloaded.check_access(&file, Capability::Write, Actor::Plugin).is_ok());
+    }
+    
+    #[test]
+    fn restricted_scopes_refuse_what_they_leave_out() {
+        let (mut manager, project) = manager("scopes");
+        std::fs::create_dir_all(project.join("keep")).unwrap();
+        std::fs::create_dir_all(project.join("view")).unwrap();
+        grant(&mut manager, &project.join("keep"), GrantScope::NoDelete);
+        grant(&mut manager, &project.join("view"), GrantScope::ReadOnly);
+        let kept = project.join("keep/a.txt");
+        let viewed = project.join("view/a.txt");
+        
+        assert!(manager.check_access(&kept, Capability::Write, Actor::User).is_ok());
+        assert!(manager.check_access(&kept, Capability::Delete, Actor::User).is_err());
+        assert!(manager.check_access(&viewed, Capability::Read, Actor::User).is_ok());
+        assert!(manager.check_access(&viewed, Capability::Write, Actor::User).is_err());
+        assert!(manager.check_access(&viewed, Capability::Create, Actor::User).is_err());
+        assert!(manager.check_access(&viewed, Capability::Delete, Actor::User).is_err());
+    }
+    
+    #[test]
+    fn deny_rules_apply_to_ai_actors_only() {
+        let (mut manager, project) = manager("deny");
+        grant(&mut manager, &project, GrantScope::ReadWrite);
+        let secret = project.join(".env");
+        
+        assert!(manager.check_access(&secret, Capability::Read, Actor::User).is_ok());
+        assert!(manager.check_access(&secret, Capability::Read, Actor::OverseerAi).is_err());
+        assert!(manager.check_access(&secret, Capability::Read, Actor::Plugin).is_err());
+    }
+    
+    #[test]
+    fn parent_dir_names_are_not_traversal() {
+        let (mut manager, project) = manager("dots");
+        grant(&mut manager, &project, GrantScope::ReadWrite);
+        
+        let dotted = project.join("foo..bar.rs");
+        let validated = validate_path(&dotted.to_string_lossy()).unwrap();
+        assert_eq!(validated, dotted);
+        assert!(manager.check_access(&validated, Capability::Write, Actor::User).is_ok());
+        
+        let traversal = format!("{}/../other.txt", project.to_string_lossy());
+        assert!(matches!(validate_path(&traversal), Err(FileSystemError::InvalidPath(_))));
+    }
+    
+    #[cfg(unix)]
+    #[test]
+    fn symlink_out_of_the_roots_is_rejected() {
+        let (mut manager, project) = manager("escape");
+        grant(&mut manager, &project, GrantScope::ReadWrite);
+        std::os::unix::fs::symlink("/etc", project.join("config")).unwrap();
+        
+        let through_link = project.join("config/passwd");
+        assert!(matches!(
+            manager.resolve(&through_link),
+            Err(FileSystemError::PermissionDenied(_))
+        ));
+        assert!(manager.check_access(&through_link, Capability::Read, Actor::User).is_err());
+    }
+    
+    #[cfg(unix)]
+    #[test]
+    fn symlink_inside_the_roots_is_followed() {
+        let (mut manager, project) = manager("inside");
+        grant(&mut manager, &project, GrantScope::ReadWrite);
+        std::fs::create_dir_all(project.join("real")).unwrap();
+        std::os::unix::fs::symlink("real", project.join("alias")).unwrap();
+        
+        let resolved = manager.resolve(&project.join("alias/a.txt")).unwrap();
+        assert_eq!(resolved, project.join("real/a.txt"));
+    }
     
-    Ok(normalized_path.starts_with(&normalized_base))
+    #[cfg(unix)]
+    #[test]
+    fn symlink_loop_fails_instead_of_hanging() {
+        let (mut manager, project) = manager("loop");
+        grant(&mut manager, &project, GrantScope::ReadWrite);
+        std::os::unix::fs::symlink("b", project.join("a")).unwrap();
+        std::os::unix::fs::symlink("a", project.join("b")).unwrap();
+        
+        let (tx, rx) = std::sync::mpsc::channel();
+        let path = project.join("a/file.txt");
+        std::thread::spawn(move || {
+            let _ = tx.send(manager.resolve(&path));
+        });
+        let result = rx
+            .recv_timeout(std::time::Duration::from_secs(5))
+            .expect("resolving a symlink loop did not finish");
+        assert!(matches!(result, Err(FileSystemError::InvalidPath(_))));
+    }
 }
-
@@ -0,0 +1,442 @@
+use chrono::{DateTime, Utc};
+use portable_pty::{native_pty_system, ChildKiller, CommandBuilder, MasterPty, PtySize};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter};
+use tokio::sync::oneshot;
+
+use crate::filesystem::FileSystemError;
+use crate::process::is_env_allowed;
+
+/// Event names for terminal output and exit
+pub const TERMINAL_OUTPUT: &str = "terminal-output";
+pub const TERMINAL_EXITED: &str = "terminal-exited";
+
+/// Identifier of a terminal session
+pub type TerminalId = u64;
+
+/// Output kept per session for panels that re-attach (1MB)
+const SCROLLBACK_BYTES: usize = 1024 * 1024;
+
+/// Size of a single read from the PTY
+const READ_BUFFER_BYTES: usize = 8 * 1024;
+
+/// Size used when a request doesn't give one
+const DEFAULT_COLS: u16 = 80;
+const DEFAULT_ROWS: u16 = 24;
+
+/// How long to wait for remaining output once the shell has exited
+const OUTPUT_DRAIN_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Shells that may be started by name (besides the user's login shell)
+const ALLOWED_SHELLS: &[&str] = &[
+    "bash",
+    "zsh",
+    "fish",
+    "sh",
+    "dash",
+    "ksh",
+    "tcsh",
+    "nu",
+    "pwsh",
+    "powershell",
+    "cmd",
+];
+
+/// Options for a new terminal session
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct TerminalOptions {
+    /// Working directory (defaults to the project root)
+    #[serde(default)]
+    pub cwd: Option<String>,
+    /// Shell to run (defaults to the user's shell): the login shell, or the name of
+    /// an allowed shell looked up on `PATH`
+    #[serde(default)]
+    pub shell: Option<String>,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub cols: Option<u16>,
+    #[serde(default)]
+    pub rows: Option<u16>,
+}
+
+/// A running terminal session
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TerminalInfo {
+    pub id: TerminalId,
+    pub shell: String,
+    pub cwd: String,
+    pub cols: u16,
+    pub rows: u16,
+    /// Window that created the session (receives its events)
+    pub window: String,
+    #[serde(with = "chrono::serde::ts_milliseconds")]
+    pub created_at: DateTime<Utc>,
+}
+
+/// Terminal output payload
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TerminalOutput {
+    pub id: TerminalId,
+    pub data: String,
+}
+
+/// Terminal exit payload
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TerminalExit {
+    pub id: TerminalId,
+    pub exit_code: Option<u32>,
+    /// Signal that ended the shell, if any
+    pub signal: Option<String>,
+}
+
+/// Most recent output of a session, capped at `SCROLLBACK_BYTES`
+#[derive(Default)]
+struct Scrollback {
+    text: String,
+}
+
+impl Scrollback {
+    fn push(&mut self, data: &str) {
+        self.text.push_str(data);
+        if self.text.len() > SCROLLBACK_BYTES {
+            let mut cut = self.text.len() - SCROLLBACK_BYTES;
+            while !self.text.is_char_boundary(cut) {
+                cut += 1;
+            }
+            self.text.drain(..cut);
+        }
+    }
+}
+
+/// Input for a session's writer thread, with where to report the outcome
+type Input = (Vec<u8>, oneshot::Sender<io::Result<()>>);
+
+struct TerminalSession {
+    info: TerminalInfo,
+    master: Box<dyn MasterPty + Send>,
+    input: mpsc::Sender<Input>,
+    killer: Box<dyn ChildKiller + Send + Sync>,
+    scrollback: Arc<Mutex<Scrollback>>,
+}
+
+type Sessions = Arc<Mutex<HashMap<TerminalId, TerminalSession>>>;
+
+/// Terminal sessions (thread-safe)
+pub struct TerminalState {
+    sessions: Sessions,
+    next_id: AtomicU64,
+}
+
+/// Convert a PTY error into a file system error
+fn pty_error(context: &str, error: impl std::fmt::Display) -> FileSystemError {
+    FileSystemError::IoError(io::Error::other(format!("{}: {}", context, error)))
+}
+
+impl TerminalState {
+    pub fn new() -> Self {
+        Self {
+            sessions: Arc::new(Mutex::new(HashMap::new())),
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    /// Run a closure on a session
+    fn with_session<T>(
+        &self,
+        id: TerminalId,
+        f: impl FnOnce(&mut TerminalSession) -> Result<T, FileSystemError>,
+    ) -> Result<T, FileSystemError> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let session = sessions
+            .get_mut(&id)
+            .ok_or_else(|| FileSystemError::NotFound(format!("No terminal session {}", id)))?;
+        f(session)
+    }
+
+    /// Sessions, oldest first
+    pub fn list(&self) -> Vec<TerminalInfo> {
+        let mut sessions: Vec<TerminalInfo> = self
+            .sessions
+            .lock()
+            .unwrap()
+            .values()
+            .map(|session| session.info.clone())
+            .collect();
+        sessions.sort_by_key(|info| info.id);
+        sessions
+    }
+
+    /// Window that created a session
+    pub fn owner(&self, id: TerminalId) -> Option<String> {
+        self.sessions
+            .lock()
+            .unwrap()
+            .get(&id)
+            .map(|session| session.info.window.clone())
+    }
+
+    /// Send input (keystrokes or pasted text) to a session
+    ///
+    /// The write happens on the session's writer thread, so a shell that stops
+    /// reading its input doesn't hold up other sessions.
+    pub async fn write(&self, id: TerminalId, data: &str) -> Result<(), FileSystemError> {
+        let input = self.with_session(id, |session| Ok(session.input.clone()))?;
+        let ended = || FileSystemError::NotFound(format!("Terminal session {} has ended", id));
+        let (done_tx, done_rx) = oneshot::channel();
+        input
+            .send((data.as_bytes().to_vec(), done_tx))
+            .map_err(|_| ended())?;
+        Ok(done_rx.await.map_err(|_| ended())??)
+    }
+
+    /// Change a session's size in character cells
+    pub fn resize(&self, id: TerminalId, cols: u16, rows: u16) -> Result<(), FileSystemError> {
+        if cols == 0 || rows == 0 {
+            return Err(FileSystemError::InvalidInput(format!(
+                "Invalid terminal size {}x{}",
+                cols, rows
+            )));
+        }
+        self.with_session(id, |session| {
+            session
+                .master
+                .resize(PtySize {
+                    rows,
+                    cols,
+                    pixel_width: 0,
+                    pixel_height: 0,
+                })
+                .map_err(|e| pty_error("Failed to resize terminal", e))?;
+            session.info.cols = cols;
+            session.info.rows = rows;
+            Ok(())
+        })
+    }
+
+    /// Output kept for a session
+    pub fn scrollback(&self, id: TerminalId) -> Result<String, FileSystemError> {
+        self.with_session(id, |session| Ok(session.scrollback.lock().unwrap().text.clone()))
+    }
+
+    /// Kill a session's shell and end the session
+    pub fn kill(&self, id: TerminalId) -> Result<(), FileSystemError> {
+        self.with_session(id, |session| Ok(session.killer.kill()?))?;
+        self.sessions.lock().unwrap().remove(&id);
+        Ok(())
+    }
+
+    /// Kill every session created by a window
+    pub fn kill_window(&self, window_label: &str) {
+        self.sessions.lock().unwrap().retain(|_, session| {
+            if session.info.window != window_label {
+                return true;
+            }
+            let _ = session.killer.kill();
+            false
+        });
+    }
+
+    /// Kill every session
+    ///
+    /// Called when the application exits.
+    pub fn shutdown(&self) {
+        for (_, mut session) in self.sessions.lock().unwrap().drain() {
+            let _ = session.killer.kill();
+        }
+    }
+}
+
+/// Start a shell in a new PTY and stream its output to the creating window
+///
+/// # Arguments
+/// * `options` - Shell, arguments and size
+/// * `cwd` - Resolved working directory
+/// * `window_label` - Window that receives the output and exit events
+/// * `app` - App handle for emitting events
+/// * `state` - Terminal state
+///
+/// # Returns
+/// * `Ok(TerminalInfo)` - The session was started
+/// * `Err(FileSystemError)` - The shell is not allowed, or the PTY or shell could not be started
+pub fn create_terminal(
+    options: TerminalOptions,
+    cwd: PathBuf,
+    window_label: &str,
+    app: AppHandle,
+    state: &TerminalState,
+) -> Result<TerminalInfo, FileSystemError> {
+    if let Some(shell) = options.shell.as_deref().filter(|shell| !is_shell_allowed(shell)) {
+        return Err(FileSystemError::PermissionDenied(format!(
+            "Shell is not allowed: {}",
+            shell
+        )));
+    }
+
+    let cols = options.cols.unwrap_or(DEFAULT_COLS).max(1);
+    let rows = options.rows.unwrap_or(DEFAULT_ROWS).max(1);
+    let pair = native_pty_system()
+        .openpty(PtySize {
+            rows,
+            cols,
+            pixel_width: 0,
+            pixel_height: 0,
+        })
+        .map_err(|e| pty_error("Failed to open terminal", e))?;
+
+    let mut command = match &options.shell {
+        Some(shell) => {
+            let mut command = CommandBuilder::new(shell);
+            command.args(&options.args);
+            command
+        }
+        None => CommandBuilder::new_default_prog(),
+    };
+    command.cwd(&cwd);
+    // The shell gets the same environment as `run_command`, not the app's secrets
+    command.env_clear();
+    for (name, value) in std::env::vars_os() {
+        if name.to_str().is_some_and(is_env_allowed) {
+            command.env(name, value);
+        }
+    }
+    command.env("TERM", "xterm-256color");
+
+    let mut child = pair
+        .slave
+        .spawn_command(command)
+        .map_err(|e| pty_error("Failed to start shell", e))?;
+    // Only the shell keeps the slave open, so reads end when it exits
+    drop(pair.slave);
+
+    let reader = pair
+        .master
+        .try_clone_reader()
+        .map_err(|e| pty_error("Failed to read terminal", e))?;
+    let mut writer = pair
+        .master
+        .take_writer()
+        .map_err(|e| pty_error("Failed to write terminal", e))?;
+
+    let id = state.next_id.fetch_add(1, Ordering::Relaxed);
+    let info = TerminalInfo {
+        id,
+        shell: options.shell.unwrap_or_else(|| "default".to_string()),
+        cwd: cwd.to_string_lossy().to_string(),
+        cols,
+        rows,
+        window: window_label.to_string(),
+        created_at: Utc::now(),
+    };
+    let scrollback = Arc::new(Mutex::new(Scrollback::default()));
+    let (input_tx, input_rx) = mpsc::channel::<Input>();
+    state.sessions.lock().unwrap().insert(
+        id,
+        TerminalSession {
+            info: info.clone(),
+            master: pair.master,
+            input: input_tx,
+            killer: child.clone_killer(),
+            scrollback: scrollback.clone(),
+        },
+    );
+
+    // PTY writes block while the shell isn't reading; input is written in order
+    // until the session is removed and its sender dropped
+    std::thread::spawn(move || {
+        for (data, done) in input_rx {
+            let _ = done.send(writer.write_all(&data).and_then(|()| writer.flush()));
+        }
+    });
+
+    // PTY reads block, so each session gets its own reader thread
+    let (drained_tx, drained_rx) = mpsc::channel();
+    let output_app = app.clone();
+    let output_window = window_label.to_string();
+    std::thread::spawn(move || {
+        forward_output(reader, id, &output_window, &output_app, &scrollback);
+        let _ = drained_tx.send(());
+    });
+
+    // The session ends when the shell exits, even if the PTY never reports EOF
+    // (ConPTY keeps it open while other processes hold the console)
+    let sessions = state.sessions.clone();
+    let window = window_label.to_string();
+    std::thread::spawn(move || {
+        let status = child.wait().ok();
+        let _ = drained_rx.recv_timeout(OUTPUT_DRAIN_TIMEOUT);
+        sessions.lock().unwrap().remove(&id);
+        let exit = TerminalExit {
+            id,
+            exit_code: status.as_ref().map(|s| s.exit_code()),
+            signal: status.as_ref().and_then(|s| s.signal()).map(str::to_string),
+        };
+        let _ = app.emit_to(window.as_str(), TERMINAL_EXITED, exit);
+    });
+
+    Ok(info)
+}
+
+/// Whether a shell may be started
+///
+/// The user's login shell is always allowed; other shells only by name, so a
+/// path can't point at an arbitrary program.
+fn is_shell_allowed(shell: &str) -> bool {
+    let login_shell = std::env::var(if cfg!(windows) { "ComSpec" } else { "SHELL" });
+    if login_shell.is_ok_and(|login_shell| login_shell == shell) {
+        return true;
+    }
+    if shell.contains(['/', '\\']) {
+        return false;
+    }
+
+    let name = match cfg!(windows) {
+        true => shell.to_ascii_lowercase(),
+        false => shell.to_string(),
+    };
+    let name = name.strip_suffix(".exe").unwrap_or(&name);
+    ALLOWED_SHELLS.contains(&name)
+}
+
+/// Emit PTY output until the shell exits, keeping it in the scrollback
+///
+/// Chunks end on UTF-8 character boundaries; an incomplete character is held
+/// back until the rest of it arrives.
+fn forward_output(
+    mut reader: Box<dyn Read + Send>,
+    id: TerminalId,
+    window_label: &str,
+    app: &AppHandle,
+    scrollback: &Mutex<Scrollback>,
+) {
+    let mut buffer = [0u8; READ_BUFFER_BYTES];
+    let mut pending: Vec<u8> = Vec::new();
+    loop {
+        let read = match reader.read(&mut buffer) {
+            Ok(0) | Err(_) => break,
+            Ok(read) => read,
+        };
+        pending.extend_from_slice(&buffer[..read]);
+
+        let complete = match std::str::from_utf8(&pending) {
+            Ok(_) => pending.len(),
+            // Invalid bytes mid-stream are replaced; only a cut-off tail waits
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(_) => pending.len(),
+        };
+        let data = String::from_utf8_lossy(&pending[..complete]).into_owned();
+        pending.drain(..complete);
+        if data.is_empty() {
+            continue;
+        }
+
+        scrollback.lock().unwrap().push(&data);
+        let _ = app.emit_to(window_label, TERMINAL_OUTPUT, TerminalOutput { id, data });
+    }
+}
@@ -1,6 +1,7 @@
 import { useEffect, useState } from 'react';
 import { ToastContainer } from './components/Toast';
 import { FileConflictHandler } from './components/Editor/FileConflictHandler';
+import { PermissionConsentHandler } from './components/Permissions';
 import { PanelGroup } from './components/Panels';
 import { EmptyCanvas } from './components/EmptyCanvas';
 import { TopBar } from './components/TopBar';
@@ -59,10 +60,8 @@ function App() {
                 // Content is missing - request permission and reload it (won't create new tab)
                 try {
                   // Request permission first (required for Tauri security)
-                  const hasPermission = await requestPathPermission(tab.filePath);
-                  if (hasPermission) {
-                    await addAllowedPath(tab.filePath);
-                  }
+                  // Approving the request records the grant in the backend
+                  await requestPathPermission(tab.filePath);
                 } catch (permissionError) {
                   // Try to add path directly (might already be allowed)
                   try {
@@ -94,10 +93,8 @@ function App() {
               
               // Request permission before reloading (required for Tauri security)
               try {
-                const hasPermission = await requestPathPermission(tab.filePath);
-                if (hasPermission) {
-                  await addAllowedPath(tab.filePath);
-                }
+                // Approving the request records the grant in the backend
+                await requestPathPermission(tab.filePath);
               } catch (permissionError) {
                 // Try to add path directly (might already be allowed)
                 try {
@@ -283,6 +280,9 @@ function App() {
 
         {/* File Conflict Handler */}
         <FileConflictHandler />
+
+        {/* Permission Consent Handler */}
+        <PermissionConsentHandler />
       </div>
     </DndTabContext>
   );
@@ -0,0 +1,102 @@
+/**
+ * Permission consent dialog
+ * Shown when the backend asks the user to approve access to a path
+ */
+
+import React, { useState } from 'react';
+import type { GrantScope, PermissionRequest } from '@/types/filesystem';
+
+interface PermissionConsentDialogProps {
+  request: PermissionRequest;
+  onAllow: (scope: GrantScope) => void;
+  onDeny: () => void;
+}
+
+const SCOPE_LABELS: Record<GrantScope, string> = {
+  read_only: 'Read only',
+  no_delete: 'Read and write, no delete',
+  read_write: 'Read and write',
+  read_write_execute: 'Read, write and run commands, no delete',
+  full: 'Full access, including running commands',
+};
+
+/**
+ * Scopes the user may grant for a request (never wider than requested)
+ */
+const grantableScopes = (requested: GrantScope): GrantScope[] => {
+  switch (requested) {
+    case 'full':
+      return [
+        'read_only',
+        'no_delete',
+        'read_write',
+        'read_write_execute',
+        'full',
+      ];
+    case 'read_write':
+      return ['read_only', 'no_delete', 'read_write'];
+    case 'read_write_execute':
+      return ['read_only', 'no_delete', 'read_write_execute'];
+    case 'no_delete':
+      return ['read_only', 'no_delete'];
+    default:
+      return ['read_only'];
+  }
+};
+
+/**
+ * Permission consent dialog component
+ * Shows options: Deny, Allow (with a scope choice)
+ */
+export const PermissionConsentDialog: React.FC<PermissionConsentDialogProps> = ({
+  request,
+  onAllow,
+  onDeny,
+}) => {
+  const [scope, setScope] = useState<GrantScope>(request.scope);
+
+  return (
+    <div className="fixed inset-0 bg-black bg-opacity-50 flex items-center justify-center z-50">
+      <div className="bg-[#252526] border border-[#3e3e42] rounded-lg p-6 max-w-md w-full mx-4 shadow-xl">
+        <h2 className="text-lg font-semibold text-[#cccccc] mb-2">
+          Permission Request
+        </h2>
+        <p className="text-sm text-[#858585] mb-2">
+          Allow access to this path and everything beneath it?
+        </p>
+        <p className="text-sm text-[#cccccc] font-mono break-all mb-2">{request.path}</p>
+        {request.reason && (
+          <p className="text-sm text-[#858585] mb-2">Reason: {request.reason}</p>
+        )}
+        <label className="block text-sm text-[#858585] mb-6">
+          Access
+          <select
+            value={scope}
+            onChange={(e) => setScope(e.target.value as GrantScope)}
+            className="mt-1 w-full bg-[#3c3c3c] border border-[#3e3e42] text-[#cccccc] rounded px-2 py-1"
+          >
+            {grantableScopes(request.scope).map((option) => (
+              <option key={option} value={option}>
+                {SCOPE_LABELS[option]}
+              </option>
+            ))}
+          </select>
+        </label>
+        <div className="flex justify-end gap-3">
+          <button
+            onClick={onDeny}
+            className="px-4 py-2 text-sm text-[#cccccc] hover:bg-[#3e3e42] rounded transition-colors"
+          >
+            Deny
+          </button>
+          <button
+            onClick={() => onAllow(scope)}
+            className="px-4 py-2 text-sm bg-[#007acc] text-white hover:bg-[#005a9e] rounded transition-colors"
+          >
+            Allow
+          </button>
+        </div>
+      </div>
+    </div>
+  );
+};
@@ -0,0 +1,87 @@
+/**
+ * Permission consent handler component
+ * Listens for backend permission requests and asks the user one at a time
+ */
+
+import React, { useEffect, useState } from 'react';
+import { listen } from '@tauri-apps/api/event';
+import { PermissionConsentDialog } from './PermissionConsentDialog';
+import { listPendingPermissionRequests, respondPathPermission } from '@/utils/fileSystem';
+import type { GrantScope, PermissionRequest, PermissionResolution } from '@/types/filesystem';
+
+/**
+ * Permission consent handler component
+ * Queues pending requests and sends the user's answer back to the backend
+ */
+export const PermissionConsentHandler: React.FC = () => {
+  const [queue, setQueue] = useState<PermissionRequest[]>([]);
+
+  useEffect(() => {
+    let disposed = false;
+    const unlistenFunctions: (() => void)[] = [];
+
+    const enqueue = (request: PermissionRequest) => {
+      setQueue((current) =>
+        current.some((r) => r.id === request.id) ? current : [...current, request]
+      );
+    };
+    const dequeue = (id: number) => {
+      setQueue((current) => current.filter((r) => r.id !== id));
+    };
+
+    const setup = async () => {
+      try {
+        const unlistenRequested = await listen<PermissionRequest>('permission-requested', (event) =>
+          enqueue(event.payload)
+        );
+        const unlistenResolved = await listen<PermissionResolution>('permission-resolved', (event) =>
+          dequeue(event.payload.id)
+        );
+        unlistenFunctions.push(unlistenRequested, unlistenResolved);
+        if (disposed) {
+          unlistenFunctions.forEach((unlisten) => unlisten());
+          return;
+        }
+
+        // Pick up requests made before this window was listening
+        const pending = await listPendingPermissionRequests();
+        pending.forEach(enqueue);
+      } catch (error) {
+        console.error('Failed to set up permission consent listeners:', error);
+      }
+    };
+    setup();
+
+    return () => {
+      disposed = true;
+      unlistenFunctions.forEach((unlisten) => unlisten());
+    };
+  }, []);
+
+  const current = queue[0];
+
+  const respond = async (approved: boolean, scope?: GrantScope) => {
+    if (!current) {
+      return;
+    }
+    setQueue((pending) => pending.filter((r) => r.id !== current.id));
+    try {
+      await respondPathPermission(current.id, approved, scope);
+    } catch (error) {
+      console.error('Failed to respond to permission request:', error);
+    }
+  };
+
+  if (!current) {
+    return null;
+  }
+
+  return (
+    <PermissionConsentDialog
+      key={current.id}
+      request={current}
+      onAllow={(scope) => respond(true, scope)}
+      onDeny={() => respond(false)}
+    />
+  );
+};
@@ -0,0 +1,6 @@
+/**
+ * Permissions component exports
+ */
+
+export * from './PermissionConsentDialog';
+export * from './PermissionConsentHandler';
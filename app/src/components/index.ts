@@ -4,4 +4,5 @@ export * from './Editor';
 export * from './Toast';
 export * from './Settings';
 export * from './Panels';
+export * from './Permissions';
 
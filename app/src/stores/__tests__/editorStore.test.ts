@@ -87,7 +87,7 @@ describe('EditorStore', () => {
       });
 
       // Mock watchFile
-      vi.mocked(fileSystem.watchFile).mockResolvedValue();
+      vi.mocked(fileSystem.watchFile).mockResolvedValue({ id: 1, watchId: 1, path: filePath });
 
       await useEditorStore.getState().openFile(filePath);
 
@@ -114,7 +114,7 @@ describe('EditorStore', () => {
         lineCount: 1,
         size: 4,
       });
-      vi.mocked(fileSystem.watchFile).mockResolvedValue();
+      vi.mocked(fileSystem.watchFile).mockResolvedValue({ id: 1, watchId: 1, path: filePath });
 
       await useEditorStore.getState().openFile(filePath);
       const firstTabId = useEditorStore.getState().fileToTabMap.get(filePath);
@@ -151,11 +151,8 @@ export const useEditorStore = create<EditorStore>()(
 
           // Request permission for the path if needed
           try {
-            const hasPermission = await requestPathPermission(path);
-            if (hasPermission) {
-              // Add to allowed paths for future access
-              await addAllowedPath(path);
-            }
+            // Approving the request records the grant in the backend
+            await requestPathPermission(path);
           } catch (permissionError) {
             // If permission request fails, try to add path directly
             // (might already be allowed or in a directory that's allowed)
@@ -554,11 +551,8 @@ export const useEditorStore = create<EditorStore>()(
           
           // Request permission for the path if needed (required for Tauri security)
           try {
-            const hasPermission = await requestPathPermission(filePath);
-            if (hasPermission) {
-              // Add to allowed paths for future access
-              await addAllowedPath(filePath);
-            }
+            // Approving the request records the grant in the backend
+            await requestPathPermission(filePath);
           } catch (permissionError) {
             // If permission request fails, try to add path directly
             // (might already be allowed or in a directory that's allowed)
@@ -2,6 +2,42 @@
  * File system type definitions for Nexus Overseer
  */
 
+/**
+ * Code of an error returned by a backend command
+ */
+export type FileSystemErrorCode =
+  | 'NotFound'
+  | 'PermissionDenied'
+  | 'InvalidPath'
+  | 'TooLarge'
+  | 'Io'
+  | 'Encoding'
+  | 'Watch'
+  | 'WatchLimitReached'
+  | 'QuotaExceeded'
+  | 'Conflict'
+  | 'InvalidInput'
+  // LLM commands
+  | 'Connection'
+  | 'ServerError'
+  | 'ModelNotFound'
+  | 'InvalidResponse'
+  | 'Unsupported'
+  | 'Cancelled'
+  | 'Timeout'
+  | 'ServerDown'
+  | 'InvalidOutput';
+
+/**
+ * Error returned by a backend command
+ */
+export interface FileSystemErrorPayload {
+  code: FileSystemErrorCode;
+  message: string;
+  path: string | null; // the requested path the error occurred at
+  retryable: boolean; // repeating the same call may succeed
+}
+
 /**
  * File permissions information
  */
@@ -55,14 +91,263 @@ export interface DirectoryEntry {
   isDirectory: boolean;
   size?: number;
   modified: string; // ISO date string
+  denied?: boolean; // matches a deny rule (sensitive file)
+}
+
+/**
+ * Watch subscription handle returned by the backend
+ */
+export interface WatchSubscription {
+  id: number;
+  watchId: number;
+  path: string;
+}
+
+/**
+ * Metadata attached to file watch events by the backend
+ * (fields are null when the path no longer exists)
+ */
+export interface FileWatchEventMetadata {
+  isDir: boolean | null;
+  size: number | null;
+  modified: number | null; // milliseconds since epoch
+  contentHash: string | null; // SHA-256, small files only
+  watchId: number;
+  sequence: number;
+  selfOriginated: boolean; // caused by our own save
+}
+
+/**
+ * A single operation that can be allowed on a path
+ */
+export type Capability = 'read' | 'write' | 'create' | 'delete' | 'watch' | 'execute';
+
+/**
+ * Named capability preset used when asking for access
+ */
+export type GrantScope =
+  | 'read_only'
+  | 'read_write'
+  | 'no_delete'
+  | 'read_write_execute'
+  | 'full';
+
+/**
+ * Who performs an operation
+ */
+export type Actor = 'user' | 'overseer_ai' | 'implementation_ai' | 'plugin';
+
+/**
+ * Context passed with file operations
+ *
+ * The acting party is decided by the backend from the calling window.
+ */
+export interface OperationContext {
+  task_id?: string; // usage is also broken down per task
+}
+
+/**
+ * Quota limits of one actor (null = unlimited)
+ */
+export interface QuotaLimits {
+  max_bytes_written: number | null; // across all tasks
+  max_files_created: number | null; // across all tasks
+  max_deletes: number | null; // across all tasks
+  max_ops_per_second: number | null;
+}
+
+/**
+ * Quota configuration and usage of one actor
+ */
+export interface QuotaStatus {
+  actor: Actor;
+  limits: QuotaLimits;
+  paused: boolean;
+  total: {
+    // usage the limits are checked against
+    bytes_written: number;
+    files_created: number;
+    deletes: number;
+  };
+  tasks: {
+    actor: Actor;
+    task_id: string | null;
+    bytes_written: number;
+    files_created: number;
+    deletes: number;
+  }[];
+}
+
+/**
+ * Payload of the `quota-exceeded` event (the actor is paused until resumed)
+ *
+ * Also the shape of rate-limit rejections, which don't pause the actor.
+ */
+export interface QuotaBreach {
+  actor: Actor;
+  task_id: string | null;
+  kind: 'bytes_written' | 'files_created' | 'deletes' | 'ops_per_second' | 'paused';
+  message: string;
+}
+
+/**
+ * How denied (sensitive) entries appear in directory listings
+ */
+export type DeniedEntryMode = 'redact' | 'mark';
+
+/**
+ * Per-project deny-rule overrides (globs relative to the project root)
+ */
+export interface DenyOverrides {
+  deny: string[];
+  allow: string[];
+}
+
+/**
+ * Persisted permission grant for a path and everything beneath it
+ */
+export interface PermissionGrant {
+  path: string;
+  capabilities: Capability[];
+  expires_at: number | null; // seconds since epoch
+  reason: string | null;
+  granted_at: number; // seconds since epoch
+}
+
+/**
+ * Permission request waiting for the user's decision
+ */
+export interface PermissionRequest {
+  id: number;
+  path: string;
+  scope: GrantScope;
+  reason: string | null;
+  requested_by: string; // label of the requesting window
+  actor: Actor; // who the permission is for
+  requested_at: number; // seconds since epoch
+}
+
+/**
+ * Payload of the `permission-resolved` event
+ */
+export type PermissionResolution =
+  | { id: number; path: string; decision: 'approved'; scope: GrantScope }
+  | { id: number; path: string; decision: 'denied' };
+
+/**
+ * Operation recorded in the audit log
+ */
+export type AuditOperation =
+  | 'permission_request'
+  | 'permission_grant'
+  | 'permission_deny'
+  | 'permission_revoke'
+  | 'policy_change'
+  | 'read_file'
+  | 'write_file'
+  | 'delete_file'
+  | 'get_metadata'
+  | 'list_directory'
+  | 'create_directory'
+  | 'delete_directory'
+  | 'watch'
+  | 'run_command'
+  | 'open_terminal';
+
+/**
+ * A single audit log record
+ */
+export interface AuditEntry {
+  timestamp: number; // ms since epoch
+  actor: Actor;
+  operation: AuditOperation;
+  path: string;
+  result: 'ok' | 'error';
+  bytes?: number; // bytes read or written
+  detail?: string; // error message or extra information
+}
+
+/**
+ * Filter for audit log queries (all fields optional)
+ */
+export interface AuditQuery {
+  since?: number; // ms since epoch
+  until?: number; // ms since epoch
+  path?: string; // path or parent directory
+  actor?: Actor;
+  operation?: AuditOperation;
+  limit?: number; // most recent N matches
+}
+
+/**
+ * Health and statistics of a backend watch
+ */
+export interface WatchStatus {
+  watch_id: number;
+  path: string;
+  mode: 'file' | 'directory' | 'recursive_directory';
+  subscribers: { subscription_id: number; window_label: string }[];
+  total_events: number;
+  events_per_second: number;
+  dropped_events: number;
+  last_error: string | null;
+  started_at: number; // seconds since epoch
+}
+
+/**
+ * A command to run via `run_command`
+ */
+export interface RunCommandRequest {
+  program: string; // name looked up on PATH, or path relative to cwd
+  args?: string[];
+  cwd: string; // needs the execute capability
+  env?: Record<string, string>; // names must be on the backend allow-list
+  timeout_ms?: number; // default 10 minutes
+  sandbox?: boolean; // no network, no privilege gain (Linux only)
+}
+
+/**
+ * A running command
+ */
+export interface ProcessInfo {
+  id: number;
+  program: string;
+  args: string[];
+  cwd: string;
+  actor: Actor;
+  window: string; // window receiving its events
+  started_at: number; // ms since epoch
+  sandboxed: boolean;
+}
+
+/**
+ * Payload of the `process-output` event (one line of output)
+ */
+export interface ProcessOutput {
+  id: number;
+  stream: 'stdout' | 'stderr';
+  data: string;
+}
+
+/**
+ * Payload of the `process-exited` event
+ */
+export interface ProcessExit {
+  id: number;
+  reason: 'exited' | 'timed_out' | 'cancelled' | 'killed' | 'failed';
+  code: number | null;
+  signal: number | null; // Unix only
+  success: boolean;
+  duration_ms: number;
+  error: string | null;
 }
 
 /**
  * File watch event types
  */
 export type FileWatchEvent =
-  | { type: 'created'; path: string }
-  | { type: 'modified'; path: string }
-  | { type: 'deleted'; path: string }
-  | { type: 'renamed'; old: string; new: string };
+  | { type: 'created'; path: string; metadata?: FileWatchEventMetadata }
+  | { type: 'modified'; path: string; metadata?: FileWatchEventMetadata }
+  | { type: 'deleted'; path: string; metadata?: FileWatchEventMetadata }
+  | { type: 'renamed'; old: string; new: string; metadata?: FileWatchEventMetadata };
 
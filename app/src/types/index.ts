@@ -1,5 +1,7 @@
 // Type exports
 export * from './filesystem';
+export * from './terminal';
+export * from './llm';
 export * from './tab';
 export * from './tabDrag';
 export * from './editor';
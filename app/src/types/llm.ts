@@ -0,0 +1,426 @@
+/**
+ * LLM type definitions for Nexus Overseer
+ */
+
+/**
+ * Who wrote a chat message
+ */
+export type ChatRole = 'system' | 'user' | 'assistant' | 'tool';
+
+export interface ChatMessage {
+  role: ChatRole;
+  content: string;
+  tool_calls?: ToolCall[]; // tools the assistant called
+  tool_call_id?: string; // call a 'tool' message answers
+}
+
+/**
+ * A function the model may call, described by a JSON schema
+ */
+export interface ToolDefinition {
+  name: string;
+  description: string;
+  parameters: Record<string, unknown>; // JSON schema of the arguments
+}
+
+/**
+ * A tool call made by the model
+ */
+export interface ToolCall {
+  id: string;
+  name: string;
+  arguments: unknown; // the raw text when the model sent invalid JSON
+  arguments_error?: string; // why the arguments couldn't be parsed
+}
+
+/**
+ * Single-prompt completion request
+ */
+export interface GenerateRequest {
+  model?: string; // defaults to the role's model
+  prompt: string;
+  system?: string;
+  options?: Record<string, unknown>; // model options (temperature, num_ctx, ...)
+}
+
+/**
+ * Chat completion request
+ */
+export interface ChatRequest {
+  model?: string; // defaults to the role's model
+  messages: ChatMessage[];
+  options?: Record<string, unknown>; // model options (temperature, num_ctx, ...)
+  tools?: ToolDefinition[]; // tools the model may call natively
+  format?: ResponseFormat; // constrain the reply to JSON
+}
+
+/**
+ * Required form of a reply (uses the provider's JSON or grammar mode)
+ */
+export type ResponseFormat =
+  | { type: 'json' }
+  | { type: 'json_schema'; schema: Record<string, unknown> };
+
+/**
+ * Why generation stopped, the same for every provider
+ */
+export type FinishReason = 'stop' | 'length' | 'tool_calls' | 'content_filter' | 'other';
+
+/**
+ * Token counts reported by the server
+ */
+export interface Usage {
+  prompt_tokens: number | null;
+  completion_tokens: number | null;
+}
+
+/**
+ * Payload of the `llm-chunk` event
+ */
+export interface LlmChunk {
+  request_id: string;
+  content: string; // text generated since the previous chunk
+  done: boolean; // last chunk of the request
+  finish_reason: FinishReason | null; // set on the last chunk
+  usage: Usage | null; // set on the last chunk
+  restart: boolean; // the request was preempted; drop the text received so far
+}
+
+/**
+ * Complete result of a request
+ */
+export interface LlmResponse {
+  model: string;
+  content: string;
+  finish_reason: FinishReason | null;
+  usage: Usage;
+  duration_ms: number;
+  tool_calls: ToolCall[]; // native tool calls the model made
+  cached: boolean; // answered from the cache
+}
+
+/**
+ * Scheduling class of a request (interactive requests go first and preempt background ones)
+ */
+export type Priority = 'interactive' | 'background';
+
+/**
+ * A queued or running model call
+ */
+export interface QueueEntry {
+  request_id: string;
+  priority: Priority;
+  elapsed_ms: number; // since the call started (running) or was queued (waiting)
+  eta_ms: number | null; // until it finishes (running) or starts (waiting); null if unknown
+  preempted: boolean; // stopping to make room for an interactive call
+}
+
+/**
+ * Request queue of one provider
+ */
+export interface ProviderQueueStatus {
+  provider: string;
+  max_concurrent: number;
+  running: QueueEntry[];
+  waiting: QueueEntry[]; // in the order they will start
+  average_ms: number | null; // running average of call durations
+}
+
+/**
+ * Caching of one request (responses are only cached when this is given)
+ */
+export interface CacheOptions {
+  files?: string[]; // files the answer depends on; the entry is dropped when one changes
+  ttl_secs?: number | null; // defaults to the configured TTL
+  refresh?: boolean; // skip the lookup and replace the entry
+}
+
+/**
+ * Response cache limits
+ */
+export interface CacheConfig {
+  max_bytes: number; // least recently used entries are evicted beyond this
+  ttl_secs: number;
+}
+
+/**
+ * Response cache statistics (counters since the app started)
+ */
+export interface CacheStats {
+  entries: number;
+  bytes: number;
+  max_bytes: number;
+  ttl_secs: number;
+  hits: number;
+  misses: number; // cached requests that had to go to the model
+  coalesced: number; // requests that shared an identical running request
+  invalidated: number; // entries dropped because a file they depend on changed
+  write_errors: number; // responses that couldn't be written to the cache
+  last_write_error: string | null;
+}
+
+/**
+ * AI role a request is made for (each can use a different provider)
+ */
+export type AiRole = 'overseer' | 'implementation';
+
+/**
+ * API a provider speaks
+ */
+export type ProviderKind = 'ollama' | 'openai_compatible';
+
+/**
+ * A configured LLM server
+ */
+export interface ProviderConfig {
+  kind: ProviderKind;
+  base_url: string; // for OpenAI-compatible servers the API root, e.g. 'http://localhost:8080/v1'
+  api_key?: string | null; // sent as a bearer token (OpenAI-compatible only)
+  max_concurrent?: number | null; // model calls run at once (1 if unset); the rest wait in line
+}
+
+/**
+ * Provider and model used by a role
+ */
+export interface RoleConfig {
+  provider?: string | null; // provider id (defaults to the default provider)
+  model?: string | null; // used when a request doesn't name one
+}
+
+/**
+ * LLM settings
+ */
+export interface LlmConfig {
+  providers: Record<string, ProviderConfig>; // servers by id
+  default_provider: string; // used by roles without their own
+  roles?: Partial<Record<AiRole, RoleConfig>>;
+  cache?: CacheConfig;
+  resilience?: ResilienceConfig;
+}
+
+/**
+ * Timeouts, retries and circuit breaker limits for calls to LLM servers
+ */
+export interface ResilienceConfig {
+  connect_timeout_ms: number;
+  first_chunk_timeout_ms: number; // includes loading the model
+  read_timeout_ms: number; // longest pause in a reply; limit for other calls
+  max_retries: number; // transient failures only, before any text streamed
+  initial_backoff_ms: number; // doubled per retry, with jitter
+  max_backoff_ms: number;
+  failure_threshold: number; // consecutive failures that mark the server down
+  cooldown_ms: number; // fail fast this long before trying again
+}
+
+/**
+ * State of a provider's circuit breaker
+ */
+export type CircuitState = 'closed' | 'open' | 'half_open';
+
+/**
+ * Payload of the `llm-provider-status` event
+ */
+export interface ProviderStatus {
+  provider: string;
+  state: CircuitState; // 'open' means requests fail with ServerDown
+  consecutive_failures: number;
+  last_error: string | null;
+  retry_in_ms: number | null; // until a trial request is let through (open only)
+}
+
+/**
+ * A model installed on (or served by) a provider
+ */
+export interface ModelStatus {
+  name: string;
+  size: number | null; // bytes on disk
+  quantization: string | null; // e.g. 'Q4_K_M'
+  parameter_size: string | null; // e.g. '8.0B'
+  family: string | null;
+  context_length: number | null; // tokens
+  loaded: boolean | null; // null if the provider can't tell
+  modified_at: string | null;
+}
+
+/**
+ * Payload of the `llm-pull-progress` event
+ */
+export interface LlmPullProgress {
+  request_id: string;
+  model: string;
+  status: string; // e.g. 'pulling manifest', 'success'
+  digest: string | null; // layer being downloaded
+  completed: number | null; // bytes of the layer downloaded so far
+  total: number | null; // size of the layer in bytes
+}
+
+/**
+ * Result of a health probe
+ */
+export interface LlmHealth {
+  provider: string;
+  reachable: boolean;
+  version: string | null; // if the API reports one
+  latency_ms: number;
+  error: string | null; // why the server is unreachable
+}
+
+/**
+ * Agent run request
+ */
+export interface AgentRequest {
+  model?: string; // defaults to the role's model
+  messages: ChatMessage[];
+  options?: Record<string, unknown>;
+  max_steps?: number; // tool rounds before stopping (default 10, at most 50)
+  tool_mode?: 'native' | 'json'; // defaults to native, falling back to json
+}
+
+/**
+ * Payload of the `llm-agent-step` event
+ */
+export interface AgentStep {
+  request_id: string;
+  step: number; // tool round, from 1
+  call: ToolCall;
+  ok: boolean;
+  result: unknown; // tool output, or the error ({ code, message, ... })
+}
+
+/**
+ * Result of an agent run
+ */
+export interface AgentResult {
+  content: string; // the model's last reply
+  stop: 'answered' | 'max_steps';
+  steps: number;
+  messages: ChatMessage[]; // the conversation including tool calls and results
+  usage: Usage;
+}
+
+/**
+ * Structured generation request
+ */
+export interface StructuredRequest {
+  model?: string; // defaults to the role's model
+  messages: ChatMessage[];
+  options?: Record<string, unknown>;
+  schema: Record<string, unknown>; // JSON schema the reply must match
+  max_repairs?: number; // times an invalid reply is sent back (default 2, at most 5)
+  constrained?: boolean; // use the provider's JSON mode (default true)
+}
+
+/**
+ * Result of structured generation
+ */
+export interface StructuredOutput<T> {
+  value: T; // the parsed and validated reply
+  content: string; // the reply's text
+  attempts: number; // model calls made
+  usage: Usage;
+}
+
+/**
+ * Tokenizer family of a model (GPT families are counted exactly, others estimated)
+ */
+export type ModelFamily =
+  | 'gpt4o'
+  | 'gpt'
+  | 'llama3'
+  | 'llama'
+  | 'mistral'
+  | 'qwen'
+  | 'gemma'
+  | 'phi'
+  | 'deep_seek'
+  | 'other';
+
+/**
+ * Token count of a text
+ */
+export interface TokenCount {
+  model: string;
+  family: ModelFamily;
+  exact: boolean; // false for estimates
+  tokens: number;
+}
+
+/**
+ * A piece of a prompt (a file, the task, the conversation so far, ...)
+ */
+export interface BudgetInput {
+  name: string; // label shown in the breakdown, e.g. a file path
+  content: string;
+  required?: boolean; // required inputs are never shortened
+  strategy?: 'truncate' | 'summarize'; // defaults to truncate
+}
+
+/**
+ * Inputs to fit into a model's context window
+ */
+export interface BudgetRequest {
+  model?: string; // defaults to the role's model
+  inputs: BudgetInput[];
+  context_length?: number; // defaults to the server's value for the model
+  reserve_output?: number; // tokens kept for the reply (default 1024, at most a quarter)
+}
+
+/**
+ * Budget of one input
+ */
+export interface BudgetItem {
+  name: string;
+  tokens: number; // as given
+  allotted: number; // allowed in the request
+  action: 'kept' | 'truncated' | 'summarized' | 'dropped';
+}
+
+/**
+ * How a request's context window is spent
+ */
+export interface BudgetReport {
+  model: string;
+  family: ModelFamily;
+  exact: boolean;
+  context_length: number;
+  reserved_output: number;
+  available: number; // for inputs, after the output reserve
+  used: number; // after fitting, including per-input overhead
+  remaining: number;
+  fits: boolean; // false if the required inputs alone don't fit
+  items: BudgetItem[]; // in request order
+}
+
+/**
+ * Inputs shortened to fit, with the breakdown
+ */
+export interface FitResult {
+  report: BudgetReport;
+  inputs: { name: string; content: string }[]; // in request order; empty if dropped
+}
+
+/**
+ * A role prompt template
+ * 
+ * Looked up in `<project>/.nexus-overseer/prompts/<role>.md`, then
+ * `<app config>/prompts/<role>.md`, then the built-in template.
+ */
+export interface TemplateInfo {
+  role: AiRole;
+  source: 'builtin' | 'app' | 'project';
+  path: string | null; // null for built-in templates
+  version: string | null; // from the front matter
+  description: string | null;
+  hash: string; // first 12 hex digits of the file's SHA-256; empty if unreadable
+  active: boolean; // the template the role uses
+  error: string | null; // why the file can't be used
+}
+
+/**
+ * A rendered system prompt
+ */
+export interface RenderedPrompt {
+  content: string;
+  template: TemplateInfo;
+  variables: Record<string, string>; // variables the template uses, with their values
+}
@@ -0,0 +1,44 @@
+/**
+ * Terminal type definitions for Nexus Overseer
+ */
+
+/**
+ * Options for a new terminal session
+ */
+export interface TerminalOptions {
+  cwd?: string; // defaults to the project root
+  shell?: string; // the login shell or an allowed name such as 'bash' (default: login shell)
+  args?: string[];
+  cols?: number;
+  rows?: number;
+}
+
+/**
+ * A running terminal session
+ */
+export interface TerminalInfo {
+  id: number;
+  shell: string;
+  cwd: string;
+  cols: number;
+  rows: number;
+  window: string; // window receiving its events
+  created_at: number; // ms since epoch
+}
+
+/**
+ * Payload of the `terminal-output` event
+ */
+export interface TerminalOutput {
+  id: number;
+  data: string;
+}
+
+/**
+ * Payload of the `terminal-exited` event
+ */
+export interface TerminalExit {
+  id: number;
+  exit_code: number | null;
+  signal: string | null;
+}
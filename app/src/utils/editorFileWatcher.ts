@@ -36,6 +36,9 @@ export async function setupEditorFileWatcher(): Promise<() => void> {
     onModified: async (event: FileWatchEvent) => {
       if (event.type !== 'modified') return;
       
+      // Our own save echoing back through the watcher - nothing to reload
+      if (event.metadata?.selfOriginated) return;
+      
       // Always get fresh state (don't use closure-captured state)
       const editorStore = useEditorStore.getState();
       const tabStore = useTabStore.getState();
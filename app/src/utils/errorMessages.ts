@@ -3,6 +3,8 @@
  * Converts technical errors into user-friendly messages
  */
 
+import { FileSystemError } from './fileSystemError';
+
 /**
  * Get user-friendly error message from an error
  * @param error - The error object or string
@@ -10,6 +12,38 @@
  * @returns User-friendly error message
  */
 export function getUserFriendlyErrorMessage(error: unknown, context: string): string {
+  // Backend errors carry a code; only untyped errors need message matching
+  if (error instanceof FileSystemError) {
+    switch (error.code) {
+      case 'NotFound':
+        return `File not found. Please check the file path and try again.`;
+      case 'PermissionDenied':
+        return `Permission denied. You don't have permission to ${context}.`;
+      case 'TooLarge':
+        return `File is too large to ${context}. Maximum file size is 10MB.`;
+      case 'Encoding':
+        return `File encoding error. The file may be corrupted or use an unsupported encoding.`;
+      case 'Conflict':
+        return `The file was changed by something else. Please try again.`;
+      case 'QuotaExceeded':
+        return `Too many file operations. Resume the paused actor to continue.`;
+      case 'Connection':
+        return `Could not reach the LLM server. Check that Ollama is running and try again.`;
+      case 'ModelNotFound':
+        return `Model not found. Pull the model in Ollama and try again.`;
+      case 'Timeout':
+        return `The LLM server stopped responding. Please try again.`;
+      case 'ServerDown':
+        return `The LLM server is down. Requests will resume once it responds again.`;
+      case 'InvalidOutput':
+        return `The model's answer wasn't in the expected format. Please try again.`;
+      default:
+        return error.retryable
+          ? `Failed to ${context}. Please try again.`
+          : `Failed to ${context}.`;
+    }
+  }
+
   const errorMessage = error instanceof Error ? error.message : String(error);
   const lowerMessage = errorMessage.toLowerCase();
 
@@ -5,11 +5,67 @@
 
 import { invoke } from '@tauri-apps/api/core';
 import type {
+  Actor,
+  AuditEntry,
+  AuditQuery,
+  Capability,
+  DeniedEntryMode,
+  DenyOverrides,
   DirectoryEntry,
   FileMetadata,
   FileReadResult,
+  FileSystemErrorPayload,
   FileWriteRequest,
+  GrantScope,
+  OperationContext,
+  PermissionGrant,
+  PermissionRequest,
+  ProcessInfo,
+  QuotaLimits,
+  QuotaStatus,
+  RunCommandRequest,
+  WatchStatus,
+  WatchSubscription,
 } from '../types/filesystem';
+import { FileSystemError } from './fileSystemError';
+
+export { FileSystemError };
+
+/**
+ * Check if a rejected invoke value is a backend error payload
+ */
+function isErrorPayload(value: unknown): value is FileSystemErrorPayload {
+  return (
+    typeof value === 'object' &&
+    value !== null &&
+    typeof (value as FileSystemErrorPayload).code === 'string' &&
+    typeof (value as FileSystemErrorPayload).message === 'string'
+  );
+}
+
+/**
+ * Prefix an error with what was being done, keeping its code
+ */
+export function withContext(error: unknown, context: string): Error {
+  if (error instanceof FileSystemError) {
+    return new FileSystemError(error, `${context}: ${error.message}`);
+  }
+  return new Error(`${context}: ${error}`);
+}
+
+/**
+ * Backend watch subscription ids held by this window, keyed by path
+ */
+const watchSubscriptions = new Map<string, number[]>();
+
+/**
+ * Remember a subscription so `unwatch(path)` can release it later
+ */
+function trackSubscription(path: string, subscription: { id: number }): void {
+  const ids = watchSubscriptions.get(path) ?? [];
+  ids.push(subscription.id);
+  watchSubscriptions.set(path, ids);
+}
 
 /**
  * Check if running in Tauri environment
@@ -20,28 +76,35 @@ function isTauriEnvironment(): boolean {
 
 /**
  * Get invoke function or throw error if not in Tauri environment
+ * 
+ * Backend errors are rethrown as `FileSystemError`.
  */
-async function safeInvoke<T>(command: string, args?: Record<string, unknown>): Promise<T> {
+export async function safeInvoke<T>(command: string, args?: Record<string, unknown>): Promise<T> {
   if (!isTauriEnvironment()) {
     throw new Error(
       'Tauri API is not available. Make sure you are running the app in a Tauri window, not in a browser.'
     );
   }
-  return await invoke<T>(command, args);
+  try {
+    return await invoke<T>(command, args);
+  } catch (error) {
+    throw isErrorPayload(error) ? new FileSystemError(error) : error;
+  }
 }
 
 /**
  * Read a file from the file system
  * 
  * @param path - The file path to read
+ * @param context - Task the operation is charged to
  * @returns File content with metadata
  * @throws Error if file read fails
  */
-export async function readFile(path: string): Promise<FileReadResult> {
+export async function readFile(path: string, context?: OperationContext): Promise<FileReadResult> {
   try {
-    return await safeInvoke<FileReadResult>('read_file_command', { path });
+    return await safeInvoke<FileReadResult>('read_file_command', { path, context });
   } catch (error) {
-    throw new Error(`Failed to read file: ${error}`);
+    throw withContext(error, 'Failed to read file');
   }
 }
 
@@ -49,9 +112,13 @@ export async function readFile(path: string): Promise<FileReadResult> {
  * Write a file to the file system
  * 
  * @param request - File write request with path, content, and options
+ * @param context - Task the operation is charged to
  * @throws Error if file write fails
  */
-export async function writeFile(request: FileWriteRequest): Promise<void> {
+export async function writeFile(
+  request: FileWriteRequest,
+  context?: OperationContext
+): Promise<void> {
   try {
     // Ensure all fields are present (Rust expects them)
     const fullRequest: FileWriteRequest = {
@@ -60,9 +127,9 @@ export async function writeFile(request: FileWriteRequest): Promise<void> {
       createIfNotExists: request.createIfNotExists ?? false,
       backup: request.backup ?? false,
     };
-    await safeInvoke('write_file_command', { request: fullRequest });
+    await safeInvoke('write_file_command', { request: fullRequest, context });
   } catch (error) {
-    throw new Error(`Failed to write file: ${error}`);
+    throw withContext(error, 'Failed to write file');
   }
 }
 
@@ -70,13 +137,14 @@ export async function writeFile(request: FileWriteRequest): Promise<void> {
  * Delete a file from the file system
  * 
  * @param path - The file path to delete
+ * @param context - Task the operation is charged to
  * @throws Error if file deletion fails
  */
-export async function deleteFile(path: string): Promise<void> {
+export async function deleteFile(path: string, context?: OperationContext): Promise<void> {
   try {
-    await safeInvoke('delete_file_command', { path });
+    await safeInvoke('delete_file_command', { path, context });
   } catch (error) {
-    throw new Error(`Failed to delete file: ${error}`);
+    throw withContext(error, 'Failed to delete file');
   }
 }
 
@@ -84,14 +152,15 @@ export async function deleteFile(path: string): Promise<void> {
  * Get file metadata
  * 
  * @param path - The file path
+ * @param context - Task the operation is charged to
  * @returns File metadata
  * @throws Error if metadata retrieval fails
  */
-export async function getFileMetadata(path: string): Promise<FileMetadata> {
+export async function getFileMetadata(path: string, context?: OperationContext): Promise<FileMetadata> {
   try {
-    return await safeInvoke<FileMetadata>('get_file_metadata_command', { path });
+    return await safeInvoke<FileMetadata>('get_file_metadata_command', { path, context });
   } catch (error) {
-    throw new Error(`Failed to get file metadata: ${error}`);
+    throw withContext(error, 'Failed to get file metadata');
   }
 }
 
@@ -99,14 +168,24 @@ export async function getFileMetadata(path: string): Promise<FileMetadata> {
  * List directory contents
  * 
  * @param path - The directory path
+ * @param context - Task the operation is charged to
+ * @param deniedEntries - Hide or mark sensitive entries (default: mark for the user, hide for AI actors)
  * @returns Array of directory entries
  * @throws Error if directory listing fails
  */
-export async function listDirectory(path: string): Promise<DirectoryEntry[]> {
+export async function listDirectory(
+  path: string,
+  context?: OperationContext,
+  deniedEntries?: DeniedEntryMode
+): Promise<DirectoryEntry[]> {
   try {
-    return await safeInvoke<DirectoryEntry[]>('list_directory_command', { path });
+    return await safeInvoke<DirectoryEntry[]>('list_directory_command', {
+      path,
+      context,
+      deniedEntries,
+    });
   } catch (error) {
-    throw new Error(`Failed to list directory: ${error}`);
+    throw withContext(error, 'Failed to list directory');
   }
 }
 
@@ -114,13 +193,14 @@ export async function listDirectory(path: string): Promise<DirectoryEntry[]> {
  * Create a directory
  * 
  * @param path - The directory path to create
+ * @param context - Task the operation is charged to
  * @throws Error if directory creation fails
  */
-export async function createDirectory(path: string): Promise<void> {
+export async function createDirectory(path: string, context?: OperationContext): Promise<void> {
   try {
-    await safeInvoke('create_directory_command', { path });
+    await safeInvoke('create_directory_command', { path, context });
   } catch (error) {
-    throw new Error(`Failed to create directory: ${error}`);
+    throw withContext(error, 'Failed to create directory');
   }
 }
 
@@ -128,13 +208,14 @@ export async function createDirectory(path: string): Promise<void> {
  * Delete a directory (recursive)
  * 
  * @param path - The directory path to delete
+ * @param context - Task the operation is charged to
  * @throws Error if directory deletion fails
  */
-export async function deleteDirectory(path: string): Promise<void> {
+export async function deleteDirectory(path: string, context?: OperationContext): Promise<void> {
   try {
-    await safeInvoke('delete_directory_command', { path });
+    await safeInvoke('delete_directory_command', { path, context });
   } catch (error) {
-    throw new Error(`Failed to delete directory: ${error}`);
+    throw withContext(error, 'Failed to delete directory');
   }
 }
 
@@ -149,81 +230,243 @@ export async function fileExists(path: string): Promise<boolean> {
   try {
     return await safeInvoke<boolean>('file_exists_command', { path });
   } catch (error) {
-    throw new Error(`Failed to check file existence: ${error}`);
+    throw withContext(error, 'Failed to check file existence');
   }
 }
 
 /**
  * Watch a file for changes
  * 
+ * Each call adds a subscription for this window; release it with `unwatch`.
+ * 
  * @param path - The file path to watch
+ * @param context - Task the operation is charged to
+ * @returns Subscription handle
  * @throws Error if watching fails
  */
-export async function watchFile(path: string): Promise<void> {
+export async function watchFile(
+  path: string,
+  context?: OperationContext
+): Promise<WatchSubscription> {
   try {
-    await safeInvoke('watch_file_command', { path });
+    const subscription = await safeInvoke<WatchSubscription>('watch_file_command', {
+      path,
+      context,
+    });
+    trackSubscription(path, subscription);
+    return subscription;
   } catch (error) {
-    throw new Error(`Failed to watch file: ${error}`);
+    throw withContext(error, 'Failed to watch file');
   }
 }
 
 /**
  * Watch a directory for changes
  * 
+ * Each call adds a subscription for this window; release it with `unwatch`.
+ * 
  * @param path - The directory path to watch
  * @param recursive - Whether to watch recursively
+ * @param context - Task the operation is charged to
+ * @returns Subscription handle
  * @throws Error if watching fails
  */
 export async function watchDirectory(
   path: string,
-  recursive: boolean = false
-): Promise<void> {
+  recursive: boolean = false,
+  context?: OperationContext
+): Promise<WatchSubscription> {
   try {
-    await safeInvoke('watch_directory_command', { path, recursive });
+    const subscription = await safeInvoke<WatchSubscription>('watch_directory_command', {
+      path,
+      recursive,
+      context,
+    });
+    trackSubscription(path, subscription);
+    return subscription;
   } catch (error) {
-    throw new Error(`Failed to watch directory: ${error}`);
+    throw withContext(error, 'Failed to watch directory');
   }
 }
 
 /**
  * Stop watching a path
  * 
+ * Releases the most recent subscription this window holds for the path.
+ * Other windows watching the same path are unaffected.
+ * 
  * @param path - The path to stop watching
  * @throws Error if unwatching fails
  */
 export async function unwatch(path: string): Promise<void> {
   try {
-    await safeInvoke('unwatch_command', { path });
+    const ids = watchSubscriptions.get(path);
+    const subscriptionId = ids?.pop();
+    if (ids && ids.length === 0) {
+      watchSubscriptions.delete(path);
+    }
+    if (subscriptionId === undefined) {
+      throw new Error(`Path not being watched: ${path}`);
+    }
+    await safeInvoke('unwatch_command', { subscriptionId });
   } catch (error) {
-    throw new Error(`Failed to unwatch: ${error}`);
+    throw withContext(error, 'Failed to unwatch');
   }
 }
 
 /**
- * Stop watching all paths
+ * Stop watching all paths watched by this window
  * 
  * @throws Error if unwatching fails
  */
 export async function unwatchAll(): Promise<void> {
   try {
+    watchSubscriptions.clear();
     await safeInvoke('unwatch_all_command');
   } catch (error) {
-    throw new Error(`Failed to unwatch all: ${error}`);
+    throw withContext(error, 'Failed to unwatch all');
+  }
+}
+
+/**
+ * Get health and statistics for all active watches
+ * 
+ * @returns Status of each watch
+ * @throws Error if retrieval fails
+ */
+export async function getWatchStatus(): Promise<WatchStatus[]> {
+  try {
+    return await safeInvoke<WatchStatus[]>('get_watch_status');
+  } catch (error) {
+    throw withContext(error, 'Failed to get watch status');
+  }
+}
+
+/**
+ * Run a command in an allowed directory
+ * 
+ * Output arrives as `process-output` events and the result as a
+ * `process-exited` event, both sent to this window.
+ * 
+ * @param request - Program, arguments, working directory and options
+ * @param context - Task the operation is charged to
+ * @returns The started process
+ * @throws Error if the command can't be started
+ */
+export async function runCommand(
+  request: RunCommandRequest,
+  context?: OperationContext
+): Promise<ProcessInfo> {
+  try {
+    return await safeInvoke<ProcessInfo>('run_command', { request, context });
+  } catch (error) {
+    throw withContext(error, 'Failed to run command');
+  }
+}
+
+/**
+ * Ask a running command to exit (it is killed after a grace period)
+ * 
+ * @param id - The process id from `runCommand`
+ * @throws Error if the process isn't running or belongs to another window
+ */
+export async function cancelProcess(id: number): Promise<void> {
+  try {
+    await safeInvoke<void>('cancel_process', { id });
+  } catch (error) {
+    throw withContext(error, 'Failed to cancel process');
+  }
+}
+
+/**
+ * Kill a running command and everything it started
+ * 
+ * @param id - The process id from `runCommand`
+ * @throws Error if the process isn't running or belongs to another window
+ */
+export async function killProcess(id: number): Promise<void> {
+  try {
+    await safeInvoke<void>('kill_process', { id });
+  } catch (error) {
+    throw withContext(error, 'Failed to kill process');
+  }
+}
+
+/**
+ * List running commands
+ * 
+ * @returns Running processes, oldest first
+ * @throws Error if retrieval fails
+ */
+export async function listProcesses(): Promise<ProcessInfo[]> {
+  try {
+    return await safeInvoke<ProcessInfo[]>('list_processes');
+  } catch (error) {
+    throw withContext(error, 'Failed to list processes');
   }
 }
 
 /**
  * Request permission for a path
  * 
+ * Resolves once the user has answered the consent prompt (or immediately if an
+ * existing grant already covers the request).
+ * 
  * @param path - The path to request permission for
+ * @param options - Requested scope (default read-write) and a reason shown to the user
  * @returns True if permission granted, false otherwise
  * @throws Error if request fails
  */
-export async function requestPathPermission(path: string): Promise<boolean> {
+export async function requestPathPermission(
+  path: string,
+  options: { scope?: GrantScope; reason?: string } = {}
+): Promise<boolean> {
+  try {
+    return await safeInvoke<boolean>('request_path_permission', {
+      path,
+      scope: options.scope ?? null,
+      reason: options.reason ?? null,
+    });
+  } catch (error) {
+    throw withContext(error, 'Failed to request path permission');
+  }
+}
+
+/**
+ * Answer a pending permission request (main window only)
+ * 
+ * @param requestId - The request to answer
+ * @param approved - Whether the user approved the request
+ * @param scope - Scope to grant; may narrow but not widen the requested scope
+ * @throws Error if responding fails or the request was already answered or timed out
+ */
+export async function respondPathPermission(
+  requestId: number,
+  approved: boolean,
+  scope?: GrantScope
+): Promise<void> {
+  try {
+    await safeInvoke<void>('respond_path_permission', {
+      requestId,
+      approved,
+      scope: scope ?? null,
+    });
+  } catch (error) {
+    throw withContext(error, 'Failed to respond to permission request');
+  }
+}
+
+/**
+ * List permission requests still waiting for the user
+ * 
+ * @returns Pending requests, oldest first
+ * @throws Error if listing fails
+ */
+export async function listPendingPermissionRequests(): Promise<PermissionRequest[]> {
   try {
-    return await safeInvoke<boolean>('request_path_permission', { path });
+    return await safeInvoke<PermissionRequest[]>('list_pending_permission_requests');
   } catch (error) {
-    throw new Error(`Failed to request path permission: ${error}`);
+    throw withContext(error, 'Failed to list pending permission requests');
   }
 }
 
@@ -231,13 +474,29 @@ export async function requestPathPermission(path: string): Promise<boolean> {
  * Add an allowed path
  * 
  * @param path - The path to add to allowed list
+ * @param options - Grant scope (default read-write) or explicit capabilities, expiry (seconds since epoch) and reason
+ * @returns The persisted grant
  * @throws Error if adding fails
  */
-export async function addAllowedPath(path: string): Promise<void> {
+export async function addAllowedPath(
+  path: string,
+  options: {
+    scope?: GrantScope;
+    capabilities?: Capability[];
+    expiresAt?: number;
+    reason?: string;
+  } = {}
+): Promise<PermissionGrant> {
   try {
-    await safeInvoke('add_allowed_path', { path });
+    return await safeInvoke<PermissionGrant>('add_allowed_path', {
+      path,
+      scope: options.scope,
+      capabilities: options.capabilities,
+      expiresAt: options.expiresAt,
+      reason: options.reason,
+    });
   } catch (error) {
-    throw new Error(`Failed to add allowed path: ${error}`);
+    throw withContext(error, 'Failed to add allowed path');
   }
 }
 
@@ -251,7 +510,158 @@ export async function getAllowedPaths(): Promise<string[]> {
   try {
     return await safeInvoke<string[]>('get_allowed_paths');
   } catch (error) {
-    throw new Error(`Failed to get allowed paths: ${error}`);
+    throw withContext(error, 'Failed to get allowed paths');
+  }
+}
+
+
+/**
+ * List all active permission grants with their metadata
+ * 
+ * @returns Array of grants
+ * @throws Error if retrieval fails
+ */
+export async function listPermissionGrants(): Promise<PermissionGrant[]> {
+  try {
+    return await safeInvoke<PermissionGrant[]>('list_permission_grants');
+  } catch (error) {
+    throw withContext(error, 'Failed to list permission grants');
+  }
+}
+
+/**
+ * Revoke the permission grant for a path
+ * 
+ * @param path - The granted path to revoke
+ * @returns True if a grant was revoked
+ * @throws Error if revoking fails
+ */
+export async function revokePermissionGrant(path: string): Promise<boolean> {
+  try {
+    return await safeInvoke<boolean>('revoke_permission_grant', { path });
+  } catch (error) {
+    throw withContext(error, 'Failed to revoke permission grant');
+  }
+}
+
+/**
+ * Get the capabilities an actor may use
+ * 
+ * @param actor - The actor to look up
+ * @returns The actor's capability ceiling
+ * @throws Error if retrieval fails
+ */
+export async function getActorCapabilities(actor: Actor): Promise<Capability[]> {
+  try {
+    return await safeInvoke<Capability[]>('get_actor_capabilities', { actor });
+  } catch (error) {
+    throw withContext(error, 'Failed to get actor capabilities');
+  }
+}
+
+/**
+ * Set the capabilities an actor may use (main window only)
+ * 
+ * @param actor - The actor to configure
+ * @param capabilities - The actor's new capability ceiling
+ * @throws Error if saving fails
+ */
+export async function setActorCapabilities(
+  actor: Actor,
+  capabilities: Capability[]
+): Promise<void> {
+  try {
+    await safeInvoke<void>('set_actor_capabilities', { actor, capabilities });
+  } catch (error) {
+    throw withContext(error, 'Failed to set actor capabilities');
+  }
+}
+
+/**
+ * Get the deny-rule overrides of every project
+ * 
+ * @returns Overrides keyed by project root
+ * @throws Error if retrieval fails
+ */
+export async function getDenyOverrides(): Promise<Record<string, DenyOverrides>> {
+  try {
+    return await safeInvoke<Record<string, DenyOverrides>>('get_deny_overrides');
+  } catch (error) {
+    throw withContext(error, 'Failed to get deny rules');
+  }
+}
+
+/**
+ * Set the deny-rule overrides for a project (main window only)
+ * 
+ * @param project - The project root
+ * @param overrides - Extra deny globs and exceptions, relative to the project root
+ * @throws Error if saving fails
+ */
+export async function setDenyOverrides(project: string, overrides: DenyOverrides): Promise<void> {
+  try {
+    await safeInvoke<void>('set_deny_overrides', { project, overrides });
+  } catch (error) {
+    throw withContext(error, 'Failed to set deny rules');
+  }
+}
+
+/**
+ * Query the audit log
+ * 
+ * @param query - Filter by time range, path, actor or operation
+ * @returns Matching entries, oldest first
+ * @throws Error if the log cannot be read
+ */
+export async function queryAuditLog(query: AuditQuery = {}): Promise<AuditEntry[]> {
+  try {
+    return await safeInvoke<AuditEntry[]>('query_audit_log', { query });
+  } catch (error) {
+    throw withContext(error, 'Failed to query audit log');
+  }
+}
+
+/**
+ * Get quota limits, pause state and usage of every actor
+ * 
+ * @returns One status per actor
+ * @throws Error if retrieval fails
+ */
+export async function getQuotaStatus(): Promise<QuotaStatus[]> {
+  try {
+    return await safeInvoke<QuotaStatus[]>('get_quota_status');
+  } catch (error) {
+    throw withContext(error, 'Failed to get quota status');
+  }
+}
+
+/**
+ * Replace an actor's quota limits (main window only)
+ * 
+ * @param actor - The actor to configure
+ * @param limits - New limits (null = unlimited)
+ * @throws Error if saving fails
+ */
+export async function setQuotaLimits(actor: Actor, limits: QuotaLimits): Promise<void> {
+  try {
+    await safeInvoke<void>('set_quota_limits', { actor, limits });
+  } catch (error) {
+    throw withContext(error, 'Failed to set quota limits');
   }
 }
 
+/**
+ * Let an actor paused by a quota breach continue (main window only)
+ * 
+ * @param actor - The paused actor
+ * @param resetUsage - Also clear the actor's per-task usage
+ * @returns True if the actor was paused
+ * @throws Error if resuming fails
+ */
+export async function resumeActor(actor: Actor, resetUsage: boolean = false): Promise<boolean> {
+  try {
+    return await safeInvoke<boolean>('resume_actor', { actor, resetUsage });
+  } catch (error) {
+    throw withContext(error, 'Failed to resume actor');
+  }
+}
@@ -0,0 +1,23 @@
+/**
+ * Typed error for failed backend commands
+ * Kept apart from fileSystem.ts so modules that mock the IPC wrappers keep the class
+ */
+
+import type { FileSystemErrorCode, FileSystemErrorPayload } from '../types/filesystem';
+
+/**
+ * Typed error thrown for failed backend commands
+ */
+export class FileSystemError extends Error {
+  readonly code: FileSystemErrorCode;
+  readonly path: string | null;
+  readonly retryable: boolean;
+
+  constructor(payload: FileSystemErrorPayload, message: string = payload.message) {
+    super(message);
+    this.name = 'FileSystemError';
+    this.code = payload.code;
+    this.path = payload.path;
+    this.retryable = payload.retryable;
+  }
+}
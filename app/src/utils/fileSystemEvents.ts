@@ -4,13 +4,44 @@
  */
 
 import { listen } from '@tauri-apps/api/event';
-import type { FileWatchEvent } from '../types/filesystem';
+import type { FileWatchEvent, FileWatchEventMetadata } from '../types/filesystem';
 
 /**
  * Event handler type for file watch events
  */
 export type FileWatchEventHandler = (event: FileWatchEvent) => void;
 
+/**
+ * Raw watch event payload as serialized by the Rust backend
+ */
+interface RawFileWatchEvent {
+  kind: 'created' | 'modified' | 'deleted' | 'renamed';
+  path: string;
+  old_path: string | null;
+  is_dir: boolean | null;
+  size: number | null;
+  modified: number | null;
+  content_hash: string | null;
+  watch_id: number;
+  sequence: number;
+  self_originated: boolean;
+}
+
+/**
+ * Extract event metadata from a raw backend payload
+ */
+function toMetadata(payload: RawFileWatchEvent): FileWatchEventMetadata {
+  return {
+    isDir: payload.is_dir,
+    size: payload.size,
+    modified: payload.modified,
+    contentHash: payload.content_hash,
+    watchId: payload.watch_id,
+    sequence: payload.sequence,
+    selfOriginated: payload.self_originated,
+  };
+}
+
 /**
  * Set up file watch event listeners
  * 
@@ -27,103 +58,50 @@ export async function setupFileWatchListeners(handlers: {
 
   // Listen for file created events
   if (handlers.onCreated) {
-    const unlisten = await listen<any>(
-      'file-created',
-      (event) => {
-        const payload = event.payload as any;
-        // Handle enum serialization: {"Created": "path"} or {"type": "Created", "path": "..."}
-        let path = '';
-        if (payload.Created) {
-          path = payload.Created;
-        } else if (payload.path) {
-          path = payload.path;
-        } else if (typeof payload === 'string') {
-          path = payload;
-        }
-        
-        handlers.onCreated?.({
-          type: 'created',
-          path,
-        });
-      }
-    );
+    const unlisten = await listen<RawFileWatchEvent>('file-created', (event) => {
+      handlers.onCreated?.({
+        type: 'created',
+        path: event.payload.path,
+        metadata: toMetadata(event.payload),
+      });
+    });
     unlistenFunctions.push(unlisten);
   }
 
   // Listen for file modified events
   if (handlers.onModified) {
-    const unlisten = await listen<any>(
-      'file-modified',
-      (event) => {
-        const payload = event.payload as any;
-        // Handle enum serialization: {"Modified": "path"} or {"type": "Modified", "path": "..."}
-        let path = '';
-        if (payload.Modified) {
-          path = payload.Modified;
-        } else if (payload.path) {
-          path = payload.path;
-        } else if (typeof payload === 'string') {
-          path = payload;
-        }
-        
-        handlers.onModified?.({
-          type: 'modified',
-          path,
-        });
-      }
-    );
+    const unlisten = await listen<RawFileWatchEvent>('file-modified', (event) => {
+      handlers.onModified?.({
+        type: 'modified',
+        path: event.payload.path,
+        metadata: toMetadata(event.payload),
+      });
+    });
     unlistenFunctions.push(unlisten);
   }
 
   // Listen for file deleted events
   if (handlers.onDeleted) {
-    const unlisten = await listen<any>(
-      'file-deleted',
-      (event) => {
-        const payload = event.payload as any;
-        // Handle enum serialization: {"Deleted": "path"} or {"type": "Deleted", "path": "..."}
-        let path = '';
-        if (payload.Deleted) {
-          path = payload.Deleted;
-        } else if (payload.path) {
-          path = payload.path;
-        } else if (typeof payload === 'string') {
-          path = payload;
-        }
-        
-        handlers.onDeleted?.({
-          type: 'deleted',
-          path,
-        });
-      }
-    );
+    const unlisten = await listen<RawFileWatchEvent>('file-deleted', (event) => {
+      handlers.onDeleted?.({
+        type: 'deleted',
+        path: event.payload.path,
+        metadata: toMetadata(event.payload),
+      });
+    });
     unlistenFunctions.push(unlisten);
   }
 
   // Listen for file renamed events
   if (handlers.onRenamed) {
-    const unlisten = await listen<any>(
-      'file-renamed',
-      (event) => {
-        const payload = event.payload as any;
-        // Handle enum serialization: {"Renamed": {"old": "...", "new": "..."}} or {"old": "...", "new": "..."}
-        let oldPath = '';
-        let newPath = '';
-        if (payload.Renamed) {
-          oldPath = payload.Renamed.old || '';
-          newPath = payload.Renamed.new || '';
-        } else if (payload.old && payload.new) {
-          oldPath = payload.old;
-          newPath = payload.new;
-        }
-        
-        handlers.onRenamed?.({
-          type: 'renamed',
-          old: oldPath,
-          new: newPath,
-        });
-      }
-    );
+    const unlisten = await listen<RawFileWatchEvent>('file-renamed', (event) => {
+      handlers.onRenamed?.({
+        type: 'renamed',
+        old: event.payload.old_path ?? '',
+        new: event.payload.path,
+        metadata: toMetadata(event.payload),
+      });
+    });
     unlistenFunctions.push(unlisten);
   }
 
@@ -132,4 +110,3 @@ export async function setupFileWatchListeners(handlers: {
     unlistenFunctions.forEach((unlisten) => unlisten());
   };
 }
-
@@ -1,6 +1,8 @@
 // Utility exports
 export * from './fileSystem';
 export * from './fileSystemEvents';
+export * from './terminal';
+export * from './llm';
 export * from './languageDetection';
 export * from './editorFileWatcher';
 export * from './pathUtils';
@@ -0,0 +1,497 @@
+/**
+ * LLM utilities for Nexus Overseer
+ * Wraps the backend LLM commands and their streamed output
+ */
+
+import { listen } from '@tauri-apps/api/event';
+import type {
+  AgentRequest,
+  AgentResult,
+  AgentStep,
+  AiRole,
+  BudgetReport,
+  BudgetRequest,
+  CacheOptions,
+  CacheStats,
+  ChatRequest,
+  FitResult,
+  GenerateRequest,
+  LlmChunk,
+  LlmConfig,
+  LlmHealth,
+  LlmPullProgress,
+  LlmResponse,
+  ModelStatus,
+  Priority,
+  ProviderQueueStatus,
+  ProviderStatus,
+  RenderedPrompt,
+  StructuredOutput,
+  StructuredRequest,
+  TemplateInfo,
+  TokenCount,
+} from '../types/llm';
+import { safeInvoke, withContext } from './fileSystem';
+
+/**
+ * Complete a prompt
+ * 
+ * Text streams as `llm-chunk` events carrying `requestId` while the call runs;
+ * a chunk with `restart` set means the request was preempted and starts over.
+ * Identical requests running at the same time share one model call.
+ * 
+ * @param requestId - Caller-chosen id (unique among in-flight requests)
+ * @param request - Model, prompt and options
+ * @param role - Role whose provider to use (defaults to the default provider)
+ * @param cache - Cache the response (and answer from the cache) when given
+ * @param priority - Place in the provider's queue (defaults to background for
+ *   the implementation role, interactive otherwise)
+ * @returns The full text and statistics
+ * @throws Error if the request fails or is cancelled
+ */
+export async function llmGenerate(
+  requestId: string,
+  request: GenerateRequest,
+  role?: AiRole,
+  cache?: CacheOptions,
+  priority?: Priority
+): Promise<LlmResponse> {
+  try {
+    return await safeInvoke<LlmResponse>('llm_generate', {
+      requestId,
+      request,
+      role,
+      priority,
+      cache,
+    });
+  } catch (error) {
+    throw withContext(error, 'Failed to generate');
+  }
+}
+
+/**
+ * Continue a conversation
+ * 
+ * Text streams as `llm-chunk` events carrying `requestId` while the call runs;
+ * a chunk with `restart` set means the request was preempted and starts over.
+ * Identical requests running at the same time share one model call.
+ * 
+ * @param requestId - Caller-chosen id (unique among in-flight requests)
+ * @param request - Model, messages and options
+ * @param role - Role whose provider to use (defaults to the default provider)
+ * @param cache - Cache the response (and answer from the cache) when given
+ * @param priority - Place in the provider's queue (defaults to background for
+ *   the implementation role, interactive otherwise)
+ * @returns The assistant's full reply and statistics
+ * @throws Error if the request fails or is cancelled
+ */
+export async function llmChat(
+  requestId: string,
+  request: ChatRequest,
+  role?: AiRole,
+  cache?: CacheOptions,
+  priority?: Priority
+): Promise<LlmResponse> {
+  try {
+    return await safeInvoke<LlmResponse>('llm_chat', {
+      requestId,
+      request,
+      role,
+      priority,
+      cache,
+    });
+  } catch (error) {
+    throw withContext(error, 'Failed to chat');
+  }
+}
+
+/**
+ * Ask for JSON matching a schema
+ * 
+ * The reply is validated against `request.schema` and sent back to the model
+ * with its problems until it matches; text streams as `llm-chunk` events and a
+ * chunk with `restart` set starts each new attempt. The value is only checked
+ * against the schema, so `T` should describe the same shape.
+ * 
+ * @param requestId - Caller-chosen id (unique among in-flight requests)
+ * @param request - Model, messages, schema and repair limit
+ * @param role - Role whose provider to use (defaults to the default provider)
+ * @param priority - Place in the provider's queue
+ * @returns The parsed value and statistics
+ * @throws Error with code `InvalidOutput` if no reply matched the schema
+ */
+export async function generateStructured<T = unknown>(
+  requestId: string,
+  request: StructuredRequest,
+  role?: AiRole,
+  priority?: Priority
+): Promise<StructuredOutput<T>> {
+  try {
+    return await safeInvoke<StructuredOutput<T>>('llm_generate_structured', {
+      requestId,
+      request,
+      role,
+      priority,
+    });
+  } catch (error) {
+    throw withContext(error, 'Failed to generate structured output');
+  }
+}
+
+/**
+ * Cancel an in-flight request
+ * 
+ * @param requestId - The request's id
+ * @returns True if the request was still running
+ */
+export async function llmCancel(requestId: string): Promise<boolean> {
+  try {
+    return await safeInvoke<boolean>('llm_cancel', { requestId });
+  } catch (error) {
+    throw withContext(error, 'Failed to cancel request');
+  }
+}
+
+/**
+ * Get the request queues
+ * 
+ * @returns Running and waiting model calls per provider, with estimated times
+ */
+export async function getLlmQueueStatus(): Promise<ProviderQueueStatus[]> {
+  try {
+    return await safeInvoke<ProviderQueueStatus[]>('llm_queue_status');
+  } catch (error) {
+    throw withContext(error, 'Failed to get LLM queue status');
+  }
+}
+
+/**
+ * Get whether each provider is up
+ * 
+ * @returns Circuit breaker state per configured provider
+ */
+export async function getProviderStatus(): Promise<ProviderStatus[]> {
+  try {
+    return await safeInvoke<ProviderStatus[]>('llm_provider_status');
+  } catch (error) {
+    throw withContext(error, 'Failed to get LLM provider status');
+  }
+}
+
+/**
+ * Listen for providers going down or coming back
+ * 
+ * @param onStatus - Called whenever a provider's circuit breaker changes state
+ * @returns Cleanup function to remove the listener
+ */
+export async function onProviderStatus(
+  onStatus: (status: ProviderStatus) => void
+): Promise<() => void> {
+  return await listen<ProviderStatus>('llm-provider-status', (event) => {
+    onStatus(event.payload);
+  });
+}
+
+/**
+ * Get the LLM settings
+ * 
+ * @returns The current settings (without API keys outside the main window)
+ */
+export async function getLlmConfig(): Promise<LlmConfig> {
+  try {
+    return await safeInvoke<LlmConfig>('llm_get_config');
+  } catch (error) {
+    throw withContext(error, 'Failed to get LLM settings');
+  }
+}
+
+/**
+ * Change the LLM settings (main window only)
+ * 
+ * @param config - The new settings
+ * @throws Error if the settings are invalid or can't be saved
+ */
+export async function setLlmConfig(config: LlmConfig): Promise<void> {
+  try {
+    await safeInvoke<void>('llm_set_config', { config });
+  } catch (error) {
+    throw withContext(error, 'Failed to save LLM settings');
+  }
+}
+
+/**
+ * Get response cache statistics
+ * 
+ * @returns Size, limits and hit counts
+ */
+export async function getLlmCacheStats(): Promise<CacheStats> {
+  try {
+    return await safeInvoke<CacheStats>('llm_cache_stats');
+  } catch (error) {
+    throw withContext(error, 'Failed to get LLM cache statistics');
+  }
+}
+
+/**
+ * Drop every cached response (main window only)
+ * 
+ * @returns The number of entries dropped
+ */
+export async function clearLlmCache(): Promise<number> {
+  try {
+    return await safeInvoke<number>('llm_cache_clear');
+  } catch (error) {
+    throw withContext(error, 'Failed to clear the LLM cache');
+  }
+}
+
+/**
+ * List the models a provider can run
+ * 
+ * @param provider - Provider id (defaults to the default provider)
+ * @returns Models with size, quantization and context length where known
+ * @throws Error if the server can't be reached
+ */
+export async function listModels(provider?: string): Promise<ModelStatus[]> {
+  try {
+    return await safeInvoke<ModelStatus[]>('llm_list_models', { provider });
+  } catch (error) {
+    throw withContext(error, 'Failed to list models');
+  }
+}
+
+/**
+ * Check whether a provider's server is reachable
+ * 
+ * @param provider - Provider id (defaults to the default provider)
+ * @returns Reachability, version and latency
+ */
+export async function checkLlmHealth(provider?: string): Promise<LlmHealth> {
+  try {
+    return await safeInvoke<LlmHealth>('llm_health', { provider });
+  } catch (error) {
+    throw withContext(error, 'Failed to check LLM server');
+  }
+}
+
+/**
+ * Load a model so the first request doesn't wait for it
+ * 
+ * @param model - Model name
+ * @param provider - Provider id (defaults to the default provider)
+ * @throws Error if the model can't be loaded
+ */
+export async function warmUpModel(model: string, provider?: string): Promise<void> {
+  try {
+    await safeInvoke<void>('llm_warm_up', { model, provider });
+  } catch (error) {
+    throw withContext(error, 'Failed to load model');
+  }
+}
+
+/**
+ * Download a model (main window only)
+ * 
+ * Progress streams as `llm-pull-progress` events; `llmCancel(requestId)` stops it.
+ * 
+ * @param requestId - Caller-chosen id (unique among in-flight requests)
+ * @param model - Model name, e.g. 'llama3:8b'
+ * @param provider - Provider id (defaults to the default provider)
+ * @throws Error if the download fails or the provider can't download models
+ */
+export async function pullModel(requestId: string, model: string, provider?: string): Promise<void> {
+  try {
+    await safeInvoke<void>('llm_pull_model', { requestId, model, provider });
+  } catch (error) {
+    throw withContext(error, 'Failed to download model');
+  }
+}
+
+/**
+ * Delete a downloaded model (main window only)
+ * 
+ * @param model - Model name
+ * @param provider - Provider id (defaults to the default provider)
+ * @throws Error if the model doesn't exist or the provider can't delete models
+ */
+export async function deleteModel(model: string, provider?: string): Promise<void> {
+  try {
+    await safeInvoke<void>('llm_delete_model', { model, provider });
+  } catch (error) {
+    throw withContext(error, 'Failed to delete model');
+  }
+}
+
+/**
+ * Listen for model download progress
+ * 
+ * @param onProgress - Called with every progress update of every download
+ * @returns Cleanup function to remove the listener
+ */
+export async function onModelPullProgress(
+  onProgress: (progress: LlmPullProgress) => void
+): Promise<() => void> {
+  return await listen<LlmPullProgress>('llm-pull-progress', (event) => {
+    onProgress(event.payload);
+  });
+}
+
+/**
+ * Count the tokens a text takes for a model
+ * 
+ * @param model - Model name (empty for the role's model)
+ * @param text - Text to count
+ * @param role - Role whose provider and model to use
+ * @returns The count, and whether it is exact or estimated
+ */
+export async function countTokens(model: string, text: string, role?: AiRole): Promise<TokenCount> {
+  try {
+    return await safeInvoke<TokenCount>('llm_count_tokens', { model, text, role });
+  } catch (error) {
+    throw withContext(error, 'Failed to count tokens');
+  }
+}
+
+/**
+ * Work out how prompt inputs fit a model's context window
+ * 
+ * Nothing is changed; use the report to show the breakdown before sending.
+ * 
+ * @param request - Inputs and limits
+ * @param role - Role whose provider and model to use
+ * @returns Tokens per input and what would happen to each
+ */
+export async function planBudget(request: BudgetRequest, role?: AiRole): Promise<BudgetReport> {
+  try {
+    return await safeInvoke<BudgetReport>('llm_plan_budget', { request, role });
+  } catch (error) {
+    throw withContext(error, 'Failed to plan context budget');
+  }
+}
+
+/**
+ * Shorten prompt inputs to fit a model's context window
+ * 
+ * Inputs are truncated, or summarized by the model, as `planBudget` reports;
+ * `llmCancel(requestId)` stops pending summaries.
+ * 
+ * @param requestId - Caller-chosen id (unique among in-flight requests)
+ * @param request - Inputs and limits
+ * @param role - Role whose provider and model to use
+ * @param priority - Place of the summaries in the provider's queue
+ * @returns The inputs to send and the breakdown
+ */
+export async function fitBudget(
+  requestId: string,
+  request: BudgetRequest,
+  role?: AiRole,
+  priority?: Priority
+): Promise<FitResult> {
+  try {
+    return await safeInvoke<FitResult>('llm_fit_budget', {
+      requestId,
+      request,
+      role,
+      priority,
+    });
+  } catch (error) {
+    throw withContext(error, 'Failed to fit context budget');
+  }
+}
+
+/**
+ * List role prompt templates
+ * 
+ * @param role - Role to list (both roles if omitted)
+ * @returns Templates, most specific first; `active` marks the one in use
+ */
+export async function listPromptTemplates(role?: AiRole): Promise<TemplateInfo[]> {
+  try {
+    return await safeInvoke<TemplateInfo[]>('list_prompt_templates', { role });
+  } catch (error) {
+    throw withContext(error, 'Failed to list prompt templates');
+  }
+}
+
+/**
+ * Render a role's system prompt
+ * 
+ * Send `content` as the conversation's system message.
+ * 
+ * @param role - Role whose template to render
+ * @param task - Text for `{{task}}`
+ * @param variables - Further variables, overriding the built-in ones
+ * @returns The prompt and the template it came from
+ * @throws Error if the template is invalid or uses an unknown variable
+ */
+export async function previewPrompt(
+  role: AiRole,
+  task?: string,
+  variables?: Record<string, string>
+): Promise<RenderedPrompt> {
+  try {
+    return await safeInvoke<RenderedPrompt>('preview_prompt', { role, task, variables });
+  } catch (error) {
+    throw withContext(error, 'Failed to render prompt');
+  }
+}
+
+/**
+ * Let a role's model use the file tools until it answers
+ * 
+ * Tool calls run as the role's AI actor with the usual permission checks;
+ * only the main window may start a run. Text streams as `llm-chunk` events
+ * and tool calls as `llm-agent-step` events; `llmCancel(requestId)` stops
+ * the run.
+ * 
+ * @param requestId - Caller-chosen id (unique among in-flight requests)
+ * @param request - Conversation and limits
+ * @param role - Role whose provider, model and actor to use
+ * @param taskId - Task the file operations are charged to
+ * @param priority - Place of the model calls in the provider's queue
+ * @returns The final answer and the full conversation
+ * @throws Error if a model call fails or the run is cancelled
+ */
+export async function runAgent(
+  requestId: string,
+  request: AgentRequest,
+  role: AiRole,
+  taskId?: string,
+  priority?: Priority
+): Promise<AgentResult> {
+  try {
+    return await safeInvoke<AgentResult>('llm_agent_run', {
+      requestId,
+      request,
+      role,
+      taskId,
+      priority,
+    });
+  } catch (error) {
+    throw withContext(error, 'Agent run failed');
+  }
+}
+
+/**
+ * Listen for agent tool calls
+ * 
+ * @param onStep - Called after each tool call of every run
+ * @returns Cleanup function to remove the listener
+ */
+export async function onAgentStep(onStep: (step: AgentStep) => void): Promise<() => void> {
+  return await listen<AgentStep>('llm-agent-step', (event) => {
+    onStep(event.payload);
+  });
+}
+
+/**
+ * Listen for streamed LLM output
+ * 
+ * @param onChunk - Called with every chunk of every request
+ * @returns Cleanup function to remove the listener
+ */
+export async function onLlmChunk(onChunk: (chunk: LlmChunk) => void): Promise<() => void> {
+  return await listen<LlmChunk>('llm-chunk', (event) => {
+    onChunk(event.payload);
+  });
+}
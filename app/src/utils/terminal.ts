@@ -0,0 +1,133 @@
+/**
+ * Terminal session utilities for Nexus Overseer
+ * Wraps the backend PTY commands and their events
+ */
+
+import { listen } from '@tauri-apps/api/event';
+import type { OperationContext } from '../types/filesystem';
+import type { TerminalExit, TerminalInfo, TerminalOptions, TerminalOutput } from '../types/terminal';
+import { safeInvoke, withContext } from './fileSystem';
+
+/**
+ * Open a terminal session
+ * 
+ * @param options - Working directory, shell and size (all optional)
+ * @param context - Task the operation is charged to
+ * @returns The new session
+ * @throws Error if the shell can't be started
+ */
+export async function createTerminal(
+  options?: TerminalOptions,
+  context?: OperationContext
+): Promise<TerminalInfo> {
+  try {
+    return await safeInvoke<TerminalInfo>('create_terminal_command', { options, context });
+  } catch (error) {
+    throw withContext(error, 'Failed to create terminal');
+  }
+}
+
+/**
+ * Send input to a terminal session
+ * 
+ * @param id - The session id
+ * @param data - Keystrokes or pasted text
+ * @throws Error if the session is gone
+ */
+export async function writeTerminal(id: number, data: string): Promise<void> {
+  try {
+    await safeInvoke<void>('write_terminal', { id, data });
+  } catch (error) {
+    throw withContext(error, 'Failed to write to terminal');
+  }
+}
+
+/**
+ * Resize a terminal session
+ * 
+ * @param id - The session id
+ * @param cols - Width in character cells
+ * @param rows - Height in character cells
+ * @throws Error if the session is gone
+ */
+export async function resizeTerminal(id: number, cols: number, rows: number): Promise<void> {
+  try {
+    await safeInvoke<void>('resize_terminal', { id, cols, rows });
+  } catch (error) {
+    throw withContext(error, 'Failed to resize terminal');
+  }
+}
+
+/**
+ * Kill a terminal session's shell
+ * 
+ * @param id - The session id
+ * @throws Error if the session is gone
+ */
+export async function killTerminal(id: number): Promise<void> {
+  try {
+    await safeInvoke<void>('kill_terminal', { id });
+  } catch (error) {
+    throw withContext(error, 'Failed to kill terminal');
+  }
+}
+
+/**
+ * Get the output kept for a session (to restore a terminal panel)
+ * 
+ * @param id - The session id
+ * @returns Most recent output (up to 1MB)
+ * @throws Error if the session is gone
+ */
+export async function getTerminalScrollback(id: number): Promise<string> {
+  try {
+    return await safeInvoke<string>('get_terminal_scrollback', { id });
+  } catch (error) {
+    throw withContext(error, 'Failed to get terminal scrollback');
+  }
+}
+
+/**
+ * List open terminal sessions
+ * 
+ * @returns Sessions, oldest first
+ * @throws Error if retrieval fails
+ */
+export async function listTerminals(): Promise<TerminalInfo[]> {
+  try {
+    return await safeInvoke<TerminalInfo[]>('list_terminals');
+  } catch (error) {
+    throw withContext(error, 'Failed to list terminals');
+  }
+}
+
+/**
+ * Set up terminal event listeners
+ * 
+ * @param handlers - Handlers for output and exit of any session
+ * @returns Cleanup function to remove all listeners
+ */
+export async function setupTerminalListeners(handlers: {
+  onOutput?: (output: TerminalOutput) => void;
+  onExit?: (exit: TerminalExit) => void;
+}): Promise<() => void> {
+  const unlistenFunctions: (() => void)[] = [];
+
+  if (handlers.onOutput) {
+    const unlisten = await listen<TerminalOutput>('terminal-output', (event) => {
+      handlers.onOutput?.(event.payload);
+    });
+    unlistenFunctions.push(unlisten);
+  }
+
+  if (handlers.onExit) {
+    const unlisten = await listen<TerminalExit>('terminal-exited', (event) => {
+      handlers.onExit?.(event.payload);
+    });
+    unlistenFunctions.push(unlisten);
+  }
+
+  return () => {
+    unlistenFunctions.forEach((unlisten) => unlisten());
+  };
+}